@@ -0,0 +1,25 @@
+//! CANopen (CiA 301) master: network management, SDO client, PDO mapping and
+//! emergency messages, with object dictionaries from EDS and DCF files, and
+//! node commissioning with layer setting services (CiA 305).
+//!
+//! ```text
+//! nmt::send_command(&mut interface, nmt::Command::ResetCommunication, nmt::ALL_NODES)?;
+//! let mut client = sdo::Client::new(&mut interface, 5);
+//! let device_type = client.upload(0x1000, 0)?;
+//! client.download(0x1017, 0, &1000u16.to_le_bytes())?;
+//! nmt::send_command(client.interface(), nmt::Command::Start, 5)?;
+//! ```
+
+use embedded_can::StandardId;
+
+pub mod eds;
+pub mod emcy;
+pub mod lss;
+pub mod nmt;
+pub mod pdo;
+pub mod sdo;
+
+/// The identifier of a function code, e.g. `0x580` for SDO responses, for `node_id`.
+fn cob_id(function: u16, node_id: u8) -> StandardId {
+    StandardId::new(function + node_id as u16).unwrap()
+}
@@ -0,0 +1,818 @@
+//! Electronic data sheets (EDS) and device configuration files (DCF) (CiA 306),
+//! parsed into an object dictionary.
+//!
+//! ```text
+//! let dictionary = ObjectDictionary::open("drive.eds")?;
+//! let heartbeat = dictionary.find("Producer heartbeat time").unwrap();
+//! let value = heartbeat.data_type.decode(&client.upload(heartbeat.index, heartbeat.subindex)?);
+//! ```
+
+use std::{collections::BTreeMap, fs, path::Path, str::FromStr};
+
+use crate::Error;
+
+/// Data types of object dictionary entries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DataType {
+    Boolean,
+    /// Signed integer with the given number of bits.
+    Integer(u8),
+    /// Unsigned integer with the given number of bits.
+    Unsigned(u8),
+    Real32,
+    Real64,
+    VisibleString,
+    OctetString,
+    UnicodeString,
+    Domain,
+    /// Other standard or complex data types.
+    Other(u16),
+}
+
+impl From<u16> for DataType {
+    fn from(code: u16) -> Self {
+        match code {
+            0x01 => DataType::Boolean,
+            0x02 => DataType::Integer(8),
+            0x03 => DataType::Integer(16),
+            0x04 => DataType::Integer(32),
+            0x05 => DataType::Unsigned(8),
+            0x06 => DataType::Unsigned(16),
+            0x07 => DataType::Unsigned(32),
+            0x08 => DataType::Real32,
+            0x09 => DataType::VisibleString,
+            0x0A => DataType::OctetString,
+            0x0B => DataType::UnicodeString,
+            0x0F => DataType::Domain,
+            0x10 => DataType::Integer(24),
+            0x11 => DataType::Real64,
+            0x12 => DataType::Integer(40),
+            0x13 => DataType::Integer(48),
+            0x14 => DataType::Integer(56),
+            0x15 => DataType::Integer(64),
+            0x16 => DataType::Unsigned(24),
+            0x18 => DataType::Unsigned(40),
+            0x19 => DataType::Unsigned(48),
+            0x1A => DataType::Unsigned(56),
+            0x1B => DataType::Unsigned(64),
+            code => DataType::Other(code),
+        }
+    }
+}
+
+impl DataType {
+    /// Size in bytes of fixed size types.
+    pub fn size(self) -> Option<usize> {
+        match self {
+            DataType::Boolean => Some(1),
+            DataType::Integer(bits) | DataType::Unsigned(bits) => Some(bits as usize / 8),
+            DataType::Real32 => Some(4),
+            DataType::Real64 => Some(8),
+            _ => None,
+        }
+    }
+
+    /// Decodes a value as transferred by SDO. Returns `None` if the data is too
+    /// short for the type.
+    pub fn decode(self, data: &[u8]) -> Option<Value> {
+        match self {
+            DataType::VisibleString => Some(Value::String(
+                String::from_utf8_lossy(data)
+                    .trim_end_matches('\0')
+                    .to_string(),
+            )),
+            DataType::UnicodeString => {
+                let units: Vec<u16> = data
+                    .chunks_exact(2)
+                    .map(|unit| u16::from_le_bytes([unit[0], unit[1]]))
+                    .collect();
+                Some(Value::String(String::from_utf16_lossy(&units)))
+            }
+            DataType::OctetString | DataType::Domain | DataType::Other(_) => {
+                Some(Value::Bytes(data.to_vec()))
+            }
+            _ => {
+                let size = self.size()?;
+                let mut bytes = [0; 8];
+                bytes[..size].copy_from_slice(data.get(..size)?);
+                self.decode_bits(u64::from_le_bytes(bytes), size as u32 * 8)
+            }
+        }
+    }
+
+    /// Encodes a value for an SDO transfer. Returns `None` if the value does not
+    /// fit the type.
+    pub fn encode(self, value: &Value) -> Option<Vec<u8>> {
+        match (self, value) {
+            (DataType::VisibleString, Value::String(s)) => Some(s.as_bytes().to_vec()),
+            (DataType::UnicodeString, Value::String(s)) => {
+                Some(s.encode_utf16().flat_map(u16::to_le_bytes).collect())
+            }
+            (DataType::OctetString | DataType::Domain | DataType::Other(_), Value::Bytes(b)) => {
+                Some(b.clone())
+            }
+            _ => {
+                let size = self.size()?;
+                let raw = self.encode_bits(value, size as u32 * 8)?;
+                Some(raw.to_le_bytes()[..size].to_vec())
+            }
+        }
+    }
+
+    /// Converts the raw bits of a numeric value, sign extending integers from `bits`.
+    pub(crate) fn decode_bits(self, raw: u64, bits: u32) -> Option<Value> {
+        match self {
+            DataType::Boolean => Some(Value::Boolean(raw != 0)),
+            DataType::Integer(_) => {
+                let shift = 64 - bits.min(64);
+                Some(Value::Integer(((raw << shift) as i64) >> shift))
+            }
+            DataType::Unsigned(_) => Some(Value::Unsigned(raw)),
+            DataType::Real32 => Some(Value::Real(f32::from_bits(raw as u32) as f64)),
+            DataType::Real64 => Some(Value::Real(f64::from_bits(raw))),
+            _ => None,
+        }
+    }
+
+    /// Converts a numeric value to raw bits, checking that it fits in `bits`.
+    pub(crate) fn encode_bits(self, value: &Value, bits: u32) -> Option<u64> {
+        let unsigned_max = u64::MAX >> (64 - bits.min(64));
+        match (self, value) {
+            (DataType::Boolean, Value::Boolean(b)) => Some(*b as u64),
+            (DataType::Integer(_), Value::Integer(_) | Value::Unsigned(_)) => {
+                let v = value.as_i128()?;
+                let limit = 1i128 << (bits.min(64) - 1);
+                (-limit..limit)
+                    .contains(&v)
+                    .then_some(v as u64 & unsigned_max)
+            }
+            (DataType::Unsigned(_), Value::Integer(_) | Value::Unsigned(_)) => {
+                let v = value.as_i128()?;
+                (0..=unsigned_max as i128).contains(&v).then_some(v as u64)
+            }
+            (DataType::Real32, _) => Some(f32::to_bits(value.as_f64()? as f32) as u64),
+            (DataType::Real64, _) => Some(f64::to_bits(value.as_f64()?)),
+            _ => None,
+        }
+    }
+}
+
+/// A value of an object dictionary entry.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Boolean(bool),
+    Integer(i64),
+    Unsigned(u64),
+    Real(f64),
+    String(String),
+    Bytes(Vec<u8>),
+}
+
+impl Value {
+    /// The value as a number, if it is numeric.
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Value::Boolean(b) => Some(b as u8 as f64),
+            Value::Integer(v) => Some(v as f64),
+            Value::Unsigned(v) => Some(v as f64),
+            Value::Real(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    fn as_i128(&self) -> Option<i128> {
+        match *self {
+            Value::Integer(v) => Some(v as i128),
+            Value::Unsigned(v) => Some(v as i128),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AccessType {
+    ReadOnly,
+    WriteOnly,
+    ReadWrite,
+    /// Read-write, mapped into RPDOs.
+    ReadWriteInput,
+    /// Read-write, mapped into TPDOs.
+    ReadWriteOutput,
+    Constant,
+}
+
+impl FromStr for AccessType {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "ro" => Ok(AccessType::ReadOnly),
+            "wo" => Ok(AccessType::WriteOnly),
+            "rw" => Ok(AccessType::ReadWrite),
+            "rwr" => Ok(AccessType::ReadWriteInput),
+            "rww" => Ok(AccessType::ReadWriteOutput),
+            "const" => Ok(AccessType::Constant),
+            _ => Err(Error(format!("Unknown access type {}", s))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ObjectType {
+    Variable,
+    Array,
+    Record,
+    /// Domain, type definition or other object codes.
+    Other(u8),
+}
+
+impl From<u8> for ObjectType {
+    fn from(code: u8) -> Self {
+        match code {
+            0x7 => ObjectType::Variable,
+            0x8 => ObjectType::Array,
+            0x9 => ObjectType::Record,
+            code => ObjectType::Other(code),
+        }
+    }
+}
+
+/// An entry of the object dictionary, addressed by index and subindex.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variable {
+    pub index: u16,
+    pub subindex: u8,
+    pub name: String,
+    pub data_type: DataType,
+    pub access_type: AccessType,
+    /// Default value as written in the file, possibly referring to `$NODEID`.
+    pub default_value: Option<String>,
+    /// Configured value of a DCF.
+    pub parameter_value: Option<String>,
+    pub low_limit: Option<String>,
+    pub high_limit: Option<String>,
+    /// Whether the entry may be mapped into PDOs.
+    pub pdo_mapping: bool,
+}
+
+impl Variable {
+    /// The configured value of a DCF or else the default value, evaluated for
+    /// `node_id`.
+    pub fn value(&self, node_id: u8) -> Option<Value> {
+        let text = self
+            .parameter_value
+            .as_deref()
+            .or(self.default_value.as_deref())?;
+        match self.data_type {
+            DataType::VisibleString | DataType::UnicodeString => {
+                Some(Value::String(text.to_string()))
+            }
+            DataType::OctetString | DataType::Domain | DataType::Other(_) => {
+                let hex: String = text.split_whitespace().collect();
+                (0..hex.len())
+                    .step_by(2)
+                    .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+                    .collect::<Option<_>>()
+                    .map(Value::Bytes)
+            }
+            DataType::Real32 | DataType::Real64 => text.trim().parse().ok().map(Value::Real),
+            DataType::Boolean => Some(Value::Boolean(parse_integer(text, Some(node_id))? != 0)),
+            DataType::Integer(_) => {
+                Some(Value::Integer(parse_integer(text, Some(node_id))? as i64))
+            }
+            DataType::Unsigned(_) => {
+                Some(Value::Unsigned(parse_integer(text, Some(node_id))? as u64))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Object {
+    pub index: u16,
+    pub name: String,
+    pub object_type: ObjectType,
+    variables: BTreeMap<u8, Variable>,
+}
+
+impl Object {
+    pub fn variable(&self, subindex: u8) -> Option<&Variable> {
+        self.variables.get(&subindex)
+    }
+
+    /// Iterates over the entries in subindex order.
+    pub fn variables(&self) -> impl Iterator<Item = &Variable> {
+        self.variables.values()
+    }
+
+    fn is_compound(&self) -> bool {
+        matches!(self.object_type, ObjectType::Array | ObjectType::Record)
+    }
+}
+
+/// The objects described by an EDS or DCF file.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ObjectDictionary {
+    /// Entries of the `[DeviceInfo]` section.
+    pub device_info: BTreeMap<String, String>,
+    /// Node ID of a DCF.
+    pub node_id: Option<u8>,
+    objects: BTreeMap<u16, Object>,
+}
+
+impl ObjectDictionary {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let bytes = fs::read(path)?;
+        String::from_utf8_lossy(&bytes).parse()
+    }
+
+    pub fn object(&self, index: u16) -> Option<&Object> {
+        self.objects.get(&index)
+    }
+
+    pub fn variable(&self, index: u16, subindex: u8) -> Option<&Variable> {
+        self.objects.get(&index)?.variable(subindex)
+    }
+
+    /// Iterates over the objects in index order.
+    pub fn objects(&self) -> impl Iterator<Item = &Object> {
+        self.objects.values()
+    }
+
+    /// Finds an entry by name, `Object.Entry` for entries of arrays and records.
+    pub fn find(&self, name: &str) -> Option<&Variable> {
+        self.objects.values().find_map(|object| {
+            if !object.is_compound() {
+                return object.variable(0).filter(|_| object.name == name);
+            }
+            let entry = name.strip_prefix(object.name.as_str())?.strip_prefix('.')?;
+            object.variables().find(|variable| variable.name == entry)
+        })
+    }
+}
+
+impl FromStr for ObjectDictionary {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let sections = parse_sections(s)?;
+        let section = |name: &str| {
+            sections
+                .iter()
+                .find(|(section, _)| section.name.eq_ignore_ascii_case(name))
+                .map(|(_, section)| section)
+        };
+
+        let mut dictionary = ObjectDictionary::default();
+        if let Some(device_info) = section("DeviceInfo") {
+            dictionary.device_info = device_info
+                .entries
+                .iter()
+                .map(|(key, value, _)| (key.clone(), value.clone()))
+                .collect();
+        }
+        if let Some(commissioning) = section("DeviceComissioning") {
+            dictionary.node_id = commissioning
+                .get("NodeID")
+                .and_then(|(value, _)| parse_integer(value, None))
+                .map(|node_id| node_id as u8);
+        }
+
+        for (header, entries) in &sections {
+            let Some(index) = header.object_index() else {
+                continue;
+            };
+            let name = entries.require("ParameterName", header.line)?.to_string();
+            let object_type = match entries.get("ObjectType") {
+                Some((value, line)) => ObjectType::from(
+                    parse_integer(value, None).ok_or_else(|| error(line, "invalid object type"))?
+                        as u8,
+                ),
+                None => ObjectType::Variable,
+            };
+
+            let mut variables = BTreeMap::new();
+            let subobjects: Vec<_> = sections
+                .iter()
+                .filter_map(|(header, entries)| {
+                    let (i, subindex) = header.subobject_index()?;
+                    (i == index).then_some((subindex, header.line, entries))
+                })
+                .collect();
+
+            if let Some((count, line)) = entries.get("CompactSubObj") {
+                let count = parse_integer(count, None)
+                    .ok_or_else(|| error(line, "invalid number of subobjects"))?
+                    as u8;
+                variables.insert(
+                    0,
+                    Variable {
+                        index,
+                        subindex: 0,
+                        name: "Number of entries".to_string(),
+                        data_type: DataType::Unsigned(8),
+                        access_type: AccessType::ReadOnly,
+                        default_value: Some(count.to_string()),
+                        parameter_value: None,
+                        low_limit: None,
+                        high_limit: None,
+                        pdo_mapping: false,
+                    },
+                );
+                let names = section(&format!("{:X}Name", index));
+                for subindex in 1..=count {
+                    let mut variable = parse_variable(entries, index, subindex, header.line)?;
+                    variable.name = names
+                        .and_then(|names| names.get(&subindex.to_string()))
+                        .map_or_else(|| format!("{}{}", name, subindex), |(n, _)| n.to_string());
+                    variables.insert(subindex, variable);
+                }
+            } else if subobjects.is_empty() {
+                let mut variable = parse_variable(entries, index, 0, header.line)?;
+                variable.name = name.clone();
+                variables.insert(0, variable);
+            } else {
+                for (subindex, line, entries) in subobjects {
+                    let mut variable = parse_variable(entries, index, subindex, line)?;
+                    variable.name = entries.require("ParameterName", line)?.to_string();
+                    variables.insert(subindex, variable);
+                }
+            }
+
+            dictionary.objects.insert(
+                index,
+                Object {
+                    index,
+                    name,
+                    object_type,
+                    variables,
+                },
+            );
+        }
+        Ok(dictionary)
+    }
+}
+
+struct SectionHeader {
+    name: String,
+    line: usize,
+}
+
+impl SectionHeader {
+    /// The index of object sections such as `[1018]`.
+    fn object_index(&self) -> Option<u16> {
+        if self.name.len() != 4 {
+            return None;
+        }
+        u16::from_str_radix(&self.name, 16).ok()
+    }
+
+    /// The index and subindex of subobject sections such as `[1018sub1]`.
+    fn subobject_index(&self) -> Option<(u16, u8)> {
+        let lower = self.name.to_ascii_lowercase();
+        let (index, subindex) = lower.split_once("sub")?;
+        if index.len() != 4 {
+            return None;
+        }
+        Some((
+            u16::from_str_radix(index, 16).ok()?,
+            u8::from_str_radix(subindex, 16).ok()?,
+        ))
+    }
+}
+
+/// The key, value and line number of each entry of a section.
+struct Entries {
+    entries: Vec<(String, String, usize)>,
+}
+
+impl Entries {
+    fn get(&self, key: &str) -> Option<(&str, usize)> {
+        self.entries
+            .iter()
+            .find(|(k, _, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, value, line)| (value.as_str(), *line))
+    }
+
+    fn require(&self, key: &str, section_line: usize) -> Result<&str, Error> {
+        self.get(key)
+            .map(|(value, _)| value)
+            .ok_or_else(|| error(section_line, &format!("missing {}", key)))
+    }
+}
+
+fn parse_sections(s: &str) -> Result<Vec<(SectionHeader, Entries)>, Error> {
+    let mut sections: Vec<(SectionHeader, Entries)> = Vec::new();
+    for (index, line) in s.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(';') {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[') {
+            let name = name
+                .strip_suffix(']')
+                .ok_or_else(|| error(index + 1, "invalid section header"))?;
+            sections.push((
+                SectionHeader {
+                    name: name.trim().to_string(),
+                    line: index + 1,
+                },
+                Entries {
+                    entries: Vec::new(),
+                },
+            ));
+            continue;
+        }
+
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| error(index + 1, "expected key=value"))?;
+        let (_, entries) = sections
+            .last_mut()
+            .ok_or_else(|| error(index + 1, "entry outside of a section"))?;
+        entries
+            .entries
+            .push((key.trim().to_string(), value.trim().to_string(), index + 1));
+    }
+    Ok(sections)
+}
+
+fn parse_variable(
+    entries: &Entries,
+    index: u16,
+    subindex: u8,
+    section_line: usize,
+) -> Result<Variable, Error> {
+    let (data_type, line) = entries
+        .get("DataType")
+        .ok_or_else(|| error(section_line, "missing DataType"))?;
+    let data_type =
+        parse_integer(data_type, None).ok_or_else(|| error(line, "invalid data type"))?;
+    let (access_type, line) = entries
+        .get("AccessType")
+        .ok_or_else(|| error(section_line, "missing AccessType"))?;
+    let access_type = access_type
+        .parse()
+        .map_err(|err: Error| error(line, &err.0))?;
+    let optional = |key| {
+        entries
+            .get(key)
+            .map(|(value, _)| value.to_string())
+            .filter(|value| !value.is_empty())
+    };
+
+    Ok(Variable {
+        index,
+        subindex,
+        name: String::new(),
+        data_type: DataType::from(data_type as u16),
+        access_type,
+        default_value: optional("DefaultValue"),
+        parameter_value: optional("ParameterValue"),
+        low_limit: optional("LowLimit"),
+        high_limit: optional("HighLimit"),
+        pdo_mapping: entries
+            .get("PDOMapping")
+            .and_then(|(value, _)| parse_integer(value, None))
+            .is_some_and(|value| value != 0),
+    })
+}
+
+/// Parses a decimal, `0x` hexadecimal or `0` prefixed octal integer, which may
+/// be a sum with `$NODEID`.
+fn parse_integer(s: &str, node_id: Option<u8>) -> Option<i128> {
+    s.split('+').try_fold(0i128, |sum, term| {
+        let term = term.trim();
+        let value = if term.eq_ignore_ascii_case("$NODEID") {
+            node_id? as i128
+        } else if let Some(hex) = term.strip_prefix("0x").or_else(|| term.strip_prefix("0X")) {
+            i128::from_str_radix(hex, 16).ok()?
+        } else if term.len() > 1
+            && let Some(octal) = term.strip_prefix('0')
+        {
+            i128::from_str_radix(octal, 8).ok()?
+        } else {
+            term.parse().ok()?
+        };
+        Some(sum + value)
+    })
+}
+
+fn error(line: usize, msg: &str) -> Error {
+    Error(format!("EDS line {}: {}", line, msg))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EDS: &str = "
+; Example drive
+[DeviceInfo]
+VendorName=Example
+ProductName=Drive
+
+[DeviceComissioning]
+NodeID=0x05
+
+[1000]
+ParameterName=Device type
+ObjectType=0x7
+DataType=0x0007
+AccessType=ro
+DefaultValue=0x00020192
+PDOMapping=0
+
+[1014]
+ParameterName=COB-ID EMCY
+DataType=0x0007
+AccessType=rw
+DefaultValue=$NODEID+0x80
+
+[1017]
+ParameterName=Producer heartbeat time
+DataType=0x0006
+AccessType=rw
+DefaultValue=0
+ParameterValue=010
+
+[1018]
+ParameterName=Identity object
+ObjectType=0x9
+SubNumber=2
+
+[1018sub0]
+ParameterName=Highest sub-index supported
+DataType=0x0005
+AccessType=ro
+DefaultValue=1
+
+[1018sub1]
+ParameterName=Vendor-ID
+DataType=0x0007
+AccessType=ro
+DefaultValue=0x1234
+
+[2000]
+ParameterName=Serial number
+DataType=0x000A
+AccessType=ro
+DefaultValue=01 02 ff
+
+[2001]
+ParameterName=Gain
+DataType=0x0008
+AccessType=rw
+DefaultValue=1.5
+
+[6000]
+ParameterName=Digital input
+ObjectType=0x8
+CompactSubObj=2
+DataType=0x0005
+AccessType=ro
+PDOMapping=1
+
+[6000Name]
+NrOfEntries=1
+1=Input A
+
+[6064]
+ParameterName=Position actual value
+DataType=0x0004
+AccessType=rww
+PDOMapping=1
+LowLimit=
+DefaultValue=-10
+";
+
+    #[test]
+    fn parse() {
+        let dictionary: ObjectDictionary = EDS.parse().unwrap();
+        assert_eq!(dictionary.device_info["ProductName"], "Drive");
+        assert_eq!(dictionary.node_id, Some(5));
+        assert_eq!(dictionary.objects().count(), 8);
+
+        let device_type = dictionary.variable(0x1000, 0).unwrap();
+        assert_eq!(device_type.name, "Device type");
+        assert_eq!(device_type.data_type, DataType::Unsigned(32));
+        assert_eq!(device_type.access_type, AccessType::ReadOnly);
+        assert!(!device_type.pdo_mapping);
+        assert_eq!(device_type.value(5), Some(Value::Unsigned(0x20192)));
+
+        let identity = dictionary.object(0x1018).unwrap();
+        assert_eq!(identity.object_type, ObjectType::Record);
+        assert_eq!(identity.variables().count(), 2);
+        let vendor_id = dictionary.find("Identity object.Vendor-ID").unwrap();
+        assert_eq!((vendor_id.index, vendor_id.subindex), (0x1018, 1));
+        assert_eq!(vendor_id.value(5), Some(Value::Unsigned(0x1234)));
+        assert!(dictionary.find("Identity object").is_none());
+        assert_eq!(dictionary.find("Device type").unwrap().index, 0x1000);
+
+        let input = dictionary.object(0x6000).unwrap();
+        assert_eq!(input.object_type, ObjectType::Array);
+        let names: Vec<_> = input.variables().map(|v| v.name.as_str()).collect();
+        assert_eq!(names, ["Number of entries", "Input A", "Digital input2"]);
+        assert_eq!(
+            input.variable(0).unwrap().value(5),
+            Some(Value::Unsigned(2))
+        );
+        assert!(input.variable(2).unwrap().pdo_mapping);
+
+        let position = dictionary.variable(0x6064, 0).unwrap();
+        assert_eq!(position.access_type, AccessType::ReadWriteOutput);
+        assert_eq!(position.low_limit, None);
+        assert_eq!(position.value(5), Some(Value::Integer(-10)));
+    }
+
+    #[test]
+    fn values() {
+        let dictionary: ObjectDictionary = EDS.parse().unwrap();
+        let value = |index| dictionary.variable(index, 0).unwrap().value(3);
+        assert_eq!(value(0x1014), Some(Value::Unsigned(0x83)));
+        // The configured value of a DCF takes precedence, here in octal.
+        assert_eq!(value(0x1017), Some(Value::Unsigned(8)));
+        assert_eq!(value(0x2000), Some(Value::Bytes(vec![1, 2, 0xFF])));
+        assert_eq!(value(0x2001), Some(Value::Real(1.5)));
+        assert_eq!(dictionary.variable(0x6000, 1).unwrap().value(3), None);
+    }
+
+    #[test]
+    fn data_types() {
+        assert_eq!(DataType::from(0x10), DataType::Integer(24));
+        assert_eq!(DataType::from(0x1B), DataType::Unsigned(64));
+        assert_eq!(DataType::from(0x20), DataType::Other(0x20));
+
+        let int16 = DataType::Integer(16);
+        assert_eq!(int16.decode(&[0xFE, 0xFF]), Some(Value::Integer(-2)));
+        assert_eq!(int16.decode(&[0xFE]), None);
+        assert_eq!(int16.encode(&Value::Integer(-2)), Some(vec![0xFE, 0xFF]));
+        assert_eq!(
+            int16.encode(&Value::Unsigned(32767)),
+            Some(vec![0xFF, 0x7F])
+        );
+        assert_eq!(int16.encode(&Value::Integer(32768)), None);
+        assert_eq!(int16.encode(&Value::Real(1.0)), None);
+
+        let uint24 = DataType::Unsigned(24);
+        assert_eq!(
+            uint24.encode(&Value::Unsigned(0x123456)),
+            Some(vec![0x56, 0x34, 0x12])
+        );
+        assert_eq!(uint24.encode(&Value::Unsigned(0x1000000)), None);
+        assert_eq!(uint24.encode(&Value::Integer(-1)), None);
+        assert_eq!(
+            uint24.decode(&[0x56, 0x34, 0x12, 0xFF]),
+            Some(Value::Unsigned(0x123456))
+        );
+
+        let real = DataType::Real32;
+        assert_eq!(real.decode(&1.5f32.to_le_bytes()), Some(Value::Real(1.5)));
+        assert_eq!(
+            real.encode(&Value::Integer(2)),
+            Some(2f32.to_le_bytes().to_vec())
+        );
+        assert_eq!(DataType::Boolean.decode(&[1]), Some(Value::Boolean(true)));
+
+        assert_eq!(
+            DataType::VisibleString.decode(b"abc\0\0"),
+            Some(Value::String("abc".to_string()))
+        );
+        let text = Value::String("µs".to_string());
+        let encoded = DataType::UnicodeString.encode(&text).unwrap();
+        assert_eq!(encoded, [0xB5, 0, b's', 0]);
+        assert_eq!(DataType::UnicodeString.decode(&encoded), Some(text));
+        assert_eq!(DataType::Domain.encode(&Value::Unsigned(1)), None);
+    }
+
+    #[test]
+    fn invalid_files() {
+        for (eds, message) in [
+            ("[1000\n", "EDS line 1: invalid section header"),
+            (
+                "ParameterName=Device type\n",
+                "EDS line 1: entry outside of a section",
+            ),
+            ("[1000]\nParameterName\n", "EDS line 2: expected key=value"),
+            (
+                "[1000]\nDataType=0x0007\nAccessType=ro\n",
+                "EDS line 1: missing ParameterName",
+            ),
+            (
+                "[1000]\nParameterName=A\nAccessType=ro\n",
+                "EDS line 1: missing DataType",
+            ),
+            (
+                "[1000]\nParameterName=A\nDataType=7\nAccessType=rx\n",
+                "EDS line 4: Unknown access type rx",
+            ),
+            (
+                "[1000]\nParameterName=A\nDataType=x\nAccessType=ro\n",
+                "EDS line 3: invalid data type",
+            ),
+        ] {
+            let err = eds.parse::<ObjectDictionary>().unwrap_err();
+            assert_eq!(err.0, message);
+        }
+    }
+}
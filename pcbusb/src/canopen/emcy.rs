@@ -0,0 +1,199 @@
+//! Emergency messages.
+//!
+//! ```text
+//! if let Some(frame) = interface.receive_timeout(Duration::from_millis(100))?
+//!     && let Some(emergency) = Emergency::decode(&frame)
+//! {
+//!     println!("node {}: {:#06x} {}", emergency.node_id, emergency.error_code, emergency.description());
+//! }
+//! ```
+
+use embedded_can::{Frame as _, Id};
+
+use crate::Frame;
+
+const EMCY: u16 = 0x080;
+
+/// Bits of the error register, object 0x1001.
+pub mod error_register {
+    pub const GENERIC: u8 = 0x01;
+    pub const CURRENT: u8 = 0x02;
+    pub const VOLTAGE: u8 = 0x04;
+    pub const TEMPERATURE: u8 = 0x08;
+    pub const COMMUNICATION: u8 = 0x10;
+    pub const DEVICE_PROFILE: u8 = 0x20;
+    pub const MANUFACTURER: u8 = 0x80;
+}
+
+const ERROR_CODES: &[(u16, &str)] = &[
+    (0x0000, "Error reset or no error"),
+    (0x1000, "Generic error"),
+    (0x2000, "Current"),
+    (0x2100, "Current, device input side"),
+    (0x2200, "Current inside the device"),
+    (0x2300, "Current, device output side"),
+    (0x3000, "Voltage"),
+    (0x3100, "Mains voltage"),
+    (0x3200, "Voltage inside the device"),
+    (0x3300, "Output voltage"),
+    (0x4000, "Temperature"),
+    (0x4100, "Ambient temperature"),
+    (0x4200, "Device temperature"),
+    (0x5000, "Device hardware"),
+    (0x6000, "Device software"),
+    (0x6100, "Internal software"),
+    (0x6200, "User software"),
+    (0x6300, "Data set"),
+    (0x7000, "Additional modules"),
+    (0x8000, "Monitoring"),
+    (0x8100, "Communication"),
+    (0x8110, "CAN overrun (objects lost)"),
+    (0x8120, "CAN in error passive mode"),
+    (0x8130, "Life guard error or heartbeat error"),
+    (0x8140, "Recovered from bus off"),
+    (0x8150, "CAN-ID collision"),
+    (0x8200, "Protocol error"),
+    (0x8210, "PDO not processed due to length error"),
+    (0x8220, "PDO length exceeded"),
+    (
+        0x8230,
+        "DAM MPDO not processed, destination object not available",
+    ),
+    (0x8240, "Unexpected SYNC data length"),
+    (0x8250, "RPDO timeout"),
+    (0x9000, "External error"),
+    (0xF000, "Additional functions"),
+    (0xFF00, "Device specific"),
+];
+
+/// An emergency message of a node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Emergency {
+    pub node_id: u8,
+    pub error_code: u16,
+    /// The error register of the node, see [`error_register`].
+    pub error_register: u8,
+    pub manufacturer_data: [u8; 5],
+}
+
+impl Emergency {
+    /// Decodes an emergency message, `None` for other frames.
+    pub fn decode(frame: &Frame) -> Option<Self> {
+        let Id::Standard(id) = frame.id() else {
+            return None;
+        };
+        let node_id = id.as_raw().checked_sub(EMCY)?;
+        if frame.is_remote_frame() || !(1..=127).contains(&node_id) || frame.data().len() < 3 {
+            return None;
+        }
+        let data = frame.data();
+        let mut manufacturer_data = [0; 5];
+        manufacturer_data[..data.len() - 3].copy_from_slice(&data[3..]);
+        Some(Self {
+            node_id: node_id as u8,
+            error_code: u16::from_le_bytes([data[0], data[1]]),
+            error_register: data[2],
+            manufacturer_data,
+        })
+    }
+
+    /// Whether the node reports that its errors were resolved.
+    pub fn is_reset(&self) -> bool {
+        self.error_code == 0
+    }
+
+    /// Describes the error code, falling back to its group for unknown codes.
+    pub fn description(&self) -> &'static str {
+        if self.is_reset() {
+            return ERROR_CODES[0].1;
+        }
+        [0xFFFF, 0xFFF0, 0xFF00, 0xF000]
+            .iter()
+            .find_map(|mask| {
+                ERROR_CODES
+                    .iter()
+                    .find(|(code, _)| *code == self.error_code & mask && *code != 0)
+            })
+            .map_or("Unknown error", |(_, description)| description)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_can::{ExtendedId, StandardId};
+
+    use super::*;
+
+    fn emergency(error_code: u16) -> Emergency {
+        Emergency {
+            node_id: 1,
+            error_code,
+            error_register: 0,
+            manufacturer_data: [0; 5],
+        }
+    }
+
+    #[test]
+    fn decode() {
+        let frame = Frame::new(
+            StandardId::new(0x085).unwrap(),
+            &[0x10, 0x81, 0x11, 1, 2, 3, 4, 5],
+        )
+        .unwrap();
+        assert_eq!(
+            Emergency::decode(&frame),
+            Some(Emergency {
+                node_id: 5,
+                error_code: 0x8110,
+                error_register: error_register::GENERIC | error_register::COMMUNICATION,
+                manufacturer_data: [1, 2, 3, 4, 5],
+            })
+        );
+
+        // Short messages without all manufacturer specific bytes.
+        let frame = Frame::new(StandardId::new(0x0FF).unwrap(), &[0x00, 0x00, 0x00, 9]).unwrap();
+        let emergency = Emergency::decode(&frame).unwrap();
+        assert_eq!(emergency.node_id, 127);
+        assert!(emergency.is_reset());
+        assert_eq!(emergency.manufacturer_data, [9, 0, 0, 0, 0]);
+
+        // SYNC, other objects, remote and extended frames.
+        for frame in [
+            Frame::new(StandardId::new(0x080).unwrap(), &[0, 0, 0]).unwrap(),
+            Frame::new(StandardId::new(0x100).unwrap(), &[0, 0, 0]).unwrap(),
+            Frame::new(StandardId::new(0x185).unwrap(), &[0, 0, 0]).unwrap(),
+            Frame::new(StandardId::new(0x085).unwrap(), &[0, 0]).unwrap(),
+            Frame::new_remote(StandardId::new(0x085).unwrap(), 8).unwrap(),
+            Frame::new(ExtendedId::new(0x085).unwrap(), &[0, 0, 0]).unwrap(),
+        ] {
+            assert_eq!(Emergency::decode(&frame), None, "{:?}", frame);
+        }
+    }
+
+    #[test]
+    fn descriptions() {
+        for (error_code, description) in [
+            (0x0000, "Error reset or no error"),
+            (0x1000, "Generic error"),
+            (0x2310, "Current, device output side"),
+            (0x3210, "Voltage inside the device"),
+            (0x4000, "Temperature"),
+            (0x8120, "CAN in error passive mode"),
+            (0x8130, "Life guard error or heartbeat error"),
+            // Unknown codes fall back to their group.
+            (0x8131, "Life guard error or heartbeat error"),
+            (0x8260, "Protocol error"),
+            (0x50AB, "Device hardware"),
+            (0xFF42, "Device specific"),
+            (0x0001, "Unknown error"),
+            (0xA000, "Unknown error"),
+        ] {
+            assert_eq!(
+                emergency(error_code).description(),
+                description,
+                "{:#06x}",
+                error_code
+            );
+        }
+    }
+}
@@ -0,0 +1,487 @@
+//! Layer setting services (CiA 305) master, for assigning node IDs and bit
+//! timing to devices by their identity.
+//!
+//! ```text
+//! let mut master = lss::Master::new(&mut interface);
+//! master.switch_state_global(lss::Mode::Waiting)?;
+//! while let Some(address) = master.fastscan()? {
+//!     println!("found {:08x}:{:08x}", address.product_code, address.serial_number);
+//!     master.configure_node_id(next_node_id)?;
+//!     master.store_configuration()?;
+//!     master.switch_state_global(lss::Mode::Waiting)?;
+//!     next_node_id += 1;
+//! }
+//! ```
+
+use std::{fmt, time::Duration};
+
+use embedded_can::{Frame as _, StandardId};
+
+use crate::{Baudrate, Frame, Interface, ResponseFilter};
+
+const LSS_REQUEST: u16 = 0x7E5;
+const LSS_RESPONSE: u16 = 0x7E4;
+
+const SWITCH_STATE_GLOBAL: u8 = 0x04;
+const CONFIGURE_NODE_ID: u8 = 0x11;
+const CONFIGURE_BIT_TIMING: u8 = 0x13;
+const ACTIVATE_BIT_TIMING: u8 = 0x15;
+const STORE_CONFIGURATION: u8 = 0x17;
+const SWITCH_STATE_SELECTIVE: u8 = 0x40;
+const SWITCH_STATE_SELECTIVE_RESPONSE: u8 = 0x44;
+const IDENTIFY_NON_CONFIGURED: u8 = 0x4C;
+const IDENTIFY_SLAVE: u8 = 0x4F;
+const NON_CONFIGURED_SLAVE: u8 = 0x50;
+const FASTSCAN: u8 = 0x51;
+const INQUIRE_IDENTITY: u8 = 0x5A;
+const INQUIRE_NODE_ID: u8 = 0x5E;
+
+/// Bit checked value of fastscan requests that resets the scan of the slaves.
+const FASTSCAN_RESET: u8 = 0x80;
+
+/// The identity of an LSS slave, as in object 0x1018.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Address {
+    pub vendor_id: u32,
+    pub product_code: u32,
+    pub revision_number: u32,
+    pub serial_number: u32,
+}
+
+impl Address {
+    fn parts(&self) -> [u32; 4] {
+        [
+            self.vendor_id,
+            self.product_code,
+            self.revision_number,
+            self.serial_number,
+        ]
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Mode {
+    Waiting,
+    Configuration,
+}
+
+/// Errors of LSS services.
+#[derive(Debug)]
+pub enum Error {
+    /// The slave rejected the configuration.
+    Rejected { error_code: u8, specific_error: u8 },
+    /// The baudrate has no entry in the CiA bit timing table.
+    UnsupportedBaudrate(Baudrate),
+    /// No response was received within the timeout.
+    Timeout,
+    /// The response does not belong to the request or is malformed.
+    InvalidResponse(Vec<u8>),
+    /// Transmission or reception of the frames failed.
+    Transport(crate::Error),
+}
+
+impl From<crate::Error> for Error {
+    fn from(err: crate::Error) -> Self {
+        Error::Transport(err)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Rejected {
+                error_code,
+                specific_error,
+            } => write!(
+                f,
+                "LSS slave rejected the configuration with error {} ({})",
+                error_code, specific_error
+            ),
+            Error::UnsupportedBaudrate(baudrate) => {
+                write!(f, "{:?} is not in the CiA bit timing table", baudrate)
+            }
+            Error::Timeout => write!(f, "No response from the LSS slave"),
+            Error::InvalidResponse(response) => write!(f, "Invalid response {:02x?}", response),
+            Error::Transport(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// An LSS master.
+pub struct Master<'a> {
+    interface: &'a mut Interface,
+    timeout: Duration,
+}
+
+impl<'a> Master<'a> {
+    pub fn new(interface: &'a mut Interface) -> Self {
+        Self {
+            interface,
+            timeout: Duration::from_millis(100),
+        }
+    }
+
+    /// Sets how long to wait for responses of the slaves. Fastscan waits this
+    /// long for every bit of the identity. Defaults to 100 ms.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn interface(&mut self) -> &mut Interface {
+        self.interface
+    }
+
+    /// Switches all slaves to `mode`.
+    pub fn switch_state_global(&mut self, mode: Mode) -> Result<(), Error> {
+        let mode = match mode {
+            Mode::Waiting => 0,
+            Mode::Configuration => 1,
+        };
+        self.send(command(SWITCH_STATE_GLOBAL, &[mode]))
+    }
+
+    /// Switches the slave with `address` to configuration mode.
+    pub fn switch_state_selective(&mut self, address: &Address) -> Result<(), Error> {
+        for (i, request) in switch_state_selective_requests(address)
+            .into_iter()
+            .enumerate()
+        {
+            if i < 3 {
+                self.send(request)?;
+            } else {
+                self.exchange(request, SWITCH_STATE_SELECTIVE_RESPONSE)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Identifies one unconfigured slave with fastscan and switches it to
+    /// configuration mode. Returns `None` if no slave without node ID is in
+    /// waiting mode.
+    pub fn fastscan(&mut self) -> Result<Option<Address>, Error> {
+        fastscan(|request| self.query(request, IDENTIFY_SLAVE).map(|r| r.is_some()))
+    }
+
+    /// Sets the pending node ID, from 1 to 127 or 255 for none, of the slave in
+    /// configuration mode.
+    pub fn configure_node_id(&mut self, node_id: u8) -> Result<(), Error> {
+        let response = self.exchange(command(CONFIGURE_NODE_ID, &[node_id]), CONFIGURE_NODE_ID)?;
+        check_error(&response)
+    }
+
+    /// Sets the pending bit timing of the slave in configuration mode, which is
+    /// applied by [`Master::activate_bit_timing`].
+    pub fn configure_bit_timing(&mut self, baudrate: Baudrate) -> Result<(), Error> {
+        let index = bit_timing_index(baudrate)?;
+        let response = self.exchange(
+            command(CONFIGURE_BIT_TIMING, &[0, index]),
+            CONFIGURE_BIT_TIMING,
+        )?;
+        check_error(&response)
+    }
+
+    /// Makes all slaves in configuration mode switch to their pending bit
+    /// timing. They stop transmitting for `switch_delay`, switch, and wait
+    /// `switch_delay` again before transmitting with the new bit timing.
+    pub fn activate_bit_timing(&mut self, switch_delay: Duration) -> Result<(), Error> {
+        let delay = u16::try_from(switch_delay.as_millis()).unwrap_or(u16::MAX);
+        self.send(command(ACTIVATE_BIT_TIMING, &delay.to_le_bytes()))
+    }
+
+    /// Stores the pending node ID and bit timing of the slave in configuration
+    /// mode in non-volatile memory.
+    pub fn store_configuration(&mut self) -> Result<(), Error> {
+        let response = self.exchange(command(STORE_CONFIGURATION, &[]), STORE_CONFIGURATION)?;
+        check_error(&response)
+    }
+
+    /// Reads the identity of the slave in configuration mode.
+    pub fn inquire_address(&mut self) -> Result<Address, Error> {
+        let mut parts = [0u32; 4];
+        for (i, part) in parts.iter_mut().enumerate() {
+            let cs = INQUIRE_IDENTITY + i as u8;
+            let response = self.exchange(command(cs, &[]), cs)?;
+            *part = u32::from_le_bytes(response[1..5].try_into().unwrap());
+        }
+        Ok(Address {
+            vendor_id: parts[0],
+            product_code: parts[1],
+            revision_number: parts[2],
+            serial_number: parts[3],
+        })
+    }
+
+    /// Reads the active node ID of the slave in configuration mode, 255 if it
+    /// has none.
+    pub fn inquire_node_id(&mut self) -> Result<u8, Error> {
+        let response = self.exchange(command(INQUIRE_NODE_ID, &[]), INQUIRE_NODE_ID)?;
+        Ok(response[1])
+    }
+
+    /// Checks whether any slave without node ID is present.
+    pub fn identify_non_configured(&mut self) -> Result<bool, Error> {
+        let request = command(IDENTIFY_NON_CONFIGURED, &[]);
+        Ok(self.query(request, NON_CONFIGURED_SLAVE)?.is_some())
+    }
+
+    fn exchange(&mut self, request: [u8; 8], response: u8) -> Result<[u8; 8], Error> {
+        self.query(request, response)?.ok_or(Error::Timeout)
+    }
+
+    /// Sends a request and waits for a response with the command specifier
+    /// `response`. Several slaves may answer at once, their identical frames
+    /// are received as one.
+    fn query(&mut self, request: [u8; 8], response: u8) -> Result<Option<[u8; 8]>, Error> {
+        let frame = Frame::new(StandardId::new(LSS_REQUEST).unwrap(), &request).unwrap();
+        let filter = ResponseFilter::new(StandardId::new(LSS_RESPONSE).unwrap())
+            .with_data(move |data| data.first() == Some(&response));
+        let Some(frame) = self.interface.request(&frame, &filter, self.timeout)? else {
+            return Ok(None);
+        };
+        frame
+            .data()
+            .try_into()
+            .map(Some)
+            .map_err(|_| Error::InvalidResponse(frame.data().to_vec()))
+    }
+
+    fn send(&mut self, request: [u8; 8]) -> Result<(), Error> {
+        let frame = Frame::new(StandardId::new(LSS_REQUEST).unwrap(), &request).unwrap();
+        Ok(embedded_can::blocking::Can::transmit(
+            self.interface,
+            &frame,
+        )?)
+    }
+}
+
+/// An LSS request with the command specifier `cs`, padded to 8 bytes.
+fn command(cs: u8, data: &[u8]) -> [u8; 8] {
+    let mut request = [0; 8];
+    request[0] = cs;
+    request[1..1 + data.len()].copy_from_slice(data);
+    request
+}
+
+/// The requests of a switch state selective service, the last one is answered
+/// by the slave.
+fn switch_state_selective_requests(address: &Address) -> [[u8; 8]; 4] {
+    let parts = address.parts();
+    std::array::from_fn(|i| command(SWITCH_STATE_SELECTIVE + i as u8, &parts[i].to_le_bytes()))
+}
+
+/// Runs a fastscan with `step`, which sends a request and reports whether any
+/// slave answered.
+fn fastscan(
+    mut step: impl FnMut([u8; 8]) -> Result<bool, Error>,
+) -> Result<Option<Address>, Error> {
+    if !step(fastscan_request(0, FASTSCAN_RESET, 0, 0))? {
+        return Ok(None);
+    }
+
+    let mut parts = [0u32; 4];
+    for sub in 0..4u8 {
+        for bit in (0..32).rev() {
+            // Slaves answer if their identity matches from bit 31 down to
+            // the checked bit.
+            if !step(fastscan_request(parts[sub as usize], bit, sub, sub))? {
+                parts[sub as usize] |= 1 << bit;
+            }
+        }
+        // Confirms the complete part and moves the slave on to the next,
+        // or to configuration mode after the serial number.
+        if !step(fastscan_request(parts[sub as usize], 0, sub, (sub + 1) % 4))? {
+            return Err(Error::Timeout);
+        }
+    }
+
+    Ok(Some(Address {
+        vendor_id: parts[0],
+        product_code: parts[1],
+        revision_number: parts[2],
+        serial_number: parts[3],
+    }))
+}
+
+/// A fastscan request with the IDNumber `id`, checking bits 31 down to
+/// `bit_checked` of identity part `sub`, with `next` the part checked next.
+fn fastscan_request(id: u32, bit_checked: u8, sub: u8, next: u8) -> [u8; 8] {
+    let mut request = command(FASTSCAN, &id.to_le_bytes());
+    request[5..8].copy_from_slice(&[bit_checked, sub, next]);
+    request
+}
+
+/// The index of `baudrate` in the CiA bit timing table.
+fn bit_timing_index(baudrate: Baudrate) -> Result<u8, Error> {
+    Ok(match baudrate {
+        Baudrate::Baud1m => 0,
+        Baudrate::Baud800k => 1,
+        Baudrate::Baud500k => 2,
+        Baudrate::Baud250k => 3,
+        Baudrate::Baud125k => 4,
+        // Reserved since CiA 305 2.0, but still used by many devices.
+        Baudrate::Baud100k => 5,
+        Baudrate::Baud50k => 6,
+        Baudrate::Baud20k => 7,
+        Baudrate::Baud10k => 8,
+        baudrate => return Err(Error::UnsupportedBaudrate(baudrate)),
+    })
+}
+
+/// Checks the error code of a configuration response.
+fn check_error(response: &[u8; 8]) -> Result<(), Error> {
+    match response[1] {
+        0 => Ok(()),
+        error_code => Err(Error::Rejected {
+            error_code,
+            specific_error: response[2],
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ADDRESS: Address = Address {
+        vendor_id: 0x0000_0319,
+        product_code: 0x1234_5678,
+        revision_number: 0x0001_0002,
+        serial_number: 0x8000_00FF,
+    };
+
+    #[test]
+    fn bit_timing_table() {
+        for (baudrate, index) in [
+            (Baudrate::Baud1m, 0),
+            (Baudrate::Baud800k, 1),
+            (Baudrate::Baud500k, 2),
+            (Baudrate::Baud250k, 3),
+            (Baudrate::Baud125k, 4),
+            (Baudrate::Baud100k, 5),
+            (Baudrate::Baud50k, 6),
+            (Baudrate::Baud20k, 7),
+            (Baudrate::Baud10k, 8),
+        ] {
+            assert_eq!(bit_timing_index(baudrate).unwrap(), index);
+        }
+        for baudrate in [Baudrate::Baud95k, Baudrate::Baud83k, Baudrate::Baud5k] {
+            assert!(matches!(
+                bit_timing_index(baudrate),
+                Err(Error::UnsupportedBaudrate(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn commands() {
+        assert_eq!(
+            command(SWITCH_STATE_GLOBAL, &[1]),
+            [0x04, 1, 0, 0, 0, 0, 0, 0]
+        );
+        assert_eq!(
+            command(CONFIGURE_BIT_TIMING, &[0, 3]),
+            [0x13, 0, 3, 0, 0, 0, 0, 0]
+        );
+        assert_eq!(
+            command(ACTIVATE_BIT_TIMING, &500u16.to_le_bytes()),
+            [0x15, 0xF4, 0x01, 0, 0, 0, 0, 0]
+        );
+
+        assert!(check_error(&[0x11, 0, 0, 0, 0, 0, 0, 0]).is_ok());
+        assert!(matches!(
+            check_error(&[0x11, 0xFF, 0x05, 0, 0, 0, 0, 0]),
+            Err(Error::Rejected {
+                error_code: 0xFF,
+                specific_error: 0x05
+            })
+        ));
+    }
+
+    #[test]
+    fn switch_state_selective() {
+        assert_eq!(
+            switch_state_selective_requests(&ADDRESS),
+            [
+                [0x40, 0x19, 0x03, 0x00, 0x00, 0, 0, 0],
+                [0x41, 0x78, 0x56, 0x34, 0x12, 0, 0, 0],
+                [0x42, 0x02, 0x00, 0x01, 0x00, 0, 0, 0],
+                [0x43, 0xFF, 0x00, 0x00, 0x80, 0, 0, 0],
+            ]
+        );
+    }
+
+    #[test]
+    fn fastscan_requests() {
+        // IDNumber, BitChecked, LSSSub and LSSNext.
+        assert_eq!(
+            fastscan_request(0x1234_5678, 31, 1, 1),
+            [0x51, 0x78, 0x56, 0x34, 0x12, 31, 1, 1]
+        );
+        assert_eq!(
+            fastscan_request(0, FASTSCAN_RESET, 0, 0),
+            [0x51, 0, 0, 0, 0, 0x80, 0, 0]
+        );
+    }
+
+    /// Answers fastscan requests like a slave with `address`.
+    fn slave(address: Address) -> impl FnMut([u8; 8]) -> Result<bool, Error> {
+        let parts = address.parts();
+        let mut sub = 0;
+        move |request| {
+            assert_eq!(request[0], FASTSCAN);
+            let id = u32::from_le_bytes(request[1..5].try_into().unwrap());
+            let [bit_checked, lss_sub, lss_next] = [request[5], request[6], request[7]];
+            if bit_checked == FASTSCAN_RESET {
+                sub = 0;
+                return Ok(true);
+            }
+            if lss_sub != sub {
+                return Ok(false);
+            }
+            let mask = u32::MAX << bit_checked;
+            let matches = (id ^ parts[sub as usize]) & mask == 0;
+            if matches {
+                sub = lss_next;
+            }
+            Ok(matches)
+        }
+    }
+
+    #[test]
+    fn fastscan_finds_slave() {
+        let mut requests = 0;
+        let mut answer = slave(ADDRESS);
+        let address = fastscan(|request| {
+            requests += 1;
+            answer(request)
+        })
+        .unwrap();
+        assert_eq!(address, Some(ADDRESS));
+        // The reset, then 32 bits and a confirmation per part.
+        assert_eq!(requests, 1 + 4 * 33);
+
+        let address = Address {
+            vendor_id: u32::MAX,
+            product_code: 0,
+            revision_number: 1,
+            serial_number: 1 << 31,
+        };
+        assert_eq!(fastscan(slave(address)).unwrap(), Some(address));
+    }
+
+    #[test]
+    fn fastscan_without_slaves() {
+        assert_eq!(fastscan(|_| Ok(false)).unwrap(), None);
+
+        // A slave that stops answering during the confirmation.
+        let mut answered = 0;
+        let result = fastscan(|request| {
+            answered += 1;
+            Ok(request[5] == FASTSCAN_RESET || answered < 20)
+        });
+        assert!(matches!(result, Err(Error::Timeout)));
+    }
+}
@@ -0,0 +1,334 @@
+//! Network management: node state commands, heartbeat consumption and node
+//! guarding.
+//!
+//! ```text
+//! let mut monitor = nmt::Monitor::new().with_heartbeat(5, Duration::from_millis(1500));
+//! loop {
+//!     if let Some(frame) = interface.receive_timeout(Duration::from_millis(100))? {
+//!         if let Some(event) = monitor.process(&frame) {
+//!             println!("{:?}", event);
+//!         }
+//!     }
+//!     for event in monitor.check() {
+//!         println!("{:?}", event);
+//!     }
+//! }
+//! ```
+
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+
+use embedded_can::{Frame as _, Id, StandardId};
+
+use super::cob_id;
+use crate::{Error, Frame, Interface, ResponseFilter};
+
+/// Node ID addressing all nodes in NMT commands.
+pub const ALL_NODES: u8 = 0;
+
+const NMT: u16 = 0x000;
+const HEARTBEAT: u16 = 0x700;
+
+/// Node state commands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Command {
+    Start,
+    Stop,
+    EnterPreOperational,
+    ResetNode,
+    ResetCommunication,
+}
+
+impl From<Command> for u8 {
+    fn from(command: Command) -> Self {
+        match command {
+            Command::Start => 0x01,
+            Command::Stop => 0x02,
+            Command::EnterPreOperational => 0x80,
+            Command::ResetNode => 0x81,
+            Command::ResetCommunication => 0x82,
+        }
+    }
+}
+
+/// Node states, as reported by heartbeat and node guarding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum State {
+    BootUp,
+    Stopped,
+    Operational,
+    PreOperational,
+    Unknown(u8),
+}
+
+impl From<u8> for State {
+    fn from(state: u8) -> Self {
+        match state {
+            0x00 => State::BootUp,
+            0x04 => State::Stopped,
+            0x05 => State::Operational,
+            0x7F => State::PreOperational,
+            state => State::Unknown(state),
+        }
+    }
+}
+
+/// Sends an NMT command to `node_id`, or to all nodes with [`ALL_NODES`].
+pub fn send_command(interface: &mut Interface, command: Command, node_id: u8) -> Result<(), Error> {
+    let frame = Frame::new(StandardId::new(NMT).unwrap(), &[command.into(), node_id]).unwrap();
+    embedded_can::blocking::Can::transmit(interface, &frame)
+}
+
+/// Changes of the monitored nodes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Event {
+    /// The node sent its boot-up message.
+    BootUp(u8),
+    StateChanged {
+        node_id: u8,
+        state: State,
+    },
+    /// No heartbeat was received within the consumer time.
+    HeartbeatLost(u8),
+    /// The node did not answer a guarding request, or without toggling.
+    GuardingFailed(u8),
+}
+
+#[derive(Debug, Default)]
+struct NodeStatus {
+    heartbeat_timeout: Option<Duration>,
+    state: Option<State>,
+    last_seen: Option<Instant>,
+    lost: bool,
+    toggle: Option<bool>,
+}
+
+/// Tracks the states of nodes from their heartbeats and guarding responses.
+#[derive(Debug, Default)]
+pub struct Monitor {
+    nodes: BTreeMap<u8, NodeStatus>,
+}
+
+impl Monitor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reports [`Event::HeartbeatLost`] if `node_id` sends no heartbeat within
+    /// `timeout`, once its first heartbeat was received.
+    pub fn with_heartbeat(mut self, node_id: u8, timeout: Duration) -> Self {
+        self.nodes.entry(node_id).or_default().heartbeat_timeout = Some(timeout);
+        self
+    }
+
+    /// The last known state of `node_id`.
+    pub fn state(&self, node_id: u8) -> Option<State> {
+        self.nodes.get(&node_id)?.state
+    }
+
+    /// Iterates over the node IDs and states of all nodes seen so far.
+    pub fn states(&self) -> impl Iterator<Item = (u8, State)> + '_ {
+        self.nodes
+            .iter()
+            .filter_map(|(node_id, status)| Some((*node_id, status.state?)))
+    }
+
+    /// Updates the node state from a heartbeat frame, other frames are ignored.
+    pub fn process(&mut self, frame: &Frame) -> Option<Event> {
+        let Id::Standard(id) = frame.id() else {
+            return None;
+        };
+        let node_id = id.as_raw().checked_sub(HEARTBEAT)?;
+        if frame.is_remote_frame() || !(1..=127).contains(&node_id) || frame.data().is_empty() {
+            return None;
+        }
+        self.update(node_id as u8, State::from(frame.data()[0] & 0x7F))
+    }
+
+    /// Reports the nodes whose heartbeat expired since the last call.
+    pub fn check(&mut self) -> Vec<Event> {
+        let now = Instant::now();
+        let mut events = Vec::new();
+        for (node_id, status) in &mut self.nodes {
+            if let (Some(timeout), Some(last_seen)) = (status.heartbeat_timeout, status.last_seen)
+                && !status.lost
+                && now.duration_since(last_seen) > timeout
+            {
+                status.lost = true;
+                events.push(Event::HeartbeatLost(*node_id));
+            }
+        }
+        events
+    }
+
+    /// Sends a node guarding request to `node_id` and checks that the response
+    /// arrives within `timeout` with the toggle bit alternated.
+    pub fn guard(
+        &mut self,
+        interface: &mut Interface,
+        node_id: u8,
+        timeout: Duration,
+    ) -> Result<Option<Event>, Error> {
+        let id = cob_id(HEARTBEAT, node_id);
+        let request = Frame::new_remote(id, 1).unwrap();
+        let filter = ResponseFilter::new(id).with_data(|data| !data.is_empty());
+        let response = interface.request(&request, &filter, timeout)?;
+        Ok(self.guarding_response(node_id, response.map(|frame| frame.data()[0])))
+    }
+
+    /// Updates the node state from the status byte of a guarding response,
+    /// `None` if the node did not answer.
+    fn guarding_response(&mut self, node_id: u8, response: Option<u8>) -> Option<Event> {
+        let Some(response) = response else {
+            return Some(Event::GuardingFailed(node_id));
+        };
+
+        let toggle = response & 0x80 != 0;
+        let status = self.nodes.entry(node_id).or_default();
+        let toggled = status.toggle.is_none_or(|previous| previous != toggle);
+        status.toggle = Some(toggle);
+        if !toggled {
+            return Some(Event::GuardingFailed(node_id));
+        }
+        self.update(node_id, State::from(response & 0x7F))
+    }
+
+    fn update(&mut self, node_id: u8, state: State) -> Option<Event> {
+        let status = self.nodes.entry(node_id).or_default();
+        let was_lost = status.lost;
+        let previous = status.state.replace(state);
+        status.last_seen = Some(Instant::now());
+        status.lost = false;
+
+        if state == State::BootUp {
+            // The toggle bit starts at zero after a reset.
+            status.toggle = None;
+            return Some(Event::BootUp(node_id));
+        }
+        (was_lost || previous != Some(state)).then_some(Event::StateChanged { node_id, state })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    fn heartbeat(node_id: u8, state: u8) -> Frame {
+        Frame::new(cob_id(HEARTBEAT, node_id), &[state]).unwrap()
+    }
+
+    #[test]
+    fn states() {
+        assert_eq!(State::from(0x00), State::BootUp);
+        assert_eq!(State::from(0x04), State::Stopped);
+        assert_eq!(State::from(0x05), State::Operational);
+        assert_eq!(State::from(0x7F), State::PreOperational);
+        assert_eq!(State::from(0x01), State::Unknown(0x01));
+        assert_eq!(u8::from(Command::Start), 0x01);
+        assert_eq!(u8::from(Command::ResetCommunication), 0x82);
+    }
+
+    #[test]
+    fn heartbeats() {
+        let mut monitor = Monitor::new();
+        assert_eq!(monitor.process(&heartbeat(5, 0x00)), Some(Event::BootUp(5)));
+        assert_eq!(
+            monitor.process(&heartbeat(5, 0x7F)),
+            Some(Event::StateChanged {
+                node_id: 5,
+                state: State::PreOperational
+            })
+        );
+        assert_eq!(monitor.process(&heartbeat(5, 0x7F)), None);
+        // The toggle bit of guarding responses is ignored in heartbeats.
+        assert_eq!(monitor.process(&heartbeat(5, 0xFF)), None);
+        assert_eq!(monitor.state(5), Some(State::PreOperational));
+
+        // Other frames.
+        let remote = Frame::new_remote(cob_id(HEARTBEAT, 5), 1).unwrap();
+        assert_eq!(monitor.process(&remote), None);
+        let emcy = Frame::new(cob_id(0x080, 5), &[0; 8]).unwrap();
+        assert_eq!(monitor.process(&emcy), None);
+        let empty = Frame::new(cob_id(HEARTBEAT, 6), &[]).unwrap();
+        assert_eq!(monitor.process(&empty), None);
+        let extended = Frame::new(embedded_can::ExtendedId::new(0x705).unwrap(), &[0x05]).unwrap();
+        assert_eq!(monitor.process(&extended), None);
+
+        monitor.process(&heartbeat(3, 0x05));
+        assert_eq!(
+            monitor.states().collect::<Vec<_>>(),
+            [(3, State::Operational), (5, State::PreOperational)]
+        );
+    }
+
+    #[test]
+    fn heartbeat_timeout() {
+        let timeout = Duration::from_millis(20);
+        let mut monitor = Monitor::new().with_heartbeat(5, timeout);
+        // Nodes are only monitored after their first heartbeat.
+        thread::sleep(timeout * 2);
+        assert_eq!(monitor.check(), []);
+
+        monitor.process(&heartbeat(5, 0x05));
+        monitor.process(&heartbeat(6, 0x05));
+        assert_eq!(monitor.check(), []);
+        thread::sleep(timeout * 2);
+        // Reported once, and only for nodes with a consumer time.
+        assert_eq!(monitor.check(), [Event::HeartbeatLost(5)]);
+        assert_eq!(monitor.check(), []);
+
+        // The state is reported again when the node comes back.
+        assert_eq!(
+            monitor.process(&heartbeat(5, 0x05)),
+            Some(Event::StateChanged {
+                node_id: 5,
+                state: State::Operational
+            })
+        );
+        assert_eq!(monitor.check(), []);
+    }
+
+    #[test]
+    fn guarding() {
+        let mut monitor = Monitor::new();
+        assert_eq!(
+            monitor.guarding_response(5, None),
+            Some(Event::GuardingFailed(5))
+        );
+        assert_eq!(
+            monitor.guarding_response(5, Some(0x05)),
+            Some(Event::StateChanged {
+                node_id: 5,
+                state: State::Operational
+            })
+        );
+        assert_eq!(monitor.guarding_response(5, Some(0x85)), None);
+        assert_eq!(monitor.guarding_response(5, Some(0x05)), None);
+        // The toggle bit did not alternate.
+        assert_eq!(
+            monitor.guarding_response(5, Some(0x05)),
+            Some(Event::GuardingFailed(5))
+        );
+        assert_eq!(
+            monitor.guarding_response(5, Some(0x84)),
+            Some(Event::StateChanged {
+                node_id: 5,
+                state: State::Stopped
+            })
+        );
+
+        // A boot-up resets the toggle bit.
+        assert_eq!(monitor.process(&heartbeat(5, 0x00)), Some(Event::BootUp(5)));
+        assert_eq!(
+            monitor.guarding_response(5, Some(0x84)),
+            Some(Event::StateChanged {
+                node_id: 5,
+                state: State::Stopped
+            })
+        );
+    }
+}
@@ -0,0 +1,468 @@
+//! Process data objects: mapping configuration through SDO, decoding and
+//! encoding of PDO frames, and SYNC production.
+//!
+//! ```text
+//! let tpdo = Pdo::new(Pdo::default_cob_id(Direction::Transmit, 1, 5).unwrap())
+//!     .with_transmission_type(1)
+//!     .with_mapping(0x6041, 0, 16)
+//!     .with_mapping(0x6064, 0, 32);
+//! pdo::configure(&mut client, Direction::Transmit, 1, &tpdo)?;
+//!
+//! let mut sync = SyncProducer::new();
+//! sync.send(&mut interface)?;
+//! if let Some(frame) = interface.receive_timeout(Duration::from_millis(10))? {
+//!     for (variable, value) in tpdo.decode(&dictionary, &frame).unwrap_or_default() {
+//!         println!("{}: {:?}", variable.name, value);
+//!     }
+//! }
+//! ```
+
+use embedded_can::{Frame as _, Id, StandardId};
+
+use super::{
+    cob_id,
+    eds::{ObjectDictionary, Value, Variable},
+    sdo::{self, AbortCode},
+};
+use crate::{Error, Frame, Interface};
+
+const SYNC: u16 = 0x080;
+
+/// Bit 31 of the COB-ID entry, set if the PDO is disabled.
+const PDO_DISABLED: u32 = 0x8000_0000;
+
+/// Receive PDOs are consumed by the node, transmit PDOs produced by it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    Receive,
+    Transmit,
+}
+
+impl Direction {
+    /// Returns the communication parameter and mapping indices of PDO `number`.
+    fn indices(self, number: u16) -> Result<(u16, u16), sdo::Error> {
+        if !(1..=512).contains(&number) {
+            return Err(sdo::Error::InvalidPdoNumber(number));
+        }
+        let offset = number - 1;
+        Ok(match self {
+            Direction::Receive => (0x1400 + offset, 0x1600 + offset),
+            Direction::Transmit => (0x1800 + offset, 0x1A00 + offset),
+        })
+    }
+}
+
+/// An object mapped into a PDO.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MappingEntry {
+    pub index: u16,
+    pub subindex: u8,
+    /// Length of the object in the PDO.
+    pub bits: u8,
+}
+
+impl MappingEntry {
+    /// Dummy entries map data types, which only reserve space in the PDO.
+    fn is_dummy(&self) -> bool {
+        self.index < 0x1000
+    }
+}
+
+impl From<u32> for MappingEntry {
+    fn from(entry: u32) -> Self {
+        Self {
+            index: (entry >> 16) as u16,
+            subindex: (entry >> 8) as u8,
+            bits: entry as u8,
+        }
+    }
+}
+
+impl From<MappingEntry> for u32 {
+    fn from(entry: MappingEntry) -> Self {
+        (entry.index as u32) << 16 | (entry.subindex as u32) << 8 | entry.bits as u32
+    }
+}
+
+/// Communication parameters and mapping of a PDO.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Pdo {
+    pub cob_id: StandardId,
+    pub enabled: bool,
+    /// 0 for acyclic synchronous, 1 to 240 for every nth SYNC, 254 and 255 for
+    /// event driven PDOs.
+    pub transmission_type: u8,
+    /// Event timer of transmit PDOs in milliseconds, 0 if disabled.
+    pub event_timer: u16,
+    pub mapping: Vec<MappingEntry>,
+}
+
+impl Pdo {
+    /// An enabled, event driven PDO without mapped objects.
+    pub fn new(cob_id: StandardId) -> Self {
+        Self {
+            cob_id,
+            enabled: true,
+            transmission_type: 255,
+            event_timer: 0,
+            mapping: Vec::new(),
+        }
+    }
+
+    /// The predefined connection set identifier of PDOs 1 to 4 of `node_id`.
+    pub fn default_cob_id(direction: Direction, number: u16, node_id: u8) -> Option<StandardId> {
+        if !(1..=4).contains(&number) {
+            return None;
+        }
+        let base = match direction {
+            Direction::Receive => 0x100,
+            Direction::Transmit => 0x080,
+        };
+        Some(cob_id(base + number * 0x100, node_id))
+    }
+
+    pub fn with_enabled(mut self, enabled: bool) -> Self {
+        self.enabled = enabled;
+        self
+    }
+
+    pub fn with_transmission_type(mut self, transmission_type: u8) -> Self {
+        self.transmission_type = transmission_type;
+        self
+    }
+
+    pub fn with_event_timer(mut self, milliseconds: u16) -> Self {
+        self.event_timer = milliseconds;
+        self
+    }
+
+    /// Appends an object to the mapping.
+    pub fn with_mapping(mut self, index: u16, subindex: u8, bits: u8) -> Self {
+        self.mapping.push(MappingEntry {
+            index,
+            subindex,
+            bits,
+        });
+        self
+    }
+
+    /// Decodes the mapped objects of a PDO frame. Returns `None` if the frame
+    /// is not this PDO. Dummy entries and objects missing from `dictionary` are
+    /// skipped, as are objects beyond the end of the frame.
+    pub fn decode<'d>(
+        &self,
+        dictionary: &'d ObjectDictionary,
+        frame: &Frame,
+    ) -> Option<Vec<(&'d Variable, Value)>> {
+        if frame.id() != Id::Standard(self.cob_id) || frame.is_remote_frame() {
+            return None;
+        }
+        let mut bytes = [0; 8];
+        bytes[..frame.data().len()].copy_from_slice(frame.data());
+        let data = u64::from_le_bytes(bytes);
+
+        let mut values = Vec::new();
+        let mut offset = 0;
+        for entry in &self.mapping {
+            let bits = entry.bits as usize;
+            if offset + bits > frame.data().len() * 8 || bits == 0 {
+                break;
+            }
+            let raw = (data >> offset) & (u64::MAX >> (64 - bits));
+            offset += bits;
+            if entry.is_dummy() {
+                continue;
+            }
+            if let Some(variable) = dictionary.variable(entry.index, entry.subindex)
+                && let Some(value) = variable.data_type.decode_bits(raw, bits as u32)
+            {
+                values.push((variable, value));
+            }
+        }
+        Some(values)
+    }
+
+    /// Encodes a PDO frame from the values of mapped objects. Objects without a
+    /// value are sent as zero.
+    pub fn encode(&self, values: &[(&Variable, Value)]) -> Result<Frame, Error> {
+        let mut data = 0u64;
+        let mut offset = 0;
+        for entry in &self.mapping {
+            let bits = entry.bits as u32;
+            if bits == 0 {
+                continue;
+            }
+            if offset + bits > 64 {
+                return Err(Error(format!(
+                    "PDO {:#05x} mapping exceeds 64 bits",
+                    self.cob_id.as_raw()
+                )));
+            }
+            let value = values.iter().find(|(variable, _)| {
+                variable.index == entry.index && variable.subindex == entry.subindex
+            });
+            if let Some((variable, value)) = value {
+                let raw = variable.data_type.encode_bits(value, bits).ok_or_else(|| {
+                    Error(format!(
+                        "Value {:?} does not fit {} ({} bits)",
+                        value, variable.name, bits
+                    ))
+                })?;
+                data |= raw << offset;
+            }
+            offset += bits;
+        }
+        let len = offset.div_ceil(8) as usize;
+        Ok(Frame::new(self.cob_id, &data.to_le_bytes()[..len]).unwrap())
+    }
+}
+
+/// Configures PDO `number`, starting at 1, of the node of `client`. The PDO is
+/// disabled while its mapping is written, as required by CiA 301.
+pub fn configure(
+    client: &mut sdo::Client<'_>,
+    direction: Direction,
+    number: u16,
+    pdo: &Pdo,
+) -> Result<(), sdo::Error> {
+    let (communication, mapping) = direction.indices(number)?;
+    let cob_id = pdo.cob_id.as_raw() as u32;
+
+    client.download(communication, 1, &(cob_id | PDO_DISABLED).to_le_bytes())?;
+    client.download(communication, 2, &[pdo.transmission_type])?;
+    if direction == Direction::Transmit && pdo.event_timer != 0 {
+        client.download(communication, 5, &pdo.event_timer.to_le_bytes())?;
+    }
+
+    client.download(mapping, 0, &[0])?;
+    for (subindex, entry) in pdo.mapping.iter().enumerate() {
+        client.download(
+            mapping,
+            subindex as u8 + 1,
+            &u32::from(*entry).to_le_bytes(),
+        )?;
+    }
+    client.download(mapping, 0, &[pdo.mapping.len() as u8])?;
+
+    if pdo.enabled {
+        client.download(communication, 1, &cob_id.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+/// Reads the configuration of PDO `number`, starting at 1, of the node of
+/// `client`.
+pub fn read(
+    client: &mut sdo::Client<'_>,
+    direction: Direction,
+    number: u16,
+) -> Result<Pdo, sdo::Error> {
+    let (communication, mapping) = direction.indices(number)?;
+
+    let cob_id = u32::from_le_bytes(fixed(client.upload(communication, 1)?)?);
+    let [transmission_type] = fixed(client.upload(communication, 2)?)?;
+    let event_timer = match client.upload(communication, 5) {
+        Ok(data) => u16::from_le_bytes(fixed(data)?),
+        // The event timer is optional.
+        Err(sdo::Error::Abort {
+            code: AbortCode::SubindexDoesNotExist,
+            ..
+        }) => 0,
+        Err(err) => return Err(err),
+    };
+
+    let [count] = fixed(client.upload(mapping, 0)?)?;
+    let mapping = (1..=count)
+        .map(|subindex| {
+            let entry = u32::from_le_bytes(fixed(client.upload(mapping, subindex)?)?);
+            Ok(MappingEntry::from(entry))
+        })
+        .collect::<Result<_, sdo::Error>>()?;
+
+    Ok(Pdo {
+        cob_id: StandardId::new((cob_id & 0x7FF) as u16).unwrap(),
+        enabled: cob_id & PDO_DISABLED == 0,
+        transmission_type,
+        event_timer,
+        mapping,
+    })
+}
+
+/// Checks the length of an uploaded value.
+fn fixed<const N: usize>(data: Vec<u8>) -> Result<[u8; N], sdo::Error> {
+    data.get(..N)
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or(sdo::Error::InvalidResponse(data))
+}
+
+/// Produces SYNC messages, optionally with a counter.
+#[derive(Debug, Clone, Default)]
+pub struct SyncProducer {
+    overflow: Option<u8>,
+    counter: u8,
+}
+
+impl SyncProducer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a counter to the SYNC messages, counting from 1 to `overflow`
+    /// (2 to 240).
+    pub fn with_counter(mut self, overflow: u8) -> Self {
+        self.overflow = Some(overflow);
+        self
+    }
+
+    /// The next SYNC message.
+    pub fn frame(&mut self) -> Frame {
+        let id = StandardId::new(SYNC).unwrap();
+        match self.overflow {
+            Some(overflow) => {
+                self.counter = if self.counter >= overflow {
+                    1
+                } else {
+                    self.counter + 1
+                };
+                Frame::new(id, &[self.counter]).unwrap()
+            }
+            None => Frame::new(id, &[]).unwrap(),
+        }
+    }
+
+    pub fn send(&mut self, interface: &mut Interface) -> Result<(), Error> {
+        let frame = self.frame();
+        embedded_can::blocking::Can::transmit(interface, &frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EDS: &str = "
+[6041]
+ParameterName=Statusword
+DataType=0x0006
+AccessType=ro
+PDOMapping=1
+
+[6061]
+ParameterName=Modes of operation display
+DataType=0x0002
+AccessType=ro
+PDOMapping=1
+
+[6064]
+ParameterName=Position actual value
+DataType=0x0004
+AccessType=ro
+PDOMapping=1
+";
+
+    fn tpdo() -> Pdo {
+        Pdo::new(Pdo::default_cob_id(Direction::Transmit, 1, 5).unwrap())
+            .with_mapping(0x6041, 0, 16)
+            // Dummy entry reserving a byte.
+            .with_mapping(0x0005, 0, 8)
+            .with_mapping(0x6064, 0, 32)
+            .with_mapping(0x6061, 0, 4)
+    }
+
+    #[test]
+    fn cob_ids_and_indices() {
+        let cob_id =
+            |direction, number| Pdo::default_cob_id(direction, number, 5).map(|id| id.as_raw());
+        assert_eq!(cob_id(Direction::Transmit, 1), Some(0x185));
+        assert_eq!(cob_id(Direction::Receive, 1), Some(0x205));
+        assert_eq!(cob_id(Direction::Receive, 4), Some(0x505));
+        assert_eq!(cob_id(Direction::Transmit, 5), None);
+        assert_eq!(cob_id(Direction::Transmit, 0), None);
+
+        assert_eq!(Direction::Transmit.indices(1).unwrap(), (0x1800, 0x1A00));
+        assert_eq!(Direction::Receive.indices(512).unwrap(), (0x15FF, 0x17FF));
+        assert!(matches!(
+            Direction::Receive.indices(0),
+            Err(sdo::Error::InvalidPdoNumber(0))
+        ));
+        assert!(matches!(
+            Direction::Transmit.indices(513),
+            Err(sdo::Error::InvalidPdoNumber(513))
+        ));
+
+        let entry = MappingEntry::from(0x6064_0020);
+        assert_eq!((entry.index, entry.subindex, entry.bits), (0x6064, 0, 32));
+        assert_eq!(u32::from(entry), 0x6064_0020);
+    }
+
+    #[test]
+    fn encode_decode() {
+        let dictionary: ObjectDictionary = EDS.parse().unwrap();
+        let variable = |index| dictionary.variable(index, 0).unwrap();
+        let pdo = tpdo();
+
+        let frame = pdo
+            .encode(&[
+                (variable(0x6064), Value::Integer(-2)),
+                (variable(0x6041), Value::Unsigned(0x0637)),
+                (variable(0x6061), Value::Integer(-8)),
+            ])
+            .unwrap();
+        assert_eq!(frame.id(), Id::Standard(pdo.cob_id));
+        assert_eq!(frame.data(), [0x37, 0x06, 0, 0xFE, 0xFF, 0xFF, 0xFF, 0x08]);
+
+        let values = pdo.decode(&dictionary, &frame).unwrap();
+        let values: Vec<_> = values
+            .into_iter()
+            .map(|(variable, value)| (variable.index, value))
+            .collect();
+        assert_eq!(
+            values,
+            [
+                (0x6041, Value::Unsigned(0x0637)),
+                (0x6064, Value::Integer(-2)),
+                (0x6061, Value::Integer(-8)),
+            ]
+        );
+
+        // Missing values are sent as zero, objects beyond the frame skipped.
+        let frame = pdo
+            .encode(&[(variable(0x6041), Value::Unsigned(1))])
+            .unwrap();
+        assert_eq!(frame.data(), [1, 0, 0, 0, 0, 0, 0, 0]);
+        let short = Frame::new(pdo.cob_id, &[1, 0, 0]).unwrap();
+        let values = pdo.decode(&dictionary, &short).unwrap();
+        assert_eq!(values.len(), 1);
+
+        let other = Frame::new(StandardId::new(0x186).unwrap(), frame.data()).unwrap();
+        assert!(pdo.decode(&dictionary, &other).is_none());
+    }
+
+    #[test]
+    fn encode_errors() {
+        let dictionary: ObjectDictionary = EDS.parse().unwrap();
+        let variable = |index| dictionary.variable(index, 0).unwrap();
+        let pdo = tpdo();
+        assert!(
+            pdo.encode(&[(variable(0x6061), Value::Integer(8))])
+                .is_err()
+        );
+        assert!(
+            pdo.encode(&[(variable(0x6041), Value::Integer(-1))])
+                .is_err()
+        );
+
+        let pdo = pdo.with_mapping(0x6064, 0, 32);
+        assert!(pdo.encode(&[]).is_err());
+    }
+
+    #[test]
+    fn sync_counter() {
+        let mut sync = SyncProducer::new();
+        assert!(sync.frame().data().is_empty());
+
+        let mut sync = SyncProducer::new().with_counter(3);
+        let counters: Vec<_> = (0..5).map(|_| sync.frame().data()[0]).collect();
+        assert_eq!(counters, [1, 2, 3, 1, 2]);
+    }
+}
@@ -0,0 +1,599 @@
+//! SDO client with expedited, segmented and block transfers.
+//!
+//! ```text
+//! let mut client = sdo::Client::new(&mut interface, 5).with_timeout(Duration::from_secs(2));
+//! let name = client.upload(0x1008, 0)?;
+//! client.download(0x6040, 0, &0x000Fu16.to_le_bytes())?;
+//! let firmware = client.block_upload(0x1F50, 1)?;
+//! ```
+
+use std::{fmt, time::Duration};
+
+use embedded_can::{Frame as _, StandardId};
+
+use super::cob_id;
+use crate::{Frame, Interface, ResponseFilter};
+
+const SDO_REQUEST: u16 = 0x600;
+const SDO_RESPONSE: u16 = 0x580;
+
+const ABORT: u8 = 0x80;
+
+/// Reasons for aborting a transfer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AbortCode {
+    ToggleBitNotAlternated,
+    Timeout,
+    InvalidCommandSpecifier,
+    InvalidBlockSize,
+    InvalidSequenceNumber,
+    CrcError,
+    OutOfMemory,
+    UnsupportedAccess,
+    WriteOnly,
+    ReadOnly,
+    ObjectDoesNotExist,
+    ObjectCannotBeMapped,
+    PdoLengthExceeded,
+    ParameterIncompatibility,
+    InternalIncompatibility,
+    HardwareError,
+    DataTypeMismatch,
+    DataTypeTooLong,
+    DataTypeTooShort,
+    SubindexDoesNotExist,
+    InvalidValue,
+    ValueTooHigh,
+    ValueTooLow,
+    MaximumLessThanMinimum,
+    ResourceNotAvailable,
+    GeneralError,
+    CannotTransfer,
+    CannotTransferLocalControl,
+    CannotTransferDeviceState,
+    NoObjectDictionary,
+    NoDataAvailable,
+    /// Reserved or manufacturer specific code.
+    Other(u32),
+}
+
+const ABORT_CODES: [(u32, AbortCode); 31] = [
+    (0x0503_0000, AbortCode::ToggleBitNotAlternated),
+    (0x0504_0000, AbortCode::Timeout),
+    (0x0504_0001, AbortCode::InvalidCommandSpecifier),
+    (0x0504_0002, AbortCode::InvalidBlockSize),
+    (0x0504_0003, AbortCode::InvalidSequenceNumber),
+    (0x0504_0004, AbortCode::CrcError),
+    (0x0504_0005, AbortCode::OutOfMemory),
+    (0x0601_0000, AbortCode::UnsupportedAccess),
+    (0x0601_0001, AbortCode::WriteOnly),
+    (0x0601_0002, AbortCode::ReadOnly),
+    (0x0602_0000, AbortCode::ObjectDoesNotExist),
+    (0x0604_0041, AbortCode::ObjectCannotBeMapped),
+    (0x0604_0042, AbortCode::PdoLengthExceeded),
+    (0x0604_0043, AbortCode::ParameterIncompatibility),
+    (0x0604_0047, AbortCode::InternalIncompatibility),
+    (0x0606_0000, AbortCode::HardwareError),
+    (0x0607_0010, AbortCode::DataTypeMismatch),
+    (0x0607_0012, AbortCode::DataTypeTooLong),
+    (0x0607_0013, AbortCode::DataTypeTooShort),
+    (0x0609_0011, AbortCode::SubindexDoesNotExist),
+    (0x0609_0030, AbortCode::InvalidValue),
+    (0x0609_0031, AbortCode::ValueTooHigh),
+    (0x0609_0032, AbortCode::ValueTooLow),
+    (0x0609_0036, AbortCode::MaximumLessThanMinimum),
+    (0x060A_0023, AbortCode::ResourceNotAvailable),
+    (0x0800_0000, AbortCode::GeneralError),
+    (0x0800_0020, AbortCode::CannotTransfer),
+    (0x0800_0021, AbortCode::CannotTransferLocalControl),
+    (0x0800_0022, AbortCode::CannotTransferDeviceState),
+    (0x0800_0023, AbortCode::NoObjectDictionary),
+    (0x0800_0024, AbortCode::NoDataAvailable),
+];
+
+impl From<u32> for AbortCode {
+    fn from(code: u32) -> Self {
+        ABORT_CODES
+            .iter()
+            .find(|(value, _)| *value == code)
+            .map_or(AbortCode::Other(code), |(_, abort_code)| *abort_code)
+    }
+}
+
+impl From<AbortCode> for u32 {
+    fn from(code: AbortCode) -> Self {
+        match code {
+            AbortCode::Other(code) => code,
+            code => ABORT_CODES
+                .iter()
+                .find(|(_, abort_code)| *abort_code == code)
+                .map(|(value, _)| *value)
+                .unwrap(),
+        }
+    }
+}
+
+/// Errors of SDO transfers.
+#[derive(Debug)]
+pub enum Error {
+    /// The transfer of the object was aborted, by the server or by the client
+    /// after a protocol error.
+    Abort {
+        index: u16,
+        subindex: u8,
+        code: AbortCode,
+    },
+    /// No response was received within the timeout.
+    Timeout,
+    /// The response does not belong to the request or is malformed.
+    InvalidResponse(Vec<u8>),
+    /// A PDO number outside of 1 to 512 was given, no transfer was started.
+    InvalidPdoNumber(u16),
+    /// Transmission or reception of the frames failed.
+    Transport(crate::Error),
+}
+
+impl From<crate::Error> for Error {
+    fn from(err: crate::Error) -> Self {
+        Error::Transport(err)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Abort {
+                index,
+                subindex,
+                code,
+            } => write!(
+                f,
+                "SDO transfer of {:#06x}:{} aborted: {:?}",
+                index, subindex, code
+            ),
+            Error::Timeout => write!(f, "No response from the SDO server"),
+            Error::InvalidResponse(response) => write!(f, "Invalid response {:02x?}", response),
+            Error::InvalidPdoNumber(number) => {
+                write!(
+                    f,
+                    "Invalid PDO number {}, PDOs are numbered 1 to 512",
+                    number
+                )
+            }
+            Error::Transport(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// An SDO client of the default channel of a node.
+pub struct Client<'a> {
+    interface: &'a mut Interface,
+    tx_id: StandardId,
+    rx_id: StandardId,
+    timeout: Duration,
+    block_size: u8,
+}
+
+impl<'a> Client<'a> {
+    /// Creates a client of the default SDO server of `node_id`, on `0x600` and
+    /// `0x580` plus the node ID.
+    pub fn new(interface: &'a mut Interface, node_id: u8) -> Self {
+        Self {
+            interface,
+            tx_id: cob_id(SDO_REQUEST, node_id),
+            rx_id: cob_id(SDO_RESPONSE, node_id),
+            timeout: Duration::from_millis(1000),
+            block_size: 127,
+        }
+    }
+
+    /// Sets how long to wait for each response of the server. Defaults to 1 s.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets the number of segments per block of block uploads, from 1 to 127.
+    /// Defaults to 127.
+    pub fn with_block_size(mut self, block_size: u8) -> Self {
+        self.block_size = block_size.clamp(1, 127);
+        self
+    }
+
+    pub fn interface(&mut self) -> &mut Interface {
+        self.interface
+    }
+
+    /// Reads an object with an expedited or segmented transfer, as chosen by
+    /// the server.
+    pub fn upload(&mut self, index: u16, subindex: u8) -> Result<Vec<u8>, Error> {
+        let object = Object { index, subindex };
+        let response = self.exchange(object, object.command(0x40, [0; 4]))?;
+        if response[0] & 0xE0 != 0x40 || !object.matches(&response) {
+            return Err(self.protocol_error(object, AbortCode::InvalidCommandSpecifier, &response));
+        }
+
+        let size_indicated = response[0] & 0x01 != 0;
+        if response[0] & 0x02 != 0 {
+            // Expedited transfer.
+            let len = if size_indicated {
+                4 - ((response[0] >> 2) & 0x3) as usize
+            } else {
+                4
+            };
+            return Ok(response[4..4 + len].to_vec());
+        }
+
+        let size = size_indicated.then(|| u32::from_le_bytes(response[4..8].try_into().unwrap()));
+        let mut data = Vec::with_capacity(size.unwrap_or_default() as usize);
+        let mut toggle = 0;
+        loop {
+            let response = self.exchange(object, [0x60 | toggle << 4, 0, 0, 0, 0, 0, 0, 0])?;
+            if response[0] & 0xE0 != 0x00 {
+                return Err(self.protocol_error(
+                    object,
+                    AbortCode::InvalidCommandSpecifier,
+                    &response,
+                ));
+            }
+            if (response[0] >> 4) & 0x1 != toggle {
+                return Err(self.protocol_error(
+                    object,
+                    AbortCode::ToggleBitNotAlternated,
+                    &response,
+                ));
+            }
+            let unused = ((response[0] >> 1) & 0x7) as usize;
+            data.extend_from_slice(&response[1..8 - unused]);
+            if response[0] & 0x01 != 0 {
+                break;
+            }
+            toggle ^= 1;
+        }
+
+        if size.is_some_and(|size| size as usize != data.len()) {
+            return Err(Error::InvalidResponse(data));
+        }
+        Ok(data)
+    }
+
+    /// Writes an object, with an expedited transfer for 1 to 4 bytes and a
+    /// segmented transfer otherwise.
+    pub fn download(&mut self, index: u16, subindex: u8, data: &[u8]) -> Result<(), Error> {
+        let object = Object { index, subindex };
+        let size = u32::try_from(data.len())
+            .map_err(|_| crate::Error("SDO data is too long".to_string()))?;
+        // An expedited transfer cannot indicate zero bytes.
+        let expedited = (1..=4).contains(&data.len());
+        let request = if expedited {
+            let mut payload = [0; 4];
+            payload[..data.len()].copy_from_slice(data);
+            object.command(0x23 | ((4 - data.len() as u8) << 2), payload)
+        } else {
+            object.command(0x21, size.to_le_bytes())
+        };
+        let response = self.exchange(object, request)?;
+        if response[0] != 0x60 || !object.matches(&response) {
+            return Err(self.protocol_error(object, AbortCode::InvalidCommandSpecifier, &response));
+        }
+        if expedited {
+            return Ok(());
+        }
+
+        // Empty data is sent as a single empty segment.
+        let segments = data.len().div_ceil(7).max(1);
+        let mut toggle = 0;
+        for index in 0..segments {
+            let chunk = &data[index * 7..(index * 7 + 7).min(data.len())];
+            let request = download_segment(chunk, toggle, index + 1 == segments);
+            let response = self.exchange(object, request)?;
+            if response[0] & 0xEF != 0x20 {
+                return Err(self.protocol_error(
+                    object,
+                    AbortCode::InvalidCommandSpecifier,
+                    &response,
+                ));
+            }
+            if (response[0] >> 4) & 0x1 != toggle {
+                return Err(self.protocol_error(
+                    object,
+                    AbortCode::ToggleBitNotAlternated,
+                    &response,
+                ));
+            }
+            toggle ^= 1;
+        }
+        Ok(())
+    }
+
+    /// Reads an object with a block transfer and CRC check, for large objects.
+    pub fn block_upload(&mut self, index: u16, subindex: u8) -> Result<Vec<u8>, Error> {
+        let object = Object { index, subindex };
+        let block_size = self.block_size;
+        // Client CRC support, protocol switch threshold zero.
+        let response = self.exchange(object, object.command(0xA4, [block_size, 0, 0, 0]))?;
+        if response[0] & 0xE1 != 0xC0 || !object.matches(&response) {
+            return Err(self.protocol_error(object, AbortCode::InvalidCommandSpecifier, &response));
+        }
+        let crc = response[0] & 0x04 != 0;
+        let size = (response[0] & 0x02 != 0)
+            .then(|| u32::from_le_bytes(response[4..8].try_into().unwrap()));
+
+        self.transmit([0xA3, 0, 0, 0, 0, 0, 0, 0])?;
+        let mut data = Vec::with_capacity(size.unwrap_or_default() as usize);
+        let mut expected = 1u8;
+        loop {
+            let segment = self.wait(object)?;
+            let sequence_number = segment[0] & 0x7F;
+            let last = segment[0] & 0x80 != 0;
+            let accepted = sequence_number == expected;
+            if accepted {
+                data.extend_from_slice(&segment[1..]);
+                expected += 1;
+            }
+            // Segments after a missing one are ignored and sent again by the
+            // server, starting after the acknowledged sequence number.
+            if last || sequence_number >= block_size {
+                self.transmit([0xA2, expected - 1, block_size, 0, 0, 0, 0, 0])?;
+                if last && accepted {
+                    break;
+                }
+                expected = 1;
+            }
+        }
+
+        let end = self.wait(object)?;
+        if end[0] & 0xE3 != 0xC1 {
+            return Err(self.protocol_error(object, AbortCode::InvalidCommandSpecifier, &end));
+        }
+        let unused = ((end[0] >> 2) & 0x7) as usize;
+        data.truncate(data.len().saturating_sub(unused));
+        if crc && crc16(&data) != u16::from_le_bytes([end[1], end[2]]) {
+            return Err(self.protocol_error(object, AbortCode::CrcError, &end));
+        }
+        self.transmit([0xA1, 0, 0, 0, 0, 0, 0, 0])?;
+
+        if size.is_some_and(|size| size as usize != data.len()) {
+            return Err(Error::InvalidResponse(data));
+        }
+        Ok(data)
+    }
+
+    /// Writes an object with a block transfer, the block size is set by the server.
+    pub fn block_download(&mut self, index: u16, subindex: u8, data: &[u8]) -> Result<(), Error> {
+        if data.is_empty() {
+            return self.download(index, subindex, data);
+        }
+        let object = Object { index, subindex };
+        let size = u32::try_from(data.len())
+            .map_err(|_| crate::Error("SDO data is too long".to_string()))?;
+        // Client CRC support, size indicated.
+        let mut response = self.exchange(object, object.command(0xC6, size.to_le_bytes()))?;
+        if response[0] & 0xE3 != 0xA0 || !object.matches(&response) {
+            return Err(self.protocol_error(object, AbortCode::InvalidCommandSpecifier, &response));
+        }
+        let crc = response[0] & 0x04 != 0;
+        let mut block_size = response[4];
+
+        let segments: Vec<&[u8]> = data.chunks(7).collect();
+        let mut position = 0;
+        while position < segments.len() {
+            if !(1..=127).contains(&block_size) {
+                return Err(self.protocol_error(object, AbortCode::InvalidBlockSize, &response));
+            }
+            let count = (segments.len() - position).min(block_size as usize);
+            for sequence_number in 1..=count {
+                let segment = segments[position + sequence_number - 1];
+                let last = position + sequence_number == segments.len();
+                let mut request = [0; 8];
+                request[0] = ((last as u8) << 7) | sequence_number as u8;
+                request[1..1 + segment.len()].copy_from_slice(segment);
+                self.transmit(request)?;
+            }
+
+            response = self.wait(object)?;
+            if response[0] & 0xE3 != 0xA2 || response[1] as usize > count {
+                return Err(self.protocol_error(
+                    object,
+                    AbortCode::InvalidCommandSpecifier,
+                    &response,
+                ));
+            }
+            position += response[1] as usize;
+            block_size = response[2];
+        }
+
+        let unused = 7 - segments[segments.len() - 1].len() as u8;
+        let [crc_low, crc_high] = if crc { crc16(data) } else { 0 }.to_le_bytes();
+        response = self.exchange(
+            object,
+            [0xC1 | (unused << 2), crc_low, crc_high, 0, 0, 0, 0, 0],
+        )?;
+        if response[0] & 0xE3 != 0xA1 {
+            return Err(self.protocol_error(object, AbortCode::InvalidCommandSpecifier, &response));
+        }
+        Ok(())
+    }
+
+    /// Sends `request` and waits for the response, failing if the server
+    /// aborts the transfer.
+    fn exchange(&mut self, object: Object, request: [u8; 8]) -> Result<[u8; 8], Error> {
+        let frame = Frame::new(self.tx_id, &request).unwrap();
+        let filter = ResponseFilter::new(self.rx_id).with_data(|data| data.len() == 8);
+        let response = self.interface.request(&frame, &filter, self.timeout)?;
+        self.check_response(object, response)
+    }
+
+    /// Waits for a frame of the server without sending a request.
+    fn wait(&mut self, object: Object) -> Result<[u8; 8], Error> {
+        let filter = ResponseFilter::new(self.rx_id).with_data(|data| data.len() == 8);
+        let response = self.interface.receive_matching(&filter, self.timeout)?;
+        self.check_response(object, response)
+    }
+
+    fn check_response(
+        &mut self,
+        object: Object,
+        response: Option<Frame>,
+    ) -> Result<[u8; 8], Error> {
+        let Some(response) = response else {
+            self.abort(object, AbortCode::Timeout)?;
+            return Err(Error::Timeout);
+        };
+        let response: [u8; 8] = response.data().try_into().unwrap();
+        if response[0] == ABORT {
+            return Err(Error::Abort {
+                index: object.index,
+                subindex: object.subindex,
+                code: u32::from_le_bytes(response[4..8].try_into().unwrap()).into(),
+            });
+        }
+        Ok(response)
+    }
+
+    /// Aborts the transfer after an unexpected response.
+    fn protocol_error(&mut self, object: Object, code: AbortCode, response: &[u8]) -> Error {
+        match self.abort(object, code) {
+            Ok(()) => Error::InvalidResponse(response.to_vec()),
+            Err(err) => err,
+        }
+    }
+
+    fn abort(&mut self, object: Object, code: AbortCode) -> Result<(), Error> {
+        self.transmit(object.command(ABORT, u32::from(code).to_le_bytes()))
+    }
+
+    fn transmit(&mut self, data: [u8; 8]) -> Result<(), Error> {
+        let frame = Frame::new(self.tx_id, &data).unwrap();
+        Ok(embedded_can::blocking::Can::transmit(
+            self.interface,
+            &frame,
+        )?)
+    }
+}
+
+/// The index and subindex of the object being transferred.
+#[derive(Clone, Copy)]
+struct Object {
+    index: u16,
+    subindex: u8,
+}
+
+impl Object {
+    fn command(self, specifier: u8, data: [u8; 4]) -> [u8; 8] {
+        let [index_low, index_high] = self.index.to_le_bytes();
+        [
+            specifier,
+            index_low,
+            index_high,
+            self.subindex,
+            data[0],
+            data[1],
+            data[2],
+            data[3],
+        ]
+    }
+
+    /// Whether the multiplexer of an initiate response refers to the object.
+    fn matches(self, response: &[u8; 8]) -> bool {
+        u16::from_le_bytes([response[1], response[2]]) == self.index && response[3] == self.subindex
+    }
+}
+
+/// Builds a download segment request carrying up to 7 bytes.
+fn download_segment(chunk: &[u8], toggle: u8, last: bool) -> [u8; 8] {
+    let mut request = [0; 8];
+    request[0] = toggle << 4 | ((7 - chunk.len() as u8) << 1) | last as u8;
+    request[1..1 + chunk.len()].copy_from_slice(chunk);
+    request
+}
+
+/// CRC-16-CCITT of block transfers, with polynomial `0x1021` and initial value zero.
+fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0, |crc, &byte| {
+        (0..8).fold(crc ^ ((byte as u16) << 8), |crc, _| {
+            if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc() {
+        // Check value of CRC-16/XMODEM.
+        assert_eq!(crc16(b"123456789"), 0x31C3);
+        assert_eq!(crc16(&[]), 0x0000);
+        assert_eq!(crc16(&[0x00]), 0x0000);
+        assert_eq!(crc16(&[0xFF]), 0x1EF0);
+    }
+
+    #[test]
+    fn abort_codes() {
+        for (value, code) in [
+            (0x0503_0000, AbortCode::ToggleBitNotAlternated),
+            (0x0504_0000, AbortCode::Timeout),
+            (0x0504_0004, AbortCode::CrcError),
+            (0x0601_0002, AbortCode::ReadOnly),
+            (0x0602_0000, AbortCode::ObjectDoesNotExist),
+            (0x0609_0011, AbortCode::SubindexDoesNotExist),
+            (0x0800_0024, AbortCode::NoDataAvailable),
+            (0x0000_0000, AbortCode::Other(0)),
+            (0x0504_0006, AbortCode::Other(0x0504_0006)),
+            (0xFFFF_FFFF, AbortCode::Other(0xFFFF_FFFF)),
+        ] {
+            assert_eq!(AbortCode::from(value), code);
+            assert_eq!(u32::from(code), value);
+        }
+
+        for (value, code) in ABORT_CODES {
+            assert_eq!(AbortCode::from(value), code);
+            assert_eq!(u32::from(code), value);
+        }
+    }
+
+    #[test]
+    fn commands() {
+        let object = Object {
+            index: 0x1018,
+            subindex: 0x02,
+        };
+        assert_eq!(
+            object.command(0x40, [0; 4]),
+            [0x40, 0x18, 0x10, 0x02, 0, 0, 0, 0]
+        );
+        assert_eq!(
+            object.command(ABORT, u32::from(AbortCode::Timeout).to_le_bytes()),
+            [0x80, 0x18, 0x10, 0x02, 0x00, 0x00, 0x04, 0x05]
+        );
+        assert!(object.matches(&[0x43, 0x18, 0x10, 0x02, 1, 2, 3, 4]));
+        assert!(!object.matches(&[0x43, 0x18, 0x10, 0x01, 1, 2, 3, 4]));
+        assert!(!object.matches(&[0x43, 0x17, 0x10, 0x02, 1, 2, 3, 4]));
+    }
+
+    #[test]
+    fn download_segments() {
+        assert_eq!(
+            download_segment(&[1, 2, 3, 4, 5, 6, 7], 0, false),
+            [0x00, 1, 2, 3, 4, 5, 6, 7]
+        );
+        assert_eq!(
+            download_segment(&[1, 2, 3, 4, 5, 6, 7], 1, false),
+            [0x10, 1, 2, 3, 4, 5, 6, 7]
+        );
+        // The number of unused bytes of the last segment.
+        assert_eq!(
+            download_segment(&[1, 2], 1, true),
+            [0x1B, 1, 2, 0, 0, 0, 0, 0]
+        );
+        assert_eq!(download_segment(&[], 0, true), [0x0F, 0, 0, 0, 0, 0, 0, 0]);
+    }
+}
@@ -0,0 +1,354 @@
+use crate::{Error, Frame, Interface};
+
+use embedded_can::Frame as _;
+
+use std::{
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+/// The scheduler thread sleeps until shortly before a deadline and spins for
+/// the remaining time to keep the jitter low.
+const SPIN_MARGIN: Duration = Duration::from_micros(500);
+
+/// Identifies a message registered with a [`CyclicScheduler`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MessageHandle(usize);
+
+/// Transmission statistics of a cyclic message.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CyclicStats {
+    /// Number of frames that have been transmitted.
+    pub sent: u64,
+    /// Number of periods that were skipped because the frame could not be sent in
+    /// time or the transmit queue was full.
+    pub missed_deadlines: u64,
+    /// Largest delay between a deadline and the actual transmission.
+    pub max_lateness: Duration,
+    /// Number of transmissions that failed with a driver error.
+    pub errors: u64,
+}
+
+struct Message {
+    handle: MessageHandle,
+    frame: Frame,
+    period: Duration,
+    next_due: Option<Instant>,
+    stats: CyclicStats,
+}
+
+#[derive(Default)]
+struct State {
+    messages: Vec<Message>,
+    next_handle: usize,
+    shutdown: bool,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    wakeup: Condvar,
+}
+
+/// Transmits registered frames at fixed periods from a dedicated thread.
+///
+/// The interface is shared with the scheduler thread, so other code should only
+/// hold the lock for short non-blocking operations to keep the jitter low.
+pub struct CyclicScheduler {
+    shared: Arc<Shared>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl CyclicScheduler {
+    /// Starts the scheduler thread, which sends on `interface` without any
+    /// messages registered yet.
+    pub fn new(interface: Arc<Mutex<Interface>>) -> Self {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State::default()),
+            wakeup: Condvar::new(),
+        });
+
+        let thread = {
+            let shared = shared.clone();
+            thread::spawn(move || run(&shared, &interface))
+        };
+
+        Self {
+            shared,
+            thread: Some(thread),
+        }
+    }
+
+    /// Registers a frame which is sent every `period`, starting immediately.
+    /// The period must not be zero.
+    pub fn add(&self, frame: Frame, period: Duration) -> Result<MessageHandle, Error> {
+        check_period(period)?;
+        let mut state = self.lock();
+        let handle = MessageHandle(state.next_handle);
+        state.next_handle += 1;
+        state.messages.push(Message {
+            handle,
+            frame,
+            period,
+            next_due: Some(Instant::now()),
+            stats: CyclicStats::default(),
+        });
+        drop(state);
+        self.shared.wakeup.notify_one();
+        Ok(handle)
+    }
+
+    /// Unregisters a message, returning its last frame.
+    pub fn remove(&self, handle: MessageHandle) -> Option<Frame> {
+        let mut state = self.lock();
+        let index = state.messages.iter().position(|m| m.handle == handle)?;
+        Some(state.messages.remove(index).frame)
+    }
+
+    /// Replaces the payload of a message, keeping its identifier. The new data
+    /// is used from the next transmission on.
+    pub fn update(&self, handle: MessageHandle, data: &[u8]) -> Result<(), Error> {
+        self.with_message(handle, |message| {
+            message.frame = Frame::new(message.frame.id(), data)
+                .ok_or_else(|| Error("Invalid frame data length".to_string()))?;
+            Ok(())
+        })?
+    }
+
+    /// Replaces the whole frame of a message.
+    pub fn set_frame(&self, handle: MessageHandle, frame: Frame) -> Result<(), Error> {
+        self.with_message(handle, |message| message.frame = frame)
+    }
+
+    /// Changes the period of a message. The period must not be zero.
+    pub fn set_period(&self, handle: MessageHandle, period: Duration) -> Result<(), Error> {
+        check_period(period)?;
+        self.with_message(handle, |message| message.period = period)
+    }
+
+    /// Resumes transmission of a stopped message.
+    pub fn start(&self, handle: MessageHandle) -> Result<(), Error> {
+        self.with_message(handle, |message| {
+            if message.next_due.is_none() {
+                message.next_due = Some(Instant::now());
+            }
+        })?;
+        self.shared.wakeup.notify_one();
+        Ok(())
+    }
+
+    /// Stops transmission of a message without unregistering it.
+    pub fn stop(&self, handle: MessageHandle) -> Result<(), Error> {
+        self.with_message(handle, |message| message.next_due = None)
+    }
+
+    /// Whether the message is being transmitted, as opposed to stopped.
+    pub fn is_running(&self, handle: MessageHandle) -> Result<bool, Error> {
+        self.with_message(handle, |message| message.next_due.is_some())
+    }
+
+    /// The transmission statistics of a message since it was added.
+    pub fn stats(&self, handle: MessageHandle) -> Result<CyclicStats, Error> {
+        self.with_message(handle, |message| message.stats)
+    }
+
+    fn with_message<T>(
+        &self,
+        handle: MessageHandle,
+        f: impl FnOnce(&mut Message) -> T,
+    ) -> Result<T, Error> {
+        let mut state = self.lock();
+        let message = state
+            .messages
+            .iter_mut()
+            .find(|m| m.handle == handle)
+            .ok_or_else(|| Error("Unknown cyclic message".to_string()))?;
+        Ok(f(message))
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.shared.state.lock().unwrap()
+    }
+}
+
+impl Drop for CyclicScheduler {
+    fn drop(&mut self) {
+        self.lock().shutdown = true;
+        self.shared.wakeup.notify_one();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn run(shared: &Shared, interface: &Mutex<Interface>) {
+    let mut state = shared.state.lock().unwrap();
+    loop {
+        if state.shutdown {
+            return;
+        }
+
+        let now = Instant::now();
+        let next_due = state.messages.iter().filter_map(|m| m.next_due).min();
+
+        match next_due {
+            None => state = shared.wakeup.wait(state).unwrap(),
+            Some(due) if due > now + SPIN_MARGIN => {
+                let timeout = due - now - SPIN_MARGIN;
+                state = shared.wakeup.wait_timeout(state, timeout).unwrap().0;
+            }
+            Some(due) if due > now => {
+                drop(state);
+                while Instant::now() < due {
+                    std::hint::spin_loop();
+                }
+                state = shared.state.lock().unwrap();
+            }
+            Some(_) => {
+                let due: Vec<_> = state
+                    .messages
+                    .iter()
+                    .filter_map(|m| {
+                        Some((
+                            m.handle,
+                            m.frame.clone(),
+                            m.next_due.filter(|due| *due <= now)?,
+                        ))
+                    })
+                    .collect();
+
+                // Other threads may use the interface while the state is
+                // unlocked, and must not wait for a transmission to be able to
+                // change the messages.
+                drop(state);
+                let results: Vec<_> = {
+                    let mut interface = interface.lock().unwrap();
+                    due.into_iter()
+                        .map(|(handle, frame, due)| {
+                            let sent_at = Instant::now();
+                            let result = embedded_can::nb::Can::transmit(&mut *interface, &frame);
+                            (handle, due, sent_at, result.map(|_| ()))
+                        })
+                        .collect()
+                };
+                state = shared.state.lock().unwrap();
+
+                for (handle, due, sent_at, result) in results {
+                    // The message may have been removed, stopped or restarted
+                    // in the meantime.
+                    if let Some(message) = state
+                        .messages
+                        .iter_mut()
+                        .find(|m| m.handle == handle && m.next_due == Some(due))
+                    {
+                        message.record(due, sent_at, result);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Message {
+    fn record(&mut self, due: Instant, sent_at: Instant, result: nb::Result<(), Error>) {
+        match result {
+            Ok(()) => self.stats.sent += 1,
+            // The transmit queue is full, e.g. without acknowledgement or while
+            // bus off. The frame is dropped rather than blocking the scheduler.
+            Err(nb::Error::WouldBlock) => self.stats.missed_deadlines += 1,
+            Err(nb::Error::Other(_)) => self.stats.errors += 1,
+        }
+
+        let lateness = sent_at.saturating_duration_since(due);
+        self.stats.max_lateness = self.stats.max_lateness.max(lateness);
+
+        let (missed, next_due) = catch_up(due, sent_at, self.period);
+        self.stats.missed_deadlines += missed as u64;
+        self.next_due = Some(next_due);
+    }
+}
+
+/// Returns the number of periods that passed between `due` and `sent_at`, and
+/// the deadline after them. These periods are skipped instead of sending a
+/// burst of frames to catch up.
+fn catch_up(due: Instant, sent_at: Instant, period: Duration) -> (u32, Instant) {
+    let lateness = sent_at.saturating_duration_since(due);
+    let missed = u32::try_from(lateness.as_nanos() / period.as_nanos()).unwrap_or(u32::MAX);
+    let next_due = period
+        .checked_mul(missed.saturating_add(1))
+        .and_then(|offset| due.checked_add(offset))
+        .unwrap_or(sent_at + period);
+    (missed, next_due)
+}
+
+fn check_period(period: Duration) -> Result<(), Error> {
+    if period.is_zero() {
+        return Err(Error(
+            "The period of a cyclic message must not be zero".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_can::StandardId;
+
+    use super::*;
+
+    const PERIOD: Duration = Duration::from_millis(10);
+
+    fn message(due: Instant) -> Message {
+        Message {
+            handle: MessageHandle(0),
+            frame: Frame::new(StandardId::new(0x100).unwrap(), &[1]).unwrap(),
+            period: PERIOD,
+            next_due: Some(due),
+            stats: CyclicStats::default(),
+        }
+    }
+
+    #[test]
+    fn catch_up_skips_passed_periods() {
+        let due = Instant::now();
+        let ms = Duration::from_millis;
+        assert_eq!(catch_up(due, due, PERIOD), (0, due + PERIOD));
+        assert_eq!(catch_up(due, due + ms(9), PERIOD), (0, due + PERIOD));
+        assert_eq!(catch_up(due, due + ms(10), PERIOD), (1, due + ms(20)));
+        assert_eq!(catch_up(due, due + ms(25), PERIOD), (2, due + ms(30)));
+        // Sent before the deadline, e.g. after spinning slightly too short.
+        assert_eq!(catch_up(due + ms(1), due, PERIOD), (0, due + ms(11)));
+
+        // More missed periods than fit the counter saturate instead of wrapping.
+        let period = Duration::from_nanos(1);
+        let (missed, next_due) = catch_up(due, due + Duration::from_secs(5), period);
+        assert_eq!(missed, u32::MAX);
+        assert_eq!(next_due, due + period * u32::MAX);
+    }
+
+    #[test]
+    fn record() {
+        let due = Instant::now();
+        let mut message = message(due);
+        message.record(due, due + Duration::from_millis(1), Ok(()));
+        assert_eq!(message.stats.sent, 1);
+        assert_eq!(message.stats.missed_deadlines, 0);
+        assert_eq!(message.stats.max_lateness, Duration::from_millis(1));
+        assert_eq!(message.next_due, Some(due + PERIOD));
+
+        // A full transmit queue drops the frame and counts as a missed deadline.
+        let due = due + PERIOD;
+        message.record(due, due, Err(nb::Error::WouldBlock));
+        assert_eq!(message.stats.sent, 1);
+        assert_eq!(message.stats.missed_deadlines, 1);
+        assert_eq!(message.next_due, Some(due + PERIOD));
+
+        let due = due + PERIOD;
+        let error = Error("bus off".to_string());
+        message.record(due, due + PERIOD * 2, Err(nb::Error::Other(error)));
+        assert_eq!(message.stats.errors, 1);
+        assert_eq!(message.stats.missed_deadlines, 3);
+        assert_eq!(message.stats.max_lateness, PERIOD * 2);
+        assert_eq!(message.next_due, Some(due + PERIOD * 3));
+    }
+}
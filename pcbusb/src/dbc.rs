@@ -0,0 +1,1143 @@
+//! Parsing of DBC databases and decoding of frames into signal values.
+//!
+//! ```text
+//! let db = Database::open("vehicle.dbc")?;
+//! if let Some(message) = db.decode(&frame) {
+//!     for signal in &message.signals {
+//!         println!("{} = {} {}", signal.name(), signal.value, signal.unit());
+//!     }
+//! }
+//! ```
+
+pub mod codegen;
+mod parser;
+
+use std::{collections::HashMap, fs, path::Path, str::FromStr};
+
+use embedded_can::Id;
+
+use crate::{Error, Frame};
+
+/// Attribute holding the raw value a signal has when it is not set explicitly.
+const START_VALUE_ATTRIBUTE: &str = "GenSigStartValue";
+
+/// Bit numbering of a signal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteOrder {
+    /// Intel byte order, the start bit is the least significant bit.
+    LittleEndian,
+    /// Motorola byte order, the start bit is the most significant bit.
+    BigEndian,
+}
+
+/// Interpretation of the raw bits of a signal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueType {
+    Unsigned,
+    /// Two's complement integer.
+    Signed,
+    /// IEEE 754 single precision, the signal is 32 bits long.
+    Float,
+    /// IEEE 754 double precision, the signal is 64 bits long.
+    Double,
+}
+
+/// Role of a signal in a multiplexed message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MultiplexIndicator {
+    /// The signal is always present.
+    Plain,
+    /// The signal selects which multiplexed signals are present.
+    Multiplexor,
+    /// The signal is present when the multiplexor has the given value.
+    Multiplexed(u64),
+    /// A multiplexed signal that is itself the multiplexor of other signals
+    /// (extended multiplexing).
+    MultiplexedMultiplexor(u64),
+}
+
+/// Multiplexor values selecting a signal with extended multiplexing, as given
+/// by `SG_MUL_VAL_`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MultiplexCondition {
+    /// Name of the multiplexor signal.
+    pub multiplexor: String,
+    /// Inclusive ranges of raw multiplexor values.
+    pub ranges: Vec<(u64, u64)>,
+}
+
+impl MultiplexCondition {
+    pub fn contains(&self, value: u64) -> bool {
+        self.ranges
+            .iter()
+            .any(|&(low, high)| (low..=high).contains(&value))
+    }
+}
+
+/// Objects attributes can be assigned to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttributeObject {
+    Database,
+    Node,
+    Message,
+    Signal,
+    EnvironmentVariable,
+}
+
+/// Value type of an attribute definition.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AttributeKind {
+    Int {
+        min: i64,
+        max: i64,
+    },
+    Hex {
+        min: i64,
+        max: i64,
+    },
+    Float {
+        min: f64,
+        max: f64,
+    },
+    String,
+    /// Enumeration, values are the index into the names.
+    Enum(Vec<String>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AttributeValue {
+    Int(i64),
+    Float(f64),
+    String(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AttributeDefinition {
+    pub name: String,
+    pub object: AttributeObject,
+    pub kind: AttributeKind,
+    pub default: Option<AttributeValue>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Node {
+    pub name: String,
+    pub comment: Option<String>,
+    pub attributes: HashMap<String, AttributeValue>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Signal {
+    pub name: String,
+    /// Bit position of the least significant bit for little endian signals,
+    /// of the most significant bit for big endian signals.
+    pub start_bit: usize,
+    /// Length in bits.
+    pub size: usize,
+    pub byte_order: ByteOrder,
+    pub value_type: ValueType,
+    pub factor: f64,
+    pub offset: f64,
+    /// Minimum physical value.
+    pub min: f64,
+    /// Maximum physical value.
+    pub max: f64,
+    pub unit: String,
+    pub receivers: Vec<String>,
+    pub multiplex: MultiplexIndicator,
+    /// Selection by a multiplexor other than the one of the message, or by
+    /// several values. Overrides the value of `multiplex`.
+    pub multiplex_condition: Option<MultiplexCondition>,
+    /// Descriptions of raw values.
+    pub value_descriptions: Vec<(i64, String)>,
+    pub comment: Option<String>,
+    pub attributes: HashMap<String, AttributeValue>,
+}
+
+/// Bit layout and scaling of a signal.
+///
+/// This is the part of a [`Signal`] needed to encode and decode it, which can
+/// also be built in constant expressions, e.g. by [generated code](codegen).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SignalFormat<'a> {
+    pub name: &'a str,
+    pub start_bit: usize,
+    pub size: usize,
+    pub byte_order: ByteOrder,
+    pub value_type: ValueType,
+    pub factor: f64,
+    pub offset: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl SignalFormat<'_> {
+    /// Returns the raw bits of the signal, or `None` if the data is too short.
+    pub fn extract(&self, data: &[u8]) -> Option<u64> {
+        let mut raw = 0u64;
+        match self.byte_order {
+            ByteOrder::LittleEndian => {
+                for i in 0..self.size {
+                    raw |= (bit(data, self.start_bit + i)? as u64) << i;
+                }
+            }
+            ByteOrder::BigEndian => {
+                let mut position = self.start_bit;
+                for _ in 0..self.size {
+                    raw = (raw << 1) | bit(data, position)? as u64;
+                    position = next_big_endian_bit(position);
+                }
+            }
+        }
+        Some(raw)
+    }
+
+    /// Writes the raw bits of the signal into `data`, returns `None` if the data is too short.
+    pub fn insert(&self, data: &mut [u8], raw: u64) -> Option<()> {
+        match self.byte_order {
+            ByteOrder::LittleEndian => {
+                for i in 0..self.size {
+                    set_bit(data, self.start_bit + i, (raw >> i) & 1 != 0)?;
+                }
+            }
+            ByteOrder::BigEndian => {
+                let mut position = self.start_bit;
+                for i in (0..self.size).rev() {
+                    set_bit(data, position, (raw >> i) & 1 != 0)?;
+                    position = next_big_endian_bit(position);
+                }
+            }
+        }
+        Some(())
+    }
+
+    /// Interprets raw bits as a two's complement integer of the signal size.
+    pub fn to_signed(&self, raw: u64) -> i64 {
+        sign_extend(raw, self.size)
+    }
+
+    /// Interprets raw bits according to the value type, without scaling.
+    pub fn raw_value(&self, raw: u64) -> f64 {
+        match self.value_type {
+            ValueType::Unsigned => raw as f64,
+            ValueType::Signed => self.to_signed(raw) as f64,
+            ValueType::Float => f32::from_bits(raw as u32) as f64,
+            ValueType::Double => f64::from_bits(raw),
+        }
+    }
+
+    /// Converts raw bits to the physical value.
+    pub fn to_physical(&self, raw: u64) -> f64 {
+        self.raw_value(raw) * self.factor + self.offset
+    }
+
+    /// Converts a physical value to raw bits, checking it against the signal range.
+    ///
+    /// The range is not checked if minimum and maximum are both zero, which
+    /// DBC editors use for signals without a range.
+    pub fn to_raw(&self, value: f64) -> Result<u64, Error> {
+        let unchecked = self.min == 0.0 && self.max == 0.0;
+        if !unchecked && (value < self.min || value > self.max) {
+            return Err(Error(format!(
+                "Value {} of signal {} is outside of its range [{}, {}]",
+                value, self.name, self.min, self.max
+            )));
+        }
+        self.raw_bits((value - self.offset) / self.factor)
+    }
+
+    /// Converts an unscaled value to raw bits, checking that it fits in the signal.
+    fn raw_bits(&self, raw: f64) -> Result<u64, Error> {
+        let raw = match self.value_type {
+            ValueType::Float => return Ok((raw as f32).to_bits() as u64),
+            ValueType::Double => return Ok(raw.to_bits()),
+            ValueType::Unsigned | ValueType::Signed => raw.round(),
+        };
+        let (min, max) = match self.value_type {
+            ValueType::Signed => (
+                -(2f64.powi(self.size as i32 - 1)),
+                2f64.powi(self.size as i32 - 1) - 1.0,
+            ),
+            _ => (0.0, 2f64.powi(self.size as i32) - 1.0),
+        };
+        if !(min..=max).contains(&raw) {
+            return Err(Error(format!(
+                "Raw value {} of signal {} does not fit in {} bits",
+                raw, self.name, self.size
+            )));
+        }
+        let bits = if raw < 0.0 {
+            raw as i64 as u64
+        } else {
+            raw as u64
+        };
+        Ok(bits & mask(self.size))
+    }
+}
+
+impl Signal {
+    pub fn format(&self) -> SignalFormat<'_> {
+        SignalFormat {
+            name: &self.name,
+            start_bit: self.start_bit,
+            size: self.size,
+            byte_order: self.byte_order,
+            value_type: self.value_type,
+            factor: self.factor,
+            offset: self.offset,
+            min: self.min,
+            max: self.max,
+        }
+    }
+
+    /// Returns the raw bits of the signal, or `None` if the data is too short.
+    pub fn extract(&self, data: &[u8]) -> Option<u64> {
+        self.format().extract(data)
+    }
+
+    /// Writes the raw bits of the signal into `data`, returns `None` if the data is too short.
+    pub fn insert(&self, data: &mut [u8], raw: u64) -> Option<()> {
+        self.format().insert(data, raw)
+    }
+
+    /// Interprets raw bits according to the value type, without scaling.
+    pub fn raw_value(&self, raw: u64) -> f64 {
+        self.format().raw_value(raw)
+    }
+
+    /// Converts raw bits to the physical value.
+    pub fn to_physical(&self, raw: u64) -> f64 {
+        self.format().to_physical(raw)
+    }
+
+    /// Converts a physical value to raw bits, see [`SignalFormat::to_raw`].
+    pub fn to_raw(&self, value: f64) -> Result<u64, Error> {
+        self.format().to_raw(value)
+    }
+
+    /// Returns the raw bits of the start value attribute, or of `default` if the
+    /// signal does not set it.
+    fn start_value(&self, default: Option<&AttributeValue>) -> Result<u64, Error> {
+        match self.attributes.get(START_VALUE_ATTRIBUTE).or(default) {
+            Some(AttributeValue::Int(value)) => self.format().raw_bits(*value as f64),
+            Some(AttributeValue::Float(value)) => self.format().raw_bits(*value),
+            _ => Ok(0),
+        }
+    }
+
+    /// Returns whether the signal is only present for some multiplexor values.
+    fn is_multiplexed(&self) -> bool {
+        self.multiplex_condition.is_some()
+            || matches!(
+                self.multiplex,
+                MultiplexIndicator::Multiplexed(_) | MultiplexIndicator::MultiplexedMultiplexor(_)
+            )
+    }
+
+    /// Returns whether a raw value of its multiplexor selects the signal.
+    fn is_selected_by(&self, value: u64) -> bool {
+        match (&self.multiplex_condition, self.multiplex) {
+            (Some(condition), _) => condition.contains(value),
+            (
+                None,
+                MultiplexIndicator::Multiplexed(selector)
+                | MultiplexIndicator::MultiplexedMultiplexor(selector),
+            ) => value == selector,
+            _ => true,
+        }
+    }
+
+    /// Returns the inclusive ranges of multiplexor values selecting the signal.
+    fn selecting_values(&self) -> Vec<(u64, u64)> {
+        match (&self.multiplex_condition, self.multiplex) {
+            (Some(condition), _) => condition.ranges.clone(),
+            (
+                None,
+                MultiplexIndicator::Multiplexed(value)
+                | MultiplexIndicator::MultiplexedMultiplexor(value),
+            ) => vec![(value, value)],
+            _ => Vec::new(),
+        }
+    }
+
+    /// Returns the description of a raw value from the value table.
+    pub fn describe(&self, raw: u64) -> Option<&str> {
+        let value = match self.value_type {
+            ValueType::Signed => sign_extend(raw, self.size),
+            _ => raw as i64,
+        };
+        self.value_descriptions
+            .iter()
+            .find(|(v, _)| *v == value)
+            .map(|(_, description)| description.as_str())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Message {
+    pub id: Id,
+    pub name: String,
+    /// Payload length in bytes.
+    pub size: usize,
+    pub transmitters: Vec<String>,
+    pub signals: Vec<Signal>,
+    pub comment: Option<String>,
+    pub attributes: HashMap<String, AttributeValue>,
+}
+
+impl Message {
+    pub fn signal(&self, name: &str) -> Option<&Signal> {
+        self.signals.iter().find(|s| s.name == name)
+    }
+
+    /// Returns the signal selecting the multiplexed signals, if any.
+    pub fn multiplexor(&self) -> Option<&Signal> {
+        self.signals
+            .iter()
+            .find(|s| s.multiplex == MultiplexIndicator::Multiplexor)
+    }
+
+    /// Returns the multiplexor selecting a multiplexed signal.
+    fn multiplexor_of(&self, signal: &Signal) -> Option<&Signal> {
+        match &signal.multiplex_condition {
+            Some(condition) => self.signal(&condition.multiplexor),
+            None => self.multiplexor(),
+        }
+    }
+
+    /// Returns the chain of multiplexors selecting a signal, starting with the
+    /// one selecting it directly. Fails if a multiplexor is missing or the
+    /// multiplexors form a cycle.
+    fn multiplexors_of<'a>(&'a self, mut signal: &'a Signal) -> Result<Vec<&'a Signal>, Error> {
+        let mut multiplexors = Vec::new();
+        while signal.is_multiplexed() {
+            let multiplexor = self.multiplexor_of(signal).ok_or_else(|| {
+                Error(format!(
+                    "Multiplexor of signal {} is missing from message {}",
+                    signal.name, self.name
+                ))
+            })?;
+            if multiplexors.len() == self.signals.len() {
+                return Err(Error(format!(
+                    "Multiplexors of signal {} form a cycle",
+                    signal.name
+                )));
+            }
+            multiplexors.push(multiplexor);
+            signal = multiplexor;
+        }
+        Ok(multiplexors)
+    }
+
+    /// Returns whether a signal is present given the raw values of the
+    /// multiplexors, following extended multiplexing down to the message's
+    /// multiplexor.
+    fn is_selected<'a>(
+        &'a self,
+        mut signal: &'a Signal,
+        raw: impl Fn(&Signal) -> Option<u64>,
+    ) -> bool {
+        // Bounded, so that cyclic multiplexors end.
+        for _ in 0..=self.signals.len() {
+            if !signal.is_multiplexed() {
+                return true;
+            }
+            let Some(multiplexor) = self.multiplexor_of(signal) else {
+                return false;
+            };
+            if !raw(multiplexor).is_some_and(|value| signal.is_selected_by(value)) {
+                return false;
+            }
+            signal = multiplexor;
+        }
+        false
+    }
+
+    /// Encodes physical signal values into the payload of the message.
+    ///
+    /// Signals that are not given keep their start value. For multiplexed
+    /// messages only the signals selected by the multiplexors are encoded.
+    pub fn encode(&self, values: &[(&str, f64)]) -> Result<Vec<u8>, Error> {
+        self.encode_with_default(values, None)
+    }
+
+    fn encode_with_default(
+        &self,
+        values: &[(&str, f64)],
+        default: Option<&AttributeValue>,
+    ) -> Result<Vec<u8>, Error> {
+        for (name, _) in values {
+            if self.signal(name).is_none() {
+                return Err(Error(format!(
+                    "Message {} has no signal {}",
+                    self.name, name
+                )));
+            }
+        }
+        let raw_value = |signal: &Signal| match values.iter().find(|(name, _)| *name == signal.name)
+        {
+            Some(&(_, value)) => signal.to_raw(value),
+            None => signal.start_value(default),
+        };
+
+        // Invalid multiplexor values are reported as such rather than as
+        // unselected signals.
+        for signal in self.signals.iter().filter(|s| {
+            matches!(
+                s.multiplex,
+                MultiplexIndicator::Multiplexor | MultiplexIndicator::MultiplexedMultiplexor(_)
+            )
+        }) {
+            raw_value(signal)?;
+        }
+        let mux = |signal: &Signal| raw_value(signal).ok();
+
+        let mut data = vec![0; self.size];
+        for signal in &self.signals {
+            if !self.is_selected(signal, mux) {
+                if values.iter().any(|(name, _)| *name == signal.name) {
+                    return Err(Error(format!(
+                        "Signal {} is not selected by the multiplexor value",
+                        signal.name
+                    )));
+                }
+                continue;
+            }
+
+            let raw = raw_value(signal)?;
+            signal.insert(&mut data, raw).ok_or_else(|| {
+                Error(format!(
+                    "Signal {} does not fit in message {}",
+                    signal.name, self.name
+                ))
+            })?;
+        }
+        Ok(data)
+    }
+
+    /// Decodes the signals present in `data`.
+    ///
+    /// Multiplexed signals are only included if their multiplexors select
+    /// them, signals that do not fit in the data are left out.
+    pub fn decode(&self, data: &[u8]) -> DecodedMessage<'_> {
+        let signals = self
+            .signals
+            .iter()
+            .filter(|s| self.is_selected(s, |mux| mux.extract(data)))
+            .filter_map(|signal| {
+                let raw = signal.extract(data)?;
+                Some(DecodedSignal {
+                    signal,
+                    raw,
+                    value: signal.to_physical(raw),
+                })
+            })
+            .collect();
+
+        DecodedMessage {
+            message: self,
+            signals,
+        }
+    }
+}
+
+/// A signal value decoded from a frame.
+#[derive(Debug, Clone, Copy)]
+pub struct DecodedSignal<'a> {
+    pub signal: &'a Signal,
+    /// Raw bits of the signal.
+    pub raw: u64,
+    /// Physical value.
+    pub value: f64,
+}
+
+impl<'a> DecodedSignal<'a> {
+    pub fn name(&self) -> &'a str {
+        &self.signal.name
+    }
+
+    pub fn unit(&self) -> &'a str {
+        &self.signal.unit
+    }
+
+    /// Returns the value table description of the value.
+    pub fn description(&self) -> Option<&'a str> {
+        self.signal.describe(self.raw)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DecodedMessage<'a> {
+    pub message: &'a Message,
+    pub signals: Vec<DecodedSignal<'a>>,
+}
+
+impl<'a> DecodedMessage<'a> {
+    pub fn name(&self) -> &'a str {
+        &self.message.name
+    }
+
+    pub fn signal(&self, name: &str) -> Option<&DecodedSignal<'a>> {
+        self.signals.iter().find(|s| s.signal.name == name)
+    }
+
+    /// Returns the physical value of a signal.
+    pub fn value(&self, name: &str) -> Option<f64> {
+        self.signal(name).map(|s| s.value)
+    }
+}
+
+/// A CAN database loaded from a DBC file.
+#[derive(Debug, Clone, Default)]
+pub struct Database {
+    pub version: String,
+    pub nodes: Vec<Node>,
+    pub messages: Vec<Message>,
+    /// Value tables shared between signals, by name.
+    pub value_tables: HashMap<String, Vec<(i64, String)>>,
+    pub comment: Option<String>,
+    pub attribute_definitions: Vec<AttributeDefinition>,
+    /// Attributes of the database itself.
+    pub attributes: HashMap<String, AttributeValue>,
+    /// Message index by identifier.
+    index: HashMap<Id, usize>,
+}
+
+impl Database {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        // DBC files are often Windows-1252 encoded, which only affects comments
+        // and descriptions.
+        let bytes = fs::read(path)?;
+        match String::from_utf8(bytes) {
+            Ok(s) => s.parse(),
+            Err(err) => err
+                .as_bytes()
+                .iter()
+                .map(|&b| b as char)
+                .collect::<String>()
+                .parse(),
+        }
+    }
+
+    pub fn message(&self, id: impl Into<Id>) -> Option<&Message> {
+        self.index.get(&id.into()).map(|&i| &self.messages[i])
+    }
+
+    pub fn message_by_name(&self, name: &str) -> Option<&Message> {
+        self.messages.iter().find(|m| m.name == name)
+    }
+
+    /// Decodes a frame, returns `None` if the database does not define its identifier.
+    pub fn decode(&self, frame: &impl embedded_can::Frame) -> Option<DecodedMessage<'_>> {
+        if frame.is_remote_frame() {
+            return None;
+        }
+        Some(self.message(frame.id())?.decode(frame.data()))
+    }
+
+    /// Builds a frame of a message from physical signal values, see [`Message::encode`].
+    ///
+    /// Signals without a start value attribute use the default of its definition.
+    pub fn encode(&self, message: &str, values: &[(&str, f64)]) -> Result<Frame, Error> {
+        let message = self
+            .message_by_name(message)
+            .ok_or_else(|| Error(format!("Unknown message {}", message)))?;
+        let data =
+            message.encode_with_default(values, self.attribute_default(START_VALUE_ATTRIBUTE))?;
+        <Frame as embedded_can::Frame>::new(message.id, &data).ok_or_else(|| {
+            Error(format!(
+                "Message {} is too long for a classic frame",
+                message.name
+            ))
+        })
+    }
+
+    /// Returns the value of an attribute of the database, or the default of its definition.
+    pub fn attribute(&self, name: &str) -> Option<&AttributeValue> {
+        self.attributes
+            .get(name)
+            .or_else(|| self.attribute_default(name))
+    }
+
+    /// Returns the default value of an attribute definition.
+    pub fn attribute_default(&self, name: &str) -> Option<&AttributeValue> {
+        self.attribute_definitions
+            .iter()
+            .find(|d| d.name == name)?
+            .default
+            .as_ref()
+    }
+
+    fn index_messages(&mut self) {
+        self.index = self
+            .messages
+            .iter()
+            .enumerate()
+            .map(|(i, m)| (m.id, i))
+            .collect();
+    }
+}
+
+impl FromStr for Database {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parser::Parser::parse(s)
+    }
+}
+
+fn bit(data: &[u8], position: usize) -> Option<u8> {
+    Some((data.get(position / 8)? >> (position % 8)) & 1)
+}
+
+fn set_bit(data: &mut [u8], position: usize, value: bool) -> Option<()> {
+    let byte = data.get_mut(position / 8)?;
+    if value {
+        *byte |= 1 << (position % 8);
+    } else {
+        *byte &= !(1 << (position % 8));
+    }
+    Some(())
+}
+
+/// Returns the next less significant bit position of a big endian signal,
+/// which continues at the most significant bit of the following byte.
+fn next_big_endian_bit(position: usize) -> usize {
+    if position.is_multiple_of(8) {
+        position + 15
+    } else {
+        position - 1
+    }
+}
+
+fn sign_extend(raw: u64, size: usize) -> i64 {
+    let shift = 64 - size as u32;
+    ((raw << shift) as i64) >> shift
+}
+
+fn mask(size: usize) -> u64 {
+    if size >= 64 {
+        u64::MAX
+    } else {
+        (1 << size) - 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_can::{ExtendedId, Frame as _, StandardId};
+
+    use super::*;
+
+    const DATABASE: &str = r#"
+VERSION "1.0"
+
+NS_ :
+    CM_
+    SG_MUL_VAL_
+
+BS_:
+
+BU_: ECU GW
+
+VAL_TABLE_ Gears 0 "Neutral" 1 "First" ;
+
+BO_ 291 Engine: 8 ECU
+ SG_ Rpm : 7|16@0+ (0.25,0) [0|16383.75] "rpm" GW
+ SG_ Temp : 23|12@0- (0.1,-40) [-40|150] "degC" GW,ECU
+ SG_ Gear : 36|4@1+ (1,0) [0|6] "" GW
+ SG_ Torque : 40|12@1- (1,0) [-2048|2047] "Nm" Vector__XXX
+
+BO_ 2566914048 Float: 8 GW
+ SG_ Value : 0|32@1- (1,0) [0|0] "" ECU
+ SG_ Motorola : 39|32@0- (1,0) [0|0] "" ECU
+
+BO_TX_BU_ 291 : ECU,GW;
+
+CM_ "database comment";
+CM_ BU_ ECU "engine control";
+CM_ BO_ 291 "engine status";
+CM_ SG_ 291 Rpm "engine speed, \"filtered\"";
+BA_DEF_ BO_ "GenMsgCycleTime" INT 0 10000;
+BA_DEF_ SG_ "GenSigStartValue" INT 0 65535;
+BA_DEF_ "BusType" STRING ;
+BA_DEF_ BU_ "NodeLayer" ENUM "Application","Network";
+BA_DEF_DEF_ "GenMsgCycleTime" 100;
+BA_DEF_DEF_ "GenSigStartValue" 0;
+BA_ "BusType" "CAN";
+BA_ "NodeLayer" BU_ GW 1;
+BA_ "GenMsgCycleTime" BO_ 291 20;
+BA_ "GenSigStartValue" SG_ 291 Temp 400;
+VAL_ 291 Gear 0 "Neutral" 1 "First" 2 "Second" ;
+SIG_VALTYPE_ 2566914048 Value : 1;
+SIG_VALTYPE_ 2566914048 Motorola : 1;
+"#;
+
+    fn format(start_bit: usize, size: usize, byte_order: ByteOrder) -> SignalFormat<'static> {
+        SignalFormat {
+            name: "Signal",
+            start_bit,
+            size,
+            byte_order,
+            value_type: ValueType::Unsigned,
+            factor: 1.0,
+            offset: 0.0,
+            min: 0.0,
+            max: 0.0,
+        }
+    }
+
+    #[test]
+    fn parse() {
+        let db: Database = DATABASE.parse().unwrap();
+        assert_eq!(db.version, "1.0");
+        assert_eq!(db.comment.as_deref(), Some("database comment"));
+        assert_eq!(db.nodes.len(), 2);
+        assert_eq!(db.nodes[0].comment.as_deref(), Some("engine control"));
+        assert_eq!(db.nodes[1].attributes["NodeLayer"], AttributeValue::Int(1));
+        assert_eq!(
+            db.attribute("BusType"),
+            Some(&AttributeValue::String("CAN".to_string()))
+        );
+        assert_eq!(db.value_tables["Gears"].len(), 2);
+        assert_eq!(
+            db.attribute_definitions[3].kind,
+            AttributeKind::Enum(vec!["Application".to_string(), "Network".to_string()])
+        );
+
+        let engine = db.message_by_name("Engine").unwrap();
+        assert_eq!(engine.id, Id::Standard(StandardId::new(0x123).unwrap()));
+        assert_eq!(engine.size, 8);
+        assert_eq!(engine.transmitters, ["ECU", "GW"]);
+        assert_eq!(engine.comment.as_deref(), Some("engine status"));
+        assert_eq!(
+            engine.attributes["GenMsgCycleTime"],
+            AttributeValue::Int(20)
+        );
+
+        let rpm = engine.signal("Rpm").unwrap();
+        assert_eq!(rpm.byte_order, ByteOrder::BigEndian);
+        assert_eq!(rpm.value_type, ValueType::Unsigned);
+        assert_eq!((rpm.factor, rpm.offset), (0.25, 0.0));
+        assert_eq!((rpm.min, rpm.max), (0.0, 16383.75));
+        assert_eq!(rpm.unit, "rpm");
+        assert_eq!(rpm.comment.as_deref(), Some("engine speed, \"filtered\""));
+        let temp = engine.signal("Temp").unwrap();
+        assert_eq!(temp.value_type, ValueType::Signed);
+        assert_eq!(temp.receivers, ["GW", "ECU"]);
+        assert!(engine.signal("Torque").unwrap().receivers.is_empty());
+
+        let float = db.message_by_name("Float").unwrap();
+        assert_eq!(
+            float.id,
+            Id::Extended(ExtendedId::new(0x1900_0000).unwrap())
+        );
+        assert_eq!(float.signals[0].value_type, ValueType::Float);
+        assert!(db.message(ExtendedId::new(0x1900_0000).unwrap()).is_some());
+    }
+
+    #[test]
+    fn decode() {
+        let db: Database = DATABASE.parse().unwrap();
+        let id = StandardId::new(0x123).unwrap();
+        let frame = Frame::new(id, &[0x27, 0x10, 0x51, 0x40, 0x20, 0x9C, 0x0F, 0x00]).unwrap();
+        let decoded = db.decode(&frame).unwrap();
+        assert_eq!(decoded.name(), "Engine");
+        assert_eq!(decoded.value("Rpm"), Some(2500.0));
+        assert!((decoded.value("Temp").unwrap() - 90.0).abs() < 1e-9);
+        assert_eq!(decoded.value("Torque"), Some(-100.0));
+        let gear = decoded.signal("Gear").unwrap();
+        assert_eq!(gear.raw, 2);
+        assert_eq!(gear.description(), Some("Second"));
+        assert_eq!(decoded.signal("Rpm").unwrap().unit(), "rpm");
+
+        // Signals beyond the end of short frames are left out.
+        let frame = Frame::new(id, &[0x27, 0x10]).unwrap();
+        let decoded = db.decode(&frame).unwrap();
+        assert_eq!(names(&decoded), ["Rpm"]);
+
+        assert!(db.decode(&Frame::new_remote(id, 8).unwrap()).is_none());
+        let unknown = Frame::new(StandardId::new(0x124).unwrap(), &[0; 8]).unwrap();
+        assert!(db.decode(&unknown).is_none());
+
+        let id = ExtendedId::new(0x1900_0000).unwrap();
+        let frame = Frame::new(id, &[0, 0, 0xC0, 0x3F, 0xC0, 0, 0, 0]).unwrap();
+        let decoded = db.decode(&frame).unwrap();
+        assert_eq!(decoded.value("Value"), Some(1.5));
+        assert_eq!(decoded.value("Motorola"), Some(-2.0));
+    }
+
+    #[test]
+    fn big_endian_across_bytes() {
+        let data = [0xA5, 0xBC, 0xF8];
+        assert_eq!(
+            format(7, 16, ByteOrder::BigEndian).extract(&data),
+            Some(0xA5BC)
+        );
+        assert_eq!(
+            format(3, 12, ByteOrder::BigEndian).extract(&data),
+            Some(0x5BC)
+        );
+        assert_eq!(
+            format(12, 10, ByteOrder::BigEndian).extract(&data),
+            Some(0x39F)
+        );
+        assert_eq!(
+            format(0, 2, ByteOrder::BigEndian).extract(&data),
+            Some(0b11)
+        );
+        assert_eq!(format(12, 14, ByteOrder::BigEndian).extract(&data), None);
+
+        let mut data = [0; 3];
+        format(12, 10, ByteOrder::BigEndian)
+            .insert(&mut data, 0x39F)
+            .unwrap();
+        assert_eq!(data, [0x00, 0x1C, 0xF8]);
+    }
+
+    #[test]
+    fn little_endian_across_bytes() {
+        let data = [0xA5, 0xBC, 0xF8];
+        assert_eq!(
+            format(0, 16, ByteOrder::LittleEndian).extract(&data),
+            Some(0xBCA5)
+        );
+        assert_eq!(
+            format(4, 12, ByteOrder::LittleEndian).extract(&data),
+            Some(0xBCA)
+        );
+        assert_eq!(
+            format(6, 12, ByteOrder::LittleEndian).extract(&data),
+            Some(0x2F2)
+        );
+        assert_eq!(format(16, 9, ByteOrder::LittleEndian).extract(&data), None);
+    }
+
+    /// Every signal that fits in 8 bytes reads back what was written, without
+    /// touching other bits.
+    #[test]
+    fn insert_extract_round_trip() {
+        for byte_order in [ByteOrder::LittleEndian, ByteOrder::BigEndian] {
+            for start_bit in 0..64 {
+                for size in 1..=64 {
+                    let format = format(start_bit, size, byte_order);
+                    if format.extract(&[0; 8]).is_none() {
+                        continue;
+                    }
+                    let raw = 0x9E37_79B9_7F4A_7C15 & mask(size);
+                    let mut data = [0; 8];
+                    format.insert(&mut data, raw).unwrap();
+                    assert_eq!(format.extract(&data), Some(raw));
+                    assert_eq!(
+                        u64::from_le_bytes(data).count_ones(),
+                        raw.count_ones(),
+                        "{:?} {} {}",
+                        byte_order,
+                        start_bit,
+                        size
+                    );
+
+                    let mut data = [0xFF; 8];
+                    format.insert(&mut data, raw).unwrap();
+                    assert_eq!(format.extract(&data), Some(raw));
+                    assert_eq!(
+                        u64::from_le_bytes(data).count_zeros(),
+                        raw.count_zeros() - (64 - size as u32)
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn signed_values() {
+        let mut format = format(4, 12, ByteOrder::LittleEndian);
+        format.value_type = ValueType::Signed;
+        assert_eq!(format.to_signed(0xFFF), -1);
+        assert_eq!(format.to_signed(0x800), -2048);
+        assert_eq!(format.to_signed(0x7FF), 2047);
+        assert_eq!(format.raw_value(0xF9C), -100.0);
+    }
+
+    #[test]
+    fn invalid_databases() {
+        for dbc in [
+            "BO_ 1 M: 8 ECU\n SG_ S : 0|0@1+ (1,0) [0|0] \"\" ECU",
+            "BO_ 1 M: 8 ECU\n SG_ S : 0|65@1+ (1,0) [0|0] \"\" ECU",
+            "BO_ 1 M: 8 ECU\n SG_ S : 0|8@2+ (1,0) [0|0] \"\" ECU",
+            "BO_ 1 M: 8 ECU\n SG_ S x : 0|8@1+ (1,0) [0|0] \"\" ECU",
+            "BO_ 1 M: 8 ECU\n SG_ S : 0|8@1+ (1,0) [0|0] \"unit ECU",
+            "SG_ S : 0|8@1+ (1,0) [0|0] \"\" ECU",
+            "BO_ 1610612736 M: 8 ECU",
+        ] {
+            assert!(dbc.parse::<Database>().is_err(), "{}", dbc);
+        }
+        let err = "VERSION \"\"\n\nBO_ 1 M: 8 ECU\n SG_ S : 0|8@3+ (1,0) [0|0] \"\" ECU"
+            .parse::<Database>()
+            .unwrap_err();
+        assert!(err.to_string().starts_with("dbc line 4:"), "{}", err);
+    }
+
+    #[test]
+    fn encode() {
+        let db: Database = DATABASE.parse().unwrap();
+        let values = [
+            ("Rpm", 2500.0),
+            ("Temp", 90.0),
+            ("Gear", 2.0),
+            ("Torque", -100.0),
+        ];
+        let frame = db.encode("Engine", &values).unwrap();
+        assert_eq!(frame.id(), Id::Standard(StandardId::new(0x123).unwrap()));
+        assert_eq!(
+            frame.data(),
+            [0x27, 0x10, 0x51, 0x40, 0x20, 0x9C, 0x0F, 0x00]
+        );
+
+        let decoded = db.decode(&frame).unwrap();
+        for (name, value) in values {
+            assert!(
+                (decoded.value(name).unwrap() - value).abs() < 1e-9,
+                "{}",
+                name
+            );
+        }
+
+        let frame = db
+            .encode("Float", &[("Value", 1.5), ("Motorola", -2.0)])
+            .unwrap();
+        assert_eq!(frame.data(), [0, 0, 0xC0, 0x3F, 0xC0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn encode_start_values() {
+        let db: Database = DATABASE.parse().unwrap();
+        // Temp has a start value of 400, which is 0 degC.
+        let frame = db.encode("Engine", &[]).unwrap();
+        assert_eq!(frame.data(), [0, 0, 0x19, 0, 0, 0, 0, 0]);
+
+        // Signals without start value use the default of the definition, but
+        // only when encoding through the database.
+        let db: Database = DATABASE
+            .replace(
+                "BA_DEF_DEF_ \"GenSigStartValue\" 0;",
+                "BA_DEF_DEF_ \"GenSigStartValue\" 3;",
+            )
+            .parse()
+            .unwrap();
+        let frame = db.encode("Engine", &[("Rpm", 0.25)]).unwrap();
+        assert_eq!(frame.data(), [0, 1, 0x19, 0, 0x30, 3, 0, 0]);
+        let message = db.message_by_name("Engine").unwrap();
+        assert_eq!(message.encode(&[]).unwrap(), [0, 0, 0x19, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn encode_errors() {
+        let db: Database = DATABASE.parse().unwrap();
+        assert!(db.encode("Engine", &[("Rpm", 16383.75)]).is_ok());
+        assert!(db.encode("Engine", &[("Rpm", 16384.0)]).is_err());
+        assert!(db.encode("Engine", &[("Temp", -40.1)]).is_err());
+        assert!(db.encode("Engine", &[("Speed", 0.0)]).is_err());
+        assert!(db.encode("Gearbox", &[]).is_err());
+
+        let db: Database = r#"
+BO_ 1 Small: 1 ECU
+ SG_ Unsigned : 0|4@1+ (1,0) [0|0] "" ECU
+ SG_ Signed : 4|4@1- (1,0) [0|0] "" ECU
+BO_ 2 Long: 12 ECU
+"#
+        .parse()
+        .unwrap();
+        let small = db.message_by_name("Small").unwrap();
+        assert_eq!(
+            small
+                .encode(&[("Unsigned", 15.0), ("Signed", -8.0)])
+                .unwrap(),
+            [0x8F]
+        );
+        assert!(small.encode(&[("Unsigned", 16.0)]).is_err());
+        assert!(small.encode(&[("Unsigned", -1.0)]).is_err());
+        assert!(small.encode(&[("Signed", 8.0)]).is_err());
+        assert!(db.message_by_name("Long").unwrap().encode(&[]).is_ok());
+        assert!(db.encode("Long", &[]).is_err());
+    }
+
+    #[test]
+    fn encode_multiplexed() {
+        let db: Database = r#"
+BO_ 1 Mux: 2 ECU
+ SG_ Selector M : 0|8@1+ (1,0) [0|0] "" ECU
+ SG_ A m0 : 8|8@1+ (1,0) [0|0] "" ECU
+ SG_ B m1 : 8|8@1+ (1,0) [0|0] "" ECU
+"#
+        .parse()
+        .unwrap();
+        let frame = db.encode("Mux", &[("A", 7.0)]).unwrap();
+        assert_eq!(frame.data(), [0, 7]);
+        let frame = db.encode("Mux", &[("Selector", 1.0), ("B", 9.0)]).unwrap();
+        assert_eq!(frame.data(), [1, 9]);
+        assert!(db.encode("Mux", &[("B", 9.0)]).is_err());
+        assert!(db.encode("Mux", &[("Selector", 1.0), ("A", 7.0)]).is_err());
+
+        let decoded = db.decode(&frame).unwrap();
+        assert_eq!(names(&decoded), ["Selector", "B"]);
+    }
+
+    const EXTENDED_MULTIPLEXING: &str = r#"
+VERSION ""
+BU_: ECU
+BO_ 100 Ext: 2 ECU
+ SG_ S0 M : 0|4@1+ (1,0) [0|0] "" Vector__XXX
+ SG_ S1 m0M : 4|4@1+ (1,0) [0|0] "" Vector__XXX
+ SG_ S2 m3 : 8|8@1+ (1,0) [0|0] "" Vector__XXX
+ SG_ S3 m1 : 8|8@1+ (1,0) [0|0] "" Vector__XXX
+SG_MUL_VAL_ 100 S1 S0 0-0;
+SG_MUL_VAL_ 100 S2 S1 3-5, 7-7;
+SG_MUL_VAL_ 100 S3 S0 1-1;
+"#;
+
+    fn names<'a>(message: &'a DecodedMessage) -> Vec<&'a str> {
+        message.signals.iter().map(|s| s.name()).collect()
+    }
+
+    #[test]
+    fn extended_multiplexing() {
+        let db: Database = EXTENDED_MULTIPLEXING.parse().unwrap();
+        let message = db.message(StandardId::new(100).unwrap()).unwrap();
+        assert_eq!(
+            message.signal("S2").unwrap().multiplex_condition,
+            Some(MultiplexCondition {
+                multiplexor: "S1".to_string(),
+                ranges: vec![(3, 5), (7, 7)],
+            })
+        );
+
+        let decoded = message.decode(&[0x40, 0xAB]);
+        assert_eq!(names(&decoded), ["S0", "S1", "S2"]);
+        assert_eq!(decoded.value("S2"), Some(171.0));
+        assert_eq!(names(&message.decode(&[0x70, 0xAB])), ["S0", "S1", "S2"]);
+        assert_eq!(names(&message.decode(&[0x60, 0xAB])), ["S0", "S1"]);
+        // S1 is not selected, so S2 is not either, whatever the bits of S1 are.
+        assert_eq!(names(&message.decode(&[0x41, 0xAB])), ["S0", "S3"]);
+
+        let frame = db.encode("Ext", &[("S1", 5.0), ("S2", 18.0)]).unwrap();
+        assert_eq!(frame.data(), [0x50, 0x12]);
+        let frame = db.encode("Ext", &[("S0", 1.0), ("S3", 18.0)]).unwrap();
+        assert_eq!(frame.data(), [0x01, 0x12]);
+        assert!(db.encode("Ext", &[("S2", 18.0)]).is_err());
+        assert!(db.encode("Ext", &[("S0", 1.0), ("S1", 5.0)]).is_err());
+    }
+
+    #[test]
+    fn invalid_extended_multiplexing() {
+        let missing =
+            EXTENDED_MULTIPLEXING.replace("SG_MUL_VAL_ 100 S3 S0", "SG_MUL_VAL_ 100 S3 S9");
+        assert!(missing.parse::<Database>().is_err());
+
+        let cycle = EXTENDED_MULTIPLEXING.replace("SG_MUL_VAL_ 100 S1 S0", "SG_MUL_VAL_ 100 S1 S2");
+        assert!(cycle.parse::<Database>().is_err());
+
+        let range = EXTENDED_MULTIPLEXING.replace("3-5", "5-3");
+        assert!(range.parse::<Database>().is_err());
+    }
+}
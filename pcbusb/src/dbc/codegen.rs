@@ -0,0 +1,839 @@
+//! Generation of typed message code from DBC databases.
+//!
+//! Meant to be called from a build script, the generated file is included
+//! into the application:
+//!
+//! ```text
+//! // build.rs
+//! fn main() {
+//!     let out = std::path::Path::new(&std::env::var("OUT_DIR").unwrap()).join("vehicle.rs");
+//!     pcbusb::dbc::codegen::generate_file("vehicle.dbc", out).unwrap();
+//!     println!("cargo:rerun-if-changed=vehicle.dbc");
+//! }
+//!
+//! // main.rs
+//! include!(concat!(env!("OUT_DIR"), "/vehicle.rs"));
+//!
+//! let mut status = EngineStatus::new();
+//! status.set_rpm(2500.0)?;
+//! status.set_gear(EngineStatusGear::Third);
+//! let frame: pcbusb::Frame = status.into();
+//! let status = EngineStatus::try_from(&frame)?;
+//! ```
+//!
+//! Every message becomes a struct holding its payload, with a getter and a
+//! setter per signal, as well as `*_raw` variants for integer signals. Signals
+//! with a value table use a generated enum, integer signals without scaling
+//! their raw type and all other signals `f64`. Getters of multiplexed signals
+//! return `None` unless their multiplexors select them, their setters select them.
+
+use std::{fmt::Write as _, fs, path::Path};
+
+use embedded_can::Id;
+
+use super::{AttributeValue, Database, Message, START_VALUE_ATTRIBUTE, Signal, ValueType};
+use crate::{Error, frame::FD_LENGTHS};
+
+const KEYWORDS: &[&str] = &[
+    "abstract", "as", "async", "await", "become", "box", "break", "const", "continue", "crate",
+    "do", "dyn", "else", "enum", "extern", "false", "final", "fn", "for", "gen", "if", "impl",
+    "in", "let", "loop", "macro", "match", "mod", "move", "mut", "override", "priv", "pub", "ref",
+    "return", "self", "static", "struct", "super", "trait", "true", "try", "type", "typeof",
+    "unsafe", "unsized", "use", "virtual", "where", "while", "yield",
+];
+
+/// Names of the generated associated items signals must not clash with.
+const RESERVED_METHODS: &[&str] = &["new", "data"];
+const RESERVED_CONSTS: &[&str] = &["ID", "SIZE"];
+/// Names generated enum variants must not take.
+const RESERVED_VARIANTS: &[&str] = &["Self", "Other"];
+
+/// Reads a DBC file and writes the code generated for it to `output`.
+pub fn generate_file(input: impl AsRef<Path>, output: impl AsRef<Path>) -> Result<(), Error> {
+    let db = Database::open(input)?;
+    fs::write(output, generate(&db)?)?;
+    Ok(())
+}
+
+/// Generates the code for all messages of a database.
+pub fn generate(db: &Database) -> Result<String, Error> {
+    let mut out = String::from("// Generated by pcbusb from a DBC database, do not edit.\n");
+    let default = db.attribute_default(START_VALUE_ATTRIBUTE);
+    let mut types = Vec::new();
+    for message in &db.messages {
+        MessageGenerator::new(message, &mut types)?.generate(&mut out, default)?;
+    }
+    Ok(out)
+}
+
+/// Returns the payload of a frame of the message with identifier `id` and
+/// length `size`, used by the generated `TryFrom` implementations.
+pub fn message_data<const N: usize>(
+    frame: &impl embedded_can::Frame,
+    id: Id,
+    size: usize,
+) -> Result<[u8; N], Error> {
+    if frame.is_remote_frame() || frame.id() != id {
+        return Err(Error(format!(
+            "Frame with identifier {:?} is not a message {:?}",
+            frame.id(),
+            id
+        )));
+    }
+    let len = frame.data().len();
+    if len < size {
+        return Err(Error(format!(
+            "Frame is {} bytes long, the message needs {} bytes",
+            len, size
+        )));
+    }
+
+    let mut data = [0; N];
+    let len = len.min(N);
+    data[..len].copy_from_slice(&frame.data()[..len]);
+    Ok(data)
+}
+
+/// How the physical value of a signal is represented.
+enum Repr {
+    /// Value table enum with the raw integer type.
+    Enum(String, &'static str),
+    /// Unscaled integer.
+    Int(&'static str),
+    /// Unscaled single precision float.
+    F32,
+    /// Any other signal.
+    F64,
+}
+
+struct SignalInfo<'a> {
+    signal: &'a Signal,
+    /// Name of the getter.
+    method: String,
+    /// Name of the setter.
+    setter: String,
+    /// Name of the raw getter, the raw setter prefixes it with `set_`.
+    raw_method: String,
+    /// Name of the signal format constant.
+    constant: String,
+    /// Raw integer type, `None` for float signals.
+    raw_type: Option<&'static str>,
+    repr: Repr,
+    /// Multiplexors selecting the signal, by index, with the values selecting
+    /// the signal or the previous multiplexor.
+    selectors: Vec<(usize, Vec<(u64, u64)>)>,
+}
+
+impl SignalInfo<'_> {
+    fn value_type(&self) -> &str {
+        match &self.repr {
+            Repr::Enum(name, _) => name,
+            Repr::Int(ty) => ty,
+            Repr::F32 => "f32",
+            Repr::F64 => "f64",
+        }
+    }
+}
+
+struct MessageGenerator<'a> {
+    message: &'a Message,
+    name: String,
+    /// Length of the payload array, the message size rounded up to a valid length.
+    len: usize,
+    fd: bool,
+    signals: Vec<SignalInfo<'a>>,
+}
+
+impl<'a> MessageGenerator<'a> {
+    /// Creates the generator of a message, `types` holds the type names
+    /// already taken and gets the ones of this message added.
+    fn new(message: &'a Message, types: &mut Vec<String>) -> Result<Self, Error> {
+        let name = unique(types, type_name(&message.name));
+        let fd = message.size > 8;
+        let len = if fd {
+            FD_LENGTHS
+                .into_iter()
+                .find(|&len| len >= message.size)
+                .ok_or_else(|| Error(format!("Message {} is too long", message.name)))?
+        } else {
+            message.size
+        };
+
+        let mut signals = Vec::new();
+        let mut items: Vec<String> = RESERVED_METHODS
+            .iter()
+            .chain(RESERVED_CONSTS)
+            .map(|item| item.to_string())
+            .collect();
+        for signal in &message.signals {
+            if signal.extract(&vec![0; message.size]).is_none() {
+                return Err(Error(format!(
+                    "Signal {} does not fit in message {}",
+                    signal.name, message.name
+                )));
+            }
+
+            let raw_type = match signal.value_type {
+                ValueType::Unsigned | ValueType::Signed => Some(int_type(signal)),
+                ValueType::Float | ValueType::Double => None,
+            };
+
+            // Signals whose names only differ in case or separators, or clash
+            // with the raw accessors of another signal, get a number appended.
+            let mut base = snake_case(&signal.name);
+            if RESERVED_METHODS.contains(&base.as_str())
+                || RESERVED_CONSTS.contains(&base.to_uppercase().as_str())
+            {
+                base.push_str("_signal");
+            }
+            let mut stem = base.clone();
+            let (method, setter, raw_method, constant) = (2..)
+                .find_map(|number| {
+                    let method = escape_keyword(&stem);
+                    let setter = format!("set_{}", stem);
+                    let raw_method = format!("{}_raw", stem);
+                    let constant = stem.to_uppercase();
+                    let mut names = vec![method.clone(), setter.clone(), constant.clone()];
+                    if raw_type.is_some() {
+                        names.push(raw_method.clone());
+                        names.push(format!("set_{}", raw_method));
+                    }
+                    if names.iter().any(|name| items.contains(name)) {
+                        stem = format!("{}_{}", base, number);
+                        return None;
+                    }
+                    items.extend(names);
+                    Some((method, setter, raw_method, constant))
+                })
+                .unwrap();
+
+            let unscaled = signal.factor == 1.0 && signal.offset == 0.0;
+            let repr = match (raw_type, signal.value_type) {
+                (Some(ty), _) if !signal.value_descriptions.is_empty() => {
+                    let enum_name = format!("{}{}", name, type_name(&stem));
+                    Repr::Enum(unique(types, enum_name), ty)
+                }
+                (Some(ty), _) if unscaled => Repr::Int(ty),
+                (None, ValueType::Float) if unscaled => Repr::F32,
+                _ => Repr::F64,
+            };
+
+            signals.push(SignalInfo {
+                signal,
+                method,
+                setter,
+                raw_method,
+                constant,
+                raw_type,
+                repr,
+                selectors: Vec::new(),
+            });
+        }
+
+        for (index, signal) in message.signals.iter().enumerate() {
+            let mut selected = signal;
+            for multiplexor in message.multiplexors_of(signal)? {
+                let position = message
+                    .signals
+                    .iter()
+                    .position(|s| std::ptr::eq(s, multiplexor))
+                    .unwrap();
+                signals[index]
+                    .selectors
+                    .push((position, selected.selecting_values()));
+                selected = multiplexor;
+            }
+        }
+
+        Ok(Self {
+            message,
+            name,
+            len,
+            fd,
+            signals,
+        })
+    }
+
+    fn generate(&self, out: &mut String, default: Option<&AttributeValue>) -> Result<(), Error> {
+        let mut data = self.message.encode_with_default(&[], default)?;
+        data.resize(self.len, 0);
+
+        let name = &self.name;
+        let message = self.message;
+        writeln!(out).unwrap();
+        write_doc(out, "", message.comment.as_deref());
+        writeln!(out, "#[derive(Debug, Clone, Copy, PartialEq, Eq)]").unwrap();
+        writeln!(out, "pub struct {} {{", name).unwrap();
+        writeln!(out, "    data: [u8; {}],", self.len).unwrap();
+        writeln!(out, "}}\n").unwrap();
+
+        writeln!(out, "impl {} {{", name).unwrap();
+        let (kind, raw_id) = match message.id {
+            Id::Standard(id) => ("Standard", id.as_raw() as u32),
+            Id::Extended(id) => ("Extended", id.as_raw()),
+        };
+        writeln!(
+            out,
+            "    pub const ID: pcbusb::Id = match pcbusb::{kind}Id::new({raw_id:#x}) {{\n        Some(id) => pcbusb::Id::{kind}(id),\n        None => unreachable!(),\n    }};"
+        )
+        .unwrap();
+        writeln!(out, "    pub const SIZE: usize = {};", message.size).unwrap();
+        for info in &self.signals {
+            let signal = info.signal;
+            writeln!(
+                out,
+                "    pub const {}: pcbusb::dbc::SignalFormat<'static> = pcbusb::dbc::SignalFormat {{",
+                info.constant
+            )
+            .unwrap();
+            writeln!(out, "        name: {:?},", signal.name).unwrap();
+            writeln!(out, "        start_bit: {},", signal.start_bit).unwrap();
+            writeln!(out, "        size: {},", signal.size).unwrap();
+            writeln!(
+                out,
+                "        byte_order: pcbusb::dbc::ByteOrder::{:?},",
+                signal.byte_order
+            )
+            .unwrap();
+            writeln!(
+                out,
+                "        value_type: pcbusb::dbc::ValueType::{:?},",
+                signal.value_type
+            )
+            .unwrap();
+            writeln!(out, "        factor: {:?},", signal.factor).unwrap();
+            writeln!(out, "        offset: {:?},", signal.offset).unwrap();
+            writeln!(out, "        min: {:?},", signal.min).unwrap();
+            writeln!(out, "        max: {:?},", signal.max).unwrap();
+            writeln!(out, "    }};").unwrap();
+        }
+
+        writeln!(
+            out,
+            "\n    /// Creates the message with the start values of its signals."
+        )
+        .unwrap();
+        writeln!(out, "    pub fn new() -> Self {{").unwrap();
+        writeln!(out, "        Self {{ data: {:?} }}", data).unwrap();
+        writeln!(out, "    }}\n").unwrap();
+        writeln!(out, "    pub fn data(&self) -> &[u8] {{").unwrap();
+        writeln!(out, "        &self.data[..Self::SIZE]").unwrap();
+        writeln!(out, "    }}").unwrap();
+
+        for info in &self.signals {
+            self.generate_accessors(out, info);
+        }
+        writeln!(out, "}}\n").unwrap();
+
+        writeln!(out, "impl Default for {} {{", name).unwrap();
+        writeln!(
+            out,
+            "    fn default() -> Self {{\n        Self::new()\n    }}"
+        )
+        .unwrap();
+        writeln!(out, "}}\n").unwrap();
+
+        let frame = if self.fd {
+            "pcbusb::FdFrame"
+        } else {
+            "pcbusb::Frame"
+        };
+        writeln!(out, "impl TryFrom<&{}> for {} {{", frame, name).unwrap();
+        writeln!(out, "    type Error = pcbusb::Error;\n").unwrap();
+        writeln!(
+            out,
+            "    fn try_from(frame: &{}) -> Result<Self, Self::Error> {{",
+            frame
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "        let data = pcbusb::dbc::codegen::message_data(frame, Self::ID, Self::SIZE)?;"
+        )
+        .unwrap();
+        writeln!(out, "        Ok(Self {{ data }})").unwrap();
+        writeln!(out, "    }}").unwrap();
+        writeln!(out, "}}\n").unwrap();
+
+        writeln!(out, "impl From<{}> for {} {{", name, frame).unwrap();
+        writeln!(out, "    fn from(message: {}) -> Self {{", name).unwrap();
+        writeln!(out, "        use pcbusb::prelude::*;").unwrap();
+        let payload = if self.fd {
+            "&message.data"
+        } else {
+            "message.data()"
+        };
+        writeln!(
+            out,
+            "        {}::new({}::ID, {}).unwrap()",
+            frame, name, payload
+        )
+        .unwrap();
+        writeln!(out, "    }}").unwrap();
+        writeln!(out, "}}").unwrap();
+
+        for info in &self.signals {
+            if let Repr::Enum(enum_name, raw_type) = &info.repr {
+                generate_enum(out, info.signal, enum_name, raw_type);
+            }
+        }
+        Ok(())
+    }
+
+    fn generate_accessors(&self, out: &mut String, info: &SignalInfo) {
+        let signal = info.signal;
+        let method = &info.method;
+        let setter = &info.setter;
+        let raw_method = &info.raw_method;
+        let format = format!("Self::{}", info.constant);
+        let value_type = info.value_type();
+
+        // Multiplexed signals are only present if their multiplexors select them.
+        let multiplexed = !info.selectors.is_empty();
+        let optional = |ty: &str| {
+            if multiplexed {
+                format!("Option<{}>", ty)
+            } else {
+                ty.to_string()
+            }
+        };
+        let condition = info
+            .selectors
+            .iter()
+            .map(|(mux, values)| {
+                let mux = &self.signals[*mux].constant;
+                match values[..] {
+                    [(low, high)] if low == high => {
+                        format!("Self::{}.extract(&self.data) == Some({})", mux, low)
+                    }
+                    _ => {
+                        let patterns: Vec<_> = values
+                            .iter()
+                            .map(|&(low, high)| {
+                                if low == high {
+                                    low.to_string()
+                                } else {
+                                    format!("{}..={}", low, high)
+                                }
+                            })
+                            .collect();
+                        format!(
+                            "matches!(Self::{}.extract(&self.data), Some({}))",
+                            mux,
+                            patterns.join(" | ")
+                        )
+                    }
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" && ");
+        let wrap = if multiplexed {
+            format!(
+                "if {} {{\n            Some(VALUE)\n        }} else {{\n            None\n        }}",
+                condition
+            )
+        } else {
+            "VALUE".to_string()
+        };
+        let getter_type = optional(value_type);
+        let select: String = info
+            .selectors
+            .iter()
+            .map(|(mux, values)| {
+                format!(
+                    "        Self::{}.insert(&mut self.data, {});\n",
+                    self.signals[*mux].constant, values[0].0
+                )
+            })
+            .collect();
+        let bits = format!("{}.extract(&self.data).unwrap_or_default()", format);
+
+        let mut doc = signal.comment.clone().unwrap_or_default();
+        if !(signal.min == 0.0 && signal.max == 0.0) {
+            if !doc.is_empty() {
+                doc.push_str("\n\n");
+            }
+            write!(doc, "Range: `[{}, {}]`", signal.min, signal.max).unwrap();
+            if !signal.unit.is_empty() {
+                write!(doc, " {}", signal.unit).unwrap();
+            }
+        }
+
+        if let Some(raw_type) = info.raw_type {
+            let raw = match signal.value_type {
+                ValueType::Signed => format!("{}.to_signed({}) as {}", format, bits, raw_type),
+                _ => format!("{} as {}", bits, raw_type),
+            };
+            writeln!(out).unwrap();
+            writeln!(
+                out,
+                "    pub fn {}(&self) -> {} {{",
+                raw_method,
+                optional(raw_type)
+            )
+            .unwrap();
+            writeln!(out, "        {}", wrap.replace("VALUE", &raw)).unwrap();
+            writeln!(out, "    }}\n").unwrap();
+            writeln!(
+                out,
+                "    pub fn set_{}(&mut self, raw: {}) {{",
+                raw_method, raw_type
+            )
+            .unwrap();
+            write!(out, "{}", select).unwrap();
+            writeln!(
+                out,
+                "        {}.insert(&mut self.data, raw as u64);",
+                format
+            )
+            .unwrap();
+            writeln!(out, "    }}").unwrap();
+        }
+
+        let value = match &info.repr {
+            Repr::Enum(name, raw_type) => {
+                let raw = match signal.value_type {
+                    ValueType::Signed => format!("{}.to_signed({}) as {}", format, bits, raw_type),
+                    _ => format!("{} as {}", bits, raw_type),
+                };
+                format!("{}::from({})", name, raw)
+            }
+            Repr::Int(ty) => match signal.value_type {
+                ValueType::Signed => format!("{}.to_signed({}) as {}", format, bits, ty),
+                _ => format!("{} as {}", bits, ty),
+            },
+            Repr::F32 => format!("{}.to_physical({}) as f32", format, bits),
+            Repr::F64 => format!("{}.to_physical({})", format, bits),
+        };
+        writeln!(out).unwrap();
+        write_doc(out, "    ", Some(&doc));
+        writeln!(out, "    pub fn {}(&self) -> {} {{", method, getter_type).unwrap();
+        writeln!(out, "        {}", wrap.replace("VALUE", &value)).unwrap();
+        writeln!(out, "    }}\n").unwrap();
+
+        match &info.repr {
+            Repr::Enum(name, raw_type) => {
+                writeln!(out, "    pub fn {}(&mut self, value: {}) {{", setter, name).unwrap();
+                writeln!(
+                    out,
+                    "        self.set_{}({}::from(value));",
+                    raw_method, raw_type
+                )
+                .unwrap();
+                writeln!(out, "    }}").unwrap();
+            }
+            repr => {
+                let physical = match repr {
+                    Repr::F64 => "value",
+                    _ => "value as f64",
+                };
+                write_doc(
+                    out,
+                    "    ",
+                    Some("Returns an error if the value is out of range."),
+                );
+                writeln!(
+                    out,
+                    "    pub fn {}(&mut self, value: {}) -> Result<(), pcbusb::Error> {{",
+                    setter, value_type
+                )
+                .unwrap();
+                writeln!(out, "        let raw = {}.to_raw({})?;", format, physical).unwrap();
+                write!(out, "{}", select).unwrap();
+                writeln!(out, "        {}.insert(&mut self.data, raw);", format).unwrap();
+                writeln!(out, "        Ok(())").unwrap();
+                writeln!(out, "    }}").unwrap();
+            }
+        }
+    }
+}
+
+fn generate_enum(out: &mut String, signal: &Signal, name: &str, raw_type: &str) {
+    let mut variants: Vec<(String, i64)> = Vec::new();
+    for (value, description) in &signal.value_descriptions {
+        let fits = match signal.value_type {
+            ValueType::Signed => {
+                let half = 1i128 << (signal.size - 1);
+                (-half..half).contains(&(*value as i128))
+            }
+            _ => *value >= 0 && (*value as i128) < (1i128 << signal.size),
+        };
+        if !fits || variants.iter().any(|(_, v)| v == value) {
+            continue;
+        }
+        let mut variant = type_name(description);
+        if RESERVED_VARIANTS.contains(&variant.as_str())
+            || variants.iter().any(|(v, _)| *v == variant)
+        {
+            write!(variant, "{}", value).unwrap();
+        }
+        variants.push((variant, *value));
+    }
+
+    writeln!(out).unwrap();
+    write_doc(
+        out,
+        "",
+        Some(&format!("Values of signal `{}`.", signal.name)),
+    );
+    writeln!(out, "#[derive(Debug, Clone, Copy, PartialEq, Eq)]").unwrap();
+    writeln!(out, "pub enum {} {{", name).unwrap();
+    for (variant, _) in &variants {
+        writeln!(out, "    {},", variant).unwrap();
+    }
+    writeln!(out, "    /// A value without description.").unwrap();
+    writeln!(out, "    Other({}),", raw_type).unwrap();
+    writeln!(out, "}}\n").unwrap();
+
+    writeln!(out, "impl From<{}> for {} {{", raw_type, name).unwrap();
+    writeln!(out, "    fn from(raw: {}) -> Self {{", raw_type).unwrap();
+    writeln!(out, "        match raw {{").unwrap();
+    for (variant, value) in &variants {
+        writeln!(out, "            {} => Self::{},", value, variant).unwrap();
+    }
+    writeln!(out, "            _ => Self::Other(raw),").unwrap();
+    writeln!(out, "        }}\n    }}\n}}\n").unwrap();
+
+    writeln!(out, "impl From<{}> for {} {{", name, raw_type).unwrap();
+    writeln!(out, "    fn from(value: {}) -> Self {{", name).unwrap();
+    writeln!(out, "        match value {{").unwrap();
+    for (variant, value) in &variants {
+        writeln!(out, "            {}::{} => {},", name, variant, value).unwrap();
+    }
+    writeln!(out, "            {}::Other(raw) => raw,", name).unwrap();
+    writeln!(out, "        }}\n    }}\n}}").unwrap();
+}
+
+fn write_doc(out: &mut String, indent: &str, doc: Option<&str>) {
+    for line in doc.unwrap_or_default().lines() {
+        if line.trim().is_empty() {
+            writeln!(out, "{}///", indent).unwrap();
+        } else {
+            writeln!(out, "{}/// {}", indent, line.trim_end()).unwrap();
+        }
+    }
+}
+
+/// Returns the smallest integer type holding the raw value of a signal.
+fn int_type(signal: &Signal) -> &'static str {
+    let signed = signal.value_type == ValueType::Signed;
+    match (signal.size, signed) {
+        (..=8, false) => "u8",
+        (..=16, false) => "u16",
+        (..=32, false) => "u32",
+        (_, false) => "u64",
+        (..=8, true) => "i8",
+        (..=16, true) => "i16",
+        (..=32, true) => "i32",
+        (_, true) => "i64",
+    }
+}
+
+/// Splits a name into words at underscores, other separators and case changes.
+fn words(name: &str) -> Vec<String> {
+    let chars: Vec<char> = name.chars().collect();
+    let mut words = Vec::new();
+    let mut word = String::new();
+    for (i, &c) in chars.iter().enumerate() {
+        if !c.is_alphanumeric() {
+            if !word.is_empty() {
+                words.push(std::mem::take(&mut word));
+            }
+            continue;
+        }
+        let previous = i.checked_sub(1).map(|i| chars[i]);
+        let next = chars.get(i + 1);
+        let boundary = c.is_uppercase()
+            && previous.is_some_and(|p| {
+                p.is_lowercase()
+                    || p.is_ascii_digit()
+                    || (p.is_uppercase() && next.is_some_and(|n| n.is_lowercase()))
+            });
+        if boundary && !word.is_empty() {
+            words.push(std::mem::take(&mut word));
+        }
+        word.push(c);
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+fn type_name(name: &str) -> String {
+    let mut result: String = words(name)
+        .iter()
+        .map(|word| {
+            let mut chars = word.chars();
+            let first = chars.next().unwrap().to_ascii_uppercase();
+            std::iter::once(first)
+                .chain(chars.flat_map(char::to_lowercase))
+                .collect::<String>()
+        })
+        .collect();
+    if result.is_empty() || result.starts_with(|c: char| c.is_ascii_digit()) {
+        result.insert_str(0, "Value");
+    }
+    result
+}
+
+fn snake_case(name: &str) -> String {
+    let mut result = words(name)
+        .iter()
+        .map(|word| word.to_lowercase())
+        .collect::<Vec<_>>()
+        .join("_");
+    if result.is_empty() || result.starts_with(|c: char| c.is_ascii_digit()) {
+        result.insert_str(0, "signal_");
+    }
+    result
+}
+
+/// Appends an underscore to keywords, raw identifiers can't be used as some
+/// keywords like `self` are not allowed as such.
+fn escape_keyword(name: &str) -> String {
+    if KEYWORDS.contains(&name) {
+        format!("{}_", name)
+    } else {
+        name.to_string()
+    }
+}
+
+/// Appends a number to a type name if it is already taken or a keyword and
+/// marks the result as taken.
+fn unique(taken: &mut Vec<String>, name: String) -> String {
+    let mut unique = name.clone();
+    let mut number = 2;
+    while unique == "Self" || taken.contains(&unique) {
+        unique = format!("{}{}", name, number);
+        number += 1;
+    }
+    taken.push(unique.clone());
+    unique
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn functions(code: &str) -> Vec<&str> {
+        code.lines()
+            .filter_map(|line| line.trim().strip_prefix("pub fn "))
+            .map(|line| &line[..line.find('(').unwrap()])
+            .collect()
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(type_name("ENGINE_status"), "EngineStatus");
+        assert_eq!(type_name("ABSData2"), "AbsData2");
+        assert_eq!(type_name("3rd gear"), "Value3rdGear");
+        assert_eq!(snake_case("EngineRPM"), "engine_rpm");
+        assert_eq!(snake_case("_1"), "signal_1");
+        assert_eq!(escape_keyword("type"), "type_");
+        assert_eq!(escape_keyword("speed"), "speed");
+    }
+
+    #[test]
+    fn generate() {
+        let db: Database = r#"
+BO_ 100 EngineStatus: 8 ECU
+ SG_ Rpm : 0|16@1+ (0.25,0) [0|16383.75] "rpm" ECU
+ SG_ Gear : 16|4@1+ (1,0) [0|0] "" ECU
+ SG_ Count : 20|4@1- (1,0) [0|0] "" ECU
+CM_ SG_ 100 Rpm "Engine speed.";
+BA_DEF_ SG_ "GenSigStartValue" INT 0 65535;
+BA_ "GenSigStartValue" SG_ 100 Rpm 4;
+VAL_ 100 Gear 0 "Neutral" 3 "Third" ;
+"#
+        .parse()
+        .unwrap();
+        let code = super::generate(&db).unwrap();
+        assert!(code.contains("pub struct EngineStatus {\n    data: [u8; 8],\n}"));
+        assert!(code.contains("pcbusb::StandardId::new(0x64)"));
+        assert!(code.contains("Self { data: [4, 0, 0, 0, 0, 0, 0, 0] }"));
+        assert!(
+            code.contains("    /// Engine speed.\n    ///\n    /// Range: `[0, 16383.75]` rpm\n")
+        );
+        assert!(code.contains("pub enum EngineStatusGear {\n    Neutral,\n    Third,\n"));
+        assert!(code.contains("pub fn count(&self) -> i8 {"));
+        assert_eq!(
+            functions(&code),
+            [
+                "new",
+                "data",
+                "rpm_raw",
+                "set_rpm_raw",
+                "rpm",
+                "set_rpm",
+                "gear_raw",
+                "set_gear_raw",
+                "gear",
+                "set_gear",
+                "count_raw",
+                "set_count_raw",
+                "count",
+                "set_count",
+            ]
+        );
+    }
+
+    #[test]
+    fn colliding_names() {
+        let db: Database = r#"
+BO_ 1 Status: 8 ECU
+ SG_ Speed : 0|8@1+ (1,0) [0|0] "" ECU
+ SG_ SpeedRaw : 8|8@1+ (1,0) [0|0] "" ECU
+ SG_ speed : 16|8@1+ (1,0) [0|0] "" ECU
+ SG_ New : 24|8@1+ (1,0) [0|0] "" ECU
+ SG_ Size : 32|8@1+ (1,0) [0|0] "" ECU
+ SG_ Type : 40|4@1+ (1,0) [0|0] "" ECU
+ SG_ Mode : 44|4@1+ (1,0) [0|0] "" ECU
+BO_ 2 StatusMode: 8 ECU
+VAL_ 1 Mode 0 "Self" 1 "Other" 2 "On" 3 "on" ;
+"#
+        .parse()
+        .unwrap();
+        let code = super::generate(&db).unwrap();
+        let functions = functions(&code);
+        for function in [
+            "speed",
+            "speed_raw",
+            "speed_raw_2",
+            "set_speed_raw_2",
+            "speed_2",
+            "set_speed_2_raw",
+            "new_signal",
+            "size_signal",
+            "type_",
+            "set_type",
+            "mode",
+        ] {
+            assert!(functions.contains(&function), "{}", function);
+        }
+        // Both messages have a constructor and a payload getter.
+        let mut unique = functions.clone();
+        unique.sort();
+        unique.dedup();
+        assert_eq!(unique.len(), functions.len() - 2);
+        for constant in [
+            "SPEED:",
+            "SPEED_RAW_2:",
+            "SPEED_2:",
+            "NEW_SIGNAL:",
+            "SIZE_SIGNAL:",
+        ] {
+            assert!(
+                code.contains(&format!("pub const {}", constant)),
+                "{}",
+                constant
+            );
+        }
+
+        // The enum of the signal takes the name of the message.
+        assert!(code.contains("pub struct StatusMode2 {"));
+        assert!(
+            code.contains("pub enum StatusMode {\n    Self0,\n    Other1,\n    On,\n    On3,\n")
+        );
+    }
+}
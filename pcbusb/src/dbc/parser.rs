@@ -0,0 +1,728 @@
+use std::collections::HashMap;
+
+use embedded_can::{ExtendedId, Id, StandardId};
+
+use super::{
+    AttributeDefinition, AttributeKind, AttributeObject, AttributeValue, ByteOrder, Database,
+    Message, MultiplexCondition, MultiplexIndicator, Node, Signal, ValueType,
+};
+use crate::Error;
+
+/// Flag of extended identifiers in DBC message IDs.
+const EXTENDED_ID_FLAG: u32 = 0x8000_0000;
+
+/// Pseudo message holding signals that are not assigned to any message.
+const INDEPENDENT_SIGNALS_MESSAGE: &str = "VECTOR__INDEPENDENT_SIG_MSG";
+
+/// Keywords that start a new statement.
+const KEYWORDS: &[&str] = &[
+    "VERSION",
+    "NS_",
+    "BS_",
+    "BU_",
+    "VAL_TABLE_",
+    "BO_",
+    "SG_",
+    "BO_TX_BU_",
+    "EV_",
+    "ENVVAR_DATA_",
+    "SGTYPE_",
+    "CM_",
+    "BA_DEF_",
+    "BA_DEF_DEF_",
+    "BA_",
+    "BA_DEF_REL_",
+    "BA_DEF_DEF_REL_",
+    "BA_REL_",
+    "VAL_",
+    "SIG_GROUP_",
+    "SIG_VALTYPE_",
+    "SIG_TYPE_REF_",
+    "SG_MUL_VAL_",
+    "CAT_DEF_",
+    "CAT_",
+    "FILTER",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    /// A number, with its original text to tell integers from floats.
+    Number(String),
+    Str(String),
+    Punct(char),
+}
+
+struct Lexer<'a> {
+    chars: std::iter::Peekable<std::str::CharIndices<'a>>,
+    input: &'a str,
+    line: usize,
+}
+
+impl<'a> Lexer<'a> {
+    fn new(input: &'a str) -> Self {
+        Self {
+            chars: input.char_indices().peekable(),
+            input,
+            line: 1,
+        }
+    }
+
+    fn tokenize(mut self) -> Result<Vec<(Token, usize)>, Error> {
+        let mut tokens = Vec::new();
+        while let Some(&(start, c)) = self.chars.peek() {
+            if c == '\n' {
+                self.line += 1;
+                self.chars.next();
+            } else if c.is_whitespace() {
+                self.chars.next();
+            } else if c == '/' && self.input[start..].starts_with("//") {
+                while self.chars.next_if(|&(_, c)| c != '\n').is_some() {}
+            } else if c == '"' {
+                let line = self.line;
+                tokens.push((Token::Str(self.string()?), line));
+            } else if c.is_ascii_digit()
+                || ((c == '-' || c == '+' || c == '.') && self.number_follows(start))
+            {
+                tokens.push((Token::Number(self.number(start)), self.line));
+            } else if c.is_alphanumeric() || c == '_' {
+                let mut end = start;
+                while let Some((i, c)) = self
+                    .chars
+                    .next_if(|&(_, c)| c.is_alphanumeric() || c == '_')
+                {
+                    end = i + c.len_utf8();
+                }
+                tokens.push((Token::Ident(self.input[start..end].to_string()), self.line));
+            } else {
+                self.chars.next();
+                tokens.push((Token::Punct(c), self.line));
+            }
+        }
+        Ok(tokens)
+    }
+
+    fn number_follows(&self, start: usize) -> bool {
+        let rest = &self.input[start + 1..];
+        rest.starts_with(|c: char| c.is_ascii_digit())
+            || (rest.starts_with('.') && rest[1..].starts_with(|c: char| c.is_ascii_digit()))
+    }
+
+    fn number(&mut self, start: usize) -> String {
+        let mut end = start;
+        let mut previous = ' ';
+        // The sign is only allowed at the start and after the exponent marker.
+        while let Some((i, c)) = self.chars.next_if(|&(i, c)| {
+            c.is_ascii_digit()
+                || c == '.'
+                || c == 'e'
+                || c == 'E'
+                || ((c == '-' || c == '+') && (i == start || previous == 'e' || previous == 'E'))
+        }) {
+            previous = c;
+            end = i + c.len_utf8();
+        }
+        self.input[start..end].to_string()
+    }
+
+    fn string(&mut self) -> Result<String, Error> {
+        self.chars.next();
+        let mut value = String::new();
+        loop {
+            match self.chars.next() {
+                Some((_, '"')) => return Ok(value),
+                Some((_, '\\')) => match self.chars.next() {
+                    Some((_, c)) => value.push(c),
+                    None => break,
+                },
+                Some((_, c)) => {
+                    if c == '\n' {
+                        self.line += 1;
+                    }
+                    value.push(c);
+                }
+                None => break,
+            }
+        }
+        Err(Error(format!(
+            "dbc line {}: unterminated string",
+            self.line
+        )))
+    }
+}
+
+/// Object an attribute value is assigned to.
+enum AttributeTarget {
+    Database,
+    Node(String),
+    Message(u32),
+    Signal(u32, String),
+    Ignored,
+}
+
+pub(super) struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+    db: Database,
+    /// Message index by raw DBC message ID.
+    messages: HashMap<u32, usize>,
+}
+
+impl Parser {
+    pub(super) fn parse(input: &str) -> Result<Database, Error> {
+        let mut parser = Parser {
+            tokens: Lexer::new(input).tokenize()?,
+            pos: 0,
+            db: Database::default(),
+            messages: HashMap::new(),
+        };
+        while parser.pos < parser.tokens.len() {
+            parser.statement()?;
+        }
+
+        let mut db = parser.db;
+        db.messages
+            .retain(|m| m.name != INDEPENDENT_SIGNALS_MESSAGE);
+        for message in &db.messages {
+            for signal in &message.signals {
+                if signal.multiplex_condition.is_some() {
+                    message.multiplexors_of(signal)?;
+                }
+            }
+        }
+        db.index_messages();
+        Ok(db)
+    }
+
+    fn error(&self, msg: &str) -> Error {
+        let line = self
+            .tokens
+            .get(self.pos)
+            .or(self.tokens.last())
+            .map_or(0, |(_, line)| *line);
+        Error(format!("dbc line {}: {}", line, msg))
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(token, _)| token)
+    }
+
+    fn next(&mut self) -> Result<Token, Error> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .map(|(token, _)| token.clone())
+            .ok_or_else(|| self.error("unexpected end of file"))?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn at_keyword(&self) -> bool {
+        matches!(self.peek(), Some(Token::Ident(ident)) if KEYWORDS.contains(&ident.as_str()))
+    }
+
+    fn eat_punct(&mut self, c: char) -> bool {
+        if self.peek() == Some(&Token::Punct(c)) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect_punct(&mut self, c: char) -> Result<(), Error> {
+        if self.eat_punct(c) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{}'", c)))
+        }
+    }
+
+    fn ident(&mut self) -> Result<String, Error> {
+        match self.next()? {
+            Token::Ident(ident) => Ok(ident),
+            _ => {
+                self.pos -= 1;
+                Err(self.error("expected identifier"))
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, Error> {
+        match self.next()? {
+            Token::Str(s) => Ok(s),
+            _ => {
+                self.pos -= 1;
+                Err(self.error("expected string"))
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<String, Error> {
+        match self.next()? {
+            Token::Number(n) => Ok(n),
+            _ => {
+                self.pos -= 1;
+                Err(self.error("expected number"))
+            }
+        }
+    }
+
+    fn float(&mut self) -> Result<f64, Error> {
+        let n = self.number()?;
+        n.parse().map_err(|_| self.error("invalid number"))
+    }
+
+    fn int(&mut self) -> Result<i64, Error> {
+        let n = self.number()?;
+        n.parse::<i64>()
+            .or_else(|_| n.parse::<f64>().map(|f| f as i64))
+            .map_err(|_| self.error("invalid integer"))
+    }
+
+    fn uint(&mut self) -> Result<u64, Error> {
+        let n = self.number()?;
+        n.parse()
+            .map_err(|_| self.error("invalid unsigned integer"))
+    }
+
+    fn skip_statement(&mut self) -> Result<(), Error> {
+        while self.next()? != Token::Punct(';') {}
+        Ok(())
+    }
+
+    fn statement(&mut self) -> Result<(), Error> {
+        let keyword = self.ident()?;
+        match keyword.as_str() {
+            "VERSION" => self.db.version = self.string()?,
+            "NS_" => {
+                self.expect_punct(':')?;
+                while self.peek().is_some() && self.peek() != Some(&Token::Ident("BS_".into())) {
+                    self.next()?;
+                }
+            }
+            "BS_" => {
+                self.expect_punct(':')?;
+                if matches!(self.peek(), Some(Token::Number(_))) {
+                    self.uint()?;
+                    self.expect_punct(':')?;
+                    self.uint()?;
+                    self.expect_punct(',')?;
+                    self.uint()?;
+                }
+            }
+            "BU_" => {
+                self.expect_punct(':')?;
+                while matches!(self.peek(), Some(Token::Ident(_))) && !self.at_keyword() {
+                    let name = self.ident()?;
+                    self.db.nodes.push(Node {
+                        name,
+                        ..Default::default()
+                    });
+                }
+            }
+            "VAL_TABLE_" => {
+                let name = self.ident()?;
+                let values = self.value_descriptions()?;
+                self.db.value_tables.insert(name, values);
+            }
+            "BO_" => self.message()?,
+            "SG_" => return Err(self.error("signal outside of a message")),
+            "BO_TX_BU_" => {
+                let id = self.uint()? as u32;
+                self.expect_punct(':')?;
+                let mut transmitters = Vec::new();
+                while !self.eat_punct(';') {
+                    if !self.eat_punct(',') {
+                        transmitters.push(self.ident()?);
+                    }
+                }
+                if let Some(message) = self.message_mut(id) {
+                    for transmitter in transmitters {
+                        if !message.transmitters.contains(&transmitter) {
+                            message.transmitters.push(transmitter);
+                        }
+                    }
+                }
+            }
+            "CM_" => self.comment()?,
+            "BA_DEF_" => self.attribute_definition()?,
+            "BA_DEF_DEF_" => {
+                let name = self.string()?;
+                let value = self.attribute_value()?;
+                self.expect_punct(';')?;
+                if let Some(definition) = self
+                    .db
+                    .attribute_definitions
+                    .iter_mut()
+                    .find(|d| d.name == name)
+                {
+                    definition.default = Some(value);
+                }
+            }
+            "BA_" => self.attribute()?,
+            "VAL_" => {
+                if !matches!(self.peek(), Some(Token::Number(_))) {
+                    // Value descriptions of environment variables.
+                    return self.skip_statement();
+                }
+                let id = self.uint()? as u32;
+                let signal = self.ident()?;
+                let values = self.value_descriptions()?;
+                if let Some(signal) = self.signal_mut(id, &signal) {
+                    signal.value_descriptions = values;
+                }
+            }
+            "SIG_VALTYPE_" => {
+                let id = self.uint()? as u32;
+                let signal = self.ident()?;
+                self.eat_punct(':');
+                let value_type = match self.uint()? {
+                    1 => ValueType::Float,
+                    2 => ValueType::Double,
+                    _ => return Err(self.error("invalid signal value type")),
+                };
+                self.expect_punct(';')?;
+                if let Some(signal) = self.signal_mut(id, &signal) {
+                    signal.value_type = value_type;
+                }
+            }
+            "SG_MUL_VAL_" => {
+                let id = self.uint()? as u32;
+                let signal = self.ident()?;
+                let multiplexor = self.ident()?;
+                let mut ranges = Vec::new();
+                while !self.eat_punct(';') {
+                    if !self.eat_punct(',') {
+                        ranges.push(self.range()?);
+                    }
+                }
+                if ranges.is_empty() {
+                    return Err(self.error("missing multiplexor values"));
+                }
+                if let Some(signal) = self.signal_mut(id, &signal) {
+                    signal.multiplex_condition = Some(MultiplexCondition {
+                        multiplexor,
+                        ranges,
+                    });
+                }
+            }
+            _ => self.skip_statement()?,
+        }
+        Ok(())
+    }
+
+    /// Parses a value range like `3-5`, which the lexer splits into the
+    /// numbers `3` and `-5`.
+    fn range(&mut self) -> Result<(u64, u64), Error> {
+        let low = self.uint()?;
+        self.eat_punct('-');
+        let high = self.number()?;
+        let high = high
+            .trim_start_matches('-')
+            .parse()
+            .map_err(|_| self.error("invalid multiplexor value range"))?;
+        if high < low {
+            return Err(self.error("invalid multiplexor value range"));
+        }
+        Ok((low, high))
+    }
+
+    fn value_descriptions(&mut self) -> Result<Vec<(i64, String)>, Error> {
+        let mut values = Vec::new();
+        while !self.eat_punct(';') {
+            let value = self.int()?;
+            let description = self.string()?;
+            values.push((value, description));
+        }
+        Ok(values)
+    }
+
+    fn message(&mut self) -> Result<(), Error> {
+        let raw_id = self.uint()? as u32;
+        let name = self.ident()?;
+        self.expect_punct(':')?;
+        let size = self.uint()? as usize;
+        let transmitter = self.ident()?;
+
+        let id = if name == INDEPENDENT_SIGNALS_MESSAGE {
+            StandardId::ZERO.into()
+        } else if raw_id & EXTENDED_ID_FLAG != 0 {
+            ExtendedId::new(raw_id & !EXTENDED_ID_FLAG)
+                .ok_or_else(|| self.error("invalid extended identifier"))?
+                .into()
+        } else {
+            u16::try_from(raw_id)
+                .ok()
+                .and_then(StandardId::new)
+                .map(Id::from)
+                .or_else(|| ExtendedId::new(raw_id).map(Id::from))
+                .ok_or_else(|| self.error("invalid identifier"))?
+        };
+
+        let mut signals = Vec::new();
+        while self.peek() == Some(&Token::Ident("SG_".into())) {
+            self.pos += 1;
+            signals.push(self.signal()?);
+        }
+
+        self.messages.insert(raw_id, self.db.messages.len());
+        self.db.messages.push(Message {
+            id,
+            name,
+            size,
+            transmitters: if transmitter == "Vector__XXX" {
+                Vec::new()
+            } else {
+                vec![transmitter]
+            },
+            signals,
+            comment: None,
+            attributes: HashMap::new(),
+        });
+        Ok(())
+    }
+
+    fn signal(&mut self) -> Result<Signal, Error> {
+        let name = self.ident()?;
+        let multiplex = match self.peek() {
+            Some(Token::Ident(indicator)) => {
+                let indicator = indicator.clone();
+                self.pos += 1;
+                parse_multiplex_indicator(&indicator)
+                    .ok_or_else(|| self.error("invalid multiplexer indicator"))?
+            }
+            _ => MultiplexIndicator::Plain,
+        };
+        self.expect_punct(':')?;
+
+        let start_bit = self.uint()? as usize;
+        self.expect_punct('|')?;
+        let size = self.uint()? as usize;
+        self.expect_punct('@')?;
+        let byte_order = match self.uint()? {
+            0 => ByteOrder::BigEndian,
+            1 => ByteOrder::LittleEndian,
+            _ => return Err(self.error("invalid byte order")),
+        };
+        let value_type = if self.eat_punct('-') {
+            ValueType::Signed
+        } else {
+            self.expect_punct('+')?;
+            ValueType::Unsigned
+        };
+        if size == 0 || size > 64 {
+            return Err(self.error("invalid signal size"));
+        }
+
+        self.expect_punct('(')?;
+        let factor = self.float()?;
+        self.expect_punct(',')?;
+        let offset = self.float()?;
+        self.expect_punct(')')?;
+        self.expect_punct('[')?;
+        let min = self.float()?;
+        self.expect_punct('|')?;
+        let max = self.float()?;
+        self.expect_punct(']')?;
+        let unit = self.string()?;
+
+        let mut receivers = Vec::new();
+        while matches!(self.peek(), Some(Token::Ident(_))) && !self.at_keyword() {
+            let receiver = self.ident()?;
+            if receiver != "Vector__XXX" {
+                receivers.push(receiver);
+            }
+            self.eat_punct(',');
+        }
+
+        Ok(Signal {
+            name,
+            start_bit,
+            size,
+            byte_order,
+            value_type,
+            factor,
+            offset,
+            min,
+            max,
+            unit,
+            receivers,
+            multiplex,
+            multiplex_condition: None,
+            value_descriptions: Vec::new(),
+            comment: None,
+            attributes: HashMap::new(),
+        })
+    }
+
+    fn comment(&mut self) -> Result<(), Error> {
+        match self.peek() {
+            Some(Token::Str(_)) => self.db.comment = Some(self.string()?),
+            Some(Token::Ident(object)) => match object.as_str() {
+                "BU_" => {
+                    self.pos += 1;
+                    let name = self.ident()?;
+                    let comment = self.string()?;
+                    if let Some(node) = self.db.nodes.iter_mut().find(|n| n.name == name) {
+                        node.comment = Some(comment);
+                    }
+                }
+                "BO_" => {
+                    self.pos += 1;
+                    let id = self.uint()? as u32;
+                    let comment = self.string()?;
+                    if let Some(message) = self.message_mut(id) {
+                        message.comment = Some(comment);
+                    }
+                }
+                "SG_" => {
+                    self.pos += 1;
+                    let id = self.uint()? as u32;
+                    let name = self.ident()?;
+                    let comment = self.string()?;
+                    if let Some(signal) = self.signal_mut(id, &name) {
+                        signal.comment = Some(comment);
+                    }
+                }
+                _ => return self.skip_statement(),
+            },
+            _ => return Err(self.error("invalid comment")),
+        }
+        self.expect_punct(';')
+    }
+
+    fn attribute_object(&mut self) -> AttributeObject {
+        let object = match self.peek() {
+            Some(Token::Ident(object)) => match object.as_str() {
+                "BU_" => AttributeObject::Node,
+                "BO_" => AttributeObject::Message,
+                "SG_" => AttributeObject::Signal,
+                "EV_" => AttributeObject::EnvironmentVariable,
+                _ => return AttributeObject::Database,
+            },
+            _ => return AttributeObject::Database,
+        };
+        self.pos += 1;
+        object
+    }
+
+    fn attribute_definition(&mut self) -> Result<(), Error> {
+        let object = self.attribute_object();
+        let name = self.string()?;
+        let kind = match self.ident()?.as_str() {
+            "INT" => AttributeKind::Int {
+                min: self.int()?,
+                max: self.int()?,
+            },
+            "HEX" => AttributeKind::Hex {
+                min: self.int()?,
+                max: self.int()?,
+            },
+            "FLOAT" => AttributeKind::Float {
+                min: self.float()?,
+                max: self.float()?,
+            },
+            "STRING" => AttributeKind::String,
+            "ENUM" => {
+                let mut values = Vec::new();
+                while !matches!(self.peek(), Some(Token::Punct(';'))) {
+                    if !self.eat_punct(',') {
+                        values.push(self.string()?);
+                    }
+                }
+                AttributeKind::Enum(values)
+            }
+            _ => return Err(self.error("invalid attribute type")),
+        };
+        self.expect_punct(';')?;
+
+        self.db.attribute_definitions.push(AttributeDefinition {
+            name,
+            object,
+            kind,
+            default: None,
+        });
+        Ok(())
+    }
+
+    fn attribute_value(&mut self) -> Result<AttributeValue, Error> {
+        match self.next()? {
+            Token::Str(s) => Ok(AttributeValue::String(s)),
+            Token::Number(n) => match n.parse::<i64>() {
+                Ok(i) => Ok(AttributeValue::Int(i)),
+                Err(_) => n
+                    .parse()
+                    .map(AttributeValue::Float)
+                    .map_err(|_| self.error("invalid attribute value")),
+            },
+            _ => {
+                self.pos -= 1;
+                Err(self.error("invalid attribute value"))
+            }
+        }
+    }
+
+    fn attribute(&mut self) -> Result<(), Error> {
+        let name = self.string()?;
+        let target = match self.attribute_object() {
+            AttributeObject::Database => AttributeTarget::Database,
+            AttributeObject::Node => AttributeTarget::Node(self.ident()?),
+            AttributeObject::Message => AttributeTarget::Message(self.uint()? as u32),
+            AttributeObject::Signal => {
+                let id = self.uint()? as u32;
+                AttributeTarget::Signal(id, self.ident()?)
+            }
+            AttributeObject::EnvironmentVariable => {
+                self.ident()?;
+                AttributeTarget::Ignored
+            }
+        };
+        let value = self.attribute_value()?;
+        self.expect_punct(';')?;
+
+        let attributes = match target {
+            AttributeTarget::Database => Some(&mut self.db.attributes),
+            AttributeTarget::Node(node) => self
+                .db
+                .nodes
+                .iter_mut()
+                .find(|n| n.name == node)
+                .map(|n| &mut n.attributes),
+            AttributeTarget::Message(id) => self.message_mut(id).map(|m| &mut m.attributes),
+            AttributeTarget::Signal(id, signal) => {
+                self.signal_mut(id, &signal).map(|s| &mut s.attributes)
+            }
+            AttributeTarget::Ignored => None,
+        };
+        if let Some(attributes) = attributes {
+            attributes.insert(name, value);
+        }
+        Ok(())
+    }
+
+    fn message_mut(&mut self, id: u32) -> Option<&mut Message> {
+        let index = *self.messages.get(&id)?;
+        self.db.messages.get_mut(index)
+    }
+
+    fn signal_mut(&mut self, id: u32, name: &str) -> Option<&mut Signal> {
+        self.message_mut(id)?
+            .signals
+            .iter_mut()
+            .find(|s| s.name == name)
+    }
+}
+
+fn parse_multiplex_indicator(s: &str) -> Option<MultiplexIndicator> {
+    if s == "M" {
+        return Some(MultiplexIndicator::Multiplexor);
+    }
+    let value = s.strip_prefix('m')?;
+    match value.strip_suffix('M') {
+        Some(value) => Some(MultiplexIndicator::MultiplexedMultiplexor(
+            value.parse().ok()?,
+        )),
+        None => Some(MultiplexIndicator::Multiplexed(value.parse().ok()?)),
+    }
+}
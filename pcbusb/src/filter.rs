@@ -1,4 +1,6 @@
-use embedded_can::Id;
+use embedded_can::{Frame as _, Id};
+
+use crate::Frame;
 
 pub struct Filter {
     pub accept_all: bool,
@@ -40,3 +42,106 @@ impl Filter {
         self
     }
 }
+
+type DataPredicate<'a> = Box<dyn Fn(&[u8]) -> bool + 'a>;
+
+/// Selects the response frame in [`Interface::request`](crate::Interface::request)
+/// by identifier and, optionally, by its data.
+pub struct ResponseFilter<'a> {
+    id: Id,
+    mask: u32,
+    predicate: Option<DataPredicate<'a>>,
+}
+
+impl<'a> ResponseFilter<'a> {
+    pub fn new(id: impl Into<Id>) -> Self {
+        Self {
+            id: id.into(),
+            mask: u32::MAX,
+            predicate: None,
+        }
+    }
+
+    /// Only compares the identifier bits set in `mask`, to accept a range of
+    /// identifiers of the same kind.
+    pub fn with_mask(mut self, mask: u32) -> Self {
+        self.mask = mask;
+        self
+    }
+
+    /// Additionally requires the frame data to satisfy `predicate`.
+    pub fn with_data(mut self, predicate: impl Fn(&[u8]) -> bool + 'a) -> Self {
+        self.predicate = Some(Box::new(predicate));
+        self
+    }
+
+    pub fn matches(&self, frame: &Frame) -> bool {
+        let id_matches = match (frame.id(), self.id) {
+            (Id::Standard(a), Id::Standard(b)) => (a.as_raw() ^ b.as_raw()) as u32 & self.mask == 0,
+            (Id::Extended(a), Id::Extended(b)) => (a.as_raw() ^ b.as_raw()) & self.mask == 0,
+            _ => false,
+        };
+        id_matches && self.predicate.as_ref().is_none_or(|p| p(frame.data()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_can::{ExtendedId, StandardId};
+
+    use super::*;
+
+    fn standard(id: u16, data: &[u8]) -> Frame {
+        Frame::new(StandardId::new(id).unwrap(), data).unwrap()
+    }
+
+    fn extended(id: u32, data: &[u8]) -> Frame {
+        Frame::new(ExtendedId::new(id).unwrap(), data).unwrap()
+    }
+
+    #[test]
+    fn identifier() {
+        let filter = ResponseFilter::new(StandardId::new(0x7E8).unwrap());
+        assert!(filter.matches(&standard(0x7E8, &[])));
+        assert!(!filter.matches(&standard(0x7E9, &[])));
+        // Standard and extended identifiers with the same value differ.
+        assert!(!filter.matches(&extended(0x7E8, &[])));
+
+        let filter = ResponseFilter::new(ExtendedId::new(0x18DA_F110).unwrap());
+        assert!(filter.matches(&extended(0x18DA_F110, &[])));
+        assert!(!filter.matches(&extended(0x18DA_F111, &[])));
+        assert!(!filter.matches(&standard(0x110, &[])));
+    }
+
+    #[test]
+    fn mask() {
+        // Any standard identifier from 0x7E8 to 0x7EF.
+        let filter = ResponseFilter::new(StandardId::new(0x7E8).unwrap()).with_mask(0x7F8);
+        assert!(filter.matches(&standard(0x7E8, &[])));
+        assert!(filter.matches(&standard(0x7EF, &[])));
+        assert!(!filter.matches(&standard(0x7E0, &[])));
+        assert!(!filter.matches(&standard(0x7F0, &[])));
+        assert!(!filter.matches(&extended(0x7E9, &[])));
+
+        // Any priority of a J1939 identifier.
+        let filter =
+            ResponseFilter::new(ExtendedId::new(0x18EA_FF00).unwrap()).with_mask(0x03FF_FFFF);
+        assert!(filter.matches(&extended(0x0CEA_FF00, &[])));
+        assert!(!filter.matches(&extended(0x18EA_FF01, &[])));
+
+        let filter = ResponseFilter::new(StandardId::new(0).unwrap()).with_mask(0);
+        assert!(filter.matches(&standard(0x7FF, &[])));
+        assert!(!filter.matches(&extended(0, &[])));
+    }
+
+    #[test]
+    fn data() {
+        let service = 0x62;
+        let filter = ResponseFilter::new(StandardId::new(0x7E8).unwrap())
+            .with_data(|data| data.get(1) == Some(&service));
+        assert!(filter.matches(&standard(0x7E8, &[0x05, 0x62, 0xF1, 0x90])));
+        assert!(!filter.matches(&standard(0x7E8, &[0x03, 0x7F, 0x22, 0x78])));
+        assert!(!filter.matches(&standard(0x7E8, &[])));
+        assert!(!filter.matches(&standard(0x7E0, &[0x05, 0x62, 0xF1, 0x90])));
+    }
+}
@@ -7,7 +7,7 @@ use crate::sys::PCAN_MESSAGE_RTR;
 use crate::sys::PCAN_MESSAGE_STANDARD;
 use crate::sys::TPCANMsg;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Frame(pub(crate) TPCANMsg);
 
 impl embedded_can::Frame for Frame {
@@ -35,7 +35,7 @@ impl embedded_can::Frame for Frame {
     }
 
     fn new_remote(id: impl Into<Id>, dlc: usize) -> Option<Frame> {
-        if dlc >= 8 {
+        if dlc > 8 {
             return None;
         }
 
@@ -69,3 +69,97 @@ impl embedded_can::Frame for Frame {
         &self.0.DATA[0..self.0.LEN as usize]
     }
 }
+
+/// Payload lengths that can be encoded by the CAN FD data length code.
+pub(crate) const FD_LENGTHS: [usize; 16] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 12, 16, 20, 24, 32, 48, 64];
+
+/// Converts a CAN FD data length code into the payload length.
+pub(crate) fn fd_dlc_to_len(dlc: u8) -> usize {
+    FD_LENGTHS[(dlc & 0x0F) as usize]
+}
+
+/// Converts a CAN FD payload length into its data length code, if it is valid.
+pub(crate) fn fd_len_to_dlc(len: usize) -> Option<u8> {
+    FD_LENGTHS
+        .iter()
+        .position(|&l| l == len)
+        .map(|dlc| dlc as u8)
+}
+
+/// A CAN FD frame with up to 64 data bytes.
+///
+/// The interface itself only transmits classic frames, this type is used to
+/// represent FD traffic in recorded traces.
+#[derive(Debug, Clone)]
+pub struct FdFrame {
+    id: Id,
+    data: [u8; 64],
+    len: u8,
+    bitrate_switch: bool,
+    error_state_indicator: bool,
+}
+
+impl FdFrame {
+    /// Sets the bit rate switch flag, i.e. whether the data phase uses the data bit rate.
+    pub fn with_bitrate_switch(mut self, brs: bool) -> Self {
+        self.bitrate_switch = brs;
+        self
+    }
+
+    /// Sets the error state indicator flag of the transmitting node.
+    pub fn with_error_state_indicator(mut self, esi: bool) -> Self {
+        self.error_state_indicator = esi;
+        self
+    }
+
+    pub fn bitrate_switch(&self) -> bool {
+        self.bitrate_switch
+    }
+
+    pub fn error_state_indicator(&self) -> bool {
+        self.error_state_indicator
+    }
+}
+
+impl embedded_can::Frame for FdFrame {
+    fn new(id: impl Into<Id>, data: &[u8]) -> Option<FdFrame> {
+        fd_len_to_dlc(data.len())?;
+
+        let mut frame = FdFrame {
+            id: id.into(),
+            data: [0; 64],
+            len: data.len() as u8,
+            bitrate_switch: false,
+            error_state_indicator: false,
+        };
+        frame.data[0..data.len()].copy_from_slice(data);
+        Some(frame)
+    }
+
+    /// CAN FD does not support remote frames.
+    fn new_remote(_id: impl Into<Id>, _dlc: usize) -> Option<FdFrame> {
+        None
+    }
+
+    fn is_extended(&self) -> bool {
+        matches!(self.id, Id::Extended(_))
+    }
+
+    fn is_remote_frame(&self) -> bool {
+        false
+    }
+
+    fn id(&self) -> Id {
+        self.id
+    }
+
+    /// Returns the data length code, which differs from the payload length for
+    /// payloads longer than 8 bytes.
+    fn dlc(&self) -> usize {
+        fd_len_to_dlc(self.len as usize).unwrap() as usize
+    }
+
+    fn data(&self) -> &[u8] {
+        &self.data[0..self.len as usize]
+    }
+}
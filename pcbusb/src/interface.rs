@@ -1,20 +1,25 @@
 use crate::sys::{
     CAN_GetValue, CAN_Initialize, CAN_Read, CAN_SetValue, CAN_Uninitialize, CAN_Write,
     PCAN_ACCEPTANCE_FILTER_11BIT, PCAN_ACCEPTANCE_FILTER_29BIT, PCAN_ERROR_OK,
-    PCAN_ERROR_QRCVEMPTY, PCAN_FILTER_CLOSE, PCAN_FILTER_CUSTOM, PCAN_FILTER_OPEN,
-    PCAN_MESSAGE_FILTER, PCAN_USBBUS1, TPCANMsg,
+    PCAN_ERROR_QRCVEMPTY, PCAN_ERROR_QXMTFULL, PCAN_FILTER_CLOSE, PCAN_FILTER_CUSTOM,
+    PCAN_FILTER_OPEN, PCAN_MESSAGE_FILTER, PCAN_USBBUS1, TPCANMsg, TPCANTimestamp,
 };
-use crate::{Baudrate, Error, Filter, Frame};
+use crate::{Baudrate, Error, Filter, Frame, ResponseFilter};
 
 use std::{
+    collections::VecDeque,
     ffi::c_void,
     mem::{self, MaybeUninit},
-    ptr,
+    thread,
+    time::{Duration, Instant},
 };
 
 #[cfg(unix)]
 use std::os::unix::io::{AsRawFd, RawFd};
 
+#[cfg(windows)]
+use std::ptr;
+
 #[cfg(windows)]
 use winapi::{
     shared::minwindef::FALSE,
@@ -81,8 +86,17 @@ pub struct Interface {
     #[allow(unused)]
     event_handle: HANDLE,
     _baudrate: Baudrate,
+    /// Frames that were read from the driver while waiting for a response and
+    /// are handed out by the next receive calls.
+    pending: VecDeque<(Frame, Duration)>,
+    /// Number of frames dropped from `pending` because it was full.
+    pending_dropped: u64,
 }
 
+/// Maximum number of frames kept while waiting for a response. The oldest
+/// frames are dropped beyond that.
+pub const MAX_PENDING: usize = 1024;
+
 impl Interface {
     pub fn init(baudrate: Baudrate) -> Result<Self, Error> {
         let pcan_channel = PCAN_USBBUS1 as u16;
@@ -142,6 +156,8 @@ impl Interface {
             channel: pcan_channel,
             event_handle,
             _baudrate: baudrate,
+            pending: VecDeque::new(),
+            pending_dropped: 0,
         };
 
         // Drain all messages that were received since `init()` has been called.
@@ -166,30 +182,52 @@ impl Drop for Interface {
 impl Interface {
     fn transmit_internal(&mut self, frame: &Frame) -> nb::Result<Option<Frame>, Error> {
         let result = unsafe { CAN_Write(self.channel, &frame.0 as *const _ as *mut _) };
-        if result == PCAN_ERROR_OK {
-            Ok(None)
-        } else {
-            Err(nb::Error::Other(Error::new(result)))
+        match result {
+            PCAN_ERROR_OK => Ok(None),
+            // The transmit queue is full, the frame can be retried once the driver
+            // has sent some of the pending frames.
+            PCAN_ERROR_QXMTFULL => Err(nb::Error::WouldBlock),
+            _ => Err(nb::Error::Other(Error::new(result))),
         }
     }
 
     fn receive_internal(&mut self) -> nb::Result<Frame, Error> {
+        self.receive_timestamped().map(|(frame, _)| frame)
+    }
+
+    fn read_internal(&mut self) -> nb::Result<(Frame, Duration), Error> {
         let mut msg = MaybeUninit::<TPCANMsg>::uninit();
-        let (result, msg) = unsafe {
-            (
-                CAN_Read(self.channel, msg.as_mut_ptr(), ptr::null_mut()),
-                msg.assume_init(),
-            )
-        };
+        let mut timestamp = MaybeUninit::<TPCANTimestamp>::uninit();
+        let result = unsafe { CAN_Read(self.channel, msg.as_mut_ptr(), timestamp.as_mut_ptr()) };
 
         match result {
             PCAN_ERROR_QRCVEMPTY => Err(nb::Error::WouldBlock),
-            PCAN_ERROR_OK => Ok(Frame(msg)),
+            PCAN_ERROR_OK => {
+                let (msg, timestamp) = unsafe { (msg.assume_init(), timestamp.assume_init()) };
+                // `millis` is a `c_ulong` in the Linux bindings.
+                #[allow(clippy::useless_conversion)]
+                let millis =
+                    u64::from(timestamp.millis) + (u64::from(timestamp.millis_overflow) << 32);
+                let timestamp =
+                    Duration::from_millis(millis) + Duration::from_micros(timestamp.micros.into());
+                Ok((Frame(msg), timestamp))
+            }
             _ => Err(nb::Error::Other(Error::new(result))),
         }
     }
 }
 
+impl Interface {
+    /// Receives a frame together with its hardware timestamp, which is relative
+    /// to the start of the driver.
+    pub fn receive_timestamped(&mut self) -> nb::Result<(Frame, Duration), Error> {
+        match self.pending.pop_front() {
+            Some(entry) => Ok(entry),
+            None => self.read_internal(),
+        }
+    }
+}
+
 impl embedded_can::nb::Can for Interface {
     type Frame = Frame;
     type Error = Error;
@@ -208,10 +246,12 @@ impl embedded_can::blocking::Can for Interface {
     type Error = Error;
 
     fn transmit(&mut self, frame: &Frame) -> Result<(), Error> {
-        match self.transmit_internal(frame) {
-            Ok(_) => Ok(()),
-            Err(nb::Error::Other(err)) => Err(err),
-            _ => panic!("The PCAN driver should never block!"),
+        loop {
+            match self.transmit_internal(frame) {
+                Ok(_) => break Ok(()),
+                Err(nb::Error::Other(err)) => break Err(err),
+                Err(nb::Error::WouldBlock) => thread::yield_now(),
+            }
         }
     }
 
@@ -282,3 +322,418 @@ impl Interface {
         };
     }
 }
+
+impl Interface {
+    /// Reads up to `max` frames that are already queued in the driver into `frames`.
+    /// Returns the number of frames read, which is zero if the queue was empty.
+    pub fn receive_batch(&mut self, frames: &mut Vec<Frame>, max: usize) -> Result<usize, Error> {
+        read_batch(|| self.receive_internal(), frames, max)
+    }
+
+    /// Like [`Interface::receive_batch`], but blocks until at least one frame
+    /// is available.
+    pub fn receive_batch_blocking(
+        &mut self,
+        frames: &mut Vec<Frame>,
+        max: usize,
+    ) -> Result<usize, Error> {
+        if max == 0 {
+            return Ok(0);
+        }
+
+        let first = embedded_can::blocking::Can::receive(self)?;
+        frames.push(first);
+        Ok(1 + self.receive_batch(frames, max - 1)?)
+    }
+
+    /// Returns an iterator which reads frames until the driver queue is empty.
+    ///
+    /// The iteration ends after the first error.
+    pub fn drain(&mut self) -> Drain<'_> {
+        Drain {
+            interface: self,
+            finished: false,
+        }
+    }
+}
+
+/// Reads frames with `read` into `frames` until `max` frames were read or
+/// `read` would block.
+fn read_batch(
+    mut read: impl FnMut() -> nb::Result<Frame, Error>,
+    frames: &mut Vec<Frame>,
+    max: usize,
+) -> Result<usize, Error> {
+    let mut count = 0;
+    while count < max {
+        match read() {
+            Ok(frame) => frames.push(frame),
+            Err(nb::Error::WouldBlock) => break,
+            Err(nb::Error::Other(err)) => return Err(err),
+        }
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// Iterator returned by [`Interface::drain`].
+pub struct Drain<'a> {
+    interface: &'a mut Interface,
+    finished: bool,
+}
+
+impl Iterator for Drain<'_> {
+    type Item = Result<Frame, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        drain_next(&mut self.finished, || self.interface.receive_internal())
+    }
+}
+
+/// Advances a drain, which is finished once the queue is empty or `read`
+/// failed, as the driver could keep returning the same error.
+fn drain_next(
+    finished: &mut bool,
+    read: impl FnOnce() -> nb::Result<Frame, Error>,
+) -> Option<Result<Frame, Error>> {
+    if *finished {
+        return None;
+    }
+    match read() {
+        Ok(frame) => Some(Ok(frame)),
+        Err(nb::Error::WouldBlock) => {
+            *finished = true;
+            None
+        }
+        Err(nb::Error::Other(err)) => {
+            *finished = true;
+            Some(Err(err))
+        }
+    }
+}
+
+impl Interface {
+    /// Blocks until a frame is received or `timeout` elapses, in which case
+    /// `Ok(None)` is returned.
+    pub fn receive_timeout(&mut self, timeout: Duration) -> Result<Option<Frame>, Error> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            match self.receive_internal() {
+                Ok(frame) => break Ok(Some(frame)),
+                Err(nb::Error::Other(err)) => break Err(err),
+                Err(nb::Error::WouldBlock) if Instant::now() >= deadline => break Ok(None),
+                Err(nb::Error::WouldBlock) => continue,
+            }
+        }
+    }
+
+    /// Returns a blocking iterator over received frames.
+    ///
+    /// The iterator never ends unless a timeout is set with [`Frames::with_timeout`].
+    pub fn frames(&mut self) -> Frames<'_> {
+        Frames {
+            interface: self,
+            timeout: None,
+        }
+    }
+}
+
+/// Iterator returned by [`Interface::frames`].
+pub struct Frames<'a> {
+    interface: &'a mut Interface,
+    timeout: Option<Duration>,
+}
+
+impl Frames<'_> {
+    /// Ends the iteration once no frame has been received for `timeout`.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+}
+
+impl Iterator for Frames<'_> {
+    type Item = Result<Frame, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.timeout {
+            Some(timeout) => self.interface.receive_timeout(timeout).transpose(),
+            None => Some(embedded_can::blocking::Can::receive(self.interface)),
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl Interface {
+    /// Returns a [`Stream`](futures_core::Stream) of received frames.
+    ///
+    /// The driver does not provide a receive event that could be used to wake
+    /// the task, so while the receive queue is empty a timer thread owned by
+    /// the stream wakes the task again after [`STREAM_POLL_INTERVAL`]. The
+    /// thread is started on the first empty poll and ends with the stream.
+    pub fn stream(&mut self) -> FrameStream<'_> {
+        FrameStream {
+            interface: self,
+            timer: None,
+        }
+    }
+}
+
+/// Time after which [`FrameStream`] checks the receive queue again when it
+/// was empty.
+#[cfg(feature = "async")]
+pub const STREAM_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Stream returned by [`Interface::stream`].
+#[cfg(feature = "async")]
+pub struct FrameStream<'a> {
+    interface: &'a mut Interface,
+    timer: Option<PollTimer>,
+}
+
+#[cfg(feature = "async")]
+impl futures_core::Stream for FrameStream<'_> {
+    type Item = Result<Frame, Error>;
+
+    fn poll_next(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Self::Item>> {
+        use std::task::Poll;
+
+        let this = self.get_mut();
+        match this.interface.receive_internal() {
+            Ok(frame) => Poll::Ready(Some(Ok(frame))),
+            Err(nb::Error::Other(err)) => Poll::Ready(Some(Err(err))),
+            Err(nb::Error::WouldBlock) => {
+                this.timer
+                    .get_or_insert_with(PollTimer::new)
+                    .wake_later(cx.waker());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// A thread waking the task of a stream [`STREAM_POLL_INTERVAL`] after it was
+/// scheduled, parked while there is nothing to wake.
+#[cfg(feature = "async")]
+struct PollTimer {
+    state: std::sync::Arc<PollTimerState>,
+    thread: thread::Thread,
+}
+
+#[cfg(feature = "async")]
+#[derive(Default)]
+struct PollTimerState {
+    waker: std::sync::Mutex<Option<std::task::Waker>>,
+    stopped: std::sync::atomic::AtomicBool,
+}
+
+#[cfg(feature = "async")]
+impl PollTimer {
+    fn new() -> Self {
+        let state = std::sync::Arc::new(PollTimerState::default());
+        let thread = {
+            let state = state.clone();
+            thread::spawn(move || {
+                while !state.stopped.load(std::sync::atomic::Ordering::Acquire) {
+                    let waker = state.waker.lock().unwrap().take();
+                    match waker {
+                        Some(waker) => {
+                            thread::sleep(STREAM_POLL_INTERVAL);
+                            waker.wake();
+                        }
+                        // Unparked by the next `wake_later` or by dropping.
+                        None => thread::park(),
+                    }
+                }
+            })
+            .thread()
+            .clone()
+        };
+        Self { state, thread }
+    }
+
+    /// Wakes `waker` after the poll interval, replacing a waker scheduled
+    /// before.
+    fn wake_later(&self, waker: &std::task::Waker) {
+        *self.state.waker.lock().unwrap() = Some(waker.clone());
+        self.thread.unpark();
+    }
+}
+
+#[cfg(feature = "async")]
+impl Drop for PollTimer {
+    fn drop(&mut self) {
+        self.state
+            .stopped
+            .store(true, std::sync::atomic::Ordering::Release);
+        self.thread.unpark();
+    }
+}
+
+impl Interface {
+    /// Transmits `frame` and waits up to `timeout` for the first frame received
+    /// afterwards that matches `response`. Returns `Ok(None)` on timeout.
+    ///
+    /// Frames that do not match are kept and returned by later receive calls.
+    /// At most [`MAX_PENDING`] frames are kept, older ones are dropped and
+    /// counted by [`Interface::pending_dropped`].
+    pub fn request(
+        &mut self,
+        frame: &Frame,
+        response: &ResponseFilter<'_>,
+        timeout: Duration,
+    ) -> Result<Option<Frame>, Error> {
+        embedded_can::blocking::Can::transmit(self, frame)?;
+        self.wait_for(response, Instant::now() + timeout)
+    }
+
+    /// Waits up to `timeout` for a frame matching `filter`, including frames that
+    /// were kept by an earlier [`Interface::request`]. Returns `Ok(None)` on timeout.
+    ///
+    /// Frames that do not match are kept and returned by later receive calls, up
+    /// to [`MAX_PENDING`] frames. The protocol clients of this crate wait for
+    /// their responses with it, so traffic of other identifiers received in the
+    /// meantime is not lost.
+    pub fn receive_matching(
+        &mut self,
+        filter: &ResponseFilter<'_>,
+        timeout: Duration,
+    ) -> Result<Option<Frame>, Error> {
+        if let Some(index) = self.pending.iter().position(|(f, _)| filter.matches(f)) {
+            return Ok(self.pending.remove(index).map(|(frame, _)| frame));
+        }
+        self.wait_for(filter, Instant::now() + timeout)
+    }
+
+    /// Number of frames dropped because more than [`MAX_PENDING`] frames were
+    /// kept while waiting for responses.
+    pub fn pending_dropped(&self) -> u64 {
+        self.pending_dropped
+    }
+
+    fn wait_for(
+        &mut self,
+        filter: &ResponseFilter<'_>,
+        deadline: Instant,
+    ) -> Result<Option<Frame>, Error> {
+        loop {
+            match self.read_internal() {
+                Ok((frame, _)) if filter.matches(&frame) => break Ok(Some(frame)),
+                Ok(entry) => self.keep_pending(entry),
+                Err(nb::Error::Other(err)) => break Err(err),
+                Err(nb::Error::WouldBlock) => {}
+            }
+            // Also checked while frames keep arriving, which would otherwise
+            // never time out on a busy bus.
+            if Instant::now() >= deadline {
+                break Ok(None);
+            }
+        }
+    }
+
+    fn keep_pending(&mut self, entry: (Frame, Duration)) {
+        if self.pending.len() >= MAX_PENDING {
+            self.pending.pop_front();
+            self.pending_dropped += 1;
+        }
+        self.pending.push_back(entry);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_can::{Frame as _, Id, StandardId};
+
+    use super::*;
+
+    fn frame(id: u16) -> Frame {
+        Frame::new(StandardId::new(id).unwrap(), &[id as u8]).unwrap()
+    }
+
+    /// A driver queue returning the given results, then `WouldBlock`.
+    fn queue(results: Vec<nb::Result<Frame, Error>>) -> impl FnMut() -> nb::Result<Frame, Error> {
+        let mut results = results.into_iter();
+        move || results.next().unwrap_or(Err(nb::Error::WouldBlock))
+    }
+
+    fn ids(frames: &[Frame]) -> Vec<Id> {
+        frames.iter().map(|frame| frame.id()).collect()
+    }
+
+    #[test]
+    fn batch() {
+        let mut read = queue((1..=5).map(|id| Ok(frame(id))).collect());
+        let mut frames = Vec::new();
+        assert_eq!(read_batch(&mut read, &mut frames, 3).unwrap(), 3);
+        assert_eq!(read_batch(&mut read, &mut frames, 0).unwrap(), 0);
+        assert_eq!(read_batch(&mut read, &mut frames, 3).unwrap(), 2);
+        assert_eq!(read_batch(&mut read, &mut frames, 3).unwrap(), 0);
+        assert_eq!(ids(&frames), ids(&(1..=5).map(frame).collect::<Vec<_>>()));
+
+        let mut read = queue(vec![
+            Ok(frame(1)),
+            Err(nb::Error::Other(Error("bus off".to_string()))),
+        ]);
+        let mut frames = Vec::new();
+        assert!(read_batch(&mut read, &mut frames, 10).is_err());
+        assert_eq!(frames.len(), 1);
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn poll_timer() {
+        use std::{
+            sync::{
+                Arc,
+                atomic::{AtomicUsize, Ordering},
+            },
+            task::{Wake, Waker},
+        };
+
+        struct Counter(AtomicUsize);
+
+        impl Wake for Counter {
+            fn wake(self: Arc<Self>) {
+                self.0.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let counter = Arc::new(Counter(AtomicUsize::new(0)));
+        let waker = Waker::from(counter.clone());
+        let timer = PollTimer::new();
+        timer.wake_later(&waker);
+        thread::sleep(STREAM_POLL_INTERVAL * 20);
+        assert_eq!(counter.0.load(Ordering::SeqCst), 1);
+
+        // The parked timer does not wake the task again by itself.
+        thread::sleep(STREAM_POLL_INTERVAL * 20);
+        assert_eq!(counter.0.load(Ordering::SeqCst), 1);
+
+        timer.wake_later(&waker);
+        thread::sleep(STREAM_POLL_INTERVAL * 20);
+        assert_eq!(counter.0.load(Ordering::SeqCst), 2);
+        drop(timer);
+    }
+
+    #[test]
+    fn drain() {
+        let mut read = queue(vec![Ok(frame(1)), Ok(frame(2))]);
+        let mut finished = false;
+        let mut frames = Vec::new();
+        while let Some(frame) = drain_next(&mut finished, &mut read) {
+            frames.push(frame.unwrap());
+        }
+        assert_eq!(frames.len(), 2);
+        assert!(finished);
+
+        // A driver failing over and over ends the drain after the first error.
+        let mut finished = false;
+        let read = || Err(nb::Error::Other(Error("bus off".to_string())));
+        assert!(drain_next(&mut finished, read).unwrap().is_err());
+        assert!(drain_next(&mut finished, read).is_none());
+        assert!(drain_next(&mut finished, read).is_none());
+    }
+}
@@ -0,0 +1,664 @@
+//! ISO-TP (ISO 15765-2) transport protocol, which carries messages of up to
+//! 4 GiB in segmented frames.
+//!
+//! Messages are transmitted in classic CAN frames only, as [`Interface`] cannot
+//! send CAN FD frames. CAN FD single frames are still understood when receiving.
+//!
+//! ```text
+//! let mut channel = isotp::Channel::new(&mut interface, StandardId::new(0x7E0).unwrap(), StandardId::new(0x7E8).unwrap())
+//!     .with_block_size(8)
+//!     .with_st_min(Duration::from_millis(1));
+//! channel.send(&[0x22, 0xF1, 0x90])?;
+//! let response = channel.recv()?;
+//! ```
+
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use embedded_can::{Frame as _, Id};
+
+use crate::{Error, Frame, Interface, ResponseFilter};
+
+const SINGLE_FRAME: u8 = 0x0;
+const FIRST_FRAME: u8 = 0x1;
+const CONSECUTIVE_FRAME: u8 = 0x2;
+const FLOW_CONTROL: u8 = 0x3;
+
+pub(crate) const FLOW_STATUS_CONTINUE: u8 = 0x0;
+const FLOW_STATUS_WAIT: u8 = 0x1;
+const FLOW_STATUS_OVERFLOW: u8 = 0x2;
+
+/// Largest message length of a first frame without the escape sequence.
+const MAX_SHORT_FIRST_FRAME_LEN: usize = 0xFFF;
+
+/// Length of a transmitted frame, including the address byte.
+const FRAME_LEN: usize = 8;
+
+const DEFAULT_PADDING: u8 = 0xCC;
+
+/// How the network addresses are encoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Addressing {
+    /// The addresses are given by the CAN identifiers only.
+    #[default]
+    Normal,
+    /// The first data byte holds the target address, `target` for transmitted
+    /// frames and `source` for received frames.
+    Extended { target: u8, source: u8 },
+    /// The first data byte holds the address extension.
+    Mixed(u8),
+}
+
+impl Addressing {
+    fn tx_prefix(self) -> Option<u8> {
+        match self {
+            Addressing::Normal => None,
+            Addressing::Extended { target, .. } => Some(target),
+            Addressing::Mixed(extension) => Some(extension),
+        }
+    }
+
+    fn rx_prefix(self) -> Option<u8> {
+        match self {
+            Addressing::Normal => None,
+            Addressing::Extended { source, .. } => Some(source),
+            Addressing::Mixed(extension) => Some(extension),
+        }
+    }
+}
+
+/// An ISO-TP connection between two identifiers.
+pub struct Channel<'a> {
+    interface: &'a mut Interface,
+    tx_id: Id,
+    rx_id: Id,
+    addressing: Addressing,
+    block_size: u8,
+    st_min: Duration,
+    padding: Option<u8>,
+    n_as: Duration,
+    n_bs: Duration,
+    n_cr: Duration,
+    max_wait_frames: u32,
+}
+
+impl<'a> Channel<'a> {
+    /// Creates a channel transmitting with `tx_id` and receiving with `rx_id`.
+    pub fn new(interface: &'a mut Interface, tx_id: impl Into<Id>, rx_id: impl Into<Id>) -> Self {
+        Self {
+            interface,
+            tx_id: tx_id.into(),
+            rx_id: rx_id.into(),
+            addressing: Addressing::Normal,
+            block_size: 0,
+            st_min: Duration::ZERO,
+            padding: Some(DEFAULT_PADDING),
+            n_as: Duration::from_millis(1000),
+            n_bs: Duration::from_millis(1000),
+            n_cr: Duration::from_millis(1000),
+            max_wait_frames: 10,
+        }
+    }
+
+    pub fn with_addressing(mut self, addressing: Addressing) -> Self {
+        self.addressing = addressing;
+        self
+    }
+
+    /// Sets the number of consecutive frames the sender may send before waiting
+    /// for the next flow control frame, `0` for no limit. Defaults to `0`.
+    pub fn with_block_size(mut self, block_size: u8) -> Self {
+        self.block_size = block_size;
+        self
+    }
+
+    /// Sets the minimum time between consecutive frames requested from the sender.
+    /// It is rounded down to 100 µs below one millisecond and capped at 127 ms.
+    pub fn with_st_min(mut self, st_min: Duration) -> Self {
+        self.st_min = st_min;
+        self
+    }
+
+    /// Sets the byte classic frames are padded to 8 bytes with, or `None` to send
+    /// frames only as long as needed. Defaults to `0xCC`.
+    pub fn with_padding(mut self, padding: Option<u8>) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    /// Sets the timeout for transmitting a frame (N_As). Defaults to one second.
+    pub fn with_n_as(mut self, timeout: Duration) -> Self {
+        self.n_as = timeout;
+        self
+    }
+
+    /// Sets the timeout of the sender waiting for a flow control frame (N_Bs).
+    /// Defaults to one second.
+    pub fn with_n_bs(mut self, timeout: Duration) -> Self {
+        self.n_bs = timeout;
+        self
+    }
+
+    /// Sets the timeout of the receiver waiting for a consecutive frame (N_Cr).
+    /// Defaults to one second.
+    pub fn with_n_cr(mut self, timeout: Duration) -> Self {
+        self.n_cr = timeout;
+        self
+    }
+
+    /// Sets how many flow control frames with the wait status are accepted in
+    /// a row before the transmission is aborted. Defaults to 10.
+    pub fn with_max_wait_frames(mut self, count: u32) -> Self {
+        self.max_wait_frames = count;
+        self
+    }
+
+    /// Returns the interface the channel runs on.
+    pub fn interface(&mut self) -> &mut Interface {
+        self.interface
+    }
+
+    /// Sends a message, segmenting it if it does not fit in a single frame.
+    pub fn send(&mut self, data: &[u8]) -> Result<(), Error> {
+        let mut pdus = segment(data, self.addressing)?;
+        self.transmit(&pdus.next().unwrap())?;
+
+        let mut pdus = pdus.peekable();
+        while pdus.peek().is_some() {
+            let (block_size, st_min) = self.wait_flow_control()?;
+
+            let mut sent = 0;
+            while let Some(pdu) = pdus.next() {
+                self.transmit(&pdu)?;
+
+                sent += 1;
+                if block_size != 0 && sent == block_size {
+                    break;
+                }
+                if pdus.peek().is_some() && !st_min.is_zero() {
+                    thread::sleep(st_min);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Blocks until a message is received.
+    pub fn recv(&mut self) -> Result<Vec<u8>, Error> {
+        loop {
+            if let Some(data) = self.recv_timeout(Duration::from_secs(1))? {
+                return Ok(data);
+            }
+        }
+    }
+
+    /// Waits up to `timeout` for the start of a message and receives it.
+    /// Returns `Ok(None)` if no message started in time.
+    pub fn recv_timeout(&mut self, timeout: Duration) -> Result<Option<Vec<u8>>, Error> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let Some(pdu) = self.receive(deadline)? else {
+                return Ok(None);
+            };
+
+            match Pdu::parse(&pdu) {
+                Some(Pdu::Single(data)) => return Ok(Some(data.to_vec())),
+                Some(Pdu::First { len, data }) => {
+                    return self.receive_segmented(len, data).map(Some);
+                }
+                // Consecutive and flow control frames outside of a transfer are ignored.
+                _ => continue,
+            }
+        }
+    }
+
+    fn receive_segmented(&mut self, len: usize, first: &[u8]) -> Result<Vec<u8>, Error> {
+        let mut data = Vec::with_capacity(len);
+        data.extend_from_slice(first);
+
+        self.send_flow_control(FLOW_STATUS_CONTINUE)?;
+        let mut sequence_number = 1u8;
+        let mut received = 0;
+        while data.len() < len {
+            let pdu = self
+                .receive(Instant::now() + self.n_cr)?
+                .ok_or_else(|| Error("ISO-TP N_Cr timeout".to_string()))?;
+
+            match Pdu::parse(&pdu) {
+                Some(Pdu::Consecutive {
+                    sequence_number: received_number,
+                    data: segment,
+                }) => {
+                    if received_number != sequence_number {
+                        return Err(Error(format!(
+                            "ISO-TP consecutive frame with sequence number {}, expected {}",
+                            received_number, sequence_number
+                        )));
+                    }
+                    let remaining = len - data.len();
+                    data.extend_from_slice(&segment[..segment.len().min(remaining)]);
+                    sequence_number = (sequence_number + 1) & 0x0F;
+
+                    received += 1;
+                    if self.block_size != 0 && received == self.block_size && data.len() < len {
+                        self.send_flow_control(FLOW_STATUS_CONTINUE)?;
+                        received = 0;
+                    }
+                }
+                Some(Pdu::Single(_) | Pdu::First { .. }) => {
+                    return Err(Error(
+                        "ISO-TP reception interrupted by a new message".to_string(),
+                    ));
+                }
+                _ => continue,
+            }
+        }
+        Ok(data)
+    }
+
+    /// Waits for a flow control frame allowing to continue, returns the block
+    /// size and minimum separation time of the receiver.
+    fn wait_flow_control(&mut self) -> Result<(u8, Duration), Error> {
+        let mut wait_frames = 0;
+        loop {
+            let pdu = self
+                .receive(Instant::now() + self.n_bs)?
+                .ok_or_else(|| Error("ISO-TP N_Bs timeout".to_string()))?;
+            let Some(Pdu::FlowControl {
+                status,
+                block_size,
+                st_min,
+            }) = Pdu::parse(&pdu)
+            else {
+                continue;
+            };
+
+            match status {
+                FLOW_STATUS_CONTINUE => return Ok((block_size, st_min)),
+                FLOW_STATUS_WAIT => {
+                    wait_frames += 1;
+                    if wait_frames > self.max_wait_frames {
+                        return Err(Error(
+                            "ISO-TP receiver exceeded the maximum number of wait frames"
+                                .to_string(),
+                        ));
+                    }
+                }
+                FLOW_STATUS_OVERFLOW => {
+                    return Err(Error("ISO-TP receiver reported an overflow".to_string()));
+                }
+                status => {
+                    return Err(Error(format!("Invalid ISO-TP flow status {}", status)));
+                }
+            }
+        }
+    }
+
+    fn send_flow_control(&mut self, status: u8) -> Result<(), Error> {
+        let pdu = flow_control(status, self.block_size, self.st_min);
+        self.transmit(&pdu)
+    }
+
+    /// Transmits a protocol data unit, adding the address byte and padding.
+    fn transmit(&mut self, pdu: &[u8]) -> Result<(), Error> {
+        let mut data = Vec::with_capacity(FRAME_LEN);
+        data.extend(self.addressing.tx_prefix());
+        data.extend_from_slice(pdu);
+        if let Some(padding) = self.padding {
+            data.resize(FRAME_LEN, padding);
+        }
+
+        let frame = Frame::new(self.tx_id, &data).unwrap();
+        let deadline = Instant::now() + self.n_as;
+        loop {
+            match embedded_can::nb::Can::transmit(self.interface, &frame) {
+                Ok(_) => return Ok(()),
+                Err(nb::Error::Other(err)) => return Err(err),
+                Err(nb::Error::WouldBlock) if Instant::now() >= deadline => {
+                    return Err(Error("ISO-TP N_As timeout".to_string()));
+                }
+                Err(nb::Error::WouldBlock) => thread::yield_now(),
+            }
+        }
+    }
+
+    /// Waits for a frame of the channel and returns its protocol data unit,
+    /// without the address byte.
+    fn receive(&mut self, deadline: Instant) -> Result<Option<Vec<u8>>, Error> {
+        let prefix = self.addressing.rx_prefix();
+        let offset = prefix.map_or(0, |_| 1);
+        let filter = ResponseFilter::new(self.rx_id)
+            .with_data(|data| data.len() > offset && prefix.is_none_or(|prefix| data[0] == prefix));
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        Ok(self
+            .interface
+            .receive_matching(&filter, timeout)?
+            .map(|frame| frame.data()[offset..].to_vec()))
+    }
+}
+
+/// A protocol data unit, without the address byte.
+pub(crate) enum Pdu<'a> {
+    Single(&'a [u8]),
+    /// The first frame of a segmented message of `len` bytes.
+    First {
+        len: usize,
+        data: &'a [u8],
+    },
+    /// Data of a consecutive frame, including the padding of the last one.
+    Consecutive {
+        sequence_number: u8,
+        data: &'a [u8],
+    },
+    FlowControl {
+        status: u8,
+        block_size: u8,
+        st_min: Duration,
+    },
+}
+
+impl<'a> Pdu<'a> {
+    /// Returns `None` for unknown or malformed protocol data units.
+    pub(crate) fn parse(pdu: &'a [u8]) -> Option<Self> {
+        match *pdu.first()? >> 4 {
+            SINGLE_FRAME => {
+                let (len, start) = match pdu[0] & 0x0F {
+                    // CAN FD single frames carry the length in the second byte.
+                    0 if pdu.len() > 8 => (pdu[1] as usize, 2),
+                    len => (len as usize, 1),
+                };
+                if len == 0 || start + len > pdu.len() {
+                    return None;
+                }
+                Some(Pdu::Single(&pdu[start..start + len]))
+            }
+            FIRST_FRAME if pdu.len() >= 2 => {
+                let len = (((pdu[0] & 0x0F) as usize) << 8) | pdu[1] as usize;
+                let (len, start) = match len {
+                    0 if pdu.len() >= 6 => (
+                        u32::from_be_bytes(pdu[2..6].try_into().unwrap()) as usize,
+                        6,
+                    ),
+                    len => (len, 2),
+                };
+                if len < pdu.len() - start {
+                    return None;
+                }
+                Some(Pdu::First {
+                    len,
+                    data: &pdu[start..],
+                })
+            }
+            CONSECUTIVE_FRAME => Some(Pdu::Consecutive {
+                sequence_number: pdu[0] & 0x0F,
+                data: &pdu[1..],
+            }),
+            FLOW_CONTROL if pdu.len() >= 3 => Some(Pdu::FlowControl {
+                status: pdu[0] & 0x0F,
+                block_size: pdu[1],
+                st_min: decode_st_min(pdu[2]),
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Splits a message into the protocol data units to transmit, a single frame
+/// or a first frame followed by the consecutive frames.
+fn segment(
+    data: &[u8],
+    addressing: Addressing,
+) -> Result<Box<dyn Iterator<Item = Vec<u8>> + '_>, Error> {
+    if data.is_empty() {
+        return Err(Error("ISO-TP messages cannot be empty".to_string()));
+    }
+    let len = data.len();
+    let pdu_len = FRAME_LEN - addressing.tx_prefix().map_or(0, |_| 1);
+
+    if len < pdu_len {
+        let mut pdu = vec![(SINGLE_FRAME << 4) | len as u8];
+        pdu.extend_from_slice(data);
+        return Ok(Box::new(std::iter::once(pdu)));
+    }
+
+    let mut first = if len <= MAX_SHORT_FIRST_FRAME_LEN {
+        vec![(FIRST_FRAME << 4) | (len >> 8) as u8, len as u8]
+    } else {
+        let len =
+            u32::try_from(len).map_err(|_| Error("ISO-TP message is too long".to_string()))?;
+        let mut pdu = vec![FIRST_FRAME << 4, 0];
+        pdu.extend_from_slice(&len.to_be_bytes());
+        pdu
+    };
+    let first_len = pdu_len - first.len();
+    first.extend_from_slice(&data[..first_len]);
+
+    let consecutive = data[first_len..]
+        .chunks(pdu_len - 1)
+        .enumerate()
+        .map(|(index, chunk)| {
+            let sequence_number = (index + 1) as u8 & 0x0F;
+            let mut pdu = vec![(CONSECUTIVE_FRAME << 4) | sequence_number];
+            pdu.extend_from_slice(chunk);
+            pdu
+        });
+    Ok(Box::new(std::iter::once(first).chain(consecutive)))
+}
+
+/// Builds a flow control protocol data unit.
+pub(crate) fn flow_control(status: u8, block_size: u8, st_min: Duration) -> [u8; 3] {
+    [
+        (FLOW_CONTROL << 4) | status,
+        block_size,
+        encode_st_min(st_min),
+    ]
+}
+
+fn encode_st_min(st_min: Duration) -> u8 {
+    match st_min.as_micros() {
+        0 => 0,
+        micros @ 100..1000 => 0xF0 + (micros / 100) as u8,
+        micros => (micros / 1000).min(0x7F) as u8,
+    }
+}
+
+/// Reserved values are interpreted as the longest time, 127 ms.
+fn decode_st_min(value: u8) -> Duration {
+    match value {
+        0x00..=0x7F => Duration::from_millis(value as u64),
+        0xF1..=0xF9 => Duration::from_micros((value - 0xF0) as u64 * 100),
+        _ => Duration::from_millis(0x7F),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_single_frame() {
+        let Some(Pdu::Single(data)) = Pdu::parse(&[0x03, 1, 2, 3, 0xCC, 0xCC, 0xCC, 0xCC]) else {
+            panic!("not a single frame");
+        };
+        assert_eq!(data, [1, 2, 3]);
+
+        // CAN FD single frame with the length escape sequence.
+        let mut pdu = vec![0x00, 20];
+        pdu.extend(1..=20);
+        pdu.resize(24, 0xCC);
+        let Some(Pdu::Single(data)) = Pdu::parse(&pdu) else {
+            panic!("not a single frame");
+        };
+        assert_eq!(data, (1..=20).collect::<Vec<u8>>());
+
+        assert!(Pdu::parse(&[0x00, 1, 2]).is_none());
+        assert!(Pdu::parse(&[0x05, 1, 2]).is_none());
+        assert!(Pdu::parse(&[0x00, 30, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]).is_none());
+        assert!(Pdu::parse(&[]).is_none());
+    }
+
+    #[test]
+    fn parse_first_frame() {
+        let Some(Pdu::First { len, data }) = Pdu::parse(&[0x10, 0x14, 1, 2, 3, 4, 5, 6]) else {
+            panic!("not a first frame");
+        };
+        assert_eq!(len, 20);
+        assert_eq!(data, [1, 2, 3, 4, 5, 6]);
+
+        let Some(Pdu::First { len, data }) = Pdu::parse(&[0x1F, 0xFF, 1, 2, 3, 4, 5, 6]) else {
+            panic!("not a first frame");
+        };
+        assert_eq!(len, 0xFFF);
+        assert_eq!(data.len(), 6);
+
+        // Messages longer than 4095 bytes use a 32 bit length.
+        let Some(Pdu::First { len, data }) =
+            Pdu::parse(&[0x10, 0x00, 0x00, 0x01, 0x00, 0x00, 1, 2])
+        else {
+            panic!("not a first frame");
+        };
+        assert_eq!(len, 0x10000);
+        assert_eq!(data, [1, 2]);
+
+        // The message must be longer than the data of the first frame.
+        assert!(Pdu::parse(&[0x10, 0x02, 1, 2, 3, 4, 5, 6]).is_none());
+        assert!(Pdu::parse(&[0x10]).is_none());
+    }
+
+    #[test]
+    fn parse_consecutive_and_flow_control() {
+        let Some(Pdu::Consecutive {
+            sequence_number,
+            data,
+        }) = Pdu::parse(&[0x2A, 1, 2, 3])
+        else {
+            panic!("not a consecutive frame");
+        };
+        assert_eq!(sequence_number, 0xA);
+        assert_eq!(data, [1, 2, 3]);
+
+        let Some(Pdu::FlowControl {
+            status,
+            block_size,
+            st_min,
+        }) = Pdu::parse(&[0x31, 8, 0xF5, 0xCC])
+        else {
+            panic!("not a flow control frame");
+        };
+        assert_eq!(status, FLOW_STATUS_WAIT);
+        assert_eq!(block_size, 8);
+        assert_eq!(st_min, Duration::from_micros(500));
+
+        assert!(Pdu::parse(&[0x30, 0]).is_none());
+        assert!(Pdu::parse(&[0x40, 0, 0]).is_none());
+    }
+
+    #[test]
+    fn st_min() {
+        let us = Duration::from_micros;
+        let ms = Duration::from_millis;
+        for (duration, value) in [
+            (Duration::ZERO, 0x00),
+            (us(50), 0x00),
+            (us(100), 0xF1),
+            (us(950), 0xF9),
+            (ms(1), 0x01),
+            (us(1500), 0x01),
+            (ms(127), 0x7F),
+            (ms(500), 0x7F),
+        ] {
+            assert_eq!(encode_st_min(duration), value, "{:?}", duration);
+        }
+
+        for (value, duration) in [
+            (0x00, Duration::ZERO),
+            (0x0A, ms(10)),
+            (0x7F, ms(127)),
+            (0xF1, us(100)),
+            (0xF9, us(900)),
+            // Reserved values.
+            (0x80, ms(127)),
+            (0xF0, ms(127)),
+            (0xFA, ms(127)),
+            (0xFF, ms(127)),
+        ] {
+            assert_eq!(decode_st_min(value), duration, "{:#04X}", value);
+        }
+
+        assert_eq!(flow_control(FLOW_STATUS_OVERFLOW, 4, ms(20)), [0x32, 4, 20]);
+    }
+
+    fn segments(len: usize, addressing: Addressing) -> Vec<Vec<u8>> {
+        let data: Vec<u8> = (0..len).map(|i| i as u8).collect();
+        let pdus: Vec<_> = segment(&data, addressing).unwrap().collect();
+
+        // The segments add up to the message again.
+        let mut reassembled = Vec::new();
+        for pdu in &pdus {
+            match Pdu::parse(pdu).unwrap() {
+                Pdu::Single(data) => reassembled.extend_from_slice(data),
+                Pdu::First {
+                    len: first_len,
+                    data,
+                } => {
+                    assert_eq!(first_len, len);
+                    reassembled.extend_from_slice(data);
+                }
+                Pdu::Consecutive { data, .. } => reassembled.extend_from_slice(data),
+                Pdu::FlowControl { .. } => panic!("unexpected flow control frame"),
+            }
+        }
+        assert_eq!(reassembled, data);
+        pdus
+    }
+
+    #[test]
+    fn segment_normal_addressing() {
+        assert_eq!(
+            segments(7, Addressing::Normal),
+            [vec![0x07, 0, 1, 2, 3, 4, 5, 6]]
+        );
+
+        let pdus = segments(8, Addressing::Normal);
+        assert_eq!(pdus, [vec![0x10, 8, 0, 1, 2, 3, 4, 5], vec![0x21, 6, 7]]);
+
+        let pdus = segments(6 + 7 * 16, Addressing::Normal);
+        assert_eq!(pdus.len(), 17);
+        assert!(pdus.iter().all(|pdu| pdu.len() == 8));
+        assert_eq!(pdus[15][0], 0x2F);
+        assert_eq!(pdus[16][0], 0x20);
+
+        // Long messages use the 32 bit length, leaving two bytes in the first frame.
+        let pdus = segments(0x1000, Addressing::Normal);
+        assert_eq!(pdus[0], [0x10, 0x00, 0x00, 0x00, 0x10, 0x00, 0, 1]);
+        assert_eq!(pdus.len(), 1 + (0x1000 - 2usize).div_ceil(7));
+    }
+
+    #[test]
+    fn segment_address_byte() {
+        for addressing in [
+            Addressing::Extended {
+                target: 0x10,
+                source: 0x20,
+            },
+            Addressing::Mixed(0x30),
+        ] {
+            assert_eq!(segments(6, addressing), [vec![0x06, 0, 1, 2, 3, 4, 5]]);
+
+            let pdus = segments(7, addressing);
+            assert_eq!(pdus, [vec![0x10, 7, 0, 1, 2, 3, 4], vec![0x21, 5, 6]]);
+
+            let pdus = segments(5 + 6 * 3, addressing);
+            assert_eq!(pdus.len(), 4);
+            assert!(pdus.iter().all(|pdu| pdu.len() == 7));
+
+            let pdus = segments(0x1000, addressing);
+            assert_eq!(pdus[0], [0x10, 0x00, 0x00, 0x00, 0x10, 0x00, 0]);
+            assert_eq!(pdus.len(), 1 + (0x1000 - 1usize).div_ceil(6));
+        }
+
+        assert!(segment(&[], Addressing::Normal).is_err());
+    }
+}
@@ -0,0 +1,842 @@
+//! SAE J1939 identifiers, transport protocol (J1939-21), requests and address
+//! claiming (J1939-81).
+//!
+//! ```text
+//! let name = Name::new()
+//!     .with_arbitrary_address_capable(true)
+//!     .with_function(130)
+//!     .with_manufacturer_code(0x123)
+//!     .with_identity_number(42);
+//! let mut node = j1939::Node::new(&mut interface, 0x80);
+//! node.claim(name)?;
+//! node.set_response(Pgn(65242), b"1.0.0*".to_vec());
+//! let engine_hours = node.request(Pgn(65253), 0x00, Duration::from_millis(1250))?;
+//! node.send(6, Pgn(65226), j1939::GLOBAL, &dm1)?;
+//! while let Some(message) = node.recv_timeout(Duration::from_secs(1))? {
+//!     println!("{:?} {:02X?}", message.id, message.data);
+//! }
+//! ```
+
+use std::{
+    collections::{HashMap, VecDeque},
+    thread,
+    time::{Duration, Instant},
+};
+
+use embedded_can::{ExtendedId, Frame as _, Id};
+
+use crate::{Error, Frame, Interface, ResponseFilter};
+
+pub mod diag;
+
+mod address;
+
+pub use address::{AddressTable, Name};
+
+/// The global destination address.
+pub const GLOBAL: u8 = 0xFF;
+
+/// The source address of nodes that could not claim an address.
+pub const NULL: u8 = 0xFE;
+
+const CONNECTION_RTS: u8 = 16;
+const CONNECTION_CTS: u8 = 17;
+const CONNECTION_END_OF_MESSAGE_ACK: u8 = 19;
+const CONNECTION_BAM: u8 = 32;
+const CONNECTION_ABORT: u8 = 255;
+
+const ABORT_TIMEOUT: u8 = 3;
+const ABORT_BAD_SEQUENCE_NUMBER: u8 = 7;
+
+const TRANSPORT_PRIORITY: u8 = 7;
+const DEFAULT_PRIORITY: u8 = 6;
+
+/// Largest message carried by the transport protocol.
+const MAX_TRANSPORT_LEN: usize = 1785;
+
+/// Time between the packets of a broadcast.
+const BAM_PACKET_INTERVAL: Duration = Duration::from_millis(50);
+const T1: Duration = Duration::from_millis(750);
+const T2: Duration = Duration::from_millis(1250);
+const T3: Duration = Duration::from_millis(1250);
+const T4: Duration = Duration::from_millis(1050);
+
+/// Time other nodes have to contend an address claim.
+const ADDRESS_CLAIM_TIMEOUT: Duration = Duration::from_millis(250);
+
+/// A parameter group number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Pgn(pub u32);
+
+impl Pgn {
+    pub const ACKNOWLEDGEMENT: Pgn = Pgn(59392);
+    pub const REQUEST: Pgn = Pgn(59904);
+    pub const TRANSPORT_DATA: Pgn = Pgn(60160);
+    pub const TRANSPORT_CONNECTION: Pgn = Pgn(60416);
+    pub const ADDRESS_CLAIMED: Pgn = Pgn(60928);
+
+    pub fn pdu_format(self) -> PduFormat {
+        if (self.0 >> 8) as u8 >= 240 {
+            PduFormat::Pdu2
+        } else {
+            PduFormat::Pdu1
+        }
+    }
+
+    fn to_bytes(self) -> [u8; 3] {
+        let [a, b, c, _] = self.0.to_le_bytes();
+        [a, b, c]
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Self {
+        Pgn(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], 0]) & 0x3FFFF)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PduFormat {
+    /// Destination specific, the PDU specific byte holds the destination address.
+    Pdu1,
+    /// Broadcast, the PDU specific byte is part of the PGN.
+    Pdu2,
+}
+
+/// A J1939 29-bit identifier.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Identifier {
+    /// Priority from 0 (highest) to 7.
+    pub priority: u8,
+    pub pgn: Pgn,
+    pub source: u8,
+    /// Destination address of PDU1 messages, [`GLOBAL`] for PDU2 messages.
+    pub destination: u8,
+}
+
+impl Identifier {
+    /// Creates an identifier of a message sent to the global address.
+    pub fn new(priority: u8, pgn: Pgn, source: u8) -> Self {
+        Self {
+            priority,
+            pgn,
+            source,
+            destination: GLOBAL,
+        }
+    }
+
+    pub fn with_destination(mut self, destination: u8) -> Self {
+        self.destination = destination;
+        self
+    }
+}
+
+impl From<ExtendedId> for Identifier {
+    fn from(id: ExtendedId) -> Self {
+        let raw = id.as_raw();
+        let pgn = Pgn((raw >> 8) & 0x3FFFF);
+        let (pgn, destination) = match pgn.pdu_format() {
+            PduFormat::Pdu1 => (Pgn(pgn.0 & 0x3FF00), pgn.0 as u8),
+            PduFormat::Pdu2 => (pgn, GLOBAL),
+        };
+        Self {
+            priority: (raw >> 26) as u8 & 0x7,
+            pgn,
+            source: raw as u8,
+            destination,
+        }
+    }
+}
+
+impl From<Identifier> for ExtendedId {
+    fn from(id: Identifier) -> Self {
+        let pgn = match id.pgn.pdu_format() {
+            PduFormat::Pdu1 => (id.pgn.0 & 0x3FF00) | id.destination as u32,
+            PduFormat::Pdu2 => id.pgn.0 & 0x3FFFF,
+        };
+        let raw = ((id.priority as u32 & 0x7) << 26) | (pgn << 8) | id.source as u32;
+        ExtendedId::new(raw).unwrap()
+    }
+}
+
+impl From<Identifier> for Id {
+    fn from(id: Identifier) -> Self {
+        Id::Extended(id.into())
+    }
+}
+
+/// Control byte of an acknowledgement message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Acknowledgement {
+    Ack,
+    Nack,
+    AccessDenied,
+    CannotRespond,
+}
+
+impl From<Acknowledgement> for u8 {
+    fn from(acknowledgement: Acknowledgement) -> Self {
+        match acknowledgement {
+            Acknowledgement::Ack => 0,
+            Acknowledgement::Nack => 1,
+            Acknowledgement::AccessDenied => 2,
+            Acknowledgement::CannotRespond => 3,
+        }
+    }
+}
+
+/// A complete message, either from a single frame or reassembled by the
+/// transport protocol.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    pub id: Identifier,
+    pub data: Vec<u8>,
+}
+
+impl Message {
+    /// The PGN requested by a request message.
+    pub fn requested_pgn(&self) -> Option<Pgn> {
+        (self.id.pgn == Pgn::REQUEST && self.data.len() >= 3).then(|| Pgn::from_bytes(&self.data))
+    }
+}
+
+/// A J1939 controller application with a source address.
+///
+/// Messages longer than 8 bytes are sent and received with the transport
+/// protocol, requests for PGNs with a response set are answered while
+/// receiving. Once an address is claimed, the node defends it and answers
+/// requests for address claimed.
+pub struct Node<'a> {
+    interface: &'a mut Interface,
+    address: u8,
+    name: Option<Name>,
+    addresses: AddressTable,
+    /// End of the contention period of the last address claim.
+    claim_deadline: Instant,
+    responses: HashMap<Pgn, Vec<u8>>,
+    /// Messages received while waiting for a response.
+    received: VecDeque<Message>,
+}
+
+impl<'a> Node<'a> {
+    /// Creates a node using `address`, which is its preferred address once
+    /// claimed with [`Node::claim`].
+    pub fn new(interface: &'a mut Interface, address: u8) -> Self {
+        Self {
+            interface,
+            address,
+            name: None,
+            addresses: AddressTable::new(),
+            claim_deadline: Instant::now(),
+            responses: HashMap::new(),
+            received: VecDeque::new(),
+        }
+    }
+
+    /// The source address, [`NULL`] if no address could be claimed.
+    pub fn address(&self) -> u8 {
+        self.address
+    }
+
+    /// The NAME of the last address claim.
+    pub fn name(&self) -> Option<Name> {
+        self.name
+    }
+
+    /// The addresses claimed by other nodes seen so far.
+    pub fn addresses(&self) -> &AddressTable {
+        &self.addresses
+    }
+
+    /// Claims the preferred address with `name` and waits for contending claims.
+    /// Returns the claimed address, which differs from the preferred one if it
+    /// was taken and `name` is arbitrary address capable.
+    ///
+    /// Fails with a cannot claim message if no address is available.
+    pub fn claim(&mut self, name: Name) -> Result<u8, Error> {
+        self.name = Some(name);
+        if self.address == NULL || self.addresses.contains(self.address) {
+            self.address = self.free_address().unwrap_or(NULL);
+        }
+        self.send_address_claim()?;
+
+        // Other messages received meanwhile are kept for the next receive calls.
+        while let Some(message) = self.receive_message(self.claim_deadline)? {
+            self.received.push_back(message);
+        }
+        if self.address == NULL {
+            return Err(Error("J1939 node cannot claim an address".to_string()));
+        }
+        Ok(self.address)
+    }
+
+    pub fn interface(&mut self) -> &mut Interface {
+        self.interface
+    }
+
+    /// Answers requests for `pgn` with `data` from now on.
+    pub fn set_response(&mut self, pgn: Pgn, data: Vec<u8>) {
+        self.responses.insert(pgn, data);
+    }
+
+    pub fn remove_response(&mut self, pgn: Pgn) -> Option<Vec<u8>> {
+        self.responses.remove(&pgn)
+    }
+
+    /// Sends a message of up to 1785 bytes. Longer messages than 8 bytes are
+    /// broadcast with BAM to the global address and sent with RTS/CTS otherwise.
+    pub fn send(
+        &mut self,
+        priority: u8,
+        pgn: Pgn,
+        destination: u8,
+        data: &[u8],
+    ) -> Result<(), Error> {
+        self.check_address()?;
+        if data.len() > MAX_TRANSPORT_LEN {
+            return Err(Error(format!(
+                "J1939 message of {} bytes exceeds {} bytes",
+                data.len(),
+                MAX_TRANSPORT_LEN
+            )));
+        }
+        if data.len() <= 8 {
+            if pgn.pdu_format() == PduFormat::Pdu2 && destination != GLOBAL {
+                return Err(Error(format!(
+                    "J1939 PGN {} cannot be sent to a specific destination",
+                    pgn.0
+                )));
+            }
+            let id = Identifier::new(priority, pgn, self.address).with_destination(destination);
+            return self.transmit(id, data);
+        }
+
+        if destination == GLOBAL {
+            self.send_bam(pgn, data)
+        } else {
+            self.send_rts(pgn, destination, data)
+        }
+    }
+
+    /// Requests `pgn` from `destination` and waits for the response. For
+    /// requests to the global address, the first response is returned.
+    ///
+    /// Commands answered with a positive acknowledgement return the
+    /// acknowledgement message. Fails if the destination does not acknowledge
+    /// the request.
+    pub fn request(
+        &mut self,
+        pgn: Pgn,
+        destination: u8,
+        timeout: Duration,
+    ) -> Result<Option<Message>, Error> {
+        self.check_address()?;
+        let id = Identifier::new(DEFAULT_PRIORITY, Pgn::REQUEST, self.address)
+            .with_destination(destination);
+        self.transmit(id, &pgn.to_bytes())?;
+
+        let deadline = Instant::now() + timeout;
+        while let Some(message) = self.receive_message(deadline)? {
+            let from_destination = destination == GLOBAL || message.id.source == destination;
+            if message.id.pgn == pgn && from_destination {
+                return Ok(Some(message));
+            }
+            if message.id.pgn == Pgn::ACKNOWLEDGEMENT
+                && from_destination
+                && message.data.len() == 8
+                && Pgn::from_bytes(&message.data[5..]) == pgn
+            {
+                if message.data[0] == u8::from(Acknowledgement::Ack) {
+                    return Ok(Some(message));
+                }
+                return Err(Error(format!(
+                    "J1939 request for PGN {} not acknowledged by {:#04x}, control byte {}",
+                    pgn.0, message.id.source, message.data[0]
+                )));
+            }
+            self.received.push_back(message);
+        }
+        Ok(None)
+    }
+
+    /// Sends an acknowledgement for `pgn` to the global address.
+    pub fn acknowledge(
+        &mut self,
+        pgn: Pgn,
+        requester: u8,
+        control: Acknowledgement,
+    ) -> Result<(), Error> {
+        let [a, b, c] = pgn.to_bytes();
+        let data = [control.into(), 0xFF, 0xFF, 0xFF, requester, a, b, c];
+        self.transmit(
+            Identifier::new(DEFAULT_PRIORITY, Pgn::ACKNOWLEDGEMENT, self.address),
+            &data,
+        )
+    }
+
+    /// Waits for the next message to this node or the global address.
+    pub fn recv(&mut self) -> Result<Message, Error> {
+        loop {
+            if let Some(message) = self.recv_timeout(Duration::from_secs(1))? {
+                return Ok(message);
+            }
+        }
+    }
+
+    /// Waits up to `timeout` for the next message to this node or the global
+    /// address. Returns `Ok(None)` on timeout.
+    ///
+    /// Requests without a response set are returned, to be answered with
+    /// [`Node::send`] or [`Node::acknowledge`].
+    pub fn recv_timeout(&mut self, timeout: Duration) -> Result<Option<Message>, Error> {
+        if let Some(message) = self.received.pop_front() {
+            return Ok(Some(message));
+        }
+        self.receive_message(Instant::now() + timeout)
+    }
+
+    fn receive_message(&mut self, deadline: Instant) -> Result<Option<Message>, Error> {
+        // Any extended identifier.
+        let filter = ResponseFilter::new(ExtendedId::ZERO).with_mask(0);
+        loop {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            let Some(frame) = self.interface.receive_matching(&filter, timeout)? else {
+                return Ok(None);
+            };
+            let Id::Extended(id) = frame.id() else {
+                continue;
+            };
+            let id = Identifier::from(id);
+            if id.destination != GLOBAL && id.destination != self.address {
+                continue;
+            }
+            if let Some(message) = self.process(id, frame.data())? {
+                return Ok(Some(message));
+            }
+        }
+    }
+
+    /// Handles transport and request frames, returns complete messages.
+    fn process(&mut self, id: Identifier, data: &[u8]) -> Result<Option<Message>, Error> {
+        match id.pgn {
+            Pgn::TRANSPORT_CONNECTION if data.len() == 8 => match data[0] {
+                CONNECTION_RTS if id.destination == self.address => self.receive_rts(id, data),
+                CONNECTION_BAM if id.destination == GLOBAL => self.receive_bam(id, data),
+                // Frames of sessions that are not in progress.
+                _ => Ok(None),
+            },
+            Pgn::TRANSPORT_DATA => Ok(None),
+            Pgn::REQUEST if data.len() >= 3 && Pgn::from_bytes(data) == Pgn::ADDRESS_CLAIMED => {
+                if self.name.is_some() {
+                    self.send_address_claim()?;
+                }
+                Ok(None)
+            }
+            Pgn::REQUEST if data.len() >= 3 => {
+                let pgn = Pgn::from_bytes(data);
+                let Some(response) = self.responses.get(&pgn).cloned() else {
+                    return Ok(Some(Message {
+                        id,
+                        data: data.to_vec(),
+                    }));
+                };
+                let destination = match pgn.pdu_format() {
+                    PduFormat::Pdu1 if id.destination != GLOBAL => id.source,
+                    _ => GLOBAL,
+                };
+                self.send(DEFAULT_PRIORITY, pgn, destination, &response)?;
+                Ok(None)
+            }
+            Pgn::ADDRESS_CLAIMED if data.len() == 8 => {
+                let message = Message {
+                    id,
+                    data: data.to_vec(),
+                };
+                let name = Name::from_bytes(data.try_into().unwrap());
+                match self.name {
+                    // Contention for our address, the lower NAME wins.
+                    Some(own)
+                        if id.source == self.address && self.address != NULL && own != name =>
+                    {
+                        if own > name {
+                            self.addresses.update(&message);
+                            self.address = self.free_address().unwrap_or(NULL);
+                        }
+                        self.send_address_claim()?;
+                    }
+                    _ => self.addresses.update(&message),
+                }
+                Ok(Some(message))
+            }
+            _ => Ok(Some(Message {
+                id,
+                data: data.to_vec(),
+            })),
+        }
+    }
+
+    /// Sends an address claimed message, or a cannot claim message if the node
+    /// has no address.
+    fn send_address_claim(&mut self) -> Result<(), Error> {
+        let name = self.name.unwrap_or_default();
+        self.transmit(
+            Identifier::new(DEFAULT_PRIORITY, Pgn::ADDRESS_CLAIMED, self.address),
+            &name.to_bytes(),
+        )?;
+        self.claim_deadline = Instant::now() + ADDRESS_CLAIM_TIMEOUT;
+        Ok(())
+    }
+
+    /// Picks an address not claimed by other nodes, if the NAME allows it.
+    fn free_address(&self) -> Option<u8> {
+        if !self.name?.arbitrary_address_capable() {
+            return None;
+        }
+        // The self-configurable address range.
+        (128..=247).find(|&address| address != self.address && !self.addresses.contains(address))
+    }
+
+    fn check_address(&self) -> Result<(), Error> {
+        if self.address == NULL {
+            return Err(Error(
+                "J1939 node without an address cannot send messages".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    fn send_bam(&mut self, pgn: Pgn, data: &[u8]) -> Result<(), Error> {
+        let packets = packet_count(data.len())?;
+        let [len_low, len_high] = (data.len() as u16).to_le_bytes();
+        let [a, b, c] = pgn.to_bytes();
+        self.transmit(
+            Identifier::new(TRANSPORT_PRIORITY, Pgn::TRANSPORT_CONNECTION, self.address),
+            &[CONNECTION_BAM, len_low, len_high, packets, 0xFF, a, b, c],
+        )?;
+        for sequence_number in 1..=packets {
+            thread::sleep(BAM_PACKET_INTERVAL);
+            self.send_packet(GLOBAL, sequence_number, data)?;
+        }
+        Ok(())
+    }
+
+    fn send_rts(&mut self, pgn: Pgn, destination: u8, data: &[u8]) -> Result<(), Error> {
+        let packets = packet_count(data.len())?;
+        let [len_low, len_high] = (data.len() as u16).to_le_bytes();
+        let [a, b, c] = pgn.to_bytes();
+        self.transmit(
+            Identifier::new(TRANSPORT_PRIORITY, Pgn::TRANSPORT_CONNECTION, self.address)
+                .with_destination(destination),
+            &[CONNECTION_RTS, len_low, len_high, packets, 0xFF, a, b, c],
+        )?;
+
+        let mut timeout = T3;
+        loop {
+            let Some(cm) = self.wait_connection(destination, pgn, timeout)? else {
+                self.abort(destination, pgn, ABORT_TIMEOUT)?;
+                return Err(Error(format!(
+                    "J1939 transfer of PGN {} to {:#04x} timed out",
+                    pgn.0, destination
+                )));
+            };
+            match cm[0] {
+                // A CTS for zero packets holds the connection open.
+                CONNECTION_CTS if cm[1] == 0 => timeout = T4,
+                CONNECTION_CTS => {
+                    let next = cm[2].max(1);
+                    let last = next.saturating_add(cm[1] - 1).min(packets);
+                    for sequence_number in next..=last {
+                        self.send_packet(destination, sequence_number, data)?;
+                    }
+                    timeout = T3;
+                }
+                CONNECTION_END_OF_MESSAGE_ACK => return Ok(()),
+                CONNECTION_ABORT => {
+                    return Err(Error(format!(
+                        "J1939 transfer of PGN {} aborted by {:#04x}, reason {}",
+                        pgn.0, destination, cm[1]
+                    )));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Sends packet `sequence_number` of `data`, padded to 8 bytes.
+    fn send_packet(
+        &mut self,
+        destination: u8,
+        sequence_number: u8,
+        data: &[u8],
+    ) -> Result<(), Error> {
+        let start = (sequence_number as usize - 1) * 7;
+        let chunk = &data[start..(start + 7).min(data.len())];
+        let mut packet = [0xFF; 8];
+        packet[0] = sequence_number;
+        packet[1..1 + chunk.len()].copy_from_slice(chunk);
+        self.transmit(
+            Identifier::new(TRANSPORT_PRIORITY, Pgn::TRANSPORT_DATA, self.address)
+                .with_destination(destination),
+            &packet,
+        )
+    }
+
+    fn receive_rts(&mut self, id: Identifier, cm: &[u8]) -> Result<Option<Message>, Error> {
+        let len = u16::from_le_bytes([cm[1], cm[2]]) as usize;
+        let packets = cm[3];
+        let max_packets = cm[4].max(1);
+        let pgn = Pgn::from_bytes(&cm[5..]);
+        if packets as usize != len.div_ceil(7) {
+            return Ok(None);
+        }
+
+        let mut data = Vec::with_capacity(packets as usize * 7);
+        // Counted in `usize` as the packet after the last one may be 256.
+        let mut next = 1;
+        while next <= packets as usize {
+            let count = (packets as usize - next + 1).min(max_packets as usize);
+            let [a, b, c] = pgn.to_bytes();
+            self.transmit(
+                Identifier::new(TRANSPORT_PRIORITY, Pgn::TRANSPORT_CONNECTION, self.address)
+                    .with_destination(id.source),
+                &[CONNECTION_CTS, count as u8, next as u8, 0xFF, 0xFF, a, b, c],
+            )?;
+
+            for index in 0..count {
+                let timeout = if index == 0 { T2 } else { T1 };
+                let Some(packet) = self.wait_packet(id.source, self.address, timeout)? else {
+                    self.abort(id.source, pgn, ABORT_TIMEOUT)?;
+                    return Ok(None);
+                };
+                if packet[0] as usize != next {
+                    self.abort(id.source, pgn, ABORT_BAD_SEQUENCE_NUMBER)?;
+                    return Ok(None);
+                }
+                data.extend_from_slice(&packet[1..]);
+                next += 1;
+            }
+        }
+        data.truncate(len);
+
+        let [a, b, c] = pgn.to_bytes();
+        self.transmit(
+            Identifier::new(TRANSPORT_PRIORITY, Pgn::TRANSPORT_CONNECTION, self.address)
+                .with_destination(id.source),
+            &[
+                CONNECTION_END_OF_MESSAGE_ACK,
+                cm[1],
+                cm[2],
+                packets,
+                0xFF,
+                a,
+                b,
+                c,
+            ],
+        )?;
+        Ok(Some(Message {
+            id: Identifier::new(id.priority, pgn, id.source).with_destination(self.address),
+            data,
+        }))
+    }
+
+    /// Reassembles a broadcast, which is dropped if a packet is missing.
+    fn receive_bam(&mut self, id: Identifier, cm: &[u8]) -> Result<Option<Message>, Error> {
+        let len = u16::from_le_bytes([cm[1], cm[2]]) as usize;
+        let packets = cm[3];
+        let pgn = Pgn::from_bytes(&cm[5..]);
+        if packets as usize != len.div_ceil(7) {
+            return Ok(None);
+        }
+
+        let mut data = Vec::with_capacity(packets as usize * 7);
+        for sequence_number in 1..=packets {
+            match self.wait_packet(id.source, GLOBAL, T1)? {
+                Some(packet) if packet[0] == sequence_number => {
+                    data.extend_from_slice(&packet[1..])
+                }
+                _ => return Ok(None),
+            }
+        }
+        data.truncate(len);
+        Ok(Some(Message {
+            id: Identifier::new(id.priority, pgn, id.source),
+            data,
+        }))
+    }
+
+    /// Waits for a connection management frame of the session for `pgn` with `source`.
+    fn wait_connection(
+        &mut self,
+        source: u8,
+        pgn: Pgn,
+        timeout: Duration,
+    ) -> Result<Option<Vec<u8>>, Error> {
+        let id =
+            Identifier::new(0, Pgn::TRANSPORT_CONNECTION, source).with_destination(self.address);
+        let pgn = pgn.to_bytes();
+        // Ignores the priority.
+        let filter = ResponseFilter::new(ExtendedId::from(id))
+            .with_mask(0x03FF_FFFF)
+            .with_data(|data| data.len() == 8 && data[5..] == pgn);
+        Ok(self
+            .interface
+            .receive_matching(&filter, timeout)?
+            .map(|frame| frame.data().to_vec()))
+    }
+
+    fn wait_packet(
+        &mut self,
+        source: u8,
+        destination: u8,
+        timeout: Duration,
+    ) -> Result<Option<Vec<u8>>, Error> {
+        let id = Identifier::new(0, Pgn::TRANSPORT_DATA, source).with_destination(destination);
+        let filter = ResponseFilter::new(ExtendedId::from(id))
+            .with_mask(0x03FF_FFFF)
+            .with_data(|data| data.len() == 8);
+        Ok(self
+            .interface
+            .receive_matching(&filter, timeout)?
+            .map(|frame| frame.data().to_vec()))
+    }
+
+    fn abort(&mut self, destination: u8, pgn: Pgn, reason: u8) -> Result<(), Error> {
+        let [a, b, c] = pgn.to_bytes();
+        self.transmit(
+            Identifier::new(TRANSPORT_PRIORITY, Pgn::TRANSPORT_CONNECTION, self.address)
+                .with_destination(destination),
+            &[CONNECTION_ABORT, reason, 0xFF, 0xFF, 0xFF, a, b, c],
+        )
+    }
+
+    fn transmit(&mut self, id: Identifier, data: &[u8]) -> Result<(), Error> {
+        let frame = Frame::new(id, data).unwrap();
+        embedded_can::blocking::Can::transmit(self.interface, &frame)
+    }
+}
+
+/// Returns the number of transport protocol packets carrying `len` bytes.
+fn packet_count(len: usize) -> Result<u8, Error> {
+    u8::try_from(len.div_ceil(7)).map_err(|_| {
+        Error(format!(
+            "J1939 message of {} bytes does not fit in 255 packets",
+            len
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn identifier(raw: u32) -> Identifier {
+        ExtendedId::new(raw).unwrap().into()
+    }
+
+    fn raw(id: Identifier) -> u32 {
+        ExtendedId::from(id).as_raw()
+    }
+
+    #[test]
+    fn pdu_format() {
+        assert_eq!(Pgn(0xEF00).pdu_format(), PduFormat::Pdu1);
+        assert_eq!(Pgn::REQUEST.pdu_format(), PduFormat::Pdu1);
+        assert_eq!(Pgn(0xF000).pdu_format(), PduFormat::Pdu2);
+        assert_eq!(Pgn(0xFECA).pdu_format(), PduFormat::Pdu2);
+        // The data page is not part of the PDU format.
+        assert_eq!(Pgn(0x1EF00).pdu_format(), PduFormat::Pdu1);
+        assert_eq!(Pgn(0x1F004).pdu_format(), PduFormat::Pdu2);
+    }
+
+    #[test]
+    fn pgn_bytes() {
+        assert_eq!(Pgn(0xFECA).to_bytes(), [0xCA, 0xFE, 0x00]);
+        assert_eq!(Pgn(0x1F004).to_bytes(), [0x04, 0xF0, 0x01]);
+        assert_eq!(Pgn::from_bytes(&[0xCA, 0xFE, 0x00]), Pgn(0xFECA));
+        // Bits above the data page are reserved.
+        assert_eq!(Pgn::from_bytes(&[0x04, 0xF0, 0xFD]), Pgn(0x1F004));
+
+        let request = Message {
+            id: Identifier::new(6, Pgn::REQUEST, 0x80),
+            data: vec![0x00, 0xEE, 0x00],
+        };
+        assert_eq!(request.requested_pgn(), Some(Pgn::ADDRESS_CLAIMED));
+        let short = Message {
+            data: vec![0x00, 0xEE],
+            ..request.clone()
+        };
+        assert_eq!(short.requested_pgn(), None);
+        let other = Message {
+            id: Identifier::new(6, Pgn(0xFECA), 0x80),
+            ..request
+        };
+        assert_eq!(other.requested_pgn(), None);
+    }
+
+    #[test]
+    fn pdu1_identifier() {
+        // Request from 0xF9 to 0x00.
+        let id = identifier(0x18EA_00F9);
+        assert_eq!(
+            id,
+            Identifier {
+                priority: 6,
+                pgn: Pgn::REQUEST,
+                source: 0xF9,
+                destination: 0x00,
+            }
+        );
+        assert_eq!(raw(id), 0x18EA_00F9);
+
+        // The destination is not part of the PGN, and overrides its low byte.
+        let id = Identifier::new(3, Pgn(0xEF12), 0x21).with_destination(0x42);
+        assert_eq!(raw(id), 0x0CEF_4221);
+        assert_eq!(identifier(raw(id)).pgn, Pgn(0xEF00));
+
+        let id = Identifier::new(7, Pgn::TRANSPORT_CONNECTION, 0x01);
+        assert_eq!(raw(id), 0x1CEC_FF01);
+    }
+
+    #[test]
+    fn pdu2_identifier() {
+        // DM1 from the engine with the group extension in the PDU specific byte.
+        let id = identifier(0x18FE_CA00);
+        assert_eq!(
+            id,
+            Identifier {
+                priority: 6,
+                pgn: Pgn(0xFECA),
+                source: 0x00,
+                destination: GLOBAL,
+            }
+        );
+        assert_eq!(raw(id), 0x18FE_CA00);
+
+        // The destination is ignored for broadcast messages.
+        let id = Identifier::new(6, Pgn(0xFECA), 0x00).with_destination(0x17);
+        assert_eq!(raw(id), 0x18FE_CA00);
+
+        // Data page and extended data page.
+        let id = identifier(0x0DF0_0403);
+        assert_eq!(id.priority, 3);
+        assert_eq!(id.pgn, Pgn(0x1F004));
+        assert_eq!(raw(id), 0x0DF0_0403);
+        assert_eq!(identifier(0x03F0_0400).pgn, Pgn(0x3F004));
+
+        // Priorities above 7 do not leak into other fields.
+        assert_eq!(raw(Identifier::new(0xFF, Pgn(0xFECA), 0x00)), 0x1CFE_CA00);
+
+        assert_eq!(
+            Id::from(id),
+            Id::Extended(ExtendedId::new(0x0DF0_0403).unwrap())
+        );
+    }
+
+    #[test]
+    fn packet_counts() {
+        assert_eq!(packet_count(9).unwrap(), 2);
+        assert_eq!(packet_count(14).unwrap(), 2);
+        assert_eq!(packet_count(15).unwrap(), 3);
+        assert_eq!(packet_count(MAX_TRANSPORT_LEN).unwrap(), 255);
+        assert!(packet_count(MAX_TRANSPORT_LEN + 1).is_err());
+        assert!(packet_count(usize::MAX).is_err());
+    }
+}
@@ -0,0 +1,294 @@
+use std::collections::BTreeMap;
+
+use super::{Message, NULL, Pgn};
+
+/// The 64-bit NAME identifying a controller application. A lower NAME has a
+/// higher priority when two nodes claim the same address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct Name(pub u64);
+
+impl Name {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether the node may pick another address if its preferred one is taken.
+    pub fn with_arbitrary_address_capable(self, capable: bool) -> Self {
+        self.with_field(63, 1, capable as u64)
+    }
+
+    /// Sets the industry group, 3 bits.
+    pub fn with_industry_group(self, group: u8) -> Self {
+        self.with_field(60, 3, group as u64)
+    }
+
+    /// Sets the vehicle system instance, 4 bits.
+    pub fn with_vehicle_system_instance(self, instance: u8) -> Self {
+        self.with_field(56, 4, instance as u64)
+    }
+
+    /// Sets the vehicle system, 7 bits.
+    pub fn with_vehicle_system(self, system: u8) -> Self {
+        self.with_field(49, 7, system as u64)
+    }
+
+    pub fn with_function(self, function: u8) -> Self {
+        self.with_field(40, 8, function as u64)
+    }
+
+    /// Sets the function instance, 5 bits.
+    pub fn with_function_instance(self, instance: u8) -> Self {
+        self.with_field(35, 5, instance as u64)
+    }
+
+    /// Sets the ECU instance, 3 bits.
+    pub fn with_ecu_instance(self, instance: u8) -> Self {
+        self.with_field(32, 3, instance as u64)
+    }
+
+    /// Sets the manufacturer code, 11 bits.
+    pub fn with_manufacturer_code(self, code: u16) -> Self {
+        self.with_field(21, 11, code as u64)
+    }
+
+    /// Sets the identity number, 21 bits.
+    pub fn with_identity_number(self, number: u32) -> Self {
+        self.with_field(0, 21, number as u64)
+    }
+
+    pub fn arbitrary_address_capable(self) -> bool {
+        self.field(63, 1) != 0
+    }
+
+    pub fn industry_group(self) -> u8 {
+        self.field(60, 3) as u8
+    }
+
+    pub fn vehicle_system_instance(self) -> u8 {
+        self.field(56, 4) as u8
+    }
+
+    pub fn vehicle_system(self) -> u8 {
+        self.field(49, 7) as u8
+    }
+
+    pub fn function(self) -> u8 {
+        self.field(40, 8) as u8
+    }
+
+    pub fn function_instance(self) -> u8 {
+        self.field(35, 5) as u8
+    }
+
+    pub fn ecu_instance(self) -> u8 {
+        self.field(32, 3) as u8
+    }
+
+    pub fn manufacturer_code(self) -> u16 {
+        self.field(21, 11) as u16
+    }
+
+    pub fn identity_number(self) -> u32 {
+        self.field(0, 21) as u32
+    }
+
+    pub fn to_bytes(self) -> [u8; 8] {
+        self.0.to_le_bytes()
+    }
+
+    pub fn from_bytes(bytes: [u8; 8]) -> Self {
+        Name(u64::from_le_bytes(bytes))
+    }
+
+    fn with_field(self, start: u32, size: u32, value: u64) -> Self {
+        let mask = ((1 << size) - 1) << start;
+        Name((self.0 & !mask) | ((value << start) & mask))
+    }
+
+    fn field(self, start: u32, size: u32) -> u64 {
+        (self.0 >> start) & ((1 << size) - 1)
+    }
+}
+
+/// The addresses claimed by other nodes, as seen in address claimed messages.
+#[derive(Debug, Clone, Default)]
+pub struct AddressTable {
+    entries: BTreeMap<u8, Name>,
+}
+
+impl AddressTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the claim of an address claimed message, other messages are
+    /// ignored. A cannot claim message removes the node from the table.
+    pub fn update(&mut self, message: &Message) {
+        if message.id.pgn != Pgn::ADDRESS_CLAIMED {
+            return;
+        }
+        let Ok(bytes) = message.data.as_slice().try_into() else {
+            return;
+        };
+        let name = Name::from_bytes(bytes);
+        // A node that claims a new address gives up its previous one.
+        self.entries.retain(|_, n| *n != name);
+        if message.id.source != NULL {
+            self.entries.insert(message.id.source, name);
+        }
+    }
+
+    pub fn remove(&mut self, address: u8) -> Option<Name> {
+        self.entries.remove(&address)
+    }
+
+    /// The NAME of the node holding `address`.
+    pub fn name(&self, address: u8) -> Option<Name> {
+        self.entries.get(&address).copied()
+    }
+
+    /// The address of the node with `name`.
+    pub fn address(&self, name: Name) -> Option<u8> {
+        self.entries
+            .iter()
+            .find(|(_, n)| **n == name)
+            .map(|(address, _)| *address)
+    }
+
+    pub fn contains(&self, address: u8) -> bool {
+        self.entries.contains_key(&address)
+    }
+
+    /// Iterates over the addresses and NAMEs in ascending address order.
+    pub fn iter(&self) -> impl Iterator<Item = (u8, Name)> + '_ {
+        self.entries.iter().map(|(address, name)| (*address, *name))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::j1939::{GLOBAL, Identifier};
+
+    fn name() -> Name {
+        Name::new()
+            .with_arbitrary_address_capable(true)
+            .with_industry_group(1)
+            .with_vehicle_system_instance(0x3)
+            .with_vehicle_system(0x7F)
+            .with_function(0x81)
+            .with_function_instance(0x1F)
+            .with_ecu_instance(0x2)
+            .with_manufacturer_code(0x123)
+            .with_identity_number(0x1ABCDE)
+    }
+
+    fn claim(source: u8, name: Name) -> Message {
+        Message {
+            id: Identifier::new(6, Pgn::ADDRESS_CLAIMED, source),
+            data: name.to_bytes().to_vec(),
+        }
+    }
+
+    #[test]
+    fn name_fields() {
+        let name = name();
+        assert_eq!(name.0, 0x93_FE_81_FA_24_7A_BC_DE);
+        assert!(name.arbitrary_address_capable());
+        assert_eq!(name.industry_group(), 1);
+        assert_eq!(name.vehicle_system_instance(), 0x3);
+        assert_eq!(name.vehicle_system(), 0x7F);
+        assert_eq!(name.function(), 0x81);
+        assert_eq!(name.function_instance(), 0x1F);
+        assert_eq!(name.ecu_instance(), 0x2);
+        assert_eq!(name.manufacturer_code(), 0x123);
+        assert_eq!(name.identity_number(), 0x1ABCDE);
+
+        // Setting a field replaces it without touching its neighbours.
+        let name = name.with_function(0x00).with_manufacturer_code(0x7FF);
+        assert_eq!(name.function(), 0x00);
+        assert_eq!(name.vehicle_system(), 0x7F);
+        assert_eq!(name.function_instance(), 0x1F);
+        assert_eq!(name.manufacturer_code(), 0x7FF);
+        assert_eq!(name.ecu_instance(), 0x2);
+        assert_eq!(name.identity_number(), 0x1ABCDE);
+
+        // Values wider than the field are truncated.
+        let name = Name::new()
+            .with_industry_group(0xFF)
+            .with_identity_number(u32::MAX);
+        assert_eq!(name.0, 0x7000_0000_001F_FFFF);
+    }
+
+    #[test]
+    fn name_bytes() {
+        let name = name();
+        let bytes = [0xDE, 0xBC, 0x7A, 0x24, 0xFA, 0x81, 0xFE, 0x93];
+        assert_eq!(name.to_bytes(), bytes);
+        assert_eq!(Name::from_bytes(bytes), name);
+    }
+
+    #[test]
+    fn contention_order() {
+        // The lower NAME wins a contention, so the arbitrary address capable
+        // bit in the most significant position loses against any fixed node.
+        let fixed = name().with_arbitrary_address_capable(false);
+        assert!(fixed < name());
+        assert!(fixed < Name::new().with_arbitrary_address_capable(true));
+        assert!(name().with_identity_number(1) < name().with_identity_number(2));
+        assert!(
+            name().with_function(0x80).with_identity_number(0x1FFFFF)
+                < name().with_function(0x81).with_identity_number(0)
+        );
+    }
+
+    #[test]
+    fn address_table() {
+        let mut table = AddressTable::new();
+        let a = name().with_identity_number(1);
+        let b = name().with_identity_number(2);
+
+        table.update(&claim(0x80, a));
+        table.update(&claim(0x81, b));
+        assert_eq!(table.len(), 2);
+        assert_eq!(table.name(0x80), Some(a));
+        assert_eq!(table.address(b), Some(0x81));
+
+        // Moving to another address frees the previous one.
+        table.update(&claim(0x90, a));
+        assert!(!table.contains(0x80));
+        assert_eq!(table.address(a), Some(0x90));
+        assert_eq!(table.iter().collect::<Vec<_>>(), [(0x81, b), (0x90, a)]);
+
+        // Another node winning the address replaces the entry.
+        table.update(&claim(0x81, a));
+        assert_eq!(table.iter().collect::<Vec<_>>(), [(0x81, a)]);
+
+        // Cannot claim removes the node.
+        table.update(&claim(NULL, a));
+        assert!(table.is_empty());
+
+        // Other messages and malformed claims are ignored.
+        table.update(&Message {
+            id: Identifier::new(6, Pgn(0xFECA), 0x80),
+            data: a.to_bytes().to_vec(),
+        });
+        table.update(&Message {
+            id: Identifier::new(6, Pgn::ADDRESS_CLAIMED, 0x80).with_destination(GLOBAL),
+            data: vec![0; 7],
+        });
+        assert!(table.is_empty());
+
+        table.update(&claim(0x80, a));
+        assert_eq!(table.remove(0x80), Some(a));
+        assert_eq!(table.remove(0x80), None);
+    }
+}
@@ -0,0 +1,421 @@
+//! J1939-73 diagnostic messages: active (DM1) and previously active (DM2)
+//! trouble codes, and clearing them with DM3 and DM11.
+//!
+//! DM1 messages with more than one trouble code are broadcast with BAM and
+//! reassembled by [`Node`]:
+//!
+//! ```text
+//! let message = node.recv()?;
+//! if message.id.pgn == diag::DM1 {
+//!     let dm1 = DiagnosticMessage::decode(&message.data)?;
+//!     for dtc in &dm1.dtcs {
+//!         println!("{:#04x}: SPN {} FMI {} ({}x)", message.id.source, dtc.spn, dtc.fmi, dtc.occurrence_count);
+//!     }
+//! }
+//! ```
+
+use std::time::Duration;
+
+use super::{DEFAULT_PRIORITY, GLOBAL, Node, Pgn};
+use crate::Error;
+
+/// Active diagnostic trouble codes.
+pub const DM1: Pgn = Pgn(65226);
+/// Previously active diagnostic trouble codes.
+pub const DM2: Pgn = Pgn(65227);
+/// Clearing of previously active trouble codes.
+pub const DM3: Pgn = Pgn(65228);
+/// Clearing of active trouble codes.
+pub const DM11: Pgn = Pgn(65235);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum LampStatus {
+    #[default]
+    Off,
+    On,
+    Error,
+    NotAvailable,
+}
+
+impl LampStatus {
+    fn from_bits(bits: u8) -> Self {
+        match bits & 0x3 {
+            0 => LampStatus::Off,
+            1 => LampStatus::On,
+            2 => LampStatus::Error,
+            _ => LampStatus::NotAvailable,
+        }
+    }
+
+    fn bits(self) -> u8 {
+        match self {
+            LampStatus::Off => 0,
+            LampStatus::On => 1,
+            LampStatus::Error => 2,
+            LampStatus::NotAvailable => 3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum FlashStatus {
+    /// Once per second.
+    Slow,
+    /// Twice per second.
+    Fast,
+    Reserved,
+    /// Not flashing, or not available.
+    #[default]
+    Off,
+}
+
+impl FlashStatus {
+    fn from_bits(bits: u8) -> Self {
+        match bits & 0x3 {
+            0 => FlashStatus::Slow,
+            1 => FlashStatus::Fast,
+            2 => FlashStatus::Reserved,
+            _ => FlashStatus::Off,
+        }
+    }
+
+    fn bits(self) -> u8 {
+        match self {
+            FlashStatus::Slow => 0,
+            FlashStatus::Fast => 1,
+            FlashStatus::Reserved => 2,
+            FlashStatus::Off => 3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Lamp {
+    pub status: LampStatus,
+    pub flash: FlashStatus,
+}
+
+/// The lamps reported in front of the trouble codes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Lamps {
+    pub malfunction_indicator: Lamp,
+    pub red_stop: Lamp,
+    pub amber_warning: Lamp,
+    pub protect: Lamp,
+}
+
+impl Lamps {
+    fn decode(status: u8, flash: u8) -> Self {
+        let lamp = |shift: u8| Lamp {
+            status: LampStatus::from_bits(status >> shift),
+            flash: FlashStatus::from_bits(flash >> shift),
+        };
+        Self {
+            malfunction_indicator: lamp(6),
+            red_stop: lamp(4),
+            amber_warning: lamp(2),
+            protect: lamp(0),
+        }
+    }
+
+    fn encode(&self) -> [u8; 2] {
+        let lamps = [
+            self.malfunction_indicator,
+            self.red_stop,
+            self.amber_warning,
+            self.protect,
+        ];
+        let mut bytes = [0; 2];
+        for lamp in lamps {
+            bytes[0] = (bytes[0] << 2) | lamp.status.bits();
+            bytes[1] = (bytes[1] << 2) | lamp.flash.bits();
+        }
+        bytes
+    }
+}
+
+/// A diagnostic trouble code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Dtc {
+    /// Suspect parameter number, 19 bits.
+    pub spn: u32,
+    /// Failure mode identifier, 5 bits.
+    pub fmi: u8,
+    /// Number of times the fault became active, 7 bits. 127 if not available.
+    pub occurrence_count: u8,
+}
+
+impl Dtc {
+    /// Decodes a trouble code with conversion method version 4.
+    fn decode(bytes: &[u8]) -> Self {
+        Self {
+            spn: u32::from_le_bytes([bytes[0], bytes[1], bytes[2] >> 5, 0]),
+            fmi: bytes[2] & 0x1F,
+            occurrence_count: bytes[3] & 0x7F,
+        }
+    }
+
+    fn encode(&self) -> [u8; 4] {
+        let [low, middle, high, _] = self.spn.to_le_bytes();
+        [
+            low,
+            middle,
+            ((high & 0x7) << 5) | (self.fmi & 0x1F),
+            self.occurrence_count & 0x7F,
+        ]
+    }
+}
+
+/// The content of a DM1 or DM2 message.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct DiagnosticMessage {
+    pub lamps: Lamps,
+    pub dtcs: Vec<Dtc>,
+}
+
+impl DiagnosticMessage {
+    pub fn decode(data: &[u8]) -> Result<Self, Error> {
+        let [status, flash, dtcs @ ..] = data else {
+            return Err(Error(format!(
+                "J1939 diagnostic message of {} bytes is too short",
+                data.len()
+            )));
+        };
+        Ok(Self {
+            lamps: Lamps::decode(*status, *flash),
+            dtcs: dtcs
+                .chunks_exact(4)
+                .map(Dtc::decode)
+                // An empty code is sent if there is none.
+                .filter(|dtc| dtc.spn != 0 || dtc.fmi != 0)
+                .collect(),
+        })
+    }
+
+    /// Encodes the message, at least 8 bytes long.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut data = self.lamps.encode().to_vec();
+        if self.dtcs.is_empty() {
+            data.extend([0; 4]);
+        }
+        for dtc in &self.dtcs {
+            data.extend(dtc.encode());
+        }
+        if data.len() < 8 {
+            data.resize(8, 0xFF);
+        }
+        data
+    }
+}
+
+/// Requests the active trouble codes (DM1) of `source`.
+pub fn active_dtcs(
+    node: &mut Node<'_>,
+    source: u8,
+    timeout: Duration,
+) -> Result<Option<DiagnosticMessage>, Error> {
+    node.request(DM1, source, timeout)?
+        .map(|message| DiagnosticMessage::decode(&message.data))
+        .transpose()
+}
+
+/// Requests the previously active trouble codes (DM2) of `source`.
+pub fn previously_active_dtcs(
+    node: &mut Node<'_>,
+    source: u8,
+    timeout: Duration,
+) -> Result<Option<DiagnosticMessage>, Error> {
+    node.request(DM2, source, timeout)?
+        .map(|message| DiagnosticMessage::decode(&message.data))
+        .transpose()
+}
+
+/// Clears the previously active trouble codes of `destination` with DM3.
+pub fn clear_previously_active_dtcs(
+    node: &mut Node<'_>,
+    destination: u8,
+    timeout: Duration,
+) -> Result<(), Error> {
+    clear(node, DM3, destination, timeout)
+}
+
+/// Clears the active trouble codes of `destination` with DM11.
+pub fn clear_active_dtcs(
+    node: &mut Node<'_>,
+    destination: u8,
+    timeout: Duration,
+) -> Result<(), Error> {
+    clear(node, DM11, destination, timeout)
+}
+
+/// Broadcasts the active trouble codes of the node as DM1, with BAM if there
+/// is more than one.
+pub fn send_active_dtcs(node: &mut Node<'_>, message: &DiagnosticMessage) -> Result<(), Error> {
+    node.send(DEFAULT_PRIORITY, DM1, GLOBAL, &message.encode())
+}
+
+/// Sends a clear command, waiting for the acknowledgement unless it is sent
+/// to the global address, which is not acknowledged.
+fn clear(node: &mut Node<'_>, pgn: Pgn, destination: u8, timeout: Duration) -> Result<(), Error> {
+    if destination == GLOBAL {
+        return node.send(DEFAULT_PRIORITY, Pgn::REQUEST, GLOBAL, &pgn.to_bytes());
+    }
+    node.request(pgn, destination, timeout)?
+        .map(|_| ())
+        .ok_or_else(|| {
+            Error(format!(
+                "J1939 clear command PGN {} not acknowledged by {:#04x}",
+                pgn.0, destination
+            ))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dtc() {
+        // SPN 100 (engine oil pressure), FMI 1, occurred 3 times.
+        let dtc = Dtc {
+            spn: 100,
+            fmi: 1,
+            occurrence_count: 3,
+        };
+        assert_eq!(dtc.encode(), [0x64, 0x00, 0x01, 0x03]);
+        assert_eq!(Dtc::decode(&[0x64, 0x00, 0x01, 0x03]), dtc);
+
+        // The three most significant bits of the 19 bit SPN share a byte with the FMI.
+        let dtc = Dtc {
+            spn: 0x7_F008,
+            fmi: 0x1F,
+            occurrence_count: 0x7F,
+        };
+        assert_eq!(dtc.encode(), [0x08, 0xF0, 0xFF, 0x7F]);
+        assert_eq!(Dtc::decode(&[0x08, 0xF0, 0xFF, 0x7F]), dtc);
+        assert_eq!(
+            Dtc::decode(&[0x00, 0x00, 0xA0, 0x01]),
+            Dtc {
+                spn: 0x5_0000,
+                fmi: 0,
+                occurrence_count: 1,
+            }
+        );
+
+        // The conversion method bit is not part of the occurrence count and
+        // always encoded as version 4.
+        assert_eq!(Dtc::decode(&[0x64, 0x00, 0x01, 0x83]).occurrence_count, 3);
+
+        // Out of range values are truncated to their fields.
+        let dtc = Dtc {
+            spn: 0xF_FFFF,
+            fmi: 0xFF,
+            occurrence_count: 0xFF,
+        };
+        assert_eq!(dtc.encode(), [0xFF, 0xFF, 0xFF, 0x7F]);
+    }
+
+    #[test]
+    fn lamps() {
+        for (status, flash, lamps) in [
+            (0x00, 0xFF, Lamps::default()),
+            (
+                0x04,
+                0xFF,
+                Lamps {
+                    amber_warning: Lamp {
+                        status: LampStatus::On,
+                        flash: FlashStatus::Off,
+                    },
+                    ..Lamps::default()
+                },
+            ),
+            (
+                0x50,
+                0x1B,
+                Lamps {
+                    malfunction_indicator: Lamp {
+                        status: LampStatus::On,
+                        flash: FlashStatus::Slow,
+                    },
+                    red_stop: Lamp {
+                        status: LampStatus::On,
+                        flash: FlashStatus::Fast,
+                    },
+                    amber_warning: Lamp {
+                        status: LampStatus::Off,
+                        flash: FlashStatus::Reserved,
+                    },
+                    protect: Lamp {
+                        status: LampStatus::Off,
+                        flash: FlashStatus::Off,
+                    },
+                },
+            ),
+            (
+                0xE2,
+                0xFF,
+                Lamps {
+                    malfunction_indicator: Lamp {
+                        status: LampStatus::NotAvailable,
+                        flash: FlashStatus::Off,
+                    },
+                    red_stop: Lamp {
+                        status: LampStatus::Error,
+                        flash: FlashStatus::Off,
+                    },
+                    amber_warning: Lamp::default(),
+                    protect: Lamp {
+                        status: LampStatus::Error,
+                        flash: FlashStatus::Off,
+                    },
+                },
+            ),
+        ] {
+            assert_eq!(Lamps::decode(status, flash), lamps);
+            assert_eq!(lamps.encode(), [status, flash]);
+        }
+    }
+
+    #[test]
+    fn no_dtc() {
+        let message = DiagnosticMessage::default();
+        let data = message.encode();
+        assert_eq!(data, [0x00, 0xFF, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF]);
+        assert_eq!(DiagnosticMessage::decode(&data).unwrap(), message);
+
+        // Only the lamps.
+        assert_eq!(DiagnosticMessage::decode(&[0x00, 0xFF]).unwrap(), message);
+        assert!(DiagnosticMessage::decode(&[0x00]).is_err());
+    }
+
+    #[test]
+    fn dm1() {
+        // Amber warning lamp on with the oil pressure trouble code.
+        let data = [0x04, 0xFF, 0x64, 0x00, 0x01, 0x03, 0xFF, 0xFF];
+        let message = DiagnosticMessage::decode(&data).unwrap();
+        assert_eq!(message.lamps.amber_warning.status, LampStatus::On);
+        assert_eq!(
+            message.dtcs,
+            [Dtc {
+                spn: 100,
+                fmi: 1,
+                occurrence_count: 3,
+            }]
+        );
+        assert_eq!(message.encode(), data);
+
+        // Two trouble codes, sent with the transport protocol.
+        let data = [0x44, 0xFF, 0x64, 0x00, 0x01, 0x03, 0x6E, 0x00, 0x00, 0x01];
+        let message = DiagnosticMessage::decode(&data).unwrap();
+        assert_eq!(message.lamps.malfunction_indicator.status, LampStatus::On);
+        assert_eq!(message.dtcs.len(), 2);
+        assert_eq!(message.dtcs[1].spn, 110);
+        assert_eq!(message.dtcs[1].fmi, 0);
+        assert_eq!(message.encode(), data);
+
+        // Trailing bytes of an incomplete code are ignored.
+        let message = DiagnosticMessage::decode(&data[..9]).unwrap();
+        assert_eq!(message.dtcs.len(), 1);
+    }
+}
@@ -5,14 +5,30 @@ pub mod prelude {
 pub use embedded_can::{ExtendedId, Id, StandardId};
 
 mod baudrate;
+mod cyclic;
 mod error;
 mod filter;
 mod frame;
 mod interface;
+mod replay;
 mod sys;
 
+pub mod canopen;
+pub mod dbc;
+pub mod isotp;
+pub mod j1939;
+pub mod log;
+pub mod obd;
+pub mod uds;
+pub mod xcp;
+
 pub use baudrate::Baudrate;
+pub use cyclic::{CyclicScheduler, CyclicStats, MessageHandle};
 pub use error::Error;
-pub use filter::Filter;
-pub use frame::Frame;
-pub use interface::Interface;
+pub use filter::{Filter, ResponseFilter};
+pub use frame::{FdFrame, Frame};
+pub use interface::{Drain, Frames, Interface, MAX_PENDING};
+pub use replay::{Replay, ReplayReport};
+
+#[cfg(feature = "async")]
+pub use interface::{FrameStream, STREAM_POLL_INTERVAL};
@@ -0,0 +1,151 @@
+//! Readers and writers for CAN trace file formats.
+
+pub mod asc;
+pub mod blf;
+pub mod candump;
+pub mod pcapng;
+pub mod trc;
+
+use std::time::Duration;
+
+use crate::{Error, FdFrame, Frame};
+
+/// Whether a recorded frame was received or transmitted by the recording node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Direction {
+    #[default]
+    Rx,
+    Tx,
+}
+
+/// An error frame as reported by the recording tool.
+///
+/// The error class uses the SocketCAN `CAN_ERR_*` bit definitions and `data`
+/// holds the error details in the SocketCAN layout. Formats that only record
+/// the occurrence of an error frame leave both zeroed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ErrorFrame {
+    pub class: u32,
+    pub data: [u8; 8],
+}
+
+/// Protocol violation, details in `data[2]` and `data[3]`.
+pub(crate) const CAN_ERR_PROT: u32 = 0x0008;
+/// Error counters are valid, TX count in `data[6]` and RX count in `data[7]`.
+pub(crate) const CAN_ERR_CNT: u32 = 0x0200;
+
+pub(crate) const CAN_ERR_PROT_BIT: u8 = 0x01;
+pub(crate) const CAN_ERR_PROT_FORM: u8 = 0x02;
+pub(crate) const CAN_ERR_PROT_STUFF: u8 = 0x04;
+pub(crate) const CAN_ERR_PROT_TX: u8 = 0x80;
+
+#[derive(Debug, Clone)]
+pub enum LogFrame {
+    Can(Frame),
+    Fd(FdFrame),
+    Error(ErrorFrame),
+}
+
+impl From<Frame> for LogFrame {
+    fn from(frame: Frame) -> Self {
+        LogFrame::Can(frame)
+    }
+}
+
+impl From<FdFrame> for LogFrame {
+    fn from(frame: FdFrame) -> Self {
+        LogFrame::Fd(frame)
+    }
+}
+
+impl From<ErrorFrame> for LogFrame {
+    fn from(frame: ErrorFrame) -> Self {
+        LogFrame::Error(frame)
+    }
+}
+
+/// A single entry of a trace file.
+#[derive(Debug, Clone)]
+pub struct Record {
+    /// Time of the frame, relative to the time base of the trace file.
+    pub timestamp: Duration,
+    /// Zero based channel the frame was recorded on.
+    pub channel: u8,
+    pub direction: Direction,
+    pub frame: LogFrame,
+}
+
+impl Record {
+    /// Creates a record for a frame received on channel 0.
+    pub fn new(timestamp: Duration, frame: impl Into<LogFrame>) -> Self {
+        Self {
+            timestamp,
+            channel: 0,
+            direction: Direction::Rx,
+            frame: frame.into(),
+        }
+    }
+}
+
+/// Parses a string of hexadecimal digit pairs, optionally separated by `.`.
+pub(crate) fn parse_hex_bytes(s: &str) -> Option<Vec<u8>> {
+    let digits: Vec<u8> = s.bytes().filter(|&b| b != b'.').collect();
+    if !digits.len().is_multiple_of(2) {
+        return None;
+    }
+    digits
+        .chunks(2)
+        .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+        .collect()
+}
+
+/// Parses a decimal number of seconds like `1436509052.249713`.
+pub(crate) fn parse_seconds(s: &str) -> Option<Duration> {
+    let (secs, frac) = s.split_once('.').unwrap_or((s, ""));
+    if frac.len() > 9 || !frac.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let secs = if secs.is_empty() {
+        0
+    } else {
+        secs.parse().ok()?
+    };
+    let nanos = if frac.is_empty() {
+        0
+    } else {
+        frac.parse::<u32>().ok()? * 10u32.pow(9 - frac.len() as u32)
+    };
+    Some(Duration::new(secs, nanos))
+}
+
+fn parse_error(format: &str, line: usize, msg: &str) -> Error {
+    Error(format!("{} line {}: {}", format, line, msg))
+}
+
+/// Returns the number of days since 1970-01-01 of a date in the proleptic
+/// Gregorian calendar.
+pub(crate) fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month = month as i64;
+    let day_of_year =
+        (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// Inverse of [`days_from_civil`], returns year, month and day.
+pub(crate) fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
@@ -0,0 +1,532 @@
+//! Vector CANalyzer/CANoe `.asc` log files.
+//!
+//! ```text
+//! date Mon Sep 30 03:06:13.191 pm 2019
+//! base hex  timestamps absolute
+//! internal events logged
+//! Begin Triggerblock Mon Sep 30 03:06:13.191 pm 2019
+//!    0.000000 Start of measurement
+//!    1.015991 1  123             Rx   d 8 01 02 03 04 05 06 07 08
+//!    1.016291 2  18EBFF00x       Tx   r 8
+//!    2.501131 1  ErrorFrame
+//!    3.871263 CANFD   1 Rx      1c8  1 0 9 12 01 02 03 04 05 06 07 08 09 0a 0b 0c
+//! End TriggerBlock
+//! ```
+
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use embedded_can::{ExtendedId, Frame as _, Id, StandardId};
+
+use super::{
+    Direction, ErrorFrame, LogFrame, Record, civil_from_days, days_from_civil, parse_error,
+    parse_seconds,
+};
+use crate::frame::fd_dlc_to_len;
+use crate::{Error, FdFrame, Frame};
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+const WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+
+/// Streaming reader of `.asc` files.
+///
+/// Record timestamps are relative to the start of the measurement, also for
+/// files logged with relative timestamps. Lines that do not describe a frame
+/// are skipped.
+pub struct Reader<R> {
+    reader: R,
+    line: String,
+    line_number: usize,
+    radix: u32,
+    relative: bool,
+    last_timestamp: Duration,
+    start_time: Option<SystemTime>,
+}
+
+impl Reader<BufReader<File>> {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Ok(Self::new(BufReader::new(File::open(path)?)))
+    }
+}
+
+impl<R: BufRead> Reader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            line: String::new(),
+            line_number: 0,
+            radix: 16,
+            relative: false,
+            last_timestamp: Duration::ZERO,
+            start_time: None,
+        }
+    }
+
+    /// Returns the start time from the `date` header, once it has been read.
+    /// The date is interpreted as UTC.
+    pub fn start_time(&self) -> Option<SystemTime> {
+        self.start_time
+    }
+
+    fn parse_line(&mut self) -> Result<Option<Record>, Error> {
+        let line_number = self.line_number;
+        let err = |msg: &str| parse_error("asc", line_number, msg);
+
+        let line = self.line.trim();
+        let mut tokens = line.split_whitespace();
+        let Some(first) = tokens.next() else {
+            return Ok(None);
+        };
+
+        match first {
+            "date" => {
+                self.start_time = parse_date(line["date".len()..].trim());
+                return Ok(None);
+            }
+            "base" => {
+                while let Some(token) = tokens.next() {
+                    match token {
+                        "hex" => self.radix = 16,
+                        "dec" => self.radix = 10,
+                        "timestamps" => self.relative = tokens.next() == Some("relative"),
+                        _ => {}
+                    }
+                }
+                return Ok(None);
+            }
+            _ => {}
+        }
+
+        let Some(mut timestamp) = parse_seconds(first) else {
+            return Ok(None);
+        };
+        if self.relative {
+            timestamp += self.last_timestamp;
+        }
+        self.last_timestamp = timestamp;
+
+        let record = match tokens.next() {
+            Some("CANFD") => {
+                let channel = tokens.next().and_then(parse_channel);
+                let direction = tokens.next().and_then(parse_direction);
+                let (Some(channel), Some(direction)) = (channel, direction) else {
+                    return Err(err("invalid CAN FD frame"));
+                };
+                let frame = self
+                    .parse_fd_frame(tokens)
+                    .ok_or_else(|| err("invalid CAN FD frame"))?;
+                Record {
+                    timestamp,
+                    channel,
+                    direction,
+                    frame,
+                }
+            }
+            Some(channel) => {
+                let Some(channel) = parse_channel(channel) else {
+                    // Status and other event lines.
+                    return Ok(None);
+                };
+                // Statistic lines of a channel.
+                if tokens.clone().next().is_some_and(|t| t.ends_with(':')) {
+                    return Ok(None);
+                }
+                let (frame, direction) = self
+                    .parse_frame(tokens)
+                    .ok_or_else(|| err("invalid CAN frame"))?;
+                Record {
+                    timestamp,
+                    channel,
+                    direction,
+                    frame,
+                }
+            }
+            None => return Ok(None),
+        };
+        Ok(Some(record))
+    }
+
+    fn parse_frame<'a>(
+        &self,
+        mut tokens: impl Iterator<Item = &'a str>,
+    ) -> Option<(LogFrame, Direction)> {
+        let id = tokens.next()?;
+        if id.eq_ignore_ascii_case("ErrorFrame") {
+            return Some((ErrorFrame::default().into(), Direction::Rx));
+        }
+        let id = self.parse_id(id)?;
+        let direction = parse_direction(tokens.next()?)?;
+
+        let frame = match tokens.next()? {
+            "r" | "R" => {
+                let dlc = match tokens.next() {
+                    Some(dlc) => usize::from_str_radix(dlc, self.radix).ok()?,
+                    None => 0,
+                };
+                Frame::new_remote(id, dlc)?
+            }
+            "d" | "D" => {
+                let dlc = usize::from_str_radix(tokens.next()?, self.radix).ok()?;
+                let data = self.parse_data(tokens, dlc)?;
+                Frame::new(id, &data)?
+            }
+            _ => return None,
+        };
+        Some((frame.into(), direction))
+    }
+
+    fn parse_fd_frame<'a>(&self, mut tokens: impl Iterator<Item = &'a str>) -> Option<LogFrame> {
+        let id = tokens.next()?;
+        if id.eq_ignore_ascii_case("ErrorFrame") {
+            return Some(ErrorFrame::default().into());
+        }
+        let id = self.parse_id(id)?;
+
+        // The identifier may be followed by a symbolic message name.
+        let mut brs = tokens.next()?;
+        if brs != "0" && brs != "1" {
+            brs = tokens.next()?;
+        }
+        let esi = tokens.next()?;
+        let dlc = u8::from_str_radix(tokens.next()?, self.radix).ok()?;
+        let len: usize = tokens.next()?.parse().ok()?;
+        if len == 0 && dlc != 0 {
+            // A remote frame, which can only be a classic frame.
+            return Frame::new_remote(id, fd_dlc_to_len(dlc)).map(LogFrame::from);
+        }
+
+        let data = self.parse_data(tokens, len)?;
+        let frame = FdFrame::new(id, &data)?
+            .with_bitrate_switch(brs == "1")
+            .with_error_state_indicator(esi == "1");
+        Some(frame.into())
+    }
+
+    fn parse_id(&self, s: &str) -> Option<Id> {
+        match s.strip_suffix(['x', 'X']) {
+            Some(id) => ExtendedId::new(u32::from_str_radix(id, self.radix).ok()?).map(Id::from),
+            None => StandardId::new(u16::from_str_radix(s, self.radix).ok()?).map(Id::from),
+        }
+    }
+
+    fn parse_data<'a>(&self, tokens: impl Iterator<Item = &'a str>, len: usize) -> Option<Vec<u8>> {
+        let data = tokens
+            .take(len)
+            .map(|b| u8::from_str_radix(b, self.radix).ok())
+            .collect::<Option<Vec<u8>>>()?;
+        (data.len() == len).then_some(data)
+    }
+}
+
+impl<R: BufRead> Iterator for Reader<R> {
+    type Item = Result<Record, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            self.line.clear();
+            match self.reader.read_line(&mut self.line) {
+                Ok(0) => return None,
+                Ok(_) => self.line_number += 1,
+                Err(err) => return Some(Err(err.into())),
+            }
+            match self.parse_line() {
+                Ok(Some(record)) => return Some(Ok(record)),
+                Ok(None) => continue,
+                Err(err) => return Some(Err(err)),
+            }
+        }
+    }
+}
+
+/// Channels are numbered from 1 in ASC files.
+fn parse_channel(s: &str) -> Option<u8> {
+    s.parse::<u8>().ok()?.checked_sub(1)
+}
+
+fn parse_direction(s: &str) -> Option<Direction> {
+    match s {
+        "Rx" => Some(Direction::Rx),
+        "Tx" | "TxRq" => Some(Direction::Tx),
+        _ => None,
+    }
+}
+
+/// Parses dates like `Mon Sep 30 03:06:13.191 pm 2019`, the time may also use
+/// the 24 hour format.
+fn parse_date(s: &str) -> Option<SystemTime> {
+    let mut tokens = s.split_whitespace();
+    let _weekday = tokens.next()?;
+    let month = tokens.next()?;
+    let month = MONTHS.iter().position(|&m| m == month)? as u32 + 1;
+    let day: u32 = tokens.next()?.parse().ok()?;
+
+    let mut time = tokens.next()?.split(':');
+    let mut hour: u64 = time.next()?.parse().ok()?;
+    let minute: u64 = time.next()?.parse().ok()?;
+    let second = parse_seconds(time.next()?)?;
+
+    let mut year = tokens.next()?;
+    match year {
+        "am" if hour == 12 => hour = 0,
+        "pm" if hour < 12 => hour += 12,
+        _ => {}
+    }
+    if year == "am" || year == "pm" {
+        year = tokens.next()?;
+    }
+    let year: i64 = year.parse().ok()?;
+
+    let days = u64::try_from(days_from_civil(year, month, day)).ok()?;
+    let secs = days * 86400 + hour * 3600 + minute * 60;
+    Some(UNIX_EPOCH + Duration::from_secs(secs) + second)
+}
+
+fn format_date(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let days = (secs / 86400) as i64;
+    let (year, month, day) = civil_from_days(days);
+    let hour = secs / 3600 % 24;
+    format!(
+        "{} {} {:02} {:02}:{:02}:{:02}.{:03} {} {}",
+        WEEKDAYS[(days + 4).rem_euclid(7) as usize],
+        MONTHS[month as usize - 1],
+        day,
+        (hour + 11) % 12 + 1,
+        secs / 60 % 60,
+        secs % 60,
+        since_epoch.subsec_millis(),
+        if hour < 12 { "am" } else { "pm" },
+        year
+    )
+}
+
+/// Writer of `.asc` files with hexadecimal values and absolute timestamps.
+///
+/// [`Writer::finish`] must be called to terminate the trigger block.
+pub struct Writer<W: Write> {
+    writer: W,
+    start_time: Option<SystemTime>,
+    header_written: bool,
+}
+
+impl Writer<BufWriter<File>> {
+    pub fn create(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Ok(Self::new(BufWriter::new(File::create(path)?)))
+    }
+}
+
+impl<W: Write> Writer<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            start_time: None,
+            header_written: false,
+        }
+    }
+
+    /// Sets the start time written to the header, defaults to the time of the
+    /// first write.
+    pub fn with_start_time(mut self, start_time: SystemTime) -> Self {
+        self.start_time = Some(start_time);
+        self
+    }
+
+    fn write_header(&mut self) -> Result<(), Error> {
+        let date = format_date(*self.start_time.get_or_insert_with(SystemTime::now));
+        writeln!(self.writer, "date {}", date)?;
+        writeln!(self.writer, "base hex  timestamps absolute")?;
+        writeln!(self.writer, "internal events logged")?;
+        writeln!(self.writer, "Begin Triggerblock {}", date)?;
+        writeln!(self.writer, "   0.000000 Start of measurement")?;
+        self.header_written = true;
+        Ok(())
+    }
+
+    pub fn write(&mut self, record: &Record) -> Result<(), Error> {
+        if !self.header_written {
+            self.write_header()?;
+        }
+
+        let channel = record.channel as u16 + 1;
+        let direction = match record.direction {
+            Direction::Rx => "Rx",
+            Direction::Tx => "Tx",
+        };
+        let timestamp = record.timestamp.as_secs_f64();
+
+        match &record.frame {
+            LogFrame::Can(frame) => {
+                write!(
+                    self.writer,
+                    "{:>11.6} {}  {:<15} {:<4} ",
+                    timestamp,
+                    channel,
+                    format_id(frame.id()),
+                    direction
+                )?;
+                if frame.is_remote_frame() {
+                    write!(self.writer, "r {:x}", frame.dlc())?;
+                } else {
+                    write!(self.writer, "d {:x}", frame.dlc())?;
+                    write_data(&mut self.writer, frame.data())?;
+                }
+            }
+            LogFrame::Fd(frame) => {
+                write!(
+                    self.writer,
+                    "{:>11.6} CANFD {:>3} {:<4} {:>8} {} {} {:x} {:>2}",
+                    timestamp,
+                    channel,
+                    direction,
+                    format_id(frame.id()),
+                    frame.bitrate_switch() as u8,
+                    frame.error_state_indicator() as u8,
+                    frame.dlc(),
+                    frame.data().len()
+                )?;
+                write_data(&mut self.writer, frame.data())?;
+            }
+            LogFrame::Error(_) => {
+                write!(self.writer, "{:>11.6} {}  ErrorFrame", timestamp, channel)?;
+            }
+        }
+        writeln!(self.writer)?;
+        Ok(())
+    }
+
+    /// Terminates the trigger block and returns the underlying writer.
+    pub fn finish(mut self) -> Result<W, Error> {
+        if !self.header_written {
+            self.write_header()?;
+        }
+        writeln!(self.writer, "End TriggerBlock")?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+fn format_id(id: Id) -> String {
+    match id {
+        Id::Standard(id) => format!("{:X}", id.as_raw()),
+        Id::Extended(id) => format!("{:X}x", id.as_raw()),
+    }
+}
+
+fn write_data(writer: &mut impl Write, data: &[u8]) -> std::io::Result<()> {
+    data.iter().try_for_each(|b| write!(writer, " {:02X}", b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOG: &str = "\
+date Mon Sep 30 03:06:13.191 pm 2019
+base hex  timestamps absolute
+internal events logged
+Begin Triggerblock Mon Sep 30 03:06:13.191 pm 2019
+   0.000000 Start of measurement
+   1.015991 1  123             Rx   d 8 01 02 03 04 05 06 07 08
+   1.016291 2  18EBFF00x       Tx   r 8
+   2.501131 1  ErrorFrame
+   3.871263 CANFD   1 Rx        1C8 1 0 9 12 01 02 03 04 05 06 07 08 09 0A 0B 0C
+End TriggerBlock
+";
+
+    #[test]
+    fn round_trip() {
+        let mut reader = Reader::new(LOG.as_bytes());
+        let records: Vec<_> = reader.by_ref().collect::<Result<_, _>>().unwrap();
+        let start_time = reader.start_time().unwrap();
+        assert_eq!(
+            start_time,
+            UNIX_EPOCH + Duration::from_millis(1_569_855_973_191)
+        );
+        assert_eq!(records.len(), 4);
+
+        let LogFrame::Can(frame) = &records[0].frame else {
+            panic!("expected a CAN frame");
+        };
+        assert_eq!(frame.id(), Id::Standard(StandardId::new(0x123).unwrap()));
+        assert_eq!(frame.data(), [1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(records[0].timestamp, Duration::from_micros(1_015_991));
+
+        let LogFrame::Can(frame) = &records[1].frame else {
+            panic!("expected a CAN frame");
+        };
+        assert!(frame.is_remote_frame());
+        assert_eq!(frame.dlc(), 8);
+        assert_eq!(
+            frame.id(),
+            Id::Extended(ExtendedId::new(0x18EBFF00).unwrap())
+        );
+        assert_eq!(records[1].channel, 1);
+        assert_eq!(records[1].direction, Direction::Tx);
+
+        assert!(matches!(records[2].frame, LogFrame::Error(_)));
+
+        let LogFrame::Fd(frame) = &records[3].frame else {
+            panic!("expected a CAN FD frame");
+        };
+        assert!(frame.bitrate_switch());
+        assert!(!frame.error_state_indicator());
+        assert_eq!(frame.data().len(), 12);
+
+        let mut writer = Writer::new(Vec::new()).with_start_time(start_time);
+        for record in &records {
+            writer.write(record).unwrap();
+        }
+        let written = String::from_utf8(writer.finish().unwrap()).unwrap();
+        assert_eq!(written, LOG);
+    }
+
+    #[test]
+    fn relative_decimal_timestamps() {
+        let log = "\
+base dec  timestamps relative
+   0.500000 1  291             Rx   d 2 1 255
+   0.250000 1  291             Rx   r
+   0.250000 1  Statistic: D 0 R 0 XD 0 XR 0 E 0 O 0 B 0.00%
+";
+        let records: Vec<_> = Reader::new(log.as_bytes())
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(records.len(), 2);
+        let LogFrame::Can(frame) = &records[0].frame else {
+            panic!("expected a CAN frame");
+        };
+        assert_eq!(frame.id(), Id::Standard(StandardId::new(0x123).unwrap()));
+        assert_eq!(frame.data(), [1, 255]);
+        assert_eq!(records[1].timestamp, Duration::from_millis(750));
+    }
+
+    #[test]
+    fn dates() {
+        let time = parse_date("Tue Feb 29 00:30:00.000 2000").unwrap();
+        assert_eq!(time, UNIX_EPOCH + Duration::from_secs(951_784_200));
+        assert_eq!(format_date(time), "Tue Feb 29 12:30:00.000 am 2000");
+        assert_eq!(parse_date("Tue Feb 29 12:30:00.000 am 2000"), Some(time));
+        assert_eq!(parse_date("Tue Foo 29 12:30:00.000 am 2000"), None);
+    }
+
+    #[test]
+    fn invalid_frames() {
+        for line in [
+            "   1.000000 1  123             Rx   d 8 01 02",
+            "   1.000000 1  800             Rx   d 1 01",
+            "   1.000000 1  123             Up   d 1 01",
+            "   1.000000 1  123             Rx   r 9",
+            "   1.000000 CANFD   1 Rx      1C8 1 0 9 12 01 02",
+        ] {
+            let result = Reader::new(line.as_bytes()).next().unwrap();
+            assert!(result.is_err(), "{}", line);
+        }
+    }
+}
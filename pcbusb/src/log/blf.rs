@@ -0,0 +1,730 @@
+//! Vector binary logging format `.blf`.
+//!
+//! BLF files consist of a file header followed by objects, which are usually
+//! bundled in zlib compressed log containers. The reader decompresses one
+//! container at a time, so files of any size can be processed.
+//!
+//! Supported objects are `CAN_MESSAGE`, `CAN_MESSAGE2`, `CAN_FD_MESSAGE`,
+//! `CAN_FD_MESSAGE_64`, `CAN_ERROR` and `CAN_ERROR_EXT`, all other objects are
+//! skipped.
+
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use embedded_can::{ExtendedId, Frame as _, Id, StandardId};
+use flate2::{Compression, read::ZlibDecoder, write::ZlibEncoder};
+
+use super::{Direction, ErrorFrame, LogFrame, Record, civil_from_days, days_from_civil};
+use crate::frame::fd_dlc_to_len;
+use crate::{Error, FdFrame, Frame};
+
+const FILE_SIGNATURE: &[u8; 4] = b"LOGG";
+const OBJECT_SIGNATURE: &[u8; 4] = b"LOBJ";
+const FILE_HEADER_SIZE: usize = 144;
+const OBJECT_HEADER_BASE_SIZE: usize = 16;
+const OBJECT_HEADER_V1_SIZE: usize = 16;
+const LOG_CONTAINER_HEADER_SIZE: usize = 16;
+
+const CAN_MESSAGE: u32 = 1;
+const CAN_ERROR: u32 = 2;
+const LOG_CONTAINER: u32 = 10;
+const CAN_ERROR_EXT: u32 = 73;
+const CAN_MESSAGE2: u32 = 86;
+const CAN_FD_MESSAGE: u32 = 100;
+const CAN_FD_MESSAGE_64: u32 = 101;
+
+const NO_COMPRESSION: u16 = 0;
+const ZLIB_DEFLATE: u16 = 2;
+
+/// Object timestamps are in units of 10 µs.
+const TIME_TEN_MICS: u32 = 0x0000_0001;
+/// Object timestamps are in units of 1 ns.
+const TIME_ONE_NANS: u32 = 0x0000_0002;
+
+const CAN_MSG_EXT: u32 = 0x8000_0000;
+const CAN_MSG_DIR_TX: u8 = 0x01;
+const CAN_MSG_REMOTE: u8 = 0x80;
+const CAN_FD_EDL: u8 = 0x01;
+const CAN_FD_BRS: u8 = 0x02;
+const CAN_FD_ESI: u8 = 0x04;
+const CAN_FD_64_REMOTE: u32 = 0x0010;
+const CAN_FD_64_EDL: u32 = 0x1000;
+const CAN_FD_64_BRS: u32 = 0x2000;
+const CAN_FD_64_ESI: u32 = 0x4000;
+
+/// Uncompressed size after which a log container is written.
+const MAX_CONTAINER_SIZE: usize = 128 * 1024;
+
+fn u16_at(buf: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(buf[offset..offset + 2].try_into().unwrap())
+}
+
+fn u32_at(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+}
+
+fn u64_at(buf: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(buf[offset..offset + 8].try_into().unwrap())
+}
+
+fn invalid(msg: &str) -> Error {
+    Error(format!("blf: {}", msg))
+}
+
+/// Streaming reader of `.blf` files.
+///
+/// Record timestamps are relative to the start time of the measurement.
+pub struct Reader<R> {
+    reader: R,
+    start_time: Option<SystemTime>,
+    object_count: u32,
+    /// Uncompressed objects, starting at `pos`.
+    buffer: Vec<u8>,
+    pos: usize,
+}
+
+impl Reader<BufReader<File>> {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read> Reader<R> {
+    /// Creates a reader and parses the file header.
+    pub fn new(mut reader: R) -> Result<Self, Error> {
+        let mut header = [0u8; FILE_HEADER_SIZE];
+        reader.read_exact(&mut header[..8])?;
+        if &header[0..4] != FILE_SIGNATURE {
+            return Err(invalid("not a BLF file"));
+        }
+        let header_size = u32_at(&header, 4) as usize;
+        if header_size < 72 {
+            return Err(invalid("invalid file header size"));
+        }
+        let len = header_size.min(FILE_HEADER_SIZE);
+        reader.read_exact(&mut header[8..len])?;
+        if header_size > len {
+            io::copy(
+                &mut reader.by_ref().take((header_size - len) as u64),
+                &mut io::sink(),
+            )?;
+        }
+
+        Ok(Self {
+            reader,
+            start_time: from_system_time(&header[40..56]),
+            object_count: u32_at(&header, 32),
+            buffer: Vec::new(),
+            pos: 0,
+        })
+    }
+
+    pub fn start_time(&self) -> Option<SystemTime> {
+        self.start_time
+    }
+
+    /// Number of objects according to the file header.
+    pub fn object_count(&self) -> u32 {
+        self.object_count
+    }
+
+    /// Reads the next top level object into the buffer, decompressing log
+    /// containers. Returns `false` at the end of the file.
+    fn fill_buffer(&mut self) -> Result<bool, Error> {
+        let mut header = [0u8; OBJECT_HEADER_BASE_SIZE];
+        match self.reader.read_exact(&mut header) {
+            Ok(()) => {}
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(false),
+            Err(err) => return Err(err.into()),
+        }
+        if &header[0..4] != OBJECT_SIGNATURE {
+            return Err(invalid("invalid object signature"));
+        }
+        let object_size = u32_at(&header, 8) as usize;
+        let object_type = u32_at(&header, 12);
+        if object_size < OBJECT_HEADER_BASE_SIZE {
+            return Err(invalid("invalid object size"));
+        }
+
+        let mut body = vec![0u8; object_size - OBJECT_HEADER_BASE_SIZE];
+        self.reader.read_exact(&mut body)?;
+        // Objects are followed by `object_size % 4` padding bytes.
+        let mut padding = [0u8; 3];
+        match self.reader.read_exact(&mut padding[..object_size % 4]) {
+            Ok(()) => {}
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => {}
+            Err(err) => return Err(err.into()),
+        }
+
+        self.buffer.drain(..self.pos);
+        self.pos = 0;
+
+        if object_type == LOG_CONTAINER {
+            if body.len() < LOG_CONTAINER_HEADER_SIZE {
+                return Err(invalid("invalid log container"));
+            }
+            let data = &body[LOG_CONTAINER_HEADER_SIZE..];
+            match u16_at(&body, 0) {
+                NO_COMPRESSION => self.buffer.extend_from_slice(data),
+                ZLIB_DEFLATE => {
+                    self.buffer.reserve(u32_at(&body, 8) as usize);
+                    ZlibDecoder::new(data).read_to_end(&mut self.buffer)?;
+                }
+                _ => return Err(invalid("unsupported compression method")),
+            }
+        } else {
+            self.buffer.extend_from_slice(&header);
+            self.buffer.extend_from_slice(&body);
+        }
+        Ok(true)
+    }
+
+    /// Parses the next object from the buffer, returns `Ok(None)` if the
+    /// buffer does not contain a complete object.
+    fn next_object(&mut self) -> Result<Option<Option<Record>>, Error> {
+        // Objects inside containers may be followed by padding, so search for
+        // the signature of the next object.
+        let available = &self.buffer[self.pos..];
+        let Some(offset) = available
+            .windows(4)
+            .take(8)
+            .position(|w| w == OBJECT_SIGNATURE)
+        else {
+            if available.len() >= 12 {
+                return Err(invalid("invalid object signature"));
+            }
+            return Ok(None);
+        };
+        let object = &available[offset..];
+        if object.len() < OBJECT_HEADER_BASE_SIZE {
+            return Ok(None);
+        }
+
+        let header_size = u16_at(object, 4) as usize;
+        let object_size = u32_at(object, 8) as usize;
+        let object_type = u32_at(object, 12);
+        if object_size < header_size || header_size < OBJECT_HEADER_BASE_SIZE {
+            return Err(invalid("invalid object size"));
+        }
+        if object.len() < object_size {
+            return Ok(None);
+        }
+        self.pos += offset + object_size;
+
+        let object = &object[..object_size];
+        if header_size < OBJECT_HEADER_BASE_SIZE + OBJECT_HEADER_V1_SIZE {
+            return Ok(Some(None));
+        }
+        // The timestamp is at the same offset in version 1 and 2 headers.
+        let flags = u32_at(object, 16);
+        let timestamp = u64_at(object, 24);
+        let timestamp = match flags {
+            TIME_TEN_MICS => Duration::from_micros(timestamp * 10),
+            _ => Duration::from_nanos(timestamp),
+        };
+
+        Ok(Some(parse_object(object_type, &object[header_size..]).map(
+            |(channel, direction, frame)| Record {
+                timestamp,
+                channel,
+                direction,
+                frame,
+            },
+        )))
+    }
+}
+
+impl<R: Read> Iterator for Reader<R> {
+    type Item = Result<Record, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.next_object() {
+                Ok(Some(Some(record))) => return Some(Ok(record)),
+                Ok(Some(None)) => continue,
+                Ok(None) => {}
+                Err(err) => return Some(Err(err)),
+            }
+            match self.fill_buffer() {
+                Ok(true) => continue,
+                Ok(false) => return None,
+                Err(err) => return Some(Err(err)),
+            }
+        }
+    }
+}
+
+fn parse_id(raw: u32) -> Option<Id> {
+    if raw & CAN_MSG_EXT != 0 {
+        ExtendedId::new(raw & !CAN_MSG_EXT).map(Id::from)
+    } else {
+        StandardId::new(raw as u16).map(Id::from)
+    }
+}
+
+/// BLF channels are numbered from 1.
+fn parse_channel(channel: u16) -> u8 {
+    channel.saturating_sub(1) as u8
+}
+
+fn parse_object(object_type: u32, data: &[u8]) -> Option<(u8, Direction, LogFrame)> {
+    let direction = |tx: bool| if tx { Direction::Tx } else { Direction::Rx };
+
+    match object_type {
+        CAN_MESSAGE | CAN_MESSAGE2 if data.len() >= 16 => {
+            let flags = data[2];
+            let dlc = data[3] as usize;
+            let id = parse_id(u32_at(data, 4))?;
+            let frame = if flags & CAN_MSG_REMOTE != 0 {
+                Frame::new_remote(id, dlc)?
+            } else {
+                Frame::new(id, &data[8..8 + dlc.min(8)])?
+            };
+            Some((
+                parse_channel(u16_at(data, 0)),
+                direction(flags & CAN_MSG_DIR_TX != 0),
+                frame.into(),
+            ))
+        }
+        CAN_FD_MESSAGE if data.len() >= 84 => {
+            let flags = data[2];
+            let dlc = data[3];
+            let id = parse_id(u32_at(data, 4))?;
+            let fd_flags = data[13];
+            let len = (data[14] as usize).min(64);
+            let frame = if fd_flags & CAN_FD_EDL != 0 {
+                FdFrame::new(id, &data[20..20 + len])?
+                    .with_bitrate_switch(fd_flags & CAN_FD_BRS != 0)
+                    .with_error_state_indicator(fd_flags & CAN_FD_ESI != 0)
+                    .into()
+            } else if flags & CAN_MSG_REMOTE != 0 {
+                Frame::new_remote(id, dlc as usize)?.into()
+            } else {
+                Frame::new(id, &data[20..20 + (dlc as usize).min(8)])?.into()
+            };
+            Some((
+                parse_channel(u16_at(data, 0)),
+                direction(flags & CAN_MSG_DIR_TX != 0),
+                frame,
+            ))
+        }
+        CAN_FD_MESSAGE_64 if data.len() >= 40 => {
+            let dlc = data[1];
+            let id = parse_id(u32_at(data, 4))?;
+            let flags = u32_at(data, 12);
+            let payload = &data[40..];
+            let frame = if flags & CAN_FD_64_EDL != 0 {
+                let len = fd_dlc_to_len(dlc);
+                // The payload may be shorter than the DLC, missing bytes are zero.
+                let mut buf = [0u8; 64];
+                let available = payload.len().min(len);
+                buf[..available].copy_from_slice(&payload[..available]);
+                FdFrame::new(id, &buf[..len])?
+                    .with_bitrate_switch(flags & CAN_FD_64_BRS != 0)
+                    .with_error_state_indicator(flags & CAN_FD_64_ESI != 0)
+                    .into()
+            } else if flags & CAN_FD_64_REMOTE != 0 {
+                Frame::new_remote(id, dlc as usize)?.into()
+            } else {
+                let len = (dlc as usize).min(8).min(payload.len());
+                Frame::new(id, &payload[..len])?.into()
+            };
+            Some((data[0].saturating_sub(1), direction(data[34] != 0), frame))
+        }
+        CAN_ERROR | CAN_ERROR_EXT if data.len() >= 2 => Some((
+            parse_channel(u16_at(data, 0)),
+            Direction::Rx,
+            ErrorFrame::default().into(),
+        )),
+        _ => None,
+    }
+}
+
+/// Converts a Windows `SYSTEMTIME` structure, which is zeroed if unset.
+fn from_system_time(buf: &[u8]) -> Option<SystemTime> {
+    let field = |i: usize| u16_at(buf, i * 2);
+    let (year, month, day) = (field(0), field(1), field(3));
+    if year == 0 || !(1..=12).contains(&month) || day == 0 {
+        return None;
+    }
+    let days = u64::try_from(days_from_civil(year as i64, month as u32, day as u32)).ok()?;
+    let secs = days * 86400 + field(4) as u64 * 3600 + field(5) as u64 * 60 + field(6) as u64;
+    Some(UNIX_EPOCH + Duration::from_secs(secs) + Duration::from_millis(field(7) as u64))
+}
+
+fn to_system_time(time: SystemTime) -> [u8; 16] {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let days = (secs / 86400) as i64;
+    let (year, month, day) = civil_from_days(days);
+    let fields = [
+        year as u16,
+        month as u16,
+        (days + 4).rem_euclid(7) as u16,
+        day as u16,
+        (secs / 3600 % 24) as u16,
+        (secs / 60 % 60) as u16,
+        (secs % 60) as u16,
+        since_epoch.subsec_millis() as u16,
+    ];
+    let mut buf = [0u8; 16];
+    for (chunk, field) in buf.chunks_mut(2).zip(fields) {
+        chunk.copy_from_slice(&field.to_le_bytes());
+    }
+    buf
+}
+
+/// Writer of `.blf` files with zlib compressed log containers.
+///
+/// The file header is updated by [`Writer::finish`], which must be called
+/// after the last record.
+pub struct Writer<W: Write + Seek> {
+    writer: W,
+    start_time: SystemTime,
+    last_timestamp: Duration,
+    object_count: u32,
+    uncompressed_size: u64,
+    container: Vec<u8>,
+}
+
+impl Writer<BufWriter<File>> {
+    pub fn create(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::new(BufWriter::new(File::create(path)?))
+    }
+}
+
+impl<W: Write + Seek> Writer<W> {
+    /// Creates a writer, reserving space for the file header.
+    pub fn new(mut writer: W) -> Result<Self, Error> {
+        writer.write_all(&[0u8; FILE_HEADER_SIZE])?;
+        Ok(Self {
+            writer,
+            start_time: SystemTime::now(),
+            last_timestamp: Duration::ZERO,
+            object_count: 0,
+            uncompressed_size: FILE_HEADER_SIZE as u64,
+            container: Vec::new(),
+        })
+    }
+
+    /// Sets the start time written to the header, defaults to the time the
+    /// writer was created.
+    pub fn with_start_time(mut self, start_time: SystemTime) -> Self {
+        self.start_time = start_time;
+        self
+    }
+
+    pub fn write(&mut self, record: &Record) -> Result<(), Error> {
+        let channel = record.channel as u16 + 1;
+        let tx = record.direction == Direction::Tx;
+
+        let (object_type, data) = match &record.frame {
+            LogFrame::Can(frame) => {
+                let mut data = [0u8; 16];
+                let mut flags = if tx { CAN_MSG_DIR_TX } else { 0 };
+                if frame.is_remote_frame() {
+                    flags |= CAN_MSG_REMOTE;
+                }
+                data[0..2].copy_from_slice(&channel.to_le_bytes());
+                data[2] = flags;
+                data[3] = frame.dlc() as u8;
+                data[4..8].copy_from_slice(&raw_id(frame.id()).to_le_bytes());
+                if !frame.is_remote_frame() {
+                    data[8..8 + frame.data().len()].copy_from_slice(frame.data());
+                }
+                (CAN_MESSAGE, data.to_vec())
+            }
+            LogFrame::Fd(frame) => {
+                let mut data = [0u8; 84];
+                let mut fd_flags = CAN_FD_EDL;
+                if frame.bitrate_switch() {
+                    fd_flags |= CAN_FD_BRS;
+                }
+                if frame.error_state_indicator() {
+                    fd_flags |= CAN_FD_ESI;
+                }
+                data[0..2].copy_from_slice(&channel.to_le_bytes());
+                data[2] = if tx { CAN_MSG_DIR_TX } else { 0 };
+                data[3] = frame.dlc() as u8;
+                data[4..8].copy_from_slice(&raw_id(frame.id()).to_le_bytes());
+                data[13] = fd_flags;
+                data[14] = frame.data().len() as u8;
+                data[20..20 + frame.data().len()].copy_from_slice(frame.data());
+                (CAN_FD_MESSAGE, data.to_vec())
+            }
+            LogFrame::Error(_) => {
+                let mut data = [0u8; 32];
+                data[0..2].copy_from_slice(&channel.to_le_bytes());
+                (CAN_ERROR_EXT, data.to_vec())
+            }
+        };
+
+        self.add_object(object_type, &data, record.timestamp)
+    }
+
+    fn add_object(
+        &mut self,
+        object_type: u32,
+        data: &[u8],
+        timestamp: Duration,
+    ) -> Result<(), Error> {
+        let header_size = OBJECT_HEADER_BASE_SIZE + OBJECT_HEADER_V1_SIZE;
+        let object_size = header_size + data.len();
+
+        self.container.extend_from_slice(OBJECT_SIGNATURE);
+        self.container
+            .extend_from_slice(&(header_size as u16).to_le_bytes());
+        self.container.extend_from_slice(&1u16.to_le_bytes());
+        self.container
+            .extend_from_slice(&(object_size as u32).to_le_bytes());
+        self.container.extend_from_slice(&object_type.to_le_bytes());
+        self.container
+            .extend_from_slice(&TIME_ONE_NANS.to_le_bytes());
+        // Client index and object version.
+        self.container.extend_from_slice(&[0u8; 4]);
+        self.container
+            .extend_from_slice(&(timestamp.as_nanos() as u64).to_le_bytes());
+        self.container.extend_from_slice(data);
+        self.container
+            .extend(std::iter::repeat_n(0u8, object_size % 4));
+
+        self.object_count += 1;
+        self.last_timestamp = self.last_timestamp.max(timestamp);
+
+        if self.container.len() >= MAX_CONTAINER_SIZE {
+            self.write_container()?;
+        }
+        Ok(())
+    }
+
+    fn write_container(&mut self) -> Result<(), Error> {
+        if self.container.is_empty() {
+            return Ok(());
+        }
+
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&self.container)?;
+        let compressed = encoder.finish()?;
+
+        let object_size = OBJECT_HEADER_BASE_SIZE + LOG_CONTAINER_HEADER_SIZE + compressed.len();
+        self.writer.write_all(OBJECT_SIGNATURE)?;
+        self.writer
+            .write_all(&(OBJECT_HEADER_BASE_SIZE as u16).to_le_bytes())?;
+        self.writer.write_all(&1u16.to_le_bytes())?;
+        self.writer.write_all(&(object_size as u32).to_le_bytes())?;
+        self.writer.write_all(&LOG_CONTAINER.to_le_bytes())?;
+
+        let mut container_header = [0u8; LOG_CONTAINER_HEADER_SIZE];
+        container_header[0..2].copy_from_slice(&ZLIB_DEFLATE.to_le_bytes());
+        container_header[8..12].copy_from_slice(&(self.container.len() as u32).to_le_bytes());
+        self.writer.write_all(&container_header)?;
+        self.writer.write_all(&compressed)?;
+        self.writer.write_all(&[0u8; 3][..object_size % 4])?;
+
+        self.uncompressed_size +=
+            (OBJECT_HEADER_BASE_SIZE + LOG_CONTAINER_HEADER_SIZE + self.container.len()) as u64;
+        self.container.clear();
+        Ok(())
+    }
+
+    /// Writes the remaining objects, updates the file header and returns the
+    /// underlying writer.
+    pub fn finish(mut self) -> Result<W, Error> {
+        self.write_container()?;
+        let file_size = self.writer.stream_position()?;
+
+        let mut header = [0u8; FILE_HEADER_SIZE];
+        header[0..4].copy_from_slice(FILE_SIGNATURE);
+        header[4..8].copy_from_slice(&(FILE_HEADER_SIZE as u32).to_le_bytes());
+        // Application ID and version, followed by the binlog version 2.6.8.1.
+        header[8..16].copy_from_slice(&[5, 0, 0, 0, 2, 6, 8, 1]);
+        header[16..24].copy_from_slice(&file_size.to_le_bytes());
+        header[24..32].copy_from_slice(&self.uncompressed_size.to_le_bytes());
+        header[32..36].copy_from_slice(&self.object_count.to_le_bytes());
+        header[40..56].copy_from_slice(&to_system_time(self.start_time));
+        header[56..72].copy_from_slice(&to_system_time(self.start_time + self.last_timestamp));
+
+        self.writer.seek(SeekFrom::Start(0))?;
+        self.writer.write_all(&header)?;
+        self.writer.seek(SeekFrom::Start(file_size))?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+fn raw_id(id: Id) -> u32 {
+    match id {
+        Id::Standard(id) => id.as_raw() as u32,
+        Id::Extended(id) => id.as_raw() | CAN_MSG_EXT,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn write(records: &[Record], start_time: SystemTime) -> Vec<u8> {
+        let mut writer = Writer::new(Cursor::new(Vec::new()))
+            .unwrap()
+            .with_start_time(start_time);
+        for record in records {
+            writer.write(record).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn round_trip() {
+        let standard = Id::Standard(StandardId::new(0x123).unwrap());
+        let extended = Id::Extended(ExtendedId::new(0x18DAF110).unwrap());
+        let records = [
+            Record::new(
+                Duration::from_micros(1_015_991),
+                Frame::new(standard, &[1, 2, 3, 4, 5, 6, 7, 8]).unwrap(),
+            ),
+            Record {
+                channel: 1,
+                direction: Direction::Tx,
+                ..Record::new(
+                    Duration::from_micros(1_016_291),
+                    Frame::new_remote(extended, 8).unwrap(),
+                )
+            },
+            Record::new(Duration::from_secs(2), ErrorFrame::default()),
+            Record::new(
+                Duration::from_nanos(3_871_263_001),
+                FdFrame::new(extended, &[0xAA; 64])
+                    .unwrap()
+                    .with_error_state_indicator(true),
+            ),
+        ];
+        let start_time = UNIX_EPOCH + Duration::from_millis(1_569_855_973_191);
+        let blf = write(&records, start_time);
+
+        let reader = Reader::new(&blf[..]).unwrap();
+        assert_eq!(reader.start_time(), Some(start_time));
+        assert_eq!(reader.object_count(), 4);
+        let read: Vec<_> = reader.collect::<Result<_, _>>().unwrap();
+        assert_eq!(read.len(), 4);
+
+        for (actual, expected) in read.iter().zip(&records) {
+            assert_eq!(actual.timestamp, expected.timestamp);
+            assert_eq!(actual.channel, expected.channel);
+            assert_eq!(actual.direction, expected.direction);
+        }
+
+        let LogFrame::Can(frame) = &read[0].frame else {
+            panic!("expected a CAN frame");
+        };
+        assert_eq!(frame.id(), standard);
+        assert_eq!(frame.data(), [1, 2, 3, 4, 5, 6, 7, 8]);
+
+        let LogFrame::Can(frame) = &read[1].frame else {
+            panic!("expected a CAN frame");
+        };
+        assert_eq!(frame.id(), extended);
+        assert!(frame.is_remote_frame());
+        assert_eq!(frame.dlc(), 8);
+
+        assert!(matches!(read[2].frame, LogFrame::Error(_)));
+
+        let LogFrame::Fd(frame) = &read[3].frame else {
+            panic!("expected a CAN FD frame");
+        };
+        assert_eq!(frame.data(), [0xAA; 64]);
+        assert!(!frame.bitrate_switch());
+        assert!(frame.error_state_indicator());
+    }
+
+    #[test]
+    fn multiple_containers() {
+        let id = Id::Standard(StandardId::new(0x7FF).unwrap());
+        let records: Vec<_> = (0..10_000u32)
+            .map(|i| {
+                Record::new(
+                    Duration::from_millis(i as u64),
+                    Frame::new(id, &i.to_le_bytes()).unwrap(),
+                )
+            })
+            .collect();
+        let blf = write(&records, UNIX_EPOCH);
+
+        let read: Vec<_> = Reader::new(&blf[..])
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(read.len(), records.len());
+        for (i, record) in read.iter().enumerate() {
+            assert_eq!(record.timestamp, Duration::from_millis(i as u64));
+            let LogFrame::Can(frame) = &record.frame else {
+                panic!("expected a CAN frame");
+            };
+            assert_eq!(frame.data(), (i as u32).to_le_bytes());
+        }
+    }
+
+    #[test]
+    fn uncompressed_fd_message_64() {
+        let mut object = Vec::new();
+        object.extend_from_slice(OBJECT_SIGNATURE);
+        object.extend_from_slice(&32u16.to_le_bytes());
+        object.extend_from_slice(&1u16.to_le_bytes());
+        object.extend_from_slice(&(32 + 40 + 12u32).to_le_bytes());
+        object.extend_from_slice(&CAN_FD_MESSAGE_64.to_le_bytes());
+        object.extend_from_slice(&TIME_TEN_MICS.to_le_bytes());
+        object.extend_from_slice(&[0; 4]);
+        object.extend_from_slice(&150u64.to_le_bytes());
+        let mut data = [0u8; 40];
+        data[0] = 2;
+        data[1] = 9;
+        data[4..8].copy_from_slice(&0x321u32.to_le_bytes());
+        data[12..16].copy_from_slice(&(CAN_FD_64_EDL | CAN_FD_64_BRS).to_le_bytes());
+        data[34] = 1;
+        object.extend_from_slice(&data);
+        object.extend_from_slice(&[0x11; 12]);
+
+        let mut container = Vec::new();
+        container.extend_from_slice(OBJECT_SIGNATURE);
+        container.extend_from_slice(&16u16.to_le_bytes());
+        container.extend_from_slice(&1u16.to_le_bytes());
+        container.extend_from_slice(&((32 + object.len()) as u32).to_le_bytes());
+        container.extend_from_slice(&LOG_CONTAINER.to_le_bytes());
+        container.extend_from_slice(&[0; LOG_CONTAINER_HEADER_SIZE]);
+        container.extend_from_slice(&object);
+
+        let mut blf = write(&[], UNIX_EPOCH);
+        blf.extend_from_slice(&container);
+
+        let read: Vec<_> = Reader::new(&blf[..])
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(read.len(), 1);
+        assert_eq!(read[0].timestamp, Duration::from_micros(1500));
+        assert_eq!(read[0].channel, 1);
+        assert_eq!(read[0].direction, Direction::Tx);
+        let LogFrame::Fd(frame) = &read[0].frame else {
+            panic!("expected a CAN FD frame");
+        };
+        assert_eq!(frame.id(), Id::Standard(StandardId::new(0x321).unwrap()));
+        assert!(frame.bitrate_switch());
+        assert_eq!(frame.data(), [0x11; 12]);
+    }
+
+    #[test]
+    fn invalid_files() {
+        assert!(Reader::new(&b"LOGX\x90\0\0\0"[..]).is_err());
+        assert!(Reader::new(&b"LOGG\x10\0\0\0"[..]).is_err());
+
+        let mut blf = write(&[], UNIX_EPOCH);
+        blf.extend_from_slice(b"LOBX\x10\0\x01\0\x10\0\0\0\x01\0\0\0");
+        let mut reader = Reader::new(&blf[..]).unwrap();
+        assert!(reader.next().unwrap().is_err());
+    }
+}
@@ -0,0 +1,395 @@
+//! The `candump -l` log format of the Linux can-utils.
+//!
+//! ```text
+//! (1436509052.249713) can0 044#2A366C2BBA
+//! (1436509052.449847) can0 12345678#R
+//! (1436509052.650004) can1 123##1112233445566778899AABB
+//! ```
+
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::Path,
+    time::Duration,
+};
+
+use embedded_can::{ExtendedId, Frame as _, Id, StandardId};
+
+use super::{Direction, ErrorFrame, LogFrame, Record, parse_error, parse_hex_bytes, parse_seconds};
+use crate::{Error, FdFrame, Frame};
+
+const CAN_ERR_FLAG: u32 = 0x2000_0000;
+const CANFD_BRS: u8 = 0x01;
+const CANFD_ESI: u8 = 0x02;
+
+/// Streaming reader of candump log files.
+///
+/// Interface names are mapped to channels in the order of their first appearance.
+pub struct Reader<R> {
+    reader: R,
+    line: String,
+    line_number: usize,
+    interfaces: Vec<String>,
+}
+
+impl Reader<BufReader<File>> {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Ok(Self::new(BufReader::new(File::open(path)?)))
+    }
+}
+
+impl<R: BufRead> Reader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            line: String::new(),
+            line_number: 0,
+            interfaces: Vec::new(),
+        }
+    }
+
+    /// Returns the interface name that was mapped to `channel`.
+    pub fn interface_name(&self, channel: u8) -> Option<&str> {
+        self.interfaces.get(channel as usize).map(String::as_str)
+    }
+
+    fn parse_line(&mut self) -> Result<Record, Error> {
+        let line_number = self.line_number;
+        let err = |msg: &str| parse_error("candump", line_number, msg);
+
+        let mut fields = self.line.split_whitespace();
+        let timestamp = fields
+            .next()
+            .and_then(|s| s.strip_prefix('('))
+            .and_then(|s| s.strip_suffix(')'))
+            .and_then(parse_seconds)
+            .ok_or_else(|| err("invalid timestamp"))?;
+        let interface = fields.next().ok_or_else(|| err("missing interface"))?;
+        let frame = fields.next().ok_or_else(|| err("missing frame"))?;
+        let direction = match fields.next() {
+            None | Some("R") => Direction::Rx,
+            Some("T") => Direction::Tx,
+            Some(_) => return Err(err("invalid direction")),
+        };
+
+        let frame = parse_frame(frame).ok_or_else(|| err("invalid frame"))?;
+
+        let channel = match self.interfaces.iter().position(|i| i == interface) {
+            Some(channel) => channel,
+            None => {
+                self.interfaces.push(interface.to_string());
+                self.interfaces.len() - 1
+            }
+        };
+        let channel = u8::try_from(channel).map_err(|_| err("too many interfaces"))?;
+
+        Ok(Record {
+            timestamp,
+            channel,
+            direction,
+            frame,
+        })
+    }
+}
+
+impl<R: BufRead> Iterator for Reader<R> {
+    type Item = Result<Record, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            self.line.clear();
+            match self.reader.read_line(&mut self.line) {
+                Ok(0) => return None,
+                Ok(_) => self.line_number += 1,
+                Err(err) => return Some(Err(err.into())),
+            }
+            if !self.line.trim().is_empty() {
+                return Some(self.parse_line());
+            }
+        }
+    }
+}
+
+fn parse_frame(s: &str) -> Option<LogFrame> {
+    let (id, rest) = s.split_once('#')?;
+    let raw_id = u32::from_str_radix(id, 16).ok()?;
+    let make_id = || -> Option<Id> {
+        match id.len() {
+            3 => StandardId::new(raw_id as u16).map(Id::from),
+            8 => ExtendedId::new(raw_id).map(Id::from),
+            _ => None,
+        }
+    };
+
+    if let Some(rest) = rest.strip_prefix('#') {
+        let flags = u8::from_str_radix(rest.get(0..1)?, 16).ok()?;
+        let data = parse_hex_bytes(rest.get(1..)?)?;
+        let frame = FdFrame::new(make_id()?, &data)?
+            .with_bitrate_switch(flags & CANFD_BRS != 0)
+            .with_error_state_indicator(flags & CANFD_ESI != 0);
+        return Some(frame.into());
+    }
+
+    // A trailing `_<dlc>` carries the raw DLC of classic frames with 8 bytes,
+    // which cannot be represented by `Frame`.
+    let rest = rest.split_once('_').map_or(rest, |(rest, _)| rest);
+
+    if let Some(len) = rest.strip_prefix('R').or_else(|| rest.strip_prefix('r')) {
+        let dlc = if len.is_empty() { 0 } else { len.parse().ok()? };
+        return Frame::new_remote(make_id()?, dlc).map(LogFrame::from);
+    }
+
+    let data = parse_hex_bytes(rest)?;
+    if id.len() == 8 && raw_id & CAN_ERR_FLAG != 0 {
+        let mut error = ErrorFrame {
+            class: raw_id & !CAN_ERR_FLAG,
+            data: [0; 8],
+        };
+        let len = data.len().min(8);
+        error.data[..len].copy_from_slice(&data[..len]);
+        return Some(error.into());
+    }
+
+    Frame::new(make_id()?, &data).map(LogFrame::from)
+}
+
+/// Writer of candump log files.
+///
+/// Channels are written as `can<channel>` unless a name has been set with
+/// [`Writer::set_interface_name`].
+pub struct Writer<W: Write> {
+    writer: W,
+    interfaces: Vec<Option<String>>,
+    direction: bool,
+}
+
+impl Writer<BufWriter<File>> {
+    pub fn create(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Ok(Self::new(BufWriter::new(File::create(path)?)))
+    }
+}
+
+impl<W: Write> Writer<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            interfaces: Vec::new(),
+            direction: false,
+        }
+    }
+
+    pub fn set_interface_name(&mut self, channel: u8, name: impl Into<String>) {
+        let channel = channel as usize;
+        if self.interfaces.len() <= channel {
+            self.interfaces.resize(channel + 1, None);
+        }
+        self.interfaces[channel] = Some(name.into());
+    }
+
+    /// Appends ` R` or ` T` to each line, as written by newer can-utils versions.
+    pub fn with_direction(mut self, direction: bool) -> Self {
+        self.direction = direction;
+        self
+    }
+
+    pub fn write(&mut self, record: &Record) -> Result<(), Error> {
+        write_timestamp(&mut self.writer, record.timestamp)?;
+        match self.interfaces.get(record.channel as usize) {
+            Some(Some(name)) => write!(self.writer, " {} ", name)?,
+            _ => write!(self.writer, " can{} ", record.channel)?,
+        }
+
+        match &record.frame {
+            LogFrame::Can(frame) => {
+                write_id(&mut self.writer, frame.id())?;
+                if frame.is_remote_frame() {
+                    write!(self.writer, "#R")?;
+                    if frame.dlc() > 0 {
+                        write!(self.writer, "{}", frame.dlc())?;
+                    }
+                } else {
+                    write!(self.writer, "#")?;
+                    write_hex(&mut self.writer, frame.data())?;
+                }
+            }
+            LogFrame::Fd(frame) => {
+                let mut flags = 0;
+                if frame.bitrate_switch() {
+                    flags |= CANFD_BRS;
+                }
+                if frame.error_state_indicator() {
+                    flags |= CANFD_ESI;
+                }
+                write_id(&mut self.writer, frame.id())?;
+                write!(self.writer, "##{:X}", flags)?;
+                write_hex(&mut self.writer, frame.data())?;
+            }
+            LogFrame::Error(error) => {
+                write!(self.writer, "{:08X}#", error.class | CAN_ERR_FLAG)?;
+                write_hex(&mut self.writer, &error.data)?;
+            }
+        }
+
+        if self.direction {
+            match record.direction {
+                Direction::Rx => write!(self.writer, " R")?,
+                Direction::Tx => write!(self.writer, " T")?,
+            }
+        }
+        writeln!(self.writer)?;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<(), Error> {
+        Ok(self.writer.flush()?)
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+fn write_timestamp(writer: &mut impl Write, timestamp: Duration) -> std::io::Result<()> {
+    write!(
+        writer,
+        "({}.{:06})",
+        timestamp.as_secs(),
+        timestamp.subsec_micros()
+    )
+}
+
+fn write_id(writer: &mut impl Write, id: Id) -> std::io::Result<()> {
+    match id {
+        Id::Standard(id) => write!(writer, "{:03X}", id.as_raw()),
+        Id::Extended(id) => write!(writer, "{:08X}", id.as_raw()),
+    }
+}
+
+fn write_hex(writer: &mut impl Write, data: &[u8]) -> std::io::Result<()> {
+    data.iter().try_for_each(|b| write!(writer, "{:02X}", b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(log: &str) -> Vec<Record> {
+        Reader::new(log.as_bytes())
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    fn write(records: &[Record]) -> String {
+        let mut writer = Writer::new(Vec::new());
+        for record in records {
+            writer.write(record).unwrap();
+        }
+        String::from_utf8(writer.into_inner()).unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        let log = "\
+(1436509052.249713) can0 044#2A366C2BBA
+(1436509052.449847) can0 12345678#R
+(1436509052.550000) vcan1 7FF#R8
+(1436509052.650004) vcan1 123##1112233445566778899AABBCC
+(1436509052.750000) can0 20000080#0000000000000000
+";
+        let records = read(log);
+        assert_eq!(records.len(), 5);
+        assert_eq!(records[0].timestamp, Duration::new(1436509052, 249_713_000));
+        assert_eq!(records[2].channel, 1);
+
+        let LogFrame::Can(frame) = &records[0].frame else {
+            panic!("expected a CAN frame");
+        };
+        assert_eq!(frame.id(), Id::Standard(StandardId::new(0x044).unwrap()));
+        assert_eq!(frame.data(), [0x2A, 0x36, 0x6C, 0x2B, 0xBA]);
+
+        let LogFrame::Can(frame) = &records[1].frame else {
+            panic!("expected a CAN frame");
+        };
+        assert!(frame.is_remote_frame());
+        assert_eq!(
+            frame.id(),
+            Id::Extended(ExtendedId::new(0x12345678).unwrap())
+        );
+        assert_eq!(frame.dlc(), 0);
+
+        let LogFrame::Can(frame) = &records[2].frame else {
+            panic!("expected a CAN frame");
+        };
+        assert!(frame.is_remote_frame());
+        assert_eq!(frame.dlc(), 8);
+
+        let LogFrame::Fd(frame) = &records[3].frame else {
+            panic!("expected a CAN FD frame");
+        };
+        assert!(frame.bitrate_switch());
+        assert!(!frame.error_state_indicator());
+        assert_eq!(frame.data().len(), 12);
+
+        let LogFrame::Error(error) = &records[4].frame else {
+            panic!("expected an error frame");
+        };
+        assert_eq!(error.class, 0x80);
+
+        let mut writer = Writer::new(Vec::new());
+        writer.set_interface_name(1, "vcan1");
+        for record in &records {
+            writer.write(record).unwrap();
+        }
+        assert_eq!(String::from_utf8(writer.into_inner()).unwrap(), log);
+    }
+
+    #[test]
+    fn direction() {
+        let log = "(0.000100) can0 123#01 T\n(0.000200) can0 123#02 R\n";
+        let records = read(log);
+        assert_eq!(records[0].direction, Direction::Tx);
+        assert_eq!(records[1].direction, Direction::Rx);
+
+        let mut writer = Writer::new(Vec::new()).with_direction(true);
+        for record in &records {
+            writer.write(record).unwrap();
+        }
+        assert_eq!(String::from_utf8(writer.into_inner()).unwrap(), log);
+        assert_eq!(
+            write(&records),
+            "(0.000100) can0 123#01\n(0.000200) can0 123#02\n"
+        );
+    }
+
+    #[test]
+    fn raw_dlc_is_ignored() {
+        let records = read("(1.000000) can0 123#1122334455667788_C\n");
+        let LogFrame::Can(frame) = &records[0].frame else {
+            panic!("expected a CAN frame");
+        };
+        assert_eq!(frame.data().len(), 8);
+    }
+
+    #[test]
+    fn invalid_lines() {
+        for line in [
+            "1.000000 can0 123#00",
+            "(1.000000) can0",
+            "(1.000000) can0 1234#00",
+            "(1.000000) can0 800#00",
+            "(1.000000) can0 123#0",
+            "(1.000000) can0 123#001122334455667788",
+            "(1.000000) can0 123#R9",
+            "(1.000000) can0 123#00 X",
+        ] {
+            let result = Reader::new(line.as_bytes()).next().unwrap();
+            assert!(result.is_err(), "{}", line);
+        }
+
+        let err = Reader::new("\n(1.0) can0 123#00\n(x) can0 123#00\n".as_bytes())
+            .nth(1)
+            .unwrap()
+            .unwrap_err();
+        assert!(err.to_string().starts_with("candump line 3:"), "{}", err);
+    }
+}
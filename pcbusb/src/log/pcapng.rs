@@ -0,0 +1,446 @@
+//! pcapng capture files with the SocketCAN link type, for viewing traffic in
+//! Wireshark.
+//!
+//! Besides regular files, captures can be written to a named pipe created with
+//! [`Writer::create_pipe`], which lets Wireshark display the traffic live:
+//!
+//! ```text
+//! wireshark -k -i /tmp/pcbusb        # Unix
+//! wireshark -k -i \\.\pipe\pcbusb    # Windows
+//! ```
+
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use embedded_can::{Frame as _, Id};
+
+use super::{Direction, LogFrame, Record};
+use crate::Error;
+
+const SECTION_HEADER_BLOCK: u32 = 0x0A0D_0D0A;
+const INTERFACE_DESCRIPTION_BLOCK: u32 = 0x0000_0001;
+const ENHANCED_PACKET_BLOCK: u32 = 0x0000_0006;
+const BYTE_ORDER_MAGIC: u32 = 0x1A2B_3C4D;
+
+const LINKTYPE_CAN_SOCKETCAN: u16 = 227;
+
+const OPT_ENDOFOPT: u16 = 0;
+const IF_NAME: u16 = 2;
+const IF_TSRESOL: u16 = 9;
+const EPB_FLAGS: u16 = 2;
+const EPB_FLAGS_INBOUND: u32 = 0x01;
+const EPB_FLAGS_OUTBOUND: u32 = 0x02;
+
+const CAN_EFF_FLAG: u32 = 0x8000_0000;
+const CAN_RTR_FLAG: u32 = 0x4000_0000;
+const CAN_ERR_FLAG: u32 = 0x2000_0000;
+const CANFD_BRS: u8 = 0x01;
+const CANFD_ESI: u8 = 0x02;
+const CANFD_FDF: u8 = 0x04;
+
+/// Writer of pcapng captures with `LINKTYPE_CAN_SOCKETCAN` packets.
+///
+/// Each channel is written as its own interface named `can<channel>`. Packet
+/// timestamps are the record timestamps added to the start time, which can be
+/// set to the time the hardware timestamps of the interface are relative to.
+/// By default the first record is dated at the time it is written.
+pub struct Writer<W: Write> {
+    writer: W,
+    start_time: Option<SystemTime>,
+    /// Interface IDs of the channels, indexed by channel.
+    interfaces: Vec<Option<u32>>,
+    interface_count: u32,
+    block: Vec<u8>,
+}
+
+impl Writer<BufWriter<File>> {
+    pub fn create(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::new(BufWriter::new(File::create(path)?))
+    }
+}
+
+impl Writer<File> {
+    /// Creates a named pipe (FIFO) at `path` and waits until a reader, like
+    /// Wireshark, opens it. The pipe is unbuffered so that every packet is
+    /// displayed immediately.
+    #[cfg(unix)]
+    pub fn create_pipe(path: impl AsRef<Path>) -> Result<Self, Error> {
+        use std::{ffi::CString, io, os::unix::ffi::OsStrExt};
+
+        let path = path.as_ref();
+        let c_path = CString::new(path.as_os_str().as_bytes())
+            .map_err(|_| Error("Invalid pipe path".to_string()))?;
+        if unsafe { libc::mkfifo(c_path.as_ptr(), 0o644) } != 0 {
+            let err = io::Error::last_os_error();
+            if err.kind() != io::ErrorKind::AlreadyExists {
+                return Err(err.into());
+            }
+        }
+
+        // Opening a FIFO for writing blocks until the other end is opened.
+        Self::new(File::options().write(true).open(path)?)
+    }
+
+    /// Creates the named pipe `\\.\pipe\<name>` and waits until a reader, like
+    /// Wireshark, connects to it. The pipe is unbuffered so that every packet
+    /// is displayed immediately.
+    #[cfg(windows)]
+    pub fn create_pipe(name: &str) -> Result<Self, Error> {
+        use std::{ffi::CString, io, os::windows::io::FromRawHandle, ptr};
+        use winapi::{
+            shared::winerror::ERROR_PIPE_CONNECTED,
+            um::{
+                handleapi::INVALID_HANDLE_VALUE,
+                namedpipeapi::ConnectNamedPipe,
+                winbase::{CreateNamedPipeA, PIPE_ACCESS_OUTBOUND, PIPE_TYPE_BYTE, PIPE_WAIT},
+            },
+        };
+
+        let path = CString::new(format!(r"\\.\pipe\{}", name))
+            .map_err(|_| Error("Invalid pipe name".to_string()))?;
+        let handle = unsafe {
+            CreateNamedPipeA(
+                path.as_ptr(),
+                PIPE_ACCESS_OUTBOUND,
+                PIPE_TYPE_BYTE | PIPE_WAIT,
+                1,
+                65536,
+                65536,
+                0,
+                ptr::null_mut(),
+            )
+        };
+        if handle == INVALID_HANDLE_VALUE {
+            return Err(io::Error::last_os_error().into());
+        }
+        let file = unsafe { File::from_raw_handle(handle as _) };
+
+        if unsafe { ConnectNamedPipe(handle, ptr::null_mut()) } == 0 {
+            let err = io::Error::last_os_error();
+            if err.raw_os_error() != Some(ERROR_PIPE_CONNECTED as i32) {
+                return Err(err.into());
+            }
+        }
+
+        Self::new(file)
+    }
+}
+
+impl<W: Write> Writer<W> {
+    /// Creates a writer and writes the section header.
+    pub fn new(writer: W) -> Result<Self, Error> {
+        let mut this = Self {
+            writer,
+            start_time: None,
+            interfaces: Vec::new(),
+            interface_count: 0,
+            block: Vec::new(),
+        };
+
+        this.begin_block(SECTION_HEADER_BLOCK);
+        this.put_u32(BYTE_ORDER_MAGIC);
+        this.put_u16(1);
+        this.put_u16(0);
+        // The section length is not known.
+        this.block.extend_from_slice(&(-1i64).to_le_bytes());
+        this.end_block()?;
+
+        Ok(this)
+    }
+
+    /// Sets the time record timestamps are relative to. Defaults to the time
+    /// of the first write minus the timestamp of the first record.
+    pub fn with_start_time(mut self, start_time: SystemTime) -> Self {
+        self.start_time = Some(start_time);
+        self
+    }
+
+    pub fn write(&mut self, record: &Record) -> Result<(), Error> {
+        let interface = self.interface(record.channel)?;
+
+        let mut packet = Vec::with_capacity(72);
+        let (can_id, len, flags, data): (_, _, _, &[u8]) = match &record.frame {
+            LogFrame::Can(frame) => {
+                let mut can_id = raw_id(frame.id());
+                if frame.is_remote_frame() {
+                    can_id |= CAN_RTR_FLAG;
+                }
+                (can_id, frame.dlc() as u8, 0, frame.data())
+            }
+            LogFrame::Fd(frame) => {
+                let mut flags = CANFD_FDF;
+                if frame.bitrate_switch() {
+                    flags |= CANFD_BRS;
+                }
+                if frame.error_state_indicator() {
+                    flags |= CANFD_ESI;
+                }
+                (
+                    raw_id(frame.id()),
+                    frame.data().len() as u8,
+                    flags,
+                    frame.data(),
+                )
+            }
+            LogFrame::Error(error) => (error.class | CAN_ERR_FLAG, 8, 0, &error.data[..]),
+        };
+        // The CAN ID is in network byte order in SocketCAN captures.
+        packet.extend_from_slice(&can_id.to_be_bytes());
+        packet.extend_from_slice(&[len, flags, 0, 0]);
+        packet.extend_from_slice(data);
+        let payload_size = if matches!(record.frame, LogFrame::Fd(_)) {
+            64
+        } else {
+            8
+        };
+        packet.resize(8 + payload_size, 0);
+
+        let start_time = *self.start_time.get_or_insert_with(|| {
+            let now = SystemTime::now();
+            now.checked_sub(record.timestamp).unwrap_or(now)
+        });
+        let timestamp = (start_time + record.timestamp)
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or(Duration::ZERO)
+            .as_nanos() as u64;
+
+        self.begin_block(ENHANCED_PACKET_BLOCK);
+        self.put_u32(interface);
+        self.put_u32((timestamp >> 32) as u32);
+        self.put_u32(timestamp as u32);
+        self.put_u32(packet.len() as u32);
+        self.put_u32(packet.len() as u32);
+        self.block.extend_from_slice(&packet);
+        let flags = match record.direction {
+            Direction::Rx => EPB_FLAGS_INBOUND,
+            Direction::Tx => EPB_FLAGS_OUTBOUND,
+        };
+        self.put_option(EPB_FLAGS, &flags.to_le_bytes());
+        self.put_option(OPT_ENDOFOPT, &[]);
+        self.end_block()
+    }
+
+    pub fn flush(&mut self) -> Result<(), Error> {
+        Ok(self.writer.flush()?)
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    /// Returns the interface ID of a channel, writing its interface description
+    /// block the first time the channel is used.
+    fn interface(&mut self, channel: u8) -> Result<u32, Error> {
+        let index = channel as usize;
+        if let Some(Some(id)) = self.interfaces.get(index) {
+            return Ok(*id);
+        }
+
+        let id = self.interface_count;
+        self.begin_block(INTERFACE_DESCRIPTION_BLOCK);
+        self.put_u16(LINKTYPE_CAN_SOCKETCAN);
+        self.put_u16(0);
+        // No snapshot length limit.
+        self.put_u32(0);
+        self.put_option(IF_NAME, format!("can{}", channel).as_bytes());
+        // Timestamps are in nanoseconds.
+        self.put_option(IF_TSRESOL, &[9]);
+        self.put_option(OPT_ENDOFOPT, &[]);
+        self.end_block()?;
+
+        if self.interfaces.len() <= index {
+            self.interfaces.resize(index + 1, None);
+        }
+        self.interfaces[index] = Some(id);
+        self.interface_count += 1;
+        Ok(id)
+    }
+
+    fn begin_block(&mut self, block_type: u32) {
+        self.block.clear();
+        self.put_u32(block_type);
+        // Placeholder for the block length.
+        self.put_u32(0);
+    }
+
+    /// Writes the block at once, so that readers of a pipe never see partial blocks.
+    fn end_block(&mut self) -> Result<(), Error> {
+        let len = (self.block.len() + 4) as u32;
+        self.block[4..8].copy_from_slice(&len.to_le_bytes());
+        self.put_u32(len);
+        self.writer.write_all(&self.block)?;
+        Ok(())
+    }
+
+    fn put_u16(&mut self, value: u16) {
+        self.block.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn put_u32(&mut self, value: u32) {
+        self.block.extend_from_slice(&value.to_le_bytes());
+    }
+
+    /// Appends an option, padded to 32 bits.
+    fn put_option(&mut self, code: u16, value: &[u8]) {
+        self.put_u16(code);
+        self.put_u16(value.len() as u16);
+        self.block.extend_from_slice(value);
+        let padding = (4 - value.len() % 4) % 4;
+        self.block.extend(std::iter::repeat_n(0, padding));
+    }
+}
+
+fn raw_id(id: Id) -> u32 {
+    match id {
+        Id::Standard(id) => id.as_raw() as u32,
+        Id::Extended(id) => id.as_raw() | CAN_EFF_FLAG,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_can::{ExtendedId, StandardId};
+
+    use super::*;
+    use crate::{FdFrame, Frame, log::ErrorFrame};
+
+    /// Splits a capture into blocks of type and body, checking the lengths.
+    fn blocks(capture: &[u8]) -> Vec<(u32, &[u8])> {
+        let mut blocks = Vec::new();
+        let mut rest = capture;
+        while !rest.is_empty() {
+            let block_type = u32::from_le_bytes(rest[0..4].try_into().unwrap());
+            let len = u32::from_le_bytes(rest[4..8].try_into().unwrap()) as usize;
+            assert_eq!(len % 4, 0);
+            let trailer = u32::from_le_bytes(rest[len - 4..len].try_into().unwrap());
+            assert_eq!(trailer as usize, len);
+            blocks.push((block_type, &rest[8..len - 4]));
+            rest = &rest[len..];
+        }
+        blocks
+    }
+
+    /// Returns the interface ID, timestamp in nanoseconds, packet and flags of
+    /// an enhanced packet block.
+    fn packet(body: &[u8]) -> (u32, u64, &[u8], u32) {
+        let word = |i: usize| u32::from_le_bytes(body[i * 4..i * 4 + 4].try_into().unwrap());
+        let timestamp = (word(1) as u64) << 32 | word(2) as u64;
+        let len = word(3) as usize;
+        assert_eq!(word(4) as usize, len);
+        let packet = &body[20..20 + len];
+        let options = &body[20 + len.next_multiple_of(4)..];
+        assert_eq!(options[0..4], [2, 0, 4, 0]);
+        let flags = u32::from_le_bytes(options[4..8].try_into().unwrap());
+        (word(0), timestamp, packet, flags)
+    }
+
+    #[test]
+    fn packets() {
+        let standard = Id::Standard(StandardId::new(0x123).unwrap());
+        let extended = Id::Extended(ExtendedId::new(0x18DAF110).unwrap());
+        let records = [
+            Record::new(
+                Duration::from_micros(1500),
+                Frame::new(standard, &[1, 2, 3]).unwrap(),
+            ),
+            Record {
+                channel: 2,
+                direction: Direction::Tx,
+                ..Record::new(
+                    Duration::from_millis(2),
+                    Frame::new_remote(extended, 8).unwrap(),
+                )
+            },
+            Record::new(
+                Duration::from_millis(3),
+                FdFrame::new(standard, &[0x55; 12])
+                    .unwrap()
+                    .with_bitrate_switch(true),
+            ),
+            Record::new(
+                Duration::from_millis(4),
+                ErrorFrame {
+                    class: 0x0208,
+                    data: [0, 0, 0x04, 0, 0, 0, 9, 3],
+                },
+            ),
+        ];
+
+        let start_time = UNIX_EPOCH + Duration::from_secs(1_500_000_000);
+        let mut writer = Writer::new(Vec::new()).unwrap().with_start_time(start_time);
+        for record in &records {
+            writer.write(record).unwrap();
+        }
+        let capture = writer.into_inner();
+        let blocks = blocks(&capture);
+
+        let types: Vec<_> = blocks.iter().map(|(t, _)| *t).collect();
+        assert_eq!(
+            types,
+            [
+                SECTION_HEADER_BLOCK,
+                INTERFACE_DESCRIPTION_BLOCK,
+                ENHANCED_PACKET_BLOCK,
+                INTERFACE_DESCRIPTION_BLOCK,
+                ENHANCED_PACKET_BLOCK,
+                ENHANCED_PACKET_BLOCK,
+                ENHANCED_PACKET_BLOCK,
+            ]
+        );
+        assert_eq!(blocks[0].1[0..4], BYTE_ORDER_MAGIC.to_le_bytes());
+        assert_eq!(blocks[1].1[0..2], LINKTYPE_CAN_SOCKETCAN.to_le_bytes());
+        assert_eq!(&blocks[3].1[12..16], b"can2");
+
+        let base = 1_500_000_000_000_000_000;
+        let (interface, timestamp, data, flags) = packet(blocks[2].1);
+        assert_eq!(interface, 0);
+        assert_eq!(timestamp, base + 1_500_000);
+        assert_eq!(data, [0, 0, 1, 0x23, 3, 0, 0, 0, 1, 2, 3, 0, 0, 0, 0, 0]);
+        assert_eq!(flags, EPB_FLAGS_INBOUND);
+
+        let (interface, _, data, flags) = packet(blocks[4].1);
+        assert_eq!(interface, 1);
+        assert_eq!(data[0..8], [0xD8, 0xDA, 0xF1, 0x10, 8, 0, 0, 0]);
+        assert_eq!(data[8..], [0; 8]);
+        assert_eq!(flags, EPB_FLAGS_OUTBOUND);
+
+        let (interface, _, data, _) = packet(blocks[5].1);
+        assert_eq!(interface, 0);
+        assert_eq!(data.len(), 72);
+        assert_eq!(data[4..6], [12, CANFD_FDF | CANFD_BRS]);
+        assert_eq!(data[8..20], [0x55; 12]);
+
+        let (_, _, data, _) = packet(blocks[6].1);
+        assert_eq!(data[0..5], [0x20, 0, 0x02, 0x08, 8]);
+        assert_eq!(data[8..], [0, 0, 0x04, 0, 0, 0, 9, 3]);
+    }
+
+    #[test]
+    fn default_start_time() {
+        let id = Id::Standard(StandardId::new(0x123).unwrap());
+        let mut writer = Writer::new(Vec::new()).unwrap();
+        let before = SystemTime::now();
+        for millis in [5_000, 6_000] {
+            let record = Record::new(Duration::from_millis(millis), Frame::new(id, &[]).unwrap());
+            writer.write(&record).unwrap();
+        }
+        let after = SystemTime::now();
+        let capture = writer.into_inner();
+        let blocks = blocks(&capture);
+
+        let timestamp = |body| {
+            let (_, timestamp, _, _) = packet(body);
+            UNIX_EPOCH + Duration::from_nanos(timestamp)
+        };
+        let first = timestamp(blocks[2].1);
+        assert!(before <= first && first <= after);
+        assert_eq!(
+            timestamp(blocks[3].1).duration_since(first).unwrap(),
+            Duration::from_secs(1)
+        );
+    }
+}
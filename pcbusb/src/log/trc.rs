@@ -0,0 +1,709 @@
+//! PCAN-View and PCAN-Explorer `.trc` trace files.
+//!
+//! Versions 1.0 to 1.3 and 2.0 to 2.1 are supported. Version 1.x files can only
+//! hold classic frames, bus numbers are available from version 1.2 on.
+//!
+//! ```text
+//! ;$FILEVERSION=2.1
+//! ;$STARTTIME=43474.4900227431
+//! ;$COLUMNS=N,O,T,B,I,d,R,L,D
+//! ;
+//!       1      1059.900 DT 1     0300 Rx -  7    00 00 00 00 04 00 00
+//!       2      1283.231 FB 2 18EFFF00 Tx - 10    01 02 03 04 05 06 07 08 09 0A 0B 0C 0D 0E 0F 10
+//! ```
+
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use embedded_can::{ExtendedId, Frame as _, Id, StandardId};
+
+use super::{
+    CAN_ERR_CNT, CAN_ERR_PROT, CAN_ERR_PROT_BIT, CAN_ERR_PROT_FORM, CAN_ERR_PROT_STUFF,
+    CAN_ERR_PROT_TX, Direction, ErrorFrame, LogFrame, Record, parse_error,
+};
+use crate::frame::fd_dlc_to_len;
+use crate::{Error, FdFrame, Frame};
+
+/// Days between the OLE automation date epoch (1899-12-30) and the UNIX epoch.
+const OLE_UNIX_EPOCH_DAYS: f64 = 25569.0;
+const SECONDS_PER_DAY: f64 = 86400.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Version {
+    V1_0,
+    V1_1,
+    V1_2,
+    V1_3,
+    V2_0,
+    V2_1,
+}
+
+impl Version {
+    fn parse(s: &str) -> Option<Self> {
+        match s.trim() {
+            "1.0" => Some(Version::V1_0),
+            "1.1" => Some(Version::V1_1),
+            "1.2" => Some(Version::V1_2),
+            "1.3" => Some(Version::V1_3),
+            "2.0" => Some(Version::V2_0),
+            "2.1" => Some(Version::V2_1),
+            _ => None,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Version::V1_0 => "1.0",
+            Version::V1_1 => "1.1",
+            Version::V1_2 => "1.2",
+            Version::V1_3 => "1.3",
+            Version::V2_0 => "2.0",
+            Version::V2_1 => "2.1",
+        }
+    }
+
+    /// The columns of a data line, as used by the `$COLUMNS` header of version 2.x.
+    fn default_columns(self) -> &'static str {
+        match self {
+            Version::V1_0 => "N,O,I,L,D",
+            Version::V1_1 => "N,O,T,I,L,D",
+            Version::V1_2 => "N,O,B,T,I,L,D",
+            Version::V1_3 => "N,O,B,T,I,R,L,D",
+            Version::V2_0 => "N,O,T,I,d,l,D",
+            Version::V2_1 => "N,O,T,B,I,d,R,L,D",
+        }
+    }
+}
+
+/// Streaming reader of `.trc` files.
+///
+/// Record timestamps are relative to the start time of the trace. Lines that
+/// do not describe a frame, like status changes and events, are skipped.
+pub struct Reader<R> {
+    reader: R,
+    line: String,
+    line_number: usize,
+    version: Version,
+    start_time: Option<SystemTime>,
+    columns: Vec<char>,
+}
+
+impl Reader<BufReader<File>> {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: BufRead> Reader<R> {
+    /// Creates a reader and parses the file header.
+    pub fn new(reader: R) -> Result<Self, Error> {
+        let mut this = Self {
+            reader,
+            line: String::new(),
+            line_number: 0,
+            version: Version::V1_0,
+            start_time: None,
+            columns: Vec::new(),
+        };
+
+        while this.reader.fill_buf()?.first() == Some(&b';') {
+            this.read_line()?;
+            this.parse_header_line()?;
+        }
+        if this.columns.is_empty() {
+            this.columns = parse_columns(this.version.default_columns());
+        }
+
+        Ok(this)
+    }
+
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    /// Returns the absolute time that record timestamps are relative to.
+    pub fn start_time(&self) -> Option<SystemTime> {
+        self.start_time
+    }
+
+    fn read_line(&mut self) -> Result<usize, Error> {
+        self.line.clear();
+        let len = self.reader.read_line(&mut self.line)?;
+        if len > 0 {
+            self.line_number += 1;
+        }
+        Ok(len)
+    }
+
+    fn parse_header_line(&mut self) -> Result<(), Error> {
+        let err = |msg: &str| parse_error("trc", self.line_number, msg);
+
+        let Some((key, value)) = self
+            .line
+            .trim()
+            .strip_prefix(";$")
+            .and_then(|s| s.split_once('='))
+        else {
+            return Ok(());
+        };
+
+        match key {
+            "FILEVERSION" => {
+                self.version = Version::parse(value).ok_or_else(|| err("unsupported version"))?;
+            }
+            "STARTTIME" => {
+                let days: f64 = value
+                    .trim()
+                    .parse()
+                    .map_err(|_| err("invalid start time"))?;
+                let secs = (days - OLE_UNIX_EPOCH_DAYS) * SECONDS_PER_DAY;
+                self.start_time = Duration::try_from_secs_f64(secs)
+                    .ok()
+                    .map(|offset| UNIX_EPOCH + offset);
+            }
+            "COLUMNS" => self.columns = parse_columns(value),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn parse_line(&self) -> Result<Option<Record>, Error> {
+        let err = |msg: &str| parse_error("trc", self.line_number, msg);
+
+        let mut tokens = self.line.split_whitespace();
+        let mut kind = Kind::Data;
+        let mut direction = Direction::Rx;
+        let mut timestamp = Duration::ZERO;
+        let mut channel = 0;
+        let mut id = None;
+        let mut len = None;
+        let mut dlc = None;
+        let mut data = Vec::new();
+
+        for &column in &self.columns {
+            if column == 'D' {
+                for token in tokens.by_ref() {
+                    match u8::from_str_radix(token, 16) {
+                        Ok(byte) if token.len() == 2 => data.push(byte),
+                        _ if token == "RTR" => kind = Kind::Remote,
+                        _ => break,
+                    }
+                }
+                break;
+            }
+
+            let Some(token) = tokens.next() else {
+                // Remote frames have no data and may end before the data column.
+                break;
+            };
+            match column {
+                'O' => {
+                    let millis: f64 = token.parse().map_err(|_| err("invalid time offset"))?;
+                    timestamp = Duration::try_from_secs_f64(millis / 1000.0)
+                        .map_err(|_| err("invalid time offset"))?;
+                }
+                'T' => match parse_type(token) {
+                    Some((k, d)) => {
+                        kind = k;
+                        direction = d.unwrap_or(direction);
+                    }
+                    None => return Ok(None),
+                },
+                'B' => {
+                    let bus: u8 = token.parse().map_err(|_| err("invalid bus"))?;
+                    channel = bus.saturating_sub(1);
+                }
+                'I' => id = Some(token),
+                'd' => {
+                    direction = match token {
+                        "Rx" => Direction::Rx,
+                        "Tx" => Direction::Tx,
+                        _ => return Err(err("invalid direction")),
+                    }
+                }
+                'l' => len = Some(token.parse().map_err(|_| err("invalid data length"))?),
+                'L' => dlc = Some(token.parse().map_err(|_| err("invalid DLC"))?),
+                _ => {}
+            }
+        }
+
+        let frame = match kind {
+            Kind::Error => error_from_trc(&data).into(),
+            Kind::Data | Kind::Remote | Kind::Fd { .. } => {
+                let id = id
+                    .and_then(parse_id)
+                    .ok_or_else(|| err("invalid identifier"))?;
+                let len = match (len, dlc) {
+                    (Some(len), _) => len,
+                    (None, Some(dlc)) if matches!(kind, Kind::Fd { .. }) => fd_dlc_to_len(dlc),
+                    (None, Some(dlc)) => dlc as usize,
+                    (None, None) => data.len(),
+                };
+                match kind {
+                    Kind::Remote => Frame::new_remote(id, len).map(LogFrame::from),
+                    Kind::Fd { brs, esi } if data.len() == len => FdFrame::new(id, &data)
+                        .map(|f| f.with_bitrate_switch(brs).with_error_state_indicator(esi))
+                        .map(LogFrame::from),
+                    _ if data.len() == len => Frame::new(id, &data).map(LogFrame::from),
+                    _ => None,
+                }
+                .ok_or_else(|| err("invalid frame"))?
+            }
+        };
+
+        Ok(Some(Record {
+            timestamp,
+            channel,
+            direction,
+            frame,
+        }))
+    }
+}
+
+impl<R: BufRead> Iterator for Reader<R> {
+    type Item = Result<Record, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.read_line() {
+                Ok(0) => return None,
+                Ok(_) => {}
+                Err(err) => return Some(Err(err)),
+            }
+
+            let line = self.line.trim();
+            if line.is_empty() || line.starts_with(';') {
+                continue;
+            }
+            match self.parse_line() {
+                Ok(Some(record)) => return Some(Ok(record)),
+                Ok(None) => continue,
+                Err(err) => return Some(Err(err)),
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Kind {
+    Data,
+    Remote,
+    Fd { brs: bool, esi: bool },
+    Error,
+}
+
+fn parse_columns(s: &str) -> Vec<char> {
+    s.split(',')
+        .filter_map(|c| c.trim().chars().next())
+        .collect()
+}
+
+/// Parses the message type column. Version 1.x uses it for the direction.
+fn parse_type(s: &str) -> Option<(Kind, Option<Direction>)> {
+    let kind = match s {
+        "Rx" => return Some((Kind::Data, Some(Direction::Rx))),
+        "Tx" => return Some((Kind::Data, Some(Direction::Tx))),
+        "DT" => Kind::Data,
+        "RR" => Kind::Remote,
+        "FD" => Kind::Fd {
+            brs: false,
+            esi: false,
+        },
+        "FB" => Kind::Fd {
+            brs: true,
+            esi: false,
+        },
+        "FE" => Kind::Fd {
+            brs: false,
+            esi: true,
+        },
+        "BI" => Kind::Fd {
+            brs: true,
+            esi: true,
+        },
+        "ER" | "Error" => Kind::Error,
+        _ => return None,
+    };
+    Some((kind, None))
+}
+
+fn parse_id(s: &str) -> Option<Id> {
+    let raw = u32::from_str_radix(s, 16).ok()?;
+    if s.len() > 4 {
+        ExtendedId::new(raw).map(Id::from)
+    } else {
+        StandardId::new(raw as u16).map(Id::from)
+    }
+}
+
+/// Error frame data bytes: error type, direction, position, RX and TX counter.
+fn error_from_trc(data: &[u8]) -> ErrorFrame {
+    let mut error = ErrorFrame::default();
+    if let Some(&kind) = data.first() {
+        error.class |= CAN_ERR_PROT;
+        error.data[2] = kind & (CAN_ERR_PROT_BIT | CAN_ERR_PROT_FORM | CAN_ERR_PROT_STUFF);
+        if data.get(1) == Some(&0) {
+            error.data[2] |= CAN_ERR_PROT_TX;
+        }
+    }
+    if let (Some(&rx), Some(&tx)) = (data.get(3), data.get(4)) {
+        error.class |= CAN_ERR_CNT;
+        error.data[6] = tx;
+        error.data[7] = rx;
+    }
+    error
+}
+
+fn error_to_trc(error: &ErrorFrame) -> [u8; 5] {
+    let prot = error.data[2];
+    let kind = match prot & (CAN_ERR_PROT_BIT | CAN_ERR_PROT_FORM | CAN_ERR_PROT_STUFF) {
+        0 => 0x08,
+        kind => kind,
+    };
+    let direction = if prot & CAN_ERR_PROT_TX != 0 { 0 } else { 1 };
+    [kind, direction, 0, error.data[7], error.data[6]]
+}
+
+/// Writer of `.trc` files.
+///
+/// Record timestamps are written relative to the start time of the trace.
+pub struct Writer<W: Write> {
+    writer: W,
+    version: Version,
+    columns: Vec<char>,
+    start_time: Option<SystemTime>,
+    message_number: u64,
+}
+
+impl Writer<BufWriter<File>> {
+    pub fn create(path: impl AsRef<Path>, version: Version) -> Result<Self, Error> {
+        Ok(Self::new(BufWriter::new(File::create(path)?), version))
+    }
+}
+
+impl<W: Write> Writer<W> {
+    pub fn new(writer: W, version: Version) -> Self {
+        Self {
+            writer,
+            version,
+            columns: parse_columns(version.default_columns()),
+            start_time: None,
+            message_number: 0,
+        }
+    }
+
+    /// Sets the start time written to the header, defaults to the time of the
+    /// first write.
+    pub fn with_start_time(mut self, start_time: SystemTime) -> Self {
+        self.start_time = Some(start_time);
+        self
+    }
+
+    fn write_header(&mut self) -> Result<(), Error> {
+        let start_time = *self.start_time.get_or_insert_with(SystemTime::now);
+        let secs = start_time
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+
+        if self.version != Version::V1_0 {
+            writeln!(self.writer, ";$FILEVERSION={}", self.version.as_str())?;
+            writeln!(
+                self.writer,
+                ";$STARTTIME={:.10}",
+                secs / SECONDS_PER_DAY + OLE_UNIX_EPOCH_DAYS
+            )?;
+        }
+        if self.version >= Version::V2_0 {
+            writeln!(self.writer, ";$COLUMNS={}", self.version.default_columns())?;
+        }
+        writeln!(self.writer, ";")?;
+        writeln!(self.writer, ";   Generated by pcbusb")?;
+        writeln!(self.writer, ";")?;
+        Ok(())
+    }
+
+    pub fn write(&mut self, record: &Record) -> Result<(), Error> {
+        if self.message_number == 0 {
+            self.write_header()?;
+        }
+        self.message_number += 1;
+
+        let v1 = self.version < Version::V2_0;
+        let direction = match record.direction {
+            Direction::Rx => "Rx",
+            Direction::Tx => "Tx",
+        };
+
+        let (kind, id, len, dlc, data): (_, _, _, _, &[u8]) = match &record.frame {
+            LogFrame::Can(frame) if frame.is_remote_frame() => {
+                let kind = if v1 { direction } else { "RR" };
+                (kind, Some(frame.id()), frame.dlc(), frame.dlc(), &[])
+            }
+            LogFrame::Can(frame) => {
+                let kind = if v1 { direction } else { "DT" };
+                let len = frame.data().len();
+                (kind, Some(frame.id()), len, len, frame.data())
+            }
+            LogFrame::Fd(_) if v1 => {
+                return Err(Error(format!(
+                    "trc version {} does not support CAN FD frames",
+                    self.version.as_str()
+                )));
+            }
+            LogFrame::Fd(frame) => {
+                let kind = match (frame.bitrate_switch(), frame.error_state_indicator()) {
+                    (false, false) => "FD",
+                    (true, false) => "FB",
+                    (false, true) => "FE",
+                    (true, true) => "BI",
+                };
+                (
+                    kind,
+                    Some(frame.id()),
+                    frame.data().len(),
+                    frame.dlc(),
+                    frame.data(),
+                )
+            }
+            LogFrame::Error(_) if v1 => {
+                return Err(Error(format!(
+                    "trc version {} does not support error frames",
+                    self.version.as_str()
+                )));
+            }
+            LogFrame::Error(error) => ("ER", None, 5, 5, &error_to_trc(error)[..]),
+        };
+
+        let millis = record.timestamp.as_secs_f64() * 1000.0;
+        for &column in &self.columns {
+            match column {
+                'N' if v1 => write!(self.writer, "{:>7})", self.message_number)?,
+                'N' => write!(self.writer, "{:>7}", self.message_number)?,
+                'O' => match self.version {
+                    Version::V1_0 => write!(self.writer, " {:>10}", millis as u64)?,
+                    _ if v1 => write!(self.writer, " {:>10.1}", millis)?,
+                    _ => write!(self.writer, " {:>13.3}", millis)?,
+                },
+                'T' => write!(self.writer, " {:<2}", kind)?,
+                'B' => write!(self.writer, " {}", record.channel as u16 + 1)?,
+                'I' => match id {
+                    Some(Id::Standard(id)) => {
+                        write!(self.writer, " {:>8}", format!("{:04X}", id.as_raw()))?
+                    }
+                    Some(Id::Extended(id)) => write!(self.writer, " {:08X}", id.as_raw())?,
+                    None => write!(self.writer, " {:>8}", "-")?,
+                },
+                'd' => write!(self.writer, " {}", direction)?,
+                'R' => write!(self.writer, " -")?,
+                'l' => write!(self.writer, " {:>2}", len)?,
+                'L' => write!(self.writer, " {:>2}", dlc)?,
+                'D' => {
+                    write!(self.writer, "   ")?;
+                    if v1 && matches!(&record.frame, LogFrame::Can(f) if f.is_remote_frame()) {
+                        write!(self.writer, " RTR")?;
+                    }
+                    for byte in data {
+                        write!(self.writer, " {:02X}", byte)?;
+                    }
+                }
+                _ => {}
+            }
+        }
+        writeln!(self.writer)?;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<(), Error> {
+        Ok(self.writer.flush()?)
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn records() -> Vec<Record> {
+        let standard = Id::Standard(StandardId::new(0x300).unwrap());
+        let extended = Id::Extended(ExtendedId::new(0x18EFFF00).unwrap());
+        vec![
+            Record::new(
+                Duration::from_micros(1_059_900),
+                Frame::new(standard, &[0, 0, 0, 0, 4, 0, 0]).unwrap(),
+            ),
+            Record {
+                direction: Direction::Tx,
+                ..Record::new(
+                    Duration::from_micros(1_100_000),
+                    Frame::new_remote(extended, 8).unwrap(),
+                )
+            },
+            Record {
+                channel: 1,
+                ..Record::new(
+                    Duration::from_micros(1_283_231),
+                    FdFrame::new(extended, &[0x55; 12])
+                        .unwrap()
+                        .with_bitrate_switch(true),
+                )
+            },
+            Record::new(
+                Duration::from_micros(1_300_000),
+                ErrorFrame {
+                    class: CAN_ERR_PROT | CAN_ERR_CNT,
+                    data: [0, 0, CAN_ERR_PROT_STUFF | CAN_ERR_PROT_TX, 0, 0, 0, 9, 3],
+                },
+            ),
+        ]
+    }
+
+    fn round_trip(version: Version, records: &[Record]) -> Vec<Record> {
+        let start_time = UNIX_EPOCH + Duration::from_secs(1_500_000_000);
+        let mut writer = Writer::new(Vec::new(), version).with_start_time(start_time);
+        for record in records {
+            writer.write(record).unwrap();
+        }
+        let trace = writer.into_inner();
+
+        let reader = Reader::new(&trace[..]).unwrap();
+        assert_eq!(reader.version(), version);
+        if version != Version::V1_0 {
+            let offset = reader
+                .start_time()
+                .unwrap()
+                .duration_since(start_time)
+                .unwrap_or_else(|err| err.duration());
+            assert!(offset < Duration::from_millis(1));
+        }
+        reader.collect::<Result<_, _>>().unwrap()
+    }
+
+    fn assert_same(actual: &Record, expected: &Record, resolution: Duration) {
+        assert!(actual.timestamp.abs_diff(expected.timestamp) < resolution);
+        assert_eq!(actual.direction, expected.direction);
+        match (&actual.frame, &expected.frame) {
+            (LogFrame::Can(actual), LogFrame::Can(expected)) => {
+                assert_eq!(actual.id(), expected.id());
+                assert_eq!(actual.is_remote_frame(), expected.is_remote_frame());
+                assert_eq!(actual.dlc(), expected.dlc());
+                assert_eq!(actual.data(), expected.data());
+            }
+            (LogFrame::Fd(actual), LogFrame::Fd(expected)) => {
+                assert_eq!(actual.id(), expected.id());
+                assert_eq!(actual.bitrate_switch(), expected.bitrate_switch());
+                assert_eq!(
+                    actual.error_state_indicator(),
+                    expected.error_state_indicator()
+                );
+                assert_eq!(actual.data(), expected.data());
+            }
+            (LogFrame::Error(actual), LogFrame::Error(expected)) => {
+                assert_eq!(actual, expected)
+            }
+            (actual, expected) => panic!("{:?} != {:?}", actual, expected),
+        }
+    }
+
+    #[test]
+    fn round_trip_v2() {
+        let records = records();
+        for version in [Version::V2_0, Version::V2_1] {
+            let read = round_trip(version, &records);
+            assert_eq!(read.len(), records.len());
+            for (actual, expected) in read.iter().zip(&records) {
+                assert_same(actual, expected, Duration::from_micros(1));
+            }
+            let channels: Vec<_> = read.iter().map(|r| r.channel).collect();
+            match version {
+                Version::V2_0 => assert_eq!(channels, [0, 0, 0, 0]),
+                _ => assert_eq!(channels, [0, 0, 1, 0]),
+            }
+        }
+    }
+
+    #[test]
+    fn round_trip_v1() {
+        let records = &records()[..2];
+        for (version, resolution) in [
+            (Version::V1_0, Duration::from_millis(1)),
+            (Version::V1_1, Duration::from_micros(100)),
+            (Version::V1_2, Duration::from_micros(100)),
+            (Version::V1_3, Duration::from_micros(100)),
+        ] {
+            let read = round_trip(version, records);
+            assert_eq!(read.len(), records.len());
+            for (actual, expected) in read.iter().zip(records) {
+                let mut expected = expected.clone();
+                // Version 1.0 has no message type column for the direction.
+                if version == Version::V1_0 {
+                    expected.direction = Direction::Rx;
+                }
+                assert_same(actual, &expected, resolution);
+            }
+        }
+    }
+
+    #[test]
+    fn v1_rejects_fd_and_error_frames() {
+        let records = records();
+        let mut writer = Writer::new(Vec::new(), Version::V1_3);
+        assert!(writer.write(&records[2]).is_err());
+        assert!(writer.write(&records[3]).is_err());
+    }
+
+    #[test]
+    fn read_pcan_view() {
+        let trace = "\
+;$FILEVERSION=2.1
+;$STARTTIME=43474.4900227431
+;$COLUMNS=N,O,T,B,I,d,R,L,D
+;
+      1      1059.900 DT 1     0300 Rx -  7    00 00 00 00 04 00 00
+      2      1070.000 ST 1          Rx    00 00 00 08
+      3      1283.231 FB 2 18EFFF00 Tx - 10    01 02 03 04 05 06 07 08 09 0A 0B 0C 0D 0E 0F 10
+      4      1290.000 RR 1     0123 Rx -  8
+";
+        let reader = Reader::new(trace.as_bytes()).unwrap();
+        let start_time = reader.start_time().unwrap();
+        let secs = start_time.duration_since(UNIX_EPOCH).unwrap().as_secs();
+        assert_eq!(secs, 1_547_034_337);
+
+        let records: Vec<_> = reader.collect::<Result<_, _>>().unwrap();
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[1].channel, 1);
+        assert_eq!(records[1].direction, Direction::Tx);
+        let LogFrame::Fd(frame) = &records[1].frame else {
+            panic!("expected a CAN FD frame");
+        };
+        assert_eq!(frame.data().len(), 16);
+        let LogFrame::Can(frame) = &records[2].frame else {
+            panic!("expected a CAN frame");
+        };
+        assert!(frame.is_remote_frame());
+        assert_eq!(frame.dlc(), 8);
+    }
+
+    #[test]
+    fn invalid_lines() {
+        for line in [
+            "      1      1059.900 DT 1     0300 Rx -  7    00 00 00",
+            "      1      1059.900 DT 1     080G Rx -  1    00",
+            "      1      xxxx.xxx DT 1     0300 Rx -  1    00",
+            "      1      1059.900 DT 1     0300 Up -  1    00",
+            "      1      1059.900 RR 1     0300 Rx -  9",
+        ] {
+            let trace = format!(";$FILEVERSION=2.1\n{}\n", line);
+            let result = Reader::new(trace.as_bytes()).unwrap().next().unwrap();
+            assert!(result.is_err(), "{}", line);
+        }
+        assert!(Reader::new(";$FILEVERSION=3.0\n".as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,585 @@
+//! OBD-II (SAE J1979) diagnostics over CAN (ISO 15765-4).
+//!
+//! Requests are sent to the functional address and every emission related ECU
+//! answers, so the results are returned per responding ECU:
+//!
+//! ```text
+//! let mut client = obd::Client::new(&mut interface);
+//! for (ecu, values) in client.live_data(0x0C)? {
+//!     println!("{:?}: {}", ecu, values[0]);
+//! }
+//! let dtcs = client.stored_dtcs()?;
+//! let vin = client.vehicle_identification_number()?;
+//! ```
+
+use std::{
+    fmt, thread,
+    time::{Duration, Instant},
+};
+
+use embedded_can::{ExtendedId, Frame as _, Id, StandardId};
+
+use crate::{
+    Error, Frame, Interface, ResponseFilter,
+    isotp::{self, Pdu},
+};
+
+const SHOW_CURRENT_DATA: u8 = 0x01;
+const SHOW_STORED_DTCS: u8 = 0x03;
+const SHOW_PENDING_DTCS: u8 = 0x07;
+const SHOW_PERMANENT_DTCS: u8 = 0x0A;
+const REQUEST_VEHICLE_INFORMATION: u8 = 0x09;
+
+const NEGATIVE_RESPONSE: u8 = 0x7F;
+const POSITIVE_RESPONSE_OFFSET: u8 = 0x40;
+const RESPONSE_PENDING: u8 = 0x78;
+
+const VEHICLE_IDENTIFICATION_NUMBER: u8 = 0x02;
+
+const PADDING: u8 = 0xCC;
+
+/// Time to wait for the next consecutive frame of a segmented response.
+const N_CR: Duration = Duration::from_millis(1000);
+
+/// Time an ECU may take after reporting a pending response.
+const P2_STAR: Duration = Duration::from_millis(5000);
+
+/// The identifiers used by ISO 15765-4.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Addressing {
+    /// 11-bit identifiers, requests on `0x7DF` and responses on `0x7E8`–`0x7EF`.
+    #[default]
+    Standard,
+    /// 29-bit identifiers, requests on `0x18DB33F1` and responses on `0x18DAF1xx`.
+    Extended,
+}
+
+impl Addressing {
+    fn functional_id(self) -> Id {
+        match self {
+            Addressing::Standard => StandardId::new(0x7DF).unwrap().into(),
+            Addressing::Extended => ExtendedId::new(0x18DB_33F1).unwrap().into(),
+        }
+    }
+
+    fn response_filter(self) -> ResponseFilter<'static> {
+        match self {
+            Addressing::Standard => {
+                ResponseFilter::new(StandardId::new(0x7E8).unwrap()).with_mask(0x7F8)
+            }
+            Addressing::Extended => {
+                ResponseFilter::new(ExtendedId::new(0x18DA_F100).unwrap()).with_mask(0x1FFF_FF00)
+            }
+        }
+    }
+
+    /// The physical request identifier of the ECU responding with `response_id`.
+    fn physical_id(response_id: Id) -> Id {
+        match response_id {
+            Id::Standard(id) => StandardId::new(id.as_raw() - 8).unwrap().into(),
+            Id::Extended(id) => {
+                let source = id.as_raw() & 0xFF;
+                ExtendedId::new(0x18DA_00F1 | (source << 8)).unwrap().into()
+            }
+        }
+    }
+}
+
+/// A diagnostic trouble code, such as `P0301`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Dtc(pub u16);
+
+impl fmt::Display for Dtc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let system = ['P', 'C', 'B', 'U'][(self.0 >> 14) as usize];
+        write!(
+            f,
+            "{}{}{:03X}",
+            system,
+            (self.0 >> 12) & 0x3,
+            self.0 & 0xFFF
+        )
+    }
+}
+
+/// A decoded physical value of a PID.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Value {
+    pub name: &'static str,
+    pub value: f64,
+    pub unit: &'static str,
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.name, self.value)?;
+        if !self.unit.is_empty() {
+            write!(f, " {}", self.unit)?;
+        }
+        Ok(())
+    }
+}
+
+/// A response of a single ECU, without the service identifier.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Response {
+    pub ecu: Id,
+    pub data: Vec<u8>,
+}
+
+/// A segmented response being received.
+struct Transfer {
+    ecu: Id,
+    len: usize,
+    data: Vec<u8>,
+    sequence_number: u8,
+    deadline: Instant,
+}
+
+/// An OBD-II scan tool.
+pub struct Client<'a> {
+    interface: &'a mut Interface,
+    addressing: Addressing,
+    timeout: Duration,
+}
+
+impl<'a> Client<'a> {
+    pub fn new(interface: &'a mut Interface) -> Self {
+        Self {
+            interface,
+            addressing: Addressing::Standard,
+            timeout: Duration::from_millis(100),
+        }
+    }
+
+    pub fn with_addressing(mut self, addressing: Addressing) -> Self {
+        self.addressing = addressing;
+        self
+    }
+
+    /// Sets how long to wait for responses after each request. Defaults to 100 ms.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn interface(&mut self) -> &mut Interface {
+        self.interface
+    }
+
+    /// Sends a functional request of up to 7 bytes and collects the positive
+    /// responses of all ECUs answering within the timeout.
+    pub fn request(&mut self, request: &[u8]) -> Result<Vec<Response>, Error> {
+        if request.is_empty() || request.len() > 7 {
+            return Err(Error(format!(
+                "OBD request of {} bytes, expected 1 to 7",
+                request.len()
+            )));
+        }
+        let service = request[0];
+        let mut data = vec![request.len() as u8];
+        data.extend_from_slice(request);
+        self.transmit(self.addressing.functional_id(), &data)?;
+
+        let filter = self.addressing.response_filter();
+        let mut deadline = Instant::now() + self.timeout;
+        let mut transfers: Vec<Transfer> = Vec::new();
+        let mut responses: Vec<Response> = Vec::new();
+        loop {
+            // Segmented responses may continue after the response timeout.
+            let wait_until = transfers
+                .iter()
+                .map(|t| t.deadline)
+                .fold(deadline, Instant::max);
+            let timeout = wait_until.saturating_duration_since(Instant::now());
+            let Some(frame) = self.interface.receive_matching(&filter, timeout)? else {
+                break;
+            };
+            transfers.retain(|t| t.deadline > Instant::now());
+
+            let ecu = frame.id();
+            let message = match Pdu::parse(frame.data()) {
+                Some(Pdu::Single(message)) => message.to_vec(),
+                Some(Pdu::First { len, data }) => {
+                    transfers.retain(|t| t.ecu != ecu);
+                    transfers.push(Transfer {
+                        ecu,
+                        len,
+                        data: data.to_vec(),
+                        sequence_number: 1,
+                        deadline: Instant::now() + N_CR,
+                    });
+                    let flow_control =
+                        isotp::flow_control(isotp::FLOW_STATUS_CONTINUE, 0, Duration::ZERO);
+                    self.transmit(Addressing::physical_id(ecu), &flow_control)?;
+                    continue;
+                }
+                Some(Pdu::Consecutive {
+                    sequence_number,
+                    data,
+                }) => {
+                    let Some(index) = transfers.iter().position(|t| t.ecu == ecu) else {
+                        continue;
+                    };
+                    let transfer = &mut transfers[index];
+                    if sequence_number != transfer.sequence_number {
+                        transfers.remove(index);
+                        continue;
+                    }
+                    let remaining = transfer.len - transfer.data.len();
+                    transfer
+                        .data
+                        .extend_from_slice(&data[..data.len().min(remaining)]);
+                    transfer.sequence_number = (transfer.sequence_number + 1) & 0x0F;
+                    transfer.deadline = Instant::now() + N_CR;
+                    if transfer.data.len() < transfer.len {
+                        continue;
+                    }
+                    transfers.remove(index).data
+                }
+                _ => continue,
+            };
+
+            match message.as_slice() {
+                [NEGATIVE_RESPONSE, s, RESPONSE_PENDING, ..] if *s == service => {
+                    deadline = deadline.max(Instant::now() + P2_STAR);
+                }
+                [s, data @ ..] if *s == service + POSITIVE_RESPONSE_OFFSET => {
+                    responses.push(Response {
+                        ecu,
+                        data: data.to_vec(),
+                    });
+                }
+                // Negative responses are treated like a missing response.
+                _ => {}
+            }
+        }
+        Ok(responses)
+    }
+
+    /// Reads the supported PIDs of service 01 of each ECU.
+    pub fn supported_pids(&mut self) -> Result<Vec<(Id, Vec<u8>)>, Error> {
+        let mut supported: Vec<(Id, Vec<u8>)> = Vec::new();
+        let mut base = 0x00u8;
+        loop {
+            for (ecu, data) in self.current_data(base)? {
+                let Ok(bitmap) = <[u8; 4]>::try_from(data.as_slice()) else {
+                    continue;
+                };
+                let bitmap = u32::from_be_bytes(bitmap);
+                let pids = (0..32)
+                    .filter(|bit| bitmap & (0x8000_0000 >> bit) != 0)
+                    .map(|bit| base + 1 + bit as u8);
+                match supported.iter_mut().find(|(id, _)| *id == ecu) {
+                    Some((_, list)) => list.extend(pids),
+                    None => supported.push((ecu, pids.collect())),
+                }
+            }
+
+            // The last PID of each range tells whether the next range is supported.
+            let next = base.wrapping_add(0x20);
+            if next == 0 || !supported.iter().any(|(_, pids)| pids.contains(&next)) {
+                break;
+            }
+            base = next;
+        }
+        Ok(supported)
+    }
+
+    /// Reads the raw data of a service 01 PID from each ECU.
+    pub fn current_data(&mut self, pid: u8) -> Result<Vec<(Id, Vec<u8>)>, Error> {
+        Ok(self
+            .request(&[SHOW_CURRENT_DATA, pid])?
+            .into_iter()
+            .filter(|r| r.data.first() == Some(&pid))
+            .map(|r| (r.ecu, r.data[1..].to_vec()))
+            .collect())
+    }
+
+    /// Reads a service 01 PID from each ECU and decodes its physical values,
+    /// see [`decode_pid`]. Responses that cannot be decoded, e.g. of unknown
+    /// PIDs, are skipped, [`Client::current_data`] returns the raw data.
+    pub fn live_data(&mut self, pid: u8) -> Result<Vec<(Id, Vec<Value>)>, Error> {
+        Ok(self
+            .current_data(pid)?
+            .into_iter()
+            .filter_map(|(ecu, data)| Some((ecu, decode_pid(pid, &data)?)))
+            .collect())
+    }
+
+    /// Reads the confirmed trouble codes with service 03.
+    pub fn stored_dtcs(&mut self) -> Result<Vec<(Id, Vec<Dtc>)>, Error> {
+        self.dtcs(SHOW_STORED_DTCS)
+    }
+
+    /// Reads the trouble codes detected during the current or last driving
+    /// cycle with service 07.
+    pub fn pending_dtcs(&mut self) -> Result<Vec<(Id, Vec<Dtc>)>, Error> {
+        self.dtcs(SHOW_PENDING_DTCS)
+    }
+
+    /// Reads the trouble codes that cannot be cleared by a scan tool with service 0A.
+    pub fn permanent_dtcs(&mut self) -> Result<Vec<(Id, Vec<Dtc>)>, Error> {
+        self.dtcs(SHOW_PERMANENT_DTCS)
+    }
+
+    fn dtcs(&mut self, service: u8) -> Result<Vec<(Id, Vec<Dtc>)>, Error> {
+        Ok(self
+            .request(&[service])?
+            .into_iter()
+            .filter_map(|r| {
+                // The first byte holds the number of trouble codes.
+                let (&count, codes) = r.data.split_first()?;
+                let dtcs = codes
+                    .chunks_exact(2)
+                    .take(count as usize)
+                    .map(|code| Dtc(u16::from_be_bytes([code[0], code[1]])))
+                    .collect();
+                Some((r.ecu, dtcs))
+            })
+            .collect())
+    }
+
+    /// Reads the vehicle identification number with service 09.
+    pub fn vehicle_identification_number(&mut self) -> Result<Vec<(Id, String)>, Error> {
+        Ok(self
+            .request(&[REQUEST_VEHICLE_INFORMATION, VEHICLE_IDENTIFICATION_NUMBER])?
+            .into_iter()
+            .filter_map(|r| match r.data.as_slice() {
+                // Info type and number of data items.
+                [VEHICLE_IDENTIFICATION_NUMBER, _, vin @ ..] => {
+                    let vin = vin
+                        .iter()
+                        .filter(|b| b.is_ascii_graphic())
+                        .map(|&b| b as char)
+                        .collect();
+                    Some((r.ecu, vin))
+                }
+                _ => None,
+            })
+            .collect())
+    }
+
+    fn transmit(&mut self, id: Id, data: &[u8]) -> Result<(), Error> {
+        let mut data = data.to_vec();
+        data.resize(8, PADDING);
+        let frame = Frame::new(id, &data).unwrap();
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            match embedded_can::nb::Can::transmit(self.interface, &frame) {
+                Ok(_) => return Ok(()),
+                Err(nb::Error::Other(err)) => return Err(err),
+                Err(nb::Error::WouldBlock) if Instant::now() >= deadline => {
+                    return Err(Error("OBD transmit timeout".to_string()));
+                }
+                Err(nb::Error::WouldBlock) => thread::yield_now(),
+            }
+        }
+    }
+}
+
+const OXYGEN_SENSOR_VOLTAGES: [&str; 8] = [
+    "Oxygen sensor 1 voltage",
+    "Oxygen sensor 2 voltage",
+    "Oxygen sensor 3 voltage",
+    "Oxygen sensor 4 voltage",
+    "Oxygen sensor 5 voltage",
+    "Oxygen sensor 6 voltage",
+    "Oxygen sensor 7 voltage",
+    "Oxygen sensor 8 voltage",
+];
+
+const OXYGEN_SENSOR_TRIMS: [&str; 8] = [
+    "Oxygen sensor 1 short term fuel trim",
+    "Oxygen sensor 2 short term fuel trim",
+    "Oxygen sensor 3 short term fuel trim",
+    "Oxygen sensor 4 short term fuel trim",
+    "Oxygen sensor 5 short term fuel trim",
+    "Oxygen sensor 6 short term fuel trim",
+    "Oxygen sensor 7 short term fuel trim",
+    "Oxygen sensor 8 short term fuel trim",
+];
+
+/// Decodes the data of a standard service 01 PID into physical values.
+/// Returns `None` for unknown PIDs or if the data is too short.
+pub fn decode_pid(pid: u8, data: &[u8]) -> Option<Vec<Value>> {
+    let byte = |index: usize| data.get(index).map(|&b| b as f64);
+    let word = |index: usize| Some(byte(index)? * 256.0 + byte(index + 1)?);
+    let value = |name, value, unit| Value { name, value, unit };
+    let percent = |name| Some(value(name, byte(0)? * 100.0 / 255.0, "%"));
+    let trim = |name| Some(value(name, (byte(0)? - 128.0) * 100.0 / 128.0, "%"));
+    let temperature = |name| Some(value(name, byte(0)? - 40.0, "°C"));
+
+    let value = match pid {
+        0x01 => {
+            let a = *data.first()?;
+            return Some(vec![
+                value("MIL", (a >> 7) as f64, ""),
+                value("DTC count", (a & 0x7F) as f64, ""),
+            ]);
+        }
+        0x04 => percent("Calculated engine load")?,
+        0x05 => temperature("Engine coolant temperature")?,
+        0x06 => trim("Short term fuel trim bank 1")?,
+        0x07 => trim("Long term fuel trim bank 1")?,
+        0x08 => trim("Short term fuel trim bank 2")?,
+        0x09 => trim("Long term fuel trim bank 2")?,
+        0x0A => value("Fuel pressure", byte(0)? * 3.0, "kPa"),
+        0x0B => value("Intake manifold absolute pressure", byte(0)?, "kPa"),
+        0x0C => value("Engine speed", word(0)? / 4.0, "rpm"),
+        0x0D => value("Vehicle speed", byte(0)?, "km/h"),
+        0x0E => value("Timing advance", byte(0)? / 2.0 - 64.0, "°"),
+        0x0F => temperature("Intake air temperature")?,
+        0x10 => value("Mass air flow rate", word(0)? / 100.0, "g/s"),
+        0x11 => percent("Throttle position")?,
+        0x14..=0x1B => {
+            let sensor = (pid - 0x14) as usize;
+            return Some(vec![
+                value(OXYGEN_SENSOR_VOLTAGES[sensor], byte(0)? / 200.0, "V"),
+                value(
+                    OXYGEN_SENSOR_TRIMS[sensor],
+                    (byte(1)? - 128.0) * 100.0 / 128.0,
+                    "%",
+                ),
+            ]);
+        }
+        0x1C => value("OBD standard", byte(0)?, ""),
+        0x1F => value("Run time since engine start", word(0)?, "s"),
+        0x21 => value("Distance traveled with MIL on", word(0)?, "km"),
+        0x22 => value("Fuel rail pressure", word(0)? * 0.079, "kPa"),
+        0x23 => value("Fuel rail gauge pressure", word(0)? * 10.0, "kPa"),
+        0x2C => percent("Commanded EGR")?,
+        0x2D => trim("EGR error")?,
+        0x2E => percent("Commanded evaporative purge")?,
+        0x2F => percent("Fuel tank level input")?,
+        0x30 => value("Warm-ups since codes cleared", byte(0)?, ""),
+        0x31 => value("Distance traveled since codes cleared", word(0)?, "km"),
+        0x33 => value("Absolute barometric pressure", byte(0)?, "kPa"),
+        0x42 => value("Control module voltage", word(0)? / 1000.0, "V"),
+        0x43 => value("Absolute load value", word(0)? * 100.0 / 255.0, "%"),
+        0x44 => value(
+            "Commanded air-fuel equivalence ratio",
+            word(0)? * 2.0 / 65536.0,
+            "",
+        ),
+        0x45 => percent("Relative throttle position")?,
+        0x46 => temperature("Ambient air temperature")?,
+        0x47 => percent("Absolute throttle position B")?,
+        0x48 => percent("Absolute throttle position C")?,
+        0x49 => percent("Accelerator pedal position D")?,
+        0x4A => percent("Accelerator pedal position E")?,
+        0x4B => percent("Accelerator pedal position F")?,
+        0x4C => percent("Commanded throttle actuator")?,
+        0x4D => value("Time run with MIL on", word(0)?, "min"),
+        0x4E => value("Time since trouble codes cleared", word(0)?, "min"),
+        0x51 => value("Fuel type", byte(0)?, ""),
+        0x52 => percent("Ethanol fuel")?,
+        0x5A => percent("Relative accelerator pedal position")?,
+        0x5B => percent("Hybrid battery pack remaining life")?,
+        0x5C => temperature("Engine oil temperature")?,
+        0x5D => value("Fuel injection timing", word(0)? / 128.0 - 210.0, "°"),
+        0x5E => value("Engine fuel rate", word(0)? / 20.0, "L/h"),
+        0xA6 => value(
+            "Odometer",
+            u32::from_be_bytes(data.get(..4)?.try_into().unwrap()) as f64 / 10.0,
+            "km",
+        ),
+        _ => return None,
+    };
+    Some(vec![value])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(pid: u8, data: &[u8]) -> Vec<(&'static str, f64, &'static str)> {
+        decode_pid(pid, data)
+            .unwrap()
+            .into_iter()
+            .map(|v| (v.name, v.value, v.unit))
+            .collect()
+    }
+
+    #[test]
+    fn pids() {
+        assert_eq!(
+            decode(0x0C, &[0x1A, 0xF8]),
+            [("Engine speed", 1726.0, "rpm")]
+        );
+        assert_eq!(
+            decode(0x0C, &[0xFF, 0xFF]),
+            [("Engine speed", 16383.75, "rpm")]
+        );
+        assert_eq!(
+            decode(0x05, &[0x7B]),
+            [("Engine coolant temperature", 83.0, "°C")]
+        );
+        assert_eq!(
+            decode(0x05, &[0x00]),
+            [("Engine coolant temperature", -40.0, "°C")]
+        );
+        assert_eq!(decode(0x0D, &[0x32]), [("Vehicle speed", 50.0, "km/h")]);
+        assert_eq!(decode(0x11, &[0xFF]), [("Throttle position", 100.0, "%")]);
+        assert_eq!(
+            decode(0x06, &[0x80]),
+            [("Short term fuel trim bank 1", 0.0, "%")]
+        );
+        assert_eq!(
+            decode(0x01, &[0x83, 0x07, 0xE5, 0x00]),
+            [("MIL", 1.0, ""), ("DTC count", 3.0, "")]
+        );
+        assert_eq!(
+            decode(0x15, &[0xC8, 0x80]),
+            [
+                ("Oxygen sensor 2 voltage", 1.0, "V"),
+                ("Oxygen sensor 2 short term fuel trim", 0.0, "%"),
+            ]
+        );
+        assert_eq!(
+            decode(0xA6, &[0x00, 0x01, 0xE2, 0x40]),
+            [("Odometer", 12345.6, "km")]
+        );
+
+        // Too short or unknown.
+        assert!(decode_pid(0x0C, &[0x1A]).is_none());
+        assert!(decode_pid(0x05, &[]).is_none());
+        assert!(decode_pid(0xA6, &[0, 0, 0]).is_none());
+        assert!(decode_pid(0x00, &[0xBE, 0x1F, 0xA8, 0x13]).is_none());
+    }
+
+    #[test]
+    fn value_display() {
+        let value = decode_pid(0x0C, &[0x1A, 0xF8]).unwrap()[0];
+        assert_eq!(value.to_string(), "Engine speed: 1726 rpm");
+        let value = decode_pid(0x1C, &[0x06]).unwrap()[0];
+        assert_eq!(value.to_string(), "OBD standard: 6");
+    }
+
+    #[test]
+    fn dtc_display() {
+        for (code, text) in [
+            (0x0133, "P0133"),
+            (0x0301, "P0301"),
+            (0x1234, "P1234"),
+            (0x4123, "C0123"),
+            (0x9ABC, "B1ABC"),
+            (0xC001, "U0001"),
+            (0xFFFF, "U3FFF"),
+        ] {
+            assert_eq!(Dtc(code).to_string(), text);
+        }
+    }
+
+    #[test]
+    fn physical_ids() {
+        let standard = |id| Id::Standard(StandardId::new(id).unwrap());
+        let extended = |id| Id::Extended(ExtendedId::new(id).unwrap());
+        assert_eq!(Addressing::physical_id(standard(0x7E8)), standard(0x7E0));
+        assert_eq!(Addressing::physical_id(standard(0x7EF)), standard(0x7E7));
+        assert_eq!(
+            Addressing::physical_id(extended(0x18DA_F110)),
+            extended(0x18DA_10F1)
+        );
+    }
+}
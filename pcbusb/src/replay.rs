@@ -0,0 +1,430 @@
+use std::{
+    collections::HashMap,
+    thread,
+    time::{Duration, Instant},
+};
+
+use embedded_can::{Frame as _, Id};
+
+use crate::log::{LogFrame, Record};
+use crate::{Error, Frame, Interface};
+
+/// Replay sleeps until shortly before a frame is due and spins for the
+/// remaining time.
+const SPIN_MARGIN: Duration = Duration::from_micros(500);
+
+/// Timing statistics of a replay.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ReplayReport {
+    /// Number of frames that have been transmitted.
+    pub sent: u64,
+    /// Number of records that could not be transmitted, like CAN FD and error frames.
+    pub skipped: u64,
+    /// Largest delay between the intended and the actual transmission time.
+    pub max_error: Duration,
+    /// Average delay between the intended and the actual transmission time.
+    pub mean_error: Duration,
+    /// Duration of the replay according to the trace timestamps and time scale.
+    pub intended_duration: Duration,
+    /// Duration the replay actually took.
+    pub actual_duration: Duration,
+}
+
+type IdFilter = Box<dyn Fn(Id) -> bool>;
+
+/// Replays recorded traces onto the bus, keeping the original timing between frames.
+///
+/// Only classic frames can be transmitted by the interface, other records are skipped.
+pub struct Replay {
+    time_scale: f64,
+    loops: Option<u32>,
+    start_offset: Duration,
+    stop_offset: Option<Duration>,
+    channel: Option<u8>,
+    filter: Option<IdFilter>,
+    remap: HashMap<Id, Id>,
+}
+
+impl Default for Replay {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Replay {
+    pub fn new() -> Self {
+        Self {
+            time_scale: 1.0,
+            loops: Some(1),
+            start_offset: Duration::ZERO,
+            stop_offset: None,
+            channel: None,
+            filter: None,
+            remap: HashMap::new(),
+        }
+    }
+
+    /// Divides the time between frames by `scale`, e.g. `2.0` replays twice as fast.
+    /// The scale must be positive, otherwise replaying fails.
+    pub fn time_scale(mut self, scale: f64) -> Self {
+        self.time_scale = scale;
+        self
+    }
+
+    /// Replays the trace `count` times, or endlessly for `None`. Endless replay
+    /// stops after a pass in which no frame was sent, e.g. for an empty trace.
+    pub fn loops(mut self, count: Option<u32>) -> Self {
+        self.loops = count;
+        self
+    }
+
+    /// Skips the records before `offset`, relative to the first record of the trace.
+    pub fn start_offset(mut self, offset: Duration) -> Self {
+        self.start_offset = offset;
+        self
+    }
+
+    /// Stops at `offset`, relative to the first record of the trace.
+    pub fn stop_offset(mut self, offset: Duration) -> Self {
+        self.stop_offset = Some(offset);
+        self
+    }
+
+    /// Only replays the records of `channel`.
+    pub fn channel(mut self, channel: u8) -> Self {
+        self.channel = Some(channel);
+        self
+    }
+
+    /// Only replays frames whose original identifier satisfies `filter`.
+    pub fn filter(mut self, filter: impl Fn(Id) -> bool + 'static) -> Self {
+        self.filter = Some(Box::new(filter));
+        self
+    }
+
+    /// Transmits frames recorded with identifier `from` with identifier `to`.
+    pub fn remap(mut self, from: impl Into<Id>, to: impl Into<Id>) -> Self {
+        self.remap.insert(from.into(), to.into());
+        self
+    }
+
+    /// Replays records held in memory.
+    pub fn play_records(
+        &self,
+        interface: &mut Interface,
+        records: &[Record],
+    ) -> Result<ReplayReport, Error> {
+        self.play(interface, || Ok(records.iter().cloned().map(Ok)))
+    }
+
+    /// Replays the records returned by `open`, which is called again for every loop
+    /// so that traces can be streamed from a file.
+    pub fn play<I, F>(&self, interface: &mut Interface, open: F) -> Result<ReplayReport, Error>
+    where
+        F: FnMut() -> Result<I, Error>,
+        I: IntoIterator<Item = Result<Record, Error>>,
+    {
+        self.run(
+            |frame| embedded_can::blocking::Can::transmit(interface, frame),
+            open,
+        )
+    }
+
+    /// Replays the records returned by `open` with `transmit`.
+    fn run<I, F>(
+        &self,
+        mut transmit: impl FnMut(&Frame) -> Result<(), Error>,
+        mut open: F,
+    ) -> Result<ReplayReport, Error>
+    where
+        F: FnMut() -> Result<I, Error>,
+        I: IntoIterator<Item = Result<Record, Error>>,
+    {
+        if self.time_scale.is_nan() || self.time_scale <= 0.0 {
+            return Err(Error(format!(
+                "The replay time scale must be positive, not {}",
+                self.time_scale
+            )));
+        }
+
+        let mut report = ReplayReport::default();
+        let mut total_error = Duration::ZERO;
+        let replay_start = Instant::now();
+
+        let mut iteration = 0;
+        while self.loops.is_none_or(|loops| iteration < loops) {
+            iteration += 1;
+            let sent = report.sent;
+
+            let mut trace_start = None;
+            let mut loop_start = Instant::now();
+            let mut loop_duration = Duration::ZERO;
+            for record in open()? {
+                let record = record?;
+                let first = *trace_start.get_or_insert(record.timestamp);
+                let offset = record.timestamp.saturating_sub(first);
+                if offset < self.start_offset {
+                    loop_start = Instant::now();
+                    continue;
+                }
+                if self.stop_offset.is_some_and(|stop| offset > stop) {
+                    break;
+                }
+                if self
+                    .channel
+                    .is_some_and(|channel| channel != record.channel)
+                {
+                    continue;
+                }
+                if let Some(filter) = &self.filter {
+                    let id = match &record.frame {
+                        LogFrame::Can(frame) => Some(frame.id()),
+                        LogFrame::Fd(frame) => Some(frame.id()),
+                        LogFrame::Error(_) => None,
+                    };
+                    if id.is_some_and(|id| !filter(id)) {
+                        continue;
+                    }
+                }
+
+                let Some(frame) = self.prepare(&record.frame) else {
+                    report.skipped += 1;
+                    continue;
+                };
+
+                let delay = (offset - self.start_offset).div_f64(self.time_scale);
+                let due = loop_start + delay;
+                sleep_until(due);
+                transmit(&frame)?;
+
+                let error = Instant::now().saturating_duration_since(due);
+                report.sent += 1;
+                report.max_error = report.max_error.max(error);
+                total_error += error;
+                loop_duration = delay;
+            }
+            report.intended_duration += loop_duration;
+
+            if self.loops.is_none() && report.sent == sent {
+                break;
+            }
+        }
+
+        report.actual_duration = replay_start.elapsed();
+        if report.sent > 0 {
+            report.mean_error = total_error.div_f64(report.sent as f64);
+        }
+        Ok(report)
+    }
+
+    /// Applies the identifier remapping, returns `None` for records that cannot
+    /// be transmitted.
+    fn prepare(&self, frame: &LogFrame) -> Option<Frame> {
+        let LogFrame::Can(frame) = frame else {
+            return None;
+        };
+        match self.remap.get(&frame.id()) {
+            Some(&id) if frame.is_remote_frame() => Frame::new_remote(id, frame.dlc()),
+            Some(&id) => Frame::new(id, frame.data()),
+            None => Some(frame.clone()),
+        }
+    }
+}
+
+fn sleep_until(deadline: Instant) {
+    let now = Instant::now();
+    if deadline > now + SPIN_MARGIN {
+        thread::sleep(deadline - now - SPIN_MARGIN);
+    }
+    while Instant::now() < deadline {
+        std::hint::spin_loop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_can::{ExtendedId, StandardId};
+
+    use super::*;
+    use crate::{FdFrame, log::ErrorFrame};
+
+    fn id(id: u16) -> Id {
+        Id::Standard(StandardId::new(id).unwrap())
+    }
+
+    fn record(millis: u64, frame_id: u16) -> Record {
+        Record::new(
+            Duration::from_millis(millis),
+            Frame::new(id(frame_id), &[frame_id as u8]).unwrap(),
+        )
+    }
+
+    /// Replays `records` and returns the report with the identifiers of the
+    /// transmitted frames and their offsets from the start of the replay.
+    fn run(replay: &Replay, records: &[Record]) -> (ReplayReport, Vec<(Id, Duration)>) {
+        let start = Instant::now();
+        let mut sent = Vec::new();
+        let report = replay
+            .run(
+                |frame| {
+                    sent.push((frame.id(), start.elapsed()));
+                    Ok(())
+                },
+                || Ok(records.iter().cloned().map(Ok)),
+            )
+            .unwrap();
+        (report, sent)
+    }
+
+    fn ids(sent: &[(Id, Duration)]) -> Vec<Id> {
+        sent.iter().map(|(id, _)| *id).collect()
+    }
+
+    #[test]
+    fn timing() {
+        let records = [record(1000, 1), record(1010, 2), record(1030, 3)];
+        let (report, sent) = run(&Replay::new(), &records);
+        assert_eq!(ids(&sent), [id(1), id(2), id(3)]);
+        assert_eq!(report.sent, 3);
+        assert_eq!(report.intended_duration, Duration::from_millis(30));
+        assert!(report.actual_duration >= Duration::from_millis(30));
+        for ((_, offset), expected) in sent.iter().zip([0, 10, 30]) {
+            assert!(*offset >= Duration::from_millis(expected));
+        }
+
+        let (report, sent) = run(&Replay::new().time_scale(2.0), &records);
+        assert_eq!(report.intended_duration, Duration::from_millis(15));
+        assert!(sent[2].1 >= Duration::from_millis(15));
+
+        let (report, sent) = run(&Replay::new().time_scale(0.5).loops(Some(2)), &records);
+        assert_eq!(sent.len(), 6);
+        assert_eq!(report.intended_duration, Duration::from_millis(120));
+        assert!(report.actual_duration >= Duration::from_millis(120));
+        assert!(report.max_error >= report.mean_error);
+    }
+
+    #[test]
+    fn invalid_time_scale() {
+        for scale in [0.0, -1.0, f64::NAN] {
+            let replay = Replay::new().time_scale(scale);
+            let result = replay.run(|_| Ok(()), || Ok(Vec::new()));
+            assert!(result.is_err(), "{}", scale);
+        }
+    }
+
+    #[test]
+    fn offsets() {
+        let records = [
+            record(100, 1),
+            record(110, 2),
+            record(120, 3),
+            record(130, 4),
+        ];
+        let replay = Replay::new()
+            .start_offset(Duration::from_millis(10))
+            .stop_offset(Duration::from_millis(20));
+        let (report, sent) = run(&replay, &records);
+        assert_eq!(ids(&sent), [id(2), id(3)]);
+        // Timing starts at the start offset.
+        assert_eq!(report.intended_duration, Duration::from_millis(10));
+    }
+
+    #[test]
+    fn selection_and_remapping() {
+        let mut on_channel_1 = record(0, 2);
+        on_channel_1.channel = 1;
+        let extended = ExtendedId::new(0x18FE_F100).unwrap();
+        let records = [
+            record(0, 1),
+            on_channel_1,
+            record(0, 3),
+            Record::new(Duration::ZERO, Frame::new_remote(id(4), 8).unwrap()),
+            Record::new(Duration::ZERO, FdFrame::new(id(5), &[0; 12]).unwrap()),
+            Record::new(Duration::ZERO, ErrorFrame::default()),
+        ];
+
+        let (report, sent) = run(&Replay::new(), &records);
+        assert_eq!(ids(&sent), [id(1), id(2), id(3), id(4)]);
+        assert_eq!(report.skipped, 2);
+
+        let (_, sent) = run(&Replay::new().channel(1), &records);
+        assert_eq!(ids(&sent), [id(2)]);
+
+        // Error frames have no identifier and are not filtered out.
+        let replay = Replay::new().filter(|id| id != Id::Standard(StandardId::new(3).unwrap()));
+        let (report, sent) = run(&replay, &records);
+        assert_eq!(ids(&sent), [id(1), id(2), id(4)]);
+        assert_eq!(report.skipped, 2);
+
+        // The filter sees the original identifiers.
+        let replay = Replay::new()
+            .remap(StandardId::new(1).unwrap(), extended)
+            .remap(StandardId::new(4).unwrap(), StandardId::new(0x40).unwrap())
+            .filter(|id| id != Id::Standard(StandardId::new(0x40).unwrap()));
+        let start = Instant::now();
+        let mut frames = Vec::new();
+        replay
+            .run(
+                |frame| {
+                    frames.push(frame.clone());
+                    Ok(())
+                },
+                || Ok(records.iter().cloned().map(Ok)),
+            )
+            .unwrap();
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert_eq!(
+            frames.iter().map(|f| f.id()).collect::<Vec<_>>(),
+            [Id::Extended(extended), id(2), id(3), id(0x40)]
+        );
+        assert_eq!(frames[0].data(), [1]);
+        assert!(frames[3].is_remote_frame());
+        assert_eq!(frames[3].dlc(), 8);
+    }
+
+    #[test]
+    fn endless_loops() {
+        // Ends after a pass without transmissions instead of spinning forever.
+        let (report, _) = run(&Replay::new().loops(None), &[]);
+        assert_eq!(report.sent, 0);
+
+        let replay = Replay::new().loops(None).channel(1);
+        let (report, _) = run(&replay, &[record(0, 1)]);
+        assert_eq!(report.sent, 0);
+
+        let mut passes = 0;
+        let replay = Replay::new().loops(None);
+        let report = replay
+            .run(
+                |_| Ok(()),
+                || {
+                    passes += 1;
+                    let records = if passes <= 3 {
+                        vec![record(0, 1)]
+                    } else {
+                        Vec::new()
+                    };
+                    Ok(records.into_iter().map(Ok))
+                },
+            )
+            .unwrap();
+        assert_eq!(report.sent, 3);
+        assert_eq!(passes, 4);
+    }
+
+    #[test]
+    fn errors() {
+        let replay = Replay::new();
+        let result = replay.run(
+            |_| Err(Error("bus off".to_string())),
+            || Ok(vec![Ok(record(0, 1))]),
+        );
+        assert!(result.is_err());
+
+        let result = replay.run(
+            |_| Ok(()),
+            || Ok(vec![Ok(record(0, 1)), Err(Error("bad line".to_string()))]),
+        );
+        assert!(result.is_err());
+    }
+}
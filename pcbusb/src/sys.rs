@@ -17,10 +17,12 @@ mod peak_compat {
 
     // Re-export the message type with the expected name
     pub use peak_can_sys::CANTPMsg as TPCANMsg;
+    pub use peak_can_sys::CANTPTimestamp as TPCANTimestamp;
 
     // Constants - convert from u32 to match expected types
     pub const PCAN_ERROR_OK: u32 = peak_can_sys::PEAK_ERROR_OK;
     pub const PCAN_ERROR_QRCVEMPTY: u32 = peak_can_sys::PEAK_ERROR_QRCVEMPTY;
+    pub const PCAN_ERROR_QXMTFULL: u32 = peak_can_sys::PEAK_ERROR_QXMTFULL;
 
     pub const PCAN_BAUD_1M: u32 = peak_can_sys::PEAK_BAUD_1M as u32;
     pub const PCAN_BAUD_5K: u32 = peak_can_sys::PEAK_BAUD_5K as u32;
@@ -0,0 +1,720 @@
+//! UDS (ISO 14229) diagnostic client running over ISO-TP.
+//!
+//! ```text
+//! let channel = isotp::Channel::new(&mut interface, StandardId::new(0x7E0).unwrap(), StandardId::new(0x7E8).unwrap());
+//! let mut client = uds::Client::new(channel);
+//! client.diagnostic_session_control(Session::Extended)?;
+//! client.security_access(0x01, |_level, seed: &[u8]| seed.iter().map(|b| b ^ 0x5A).collect())?;
+//! let vin = client.read_data_by_identifier(0xF190)?;
+//! ```
+
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+use crate::isotp::Channel;
+
+pub mod flash;
+
+const DIAGNOSTIC_SESSION_CONTROL: u8 = 0x10;
+const ECU_RESET: u8 = 0x11;
+const CLEAR_DIAGNOSTIC_INFORMATION: u8 = 0x14;
+const READ_DTC_INFORMATION: u8 = 0x19;
+const READ_DATA_BY_IDENTIFIER: u8 = 0x22;
+const SECURITY_ACCESS: u8 = 0x27;
+const WRITE_DATA_BY_IDENTIFIER: u8 = 0x2E;
+const ROUTINE_CONTROL: u8 = 0x31;
+const REQUEST_DOWNLOAD: u8 = 0x34;
+const TRANSFER_DATA: u8 = 0x36;
+const REQUEST_TRANSFER_EXIT: u8 = 0x37;
+const TESTER_PRESENT: u8 = 0x3E;
+
+const NEGATIVE_RESPONSE: u8 = 0x7F;
+const POSITIVE_RESPONSE_OFFSET: u8 = 0x40;
+const SUPPRESS_POSITIVE_RESPONSE: u8 = 0x80;
+
+const REPORT_DTC_BY_STATUS_MASK: u8 = 0x02;
+const REPORT_SUPPORTED_DTC: u8 = 0x0A;
+
+/// Negative response codes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NegativeResponseCode {
+    GeneralReject,
+    ServiceNotSupported,
+    SubFunctionNotSupported,
+    IncorrectMessageLengthOrInvalidFormat,
+    ResponseTooLong,
+    BusyRepeatRequest,
+    ConditionsNotCorrect,
+    RequestSequenceError,
+    NoResponseFromSubnetComponent,
+    FailurePreventsExecutionOfRequestedAction,
+    RequestOutOfRange,
+    SecurityAccessDenied,
+    AuthenticationRequired,
+    InvalidKey,
+    ExceededNumberOfAttempts,
+    RequiredTimeDelayNotExpired,
+    UploadDownloadNotAccepted,
+    TransferDataSuspended,
+    GeneralProgrammingFailure,
+    WrongBlockSequenceCounter,
+    RequestCorrectlyReceivedResponsePending,
+    SubFunctionNotSupportedInActiveSession,
+    ServiceNotSupportedInActiveSession,
+    RpmTooHigh,
+    RpmTooLow,
+    EngineIsRunning,
+    EngineIsNotRunning,
+    EngineRunTimeTooLow,
+    TemperatureTooHigh,
+    TemperatureTooLow,
+    VehicleSpeedTooHigh,
+    VehicleSpeedTooLow,
+    ThrottlePedalTooHigh,
+    ThrottlePedalTooLow,
+    TransmissionRangeNotInNeutral,
+    TransmissionRangeNotInGear,
+    BrakeSwitchNotClosed,
+    ShifterLeverNotInPark,
+    TorqueConverterClutchLocked,
+    VoltageTooHigh,
+    VoltageTooLow,
+    /// Reserved or manufacturer specific code.
+    Other(u8),
+}
+
+impl From<u8> for NegativeResponseCode {
+    fn from(code: u8) -> Self {
+        use NegativeResponseCode::*;
+        match code {
+            0x10 => GeneralReject,
+            0x11 => ServiceNotSupported,
+            0x12 => SubFunctionNotSupported,
+            0x13 => IncorrectMessageLengthOrInvalidFormat,
+            0x14 => ResponseTooLong,
+            0x21 => BusyRepeatRequest,
+            0x22 => ConditionsNotCorrect,
+            0x24 => RequestSequenceError,
+            0x25 => NoResponseFromSubnetComponent,
+            0x26 => FailurePreventsExecutionOfRequestedAction,
+            0x31 => RequestOutOfRange,
+            0x33 => SecurityAccessDenied,
+            0x34 => AuthenticationRequired,
+            0x35 => InvalidKey,
+            0x36 => ExceededNumberOfAttempts,
+            0x37 => RequiredTimeDelayNotExpired,
+            0x70 => UploadDownloadNotAccepted,
+            0x71 => TransferDataSuspended,
+            0x72 => GeneralProgrammingFailure,
+            0x73 => WrongBlockSequenceCounter,
+            0x78 => RequestCorrectlyReceivedResponsePending,
+            0x7E => SubFunctionNotSupportedInActiveSession,
+            0x7F => ServiceNotSupportedInActiveSession,
+            0x81 => RpmTooHigh,
+            0x82 => RpmTooLow,
+            0x83 => EngineIsRunning,
+            0x84 => EngineIsNotRunning,
+            0x85 => EngineRunTimeTooLow,
+            0x86 => TemperatureTooHigh,
+            0x87 => TemperatureTooLow,
+            0x88 => VehicleSpeedTooHigh,
+            0x89 => VehicleSpeedTooLow,
+            0x8A => ThrottlePedalTooHigh,
+            0x8B => ThrottlePedalTooLow,
+            0x8C => TransmissionRangeNotInNeutral,
+            0x8D => TransmissionRangeNotInGear,
+            0x8F => BrakeSwitchNotClosed,
+            0x90 => ShifterLeverNotInPark,
+            0x91 => TorqueConverterClutchLocked,
+            0x92 => VoltageTooHigh,
+            0x93 => VoltageTooLow,
+            code => Other(code),
+        }
+    }
+}
+
+impl From<NegativeResponseCode> for u8 {
+    fn from(code: NegativeResponseCode) -> Self {
+        use NegativeResponseCode::*;
+        match code {
+            GeneralReject => 0x10,
+            ServiceNotSupported => 0x11,
+            SubFunctionNotSupported => 0x12,
+            IncorrectMessageLengthOrInvalidFormat => 0x13,
+            ResponseTooLong => 0x14,
+            BusyRepeatRequest => 0x21,
+            ConditionsNotCorrect => 0x22,
+            RequestSequenceError => 0x24,
+            NoResponseFromSubnetComponent => 0x25,
+            FailurePreventsExecutionOfRequestedAction => 0x26,
+            RequestOutOfRange => 0x31,
+            SecurityAccessDenied => 0x33,
+            AuthenticationRequired => 0x34,
+            InvalidKey => 0x35,
+            ExceededNumberOfAttempts => 0x36,
+            RequiredTimeDelayNotExpired => 0x37,
+            UploadDownloadNotAccepted => 0x70,
+            TransferDataSuspended => 0x71,
+            GeneralProgrammingFailure => 0x72,
+            WrongBlockSequenceCounter => 0x73,
+            RequestCorrectlyReceivedResponsePending => 0x78,
+            SubFunctionNotSupportedInActiveSession => 0x7E,
+            ServiceNotSupportedInActiveSession => 0x7F,
+            RpmTooHigh => 0x81,
+            RpmTooLow => 0x82,
+            EngineIsRunning => 0x83,
+            EngineIsNotRunning => 0x84,
+            EngineRunTimeTooLow => 0x85,
+            TemperatureTooHigh => 0x86,
+            TemperatureTooLow => 0x87,
+            VehicleSpeedTooHigh => 0x88,
+            VehicleSpeedTooLow => 0x89,
+            ThrottlePedalTooHigh => 0x8A,
+            ThrottlePedalTooLow => 0x8B,
+            TransmissionRangeNotInNeutral => 0x8C,
+            TransmissionRangeNotInGear => 0x8D,
+            BrakeSwitchNotClosed => 0x8F,
+            ShifterLeverNotInPark => 0x90,
+            TorqueConverterClutchLocked => 0x91,
+            VoltageTooHigh => 0x92,
+            VoltageTooLow => 0x93,
+            Other(code) => code,
+        }
+    }
+}
+
+/// Errors of UDS requests.
+#[derive(Debug)]
+pub enum Error {
+    /// The ECU rejected the request of `service`.
+    Negative {
+        service: u8,
+        code: NegativeResponseCode,
+    },
+    /// No response was received within P2, or P2* after a response pending.
+    Timeout,
+    /// The response does not belong to the request or is too short.
+    InvalidResponse(Vec<u8>),
+    /// The verification routine reported an error in its status record.
+    VerificationFailed(Vec<u8>),
+    /// Transmission or reception of the ISO-TP messages failed.
+    Transport(crate::Error),
+}
+
+impl From<crate::Error> for Error {
+    fn from(err: crate::Error) -> Self {
+        Error::Transport(err)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Negative { service, code } => {
+                write!(
+                    f,
+                    "Negative response to service {:#04x}: {:?}",
+                    service, code
+                )
+            }
+            Error::Timeout => write!(f, "No response from the ECU"),
+            Error::InvalidResponse(response) => write!(f, "Invalid response {:02x?}", response),
+            Error::VerificationFailed(status) => {
+                write!(f, "Verification failed with status {:02x?}", status)
+            }
+            Error::Transport(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Diagnostic sessions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Session {
+    Default,
+    Programming,
+    Extended,
+    SafetySystem,
+    /// Manufacturer or supplier specific session.
+    Other(u8),
+}
+
+impl From<Session> for u8 {
+    fn from(session: Session) -> Self {
+        match session {
+            Session::Default => 0x01,
+            Session::Programming => 0x02,
+            Session::Extended => 0x03,
+            Session::SafetySystem => 0x04,
+            Session::Other(session) => session,
+        }
+    }
+}
+
+/// Response timing reported by the ECU when entering a session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SessionTiming {
+    /// Maximum time until the ECU starts its response.
+    pub p2: Duration,
+    /// Maximum time until the ECU responds after a response pending.
+    pub p2_star: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResetType {
+    Hard,
+    KeyOffOn,
+    Soft,
+    EnableRapidPowerShutDown,
+    DisableRapidPowerShutDown,
+    /// Manufacturer or supplier specific reset.
+    Other(u8),
+}
+
+impl From<ResetType> for u8 {
+    fn from(reset: ResetType) -> Self {
+        match reset {
+            ResetType::Hard => 0x01,
+            ResetType::KeyOffOn => 0x02,
+            ResetType::Soft => 0x03,
+            ResetType::EnableRapidPowerShutDown => 0x04,
+            ResetType::DisableRapidPowerShutDown => 0x05,
+            ResetType::Other(reset) => reset,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RoutineControl {
+    Start,
+    Stop,
+    RequestResults,
+}
+
+impl From<RoutineControl> for u8 {
+    fn from(control: RoutineControl) -> Self {
+        match control {
+            RoutineControl::Start => 0x01,
+            RoutineControl::Stop => 0x02,
+            RoutineControl::RequestResults => 0x03,
+        }
+    }
+}
+
+/// A diagnostic trouble code with its status byte.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Dtc {
+    /// The 3 byte DTC number.
+    pub code: u32,
+    pub status: u8,
+}
+
+/// Computes the key for a seed of a security access level.
+///
+/// Implemented for closures taking the level and the seed.
+pub trait SeedKey {
+    fn key(&mut self, level: u8, seed: &[u8]) -> Vec<u8>;
+}
+
+impl<F: FnMut(u8, &[u8]) -> Vec<u8>> SeedKey for F {
+    fn key(&mut self, level: u8, seed: &[u8]) -> Vec<u8> {
+        self(level, seed)
+    }
+}
+
+/// A UDS client talking to one ECU.
+pub struct Client<'a> {
+    channel: Channel<'a>,
+    p2: Duration,
+    p2_star: Duration,
+    keep_alive: Option<Duration>,
+    last_request: Instant,
+}
+
+impl<'a> Client<'a> {
+    pub fn new(channel: Channel<'a>) -> Self {
+        Self {
+            channel,
+            p2: Duration::from_millis(50),
+            p2_star: Duration::from_millis(5000),
+            keep_alive: None,
+            last_request: Instant::now(),
+        }
+    }
+
+    /// Sets the response timeout, defaults to 50 ms. It is updated with the
+    /// value reported by the ECU on session changes.
+    pub fn with_p2(mut self, p2: Duration) -> Self {
+        self.p2 = p2;
+        self
+    }
+
+    /// Sets the response timeout after a response pending, defaults to 5 s. It is
+    /// updated with the value reported by the ECU on session changes.
+    pub fn with_p2_star(mut self, p2_star: Duration) -> Self {
+        self.p2_star = p2_star;
+        self
+    }
+
+    /// Sends a tester present without response whenever no request has been
+    /// sent for `interval`, see [`Client::keep_alive`].
+    pub fn with_keep_alive(mut self, interval: Duration) -> Self {
+        self.keep_alive = Some(interval);
+        self
+    }
+
+    /// Returns the ISO-TP channel of the client.
+    pub fn channel(&mut self) -> &mut Channel<'a> {
+        &mut self.channel
+    }
+
+    /// Keeps a non-default session alive by sending a tester present if the keep
+    /// alive interval has elapsed since the last request. It should be called
+    /// periodically while the client is otherwise idle.
+    pub fn keep_alive(&mut self) -> Result<(), Error> {
+        match self.keep_alive {
+            Some(interval) if self.last_request.elapsed() >= interval => self.tester_present(true),
+            _ => Ok(()),
+        }
+    }
+
+    /// Sends a request and returns the positive response, including its service
+    /// identifier. Response pending messages are waited out.
+    pub fn request(&mut self, request: &[u8]) -> Result<Vec<u8>, Error> {
+        let service = request.first().copied().unwrap_or_default();
+        self.channel.send(request)?;
+        self.last_request = Instant::now();
+
+        wait_response(service, self.p2, self.p2_star, |timeout| {
+            self.channel.recv_timeout(timeout)
+        })
+    }
+
+    /// Sends a request with the suppress positive response bit set in the
+    /// sub-function. Only negative responses are reported by the ECU.
+    fn request_suppressed(&mut self, request: &[u8]) -> Result<(), Error> {
+        let mut request = request.to_vec();
+        request[1] |= SUPPRESS_POSITIVE_RESPONSE;
+        self.channel.send(&request)?;
+        self.last_request = Instant::now();
+
+        match self.channel.recv_timeout(self.p2)? {
+            Some(response) if response.len() >= 3 && response[0] == NEGATIVE_RESPONSE => {
+                Err(Error::Negative {
+                    service: request[0],
+                    code: response[2].into(),
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Changes the diagnostic session and adopts the timing reported by the ECU.
+    pub fn diagnostic_session_control(&mut self, session: Session) -> Result<SessionTiming, Error> {
+        let response = self.request(&[DIAGNOSTIC_SESSION_CONTROL, session.into()])?;
+        let timing = match response[..] {
+            [_, _, p2_hi, p2_lo, p2_star_hi, p2_star_lo, ..] => SessionTiming {
+                p2: Duration::from_millis(u16::from_be_bytes([p2_hi, p2_lo]) as u64),
+                p2_star: Duration::from_millis(
+                    u16::from_be_bytes([p2_star_hi, p2_star_lo]) as u64 * 10,
+                ),
+            },
+            _ => return Err(Error::InvalidResponse(response)),
+        };
+        self.p2 = timing.p2;
+        self.p2_star = timing.p2_star;
+        Ok(timing)
+    }
+
+    /// Sends a tester present, with `suppress_response` the ECU does not respond.
+    pub fn tester_present(&mut self, suppress_response: bool) -> Result<(), Error> {
+        if suppress_response {
+            self.request_suppressed(&[TESTER_PRESENT, 0x00])
+        } else {
+            self.request(&[TESTER_PRESENT, 0x00]).map(|_| ())
+        }
+    }
+
+    /// Unlocks the security access `level`, which must be odd, with the key
+    /// computed from the seed. A zero seed means the level is already unlocked.
+    pub fn security_access(&mut self, level: u8, mut seed_key: impl SeedKey) -> Result<(), Error> {
+        let response = self.request(&[SECURITY_ACCESS, level])?;
+        let seed = response.get(2..).unwrap_or_default();
+        if seed.iter().all(|&b| b == 0) {
+            return Ok(());
+        }
+
+        let mut request = vec![SECURITY_ACCESS, level.wrapping_add(1)];
+        request.extend(seed_key.key(level, seed));
+        self.request(&request).map(|_| ())
+    }
+
+    pub fn ecu_reset(&mut self, reset: ResetType) -> Result<(), Error> {
+        self.request(&[ECU_RESET, reset.into()]).map(|_| ())
+    }
+
+    pub fn read_data_by_identifier(&mut self, identifier: u16) -> Result<Vec<u8>, Error> {
+        let [hi, lo] = identifier.to_be_bytes();
+        let response = self.request(&[READ_DATA_BY_IDENTIFIER, hi, lo])?;
+        if response.get(1..3) != Some(&[hi, lo]) {
+            return Err(Error::InvalidResponse(response));
+        }
+        Ok(response[3..].to_vec())
+    }
+
+    pub fn write_data_by_identifier(&mut self, identifier: u16, data: &[u8]) -> Result<(), Error> {
+        let mut request = vec![WRITE_DATA_BY_IDENTIFIER];
+        request.extend(identifier.to_be_bytes());
+        request.extend_from_slice(data);
+        self.request(&request).map(|_| ())
+    }
+
+    /// Controls a routine and returns its status record.
+    pub fn routine_control(
+        &mut self,
+        control: RoutineControl,
+        identifier: u16,
+        options: &[u8],
+    ) -> Result<Vec<u8>, Error> {
+        let mut request = vec![ROUTINE_CONTROL, control.into()];
+        request.extend(identifier.to_be_bytes());
+        request.extend_from_slice(options);
+        let response = self.request(&request)?;
+        if response.len() < 4 || response[2..4] != identifier.to_be_bytes() {
+            return Err(Error::InvalidResponse(response));
+        }
+        Ok(response[4..].to_vec())
+    }
+
+    /// Reads the DTCs whose status matches `mask`, returns the status
+    /// availability mask of the ECU and the DTCs.
+    pub fn read_dtc_by_status_mask(&mut self, mask: u8) -> Result<(u8, Vec<Dtc>), Error> {
+        self.read_dtcs(&[READ_DTC_INFORMATION, REPORT_DTC_BY_STATUS_MASK, mask])
+    }
+
+    /// Reads all DTCs supported by the ECU, returns the status availability
+    /// mask of the ECU and the DTCs.
+    pub fn read_supported_dtcs(&mut self) -> Result<(u8, Vec<Dtc>), Error> {
+        self.read_dtcs(&[READ_DTC_INFORMATION, REPORT_SUPPORTED_DTC])
+    }
+
+    fn read_dtcs(&mut self, request: &[u8]) -> Result<(u8, Vec<Dtc>), Error> {
+        let response = self.request(request)?;
+        if response.len() < 3 || !(response.len() - 3).is_multiple_of(4) {
+            return Err(Error::InvalidResponse(response));
+        }
+        let dtcs = response[3..]
+            .chunks(4)
+            .map(|record| Dtc {
+                code: u32::from_be_bytes([0, record[0], record[1], record[2]]),
+                status: record[3],
+            })
+            .collect();
+        Ok((response[2], dtcs))
+    }
+
+    /// Requests a download of `size` bytes to `address`, both sent as 4 bytes.
+    /// Returns the maximum length of transfer data requests accepted by the ECU,
+    /// including the service identifier and block sequence counter.
+    pub fn request_download(
+        &mut self,
+        data_format: u8,
+        address: u32,
+        size: u32,
+    ) -> Result<usize, Error> {
+        let mut request = vec![REQUEST_DOWNLOAD, data_format, 0x44];
+        request.extend(address.to_be_bytes());
+        request.extend(size.to_be_bytes());
+        let response = self.request(&request)?;
+
+        let len = response.get(1).map_or(0, |format| (format >> 4) as usize);
+        match response.get(2..2 + len) {
+            Some(bytes) if (1..=8).contains(&len) => {
+                Ok(bytes.iter().fold(0usize, |max, &b| (max << 8) | b as usize))
+            }
+            _ => Err(Error::InvalidResponse(response)),
+        }
+    }
+
+    /// Transfers a block of data, returns the transfer response parameters.
+    pub fn transfer_data(&mut self, sequence_counter: u8, data: &[u8]) -> Result<Vec<u8>, Error> {
+        let mut request = Vec::with_capacity(data.len() + 2);
+        request.extend([TRANSFER_DATA, sequence_counter]);
+        request.extend_from_slice(data);
+        let response = self.request(&request)?;
+        if response.get(1) != Some(&sequence_counter) {
+            return Err(Error::InvalidResponse(response));
+        }
+        Ok(response[2..].to_vec())
+    }
+
+    /// Ends a transfer, returns the transfer response parameters.
+    pub fn request_transfer_exit(&mut self) -> Result<Vec<u8>, Error> {
+        let response = self.request(&[REQUEST_TRANSFER_EXIT])?;
+        Ok(response[1..].to_vec())
+    }
+
+    /// Clears the DTCs of a group, `0xFFFFFF` for all groups.
+    pub fn clear_diagnostic_information(&mut self, group: u32) -> Result<(), Error> {
+        let [_, a, b, c] = group.to_be_bytes();
+        self.request(&[CLEAR_DIAGNOSTIC_INFORMATION, a, b, c])
+            .map(|_| ())
+    }
+}
+
+/// Receives responses with `recv` until the positive or negative response to
+/// `service` arrives, waiting up to `p2` and up to `p2_star` after a response
+/// pending.
+fn wait_response(
+    service: u8,
+    p2: Duration,
+    p2_star: Duration,
+    mut recv: impl FnMut(Duration) -> Result<Option<Vec<u8>>, crate::Error>,
+) -> Result<Vec<u8>, Error> {
+    let mut timeout = p2;
+    loop {
+        let response = recv(timeout)?.ok_or(Error::Timeout)?;
+        match response[..] {
+            [NEGATIVE_RESPONSE, s, code, ..] if s == service => {
+                match NegativeResponseCode::from(code) {
+                    NegativeResponseCode::RequestCorrectlyReceivedResponsePending => {
+                        timeout = p2_star;
+                    }
+                    code => return Err(Error::Negative { service, code }),
+                }
+            }
+            [s, ..] if s == service.wrapping_add(POSITIVE_RESPONSE_OFFSET) => {
+                return Ok(response);
+            }
+            // Responses to other requests, e.g. of a previous timeout.
+            _ => continue,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const P2: Duration = Duration::from_millis(50);
+    const P2_STAR: Duration = Duration::from_millis(5000);
+
+    #[test]
+    fn negative_response_codes() {
+        use NegativeResponseCode::*;
+        for (value, code) in [
+            (0x10, GeneralReject),
+            (0x11, ServiceNotSupported),
+            (0x13, IncorrectMessageLengthOrInvalidFormat),
+            (0x22, ConditionsNotCorrect),
+            (0x31, RequestOutOfRange),
+            (0x33, SecurityAccessDenied),
+            (0x35, InvalidKey),
+            (0x73, WrongBlockSequenceCounter),
+            (0x78, RequestCorrectlyReceivedResponsePending),
+            (0x7F, ServiceNotSupportedInActiveSession),
+            (0x93, VoltageTooLow),
+            (0x00, Other(0x00)),
+            (0x23, Other(0x23)),
+            (0x8E, Other(0x8E)),
+            (0xF0, Other(0xF0)),
+        ] {
+            assert_eq!(NegativeResponseCode::from(value), code);
+            assert_eq!(u8::from(code), value);
+        }
+
+        for value in 0..=u8::MAX {
+            assert_eq!(u8::from(NegativeResponseCode::from(value)), value);
+        }
+    }
+
+    /// Runs `wait_response` on scripted responses, returns the result and the
+    /// timeouts the responses were waited for with.
+    fn wait(service: u8, responses: &[Option<&[u8]>]) -> (Result<Vec<u8>, Error>, Vec<Duration>) {
+        let mut responses = responses.iter();
+        let mut timeouts = Vec::new();
+        let result = wait_response(service, P2, P2_STAR, |timeout| {
+            timeouts.push(timeout);
+            Ok(responses
+                .next()
+                .expect("no more responses")
+                .map(<[u8]>::to_vec))
+        });
+        (result, timeouts)
+    }
+
+    #[test]
+    fn positive_response() {
+        let (result, timeouts) = wait(0x22, &[Some(&[0x62, 0xF1, 0x90, 0x57])]);
+        assert_eq!(result.unwrap(), [0x62, 0xF1, 0x90, 0x57]);
+        assert_eq!(timeouts, [P2]);
+
+        // Responses to other services are skipped.
+        let (result, timeouts) = wait(
+            0x22,
+            &[
+                Some(&[0x50, 0x03]),
+                Some(&[0x7F, 0x10, 0x22]),
+                Some(&[0x62, 0xF1, 0x90]),
+            ],
+        );
+        assert_eq!(result.unwrap(), [0x62, 0xF1, 0x90]);
+        assert_eq!(timeouts, [P2, P2, P2]);
+    }
+
+    #[test]
+    fn negative_response() {
+        let (result, _) = wait(0x27, &[Some(&[0x7F, 0x27, 0x35])]);
+        let Err(Error::Negative { service, code }) = result else {
+            panic!("expected a negative response, got {:?}", result);
+        };
+        assert_eq!(service, 0x27);
+        assert_eq!(code, NegativeResponseCode::InvalidKey);
+
+        // Too short to carry a code.
+        let (result, _) = wait(0x27, &[Some(&[0x7F, 0x27]), None]);
+        assert!(matches!(result, Err(Error::Timeout)));
+    }
+
+    #[test]
+    fn response_pending() {
+        let (result, timeouts) = wait(
+            0x31,
+            &[
+                Some(&[0x7F, 0x31, 0x78]),
+                Some(&[0x7F, 0x31, 0x78]),
+                Some(&[0x71, 0x01, 0xFF, 0x00]),
+            ],
+        );
+        assert_eq!(result.unwrap(), [0x71, 0x01, 0xFF, 0x00]);
+        assert_eq!(timeouts, [P2, P2_STAR, P2_STAR]);
+
+        let (result, timeouts) = wait(
+            0x31,
+            &[Some(&[0x7F, 0x31, 0x78]), Some(&[0x7F, 0x31, 0x22])],
+        );
+        assert!(matches!(
+            result,
+            Err(Error::Negative {
+                service: 0x31,
+                code: NegativeResponseCode::ConditionsNotCorrect
+            })
+        ));
+        assert_eq!(timeouts, [P2, P2_STAR]);
+
+        let (result, timeouts) = wait(0x31, &[Some(&[0x7F, 0x31, 0x78]), None]);
+        assert!(matches!(result, Err(Error::Timeout)));
+        assert_eq!(timeouts, [P2, P2_STAR]);
+    }
+
+    #[test]
+    fn transport_error() {
+        let result = wait_response(0x22, P2, P2_STAR, |_| {
+            Err(crate::Error("ISO-TP N_Cr timeout".to_string()))
+        });
+        assert!(matches!(result, Err(Error::Transport(_))));
+    }
+}
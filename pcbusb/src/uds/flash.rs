@@ -0,0 +1,476 @@
+//! Flash programming of ECUs from Intel HEX and Motorola S-record images.
+//!
+//! The programming session and security access are up to the caller, as they
+//! differ between manufacturers:
+//!
+//! ```text
+//! let image = Image::open("firmware.hex")?;
+//! client.diagnostic_session_control(Session::Programming)?;
+//! client.security_access(0x11, seed_key)?;
+//! Flasher::new()
+//!     .with_erase_routine(0xFF00)
+//!     .with_verify_routine(0x0202, Vec::new())
+//!     .with_progress(|p| println!("{} / {} bytes", p.sent, p.total))
+//!     .flash(&mut client, &image)?;
+//! client.ecu_reset(ResetType::Hard)?;
+//! ```
+
+use std::{fs, path::Path};
+
+use super::{Client, Error, RoutineControl};
+
+/// A contiguous block of memory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    pub address: u32,
+    pub data: Vec<u8>,
+}
+
+/// Memory contents of a firmware image, as segments sorted by address.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Image {
+    pub segments: Vec<Segment>,
+    /// Start address given by the image, if any.
+    pub entry_point: Option<u32>,
+}
+
+impl Image {
+    /// Reads an Intel HEX or S-record file, the format is detected from its content.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, crate::Error> {
+        let content = fs::read_to_string(path)?;
+        match content.trim_start().chars().next() {
+            Some(':') => Self::from_ihex(&content),
+            Some('S') => Self::from_srec(&content),
+            _ => Err(crate::Error("Unknown image format".to_string())),
+        }
+    }
+
+    /// Parses an Intel HEX image.
+    pub fn from_ihex(s: &str) -> Result<Self, crate::Error> {
+        let mut builder = ImageBuilder::default();
+        let mut base = 0u32;
+        for (index, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let error = |msg: &str| crate::Error(format!("Intel HEX line {}: {}", index + 1, msg));
+
+            let record = line
+                .strip_prefix(':')
+                .and_then(decode_hex)
+                .ok_or_else(|| error("invalid record"))?;
+            if record.len() < 5 || record.len() != record[0] as usize + 5 {
+                return Err(error("invalid record length"));
+            }
+            if record.iter().fold(0u8, |sum, &b| sum.wrapping_add(b)) != 0 {
+                return Err(error("checksum mismatch"));
+            }
+
+            let offset = u16::from_be_bytes([record[1], record[2]]) as u32;
+            let data = &record[4..record.len() - 1];
+            match record[3] {
+                0x00 => builder.add(base.wrapping_add(offset), data),
+                0x01 => break,
+                0x02 if data.len() == 2 => {
+                    base = (u16::from_be_bytes([data[0], data[1]]) as u32) << 4
+                }
+                0x03 if data.len() == 4 => {
+                    let segment = u16::from_be_bytes([data[0], data[1]]) as u32;
+                    let offset = u16::from_be_bytes([data[2], data[3]]) as u32;
+                    builder.entry_point = Some((segment << 4) + offset);
+                }
+                0x04 if data.len() == 2 => {
+                    base = (u16::from_be_bytes([data[0], data[1]]) as u32) << 16
+                }
+                0x05 if data.len() == 4 => {
+                    builder.entry_point = Some(u32::from_be_bytes(data.try_into().unwrap()))
+                }
+                _ => return Err(error("invalid record type")),
+            }
+        }
+        builder.build()
+    }
+
+    /// Parses a Motorola S-record image.
+    pub fn from_srec(s: &str) -> Result<Self, crate::Error> {
+        let mut builder = ImageBuilder::default();
+        for (index, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let error = |msg: &str| crate::Error(format!("S-record line {}: {}", index + 1, msg));
+
+            let mut chars = line.chars();
+            if chars.next() != Some('S') {
+                return Err(error("invalid record"));
+            }
+            let record_type = chars.next().ok_or_else(|| error("invalid record"))?;
+            let record = decode_hex(chars.as_str()).ok_or_else(|| error("invalid record"))?;
+            if record.is_empty() || record.len() != record[0] as usize + 1 {
+                return Err(error("invalid record length"));
+            }
+            let (checksum, bytes) = record.split_last().unwrap();
+            if !bytes.iter().fold(0u8, |sum, &b| sum.wrapping_add(b)) != *checksum {
+                return Err(error("checksum mismatch"));
+            }
+
+            let address_len = match record_type {
+                '0' | '1' | '5' | '9' => 2,
+                '2' | '6' | '8' => 3,
+                '3' | '7' => 4,
+                _ => return Err(error("invalid record type")),
+            };
+            if bytes.len() < 1 + address_len {
+                return Err(error("invalid record length"));
+            }
+            let address = bytes[1..1 + address_len]
+                .iter()
+                .fold(0u32, |address, &b| (address << 8) | b as u32);
+            let data = &bytes[1 + address_len..];
+            match record_type {
+                '1' | '2' | '3' => builder.add(address, data),
+                '7' | '8' | '9' => builder.entry_point = Some(address),
+                // Header and record counts.
+                _ => {}
+            }
+        }
+        builder.build()
+    }
+
+    /// Total number of data bytes.
+    pub fn len(&self) -> usize {
+        self.segments.iter().map(|s| s.data.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.segments.is_empty()
+    }
+}
+
+#[derive(Default)]
+struct ImageBuilder {
+    chunks: Vec<Segment>,
+    entry_point: Option<u32>,
+}
+
+impl ImageBuilder {
+    fn add(&mut self, address: u32, data: &[u8]) {
+        if data.is_empty() {
+            return;
+        }
+        // Records usually follow each other, so they are merged right away.
+        if let Some(last) = self.chunks.last_mut()
+            && last.address as u64 + last.data.len() as u64 == address as u64
+        {
+            last.data.extend_from_slice(data);
+            return;
+        }
+        self.chunks.push(Segment {
+            address,
+            data: data.to_vec(),
+        });
+    }
+
+    /// Sorts the chunks and merges adjacent ones into segments.
+    fn build(mut self) -> Result<Image, crate::Error> {
+        self.chunks.sort_by_key(|chunk| chunk.address);
+        let mut segments: Vec<Segment> = Vec::new();
+        for chunk in self.chunks {
+            if let Some(last) = segments.last_mut() {
+                let end = last.address as u64 + last.data.len() as u64;
+                if (chunk.address as u64) < end {
+                    return Err(crate::Error(format!(
+                        "Image data overlaps at address {:#010x}",
+                        chunk.address
+                    )));
+                }
+                if chunk.address as u64 == end {
+                    last.data.extend(chunk.data);
+                    continue;
+                }
+            }
+            segments.push(chunk);
+        }
+        Ok(Image {
+            segments,
+            entry_point: self.entry_point,
+        })
+    }
+}
+
+fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// State of a flashing operation, passed to the progress callback after
+/// every transferred block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Progress {
+    /// Index of the segment being transferred.
+    pub segment: usize,
+    pub segments: usize,
+    /// Number of bytes transferred so far, of all segments.
+    pub sent: usize,
+    /// Number of bytes of all segments.
+    pub total: usize,
+}
+
+type ProgressCallback<'a> = Box<dyn FnMut(Progress) + 'a>;
+
+/// Downloads images with RequestDownload, TransferData and RequestTransferExit.
+pub struct Flasher<'a> {
+    data_format: u8,
+    max_block_len: Option<usize>,
+    erase_routine: Option<u16>,
+    verify_routine: Option<(u16, Vec<u8>)>,
+    progress: Option<ProgressCallback<'a>>,
+}
+
+impl Default for Flasher<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> Flasher<'a> {
+    pub fn new() -> Self {
+        Self {
+            data_format: 0x00,
+            max_block_len: None,
+            erase_routine: None,
+            verify_routine: None,
+            progress: None,
+        }
+    }
+
+    /// Sets the data format identifier of the download requests, which selects
+    /// compression and encryption. Defaults to `0x00`, plain data.
+    pub fn with_data_format(mut self, data_format: u8) -> Self {
+        self.data_format = data_format;
+        self
+    }
+
+    /// Limits the transfer data requests to `len` bytes, including the service
+    /// identifier and block sequence counter, if the ECU accepts longer ones.
+    pub fn with_max_block_len(mut self, len: usize) -> Self {
+        self.max_block_len = Some(len);
+        self
+    }
+
+    /// Starts the routine `identifier` with the address and size of each segment
+    /// before downloading it, to erase the memory.
+    pub fn with_erase_routine(mut self, identifier: u16) -> Self {
+        self.erase_routine = Some(identifier);
+        self
+    }
+
+    /// Starts the routine `identifier` with `options` after the download. The
+    /// verification fails if the first byte of its status record is not zero.
+    pub fn with_verify_routine(mut self, identifier: u16, options: Vec<u8>) -> Self {
+        self.verify_routine = Some((identifier, options));
+        self
+    }
+
+    pub fn with_progress(mut self, progress: impl FnMut(Progress) + 'a) -> Self {
+        self.progress = Some(Box::new(progress));
+        self
+    }
+
+    /// Downloads all segments of the image and runs the verification routine.
+    pub fn flash(&mut self, client: &mut Client<'_>, image: &Image) -> Result<(), Error> {
+        let mut progress = Progress {
+            segment: 0,
+            segments: image.segments.len(),
+            sent: 0,
+            total: image.len(),
+        };
+
+        for (index, segment) in image.segments.iter().enumerate() {
+            progress.segment = index;
+            let size = u32::try_from(segment.data.len())
+                .map_err(|_| crate::Error("Segment is too large".to_string()))?;
+
+            if let Some(routine) = self.erase_routine {
+                let mut options = vec![0x44];
+                options.extend(segment.address.to_be_bytes());
+                options.extend(size.to_be_bytes());
+                client.routine_control(RoutineControl::Start, routine, &options)?;
+            }
+
+            let max_len = client.request_download(self.data_format, segment.address, size)?;
+            let max_len = self.max_block_len.map_or(max_len, |len| len.min(max_len));
+            // The service identifier and block sequence counter take two bytes.
+            if max_len <= 2 {
+                return Err(crate::Error(format!("Block length {} is too short", max_len)).into());
+            }
+
+            let mut sequence_counter = 1u8;
+            for block in segment.data.chunks(max_len - 2) {
+                client.transfer_data(sequence_counter, block)?;
+                sequence_counter = sequence_counter.wrapping_add(1);
+
+                progress.sent += block.len();
+                if let Some(callback) = &mut self.progress {
+                    callback(progress);
+                }
+            }
+            client.request_transfer_exit()?;
+        }
+
+        if let Some((routine, options)) = &self.verify_routine {
+            let status = client.routine_control(RoutineControl::Start, *routine, options)?;
+            if status.first().is_some_and(|&b| b != 0) {
+                return Err(Error::VerificationFailed(status));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ihex() {
+        let image = Image::from_ihex(
+            ":10010000214601360121470136007EFE09D2190140
+:100110002146017E17C20001FF5F16002148011928
+:10012000194E79234623965778239EDA3F01B2CAA7
+:100130003F0156702B5E712B722B732146013421C7
+:00000001FF
+",
+        )
+        .unwrap();
+        assert_eq!(image.segments.len(), 1);
+        assert_eq!(image.segments[0].address, 0x100);
+        assert_eq!(image.segments[0].data[..4], [0x21, 0x46, 0x01, 0x36]);
+        assert_eq!(image.len(), 64);
+        assert_eq!(image.entry_point, None);
+    }
+
+    #[test]
+    fn ihex_addresses() {
+        // Out of order and with a gap, the records after the end are ignored.
+        let image = Image::from_ihex(
+            ":020000040800F2
+:0400100005060708D2
+:0400000001020304F2
+
+:0100040009F2
+:0400000508000131BD
+:00000001FF
+:0100040009F2
+",
+        )
+        .unwrap();
+        assert_eq!(
+            image.segments,
+            [
+                Segment {
+                    address: 0x0800_0000,
+                    data: vec![1, 2, 3, 4, 9],
+                },
+                Segment {
+                    address: 0x0800_0010,
+                    data: vec![5, 6, 7, 8],
+                },
+            ]
+        );
+        assert_eq!(image.entry_point, Some(0x0800_0131));
+
+        let image =
+            Image::from_ihex(":020000021000EC\n:01002000AA35\n:0400000312340010A3").unwrap();
+        assert_eq!(image.segments[0].address, 0x10020);
+        assert_eq!(image.entry_point, Some(0x12350));
+    }
+
+    #[test]
+    fn invalid_ihex() {
+        for (image, message) in [
+            (":0400000001020304F3", "line 1: checksum mismatch"),
+            (":00000001FF\n", ""),
+            (":0400000001020304", "line 1: invalid record length"),
+            ("0400000001020304F2", "line 1: invalid record"),
+            (":0400000001020304F", "line 1: invalid record"),
+            (":020000060800F0", "line 1: invalid record type"),
+            (
+                ":0100020009F4\n:0400000001020304F2",
+                "overlaps at address 0x00000002",
+            ),
+        ] {
+            let result = Image::from_ihex(image);
+            if message.is_empty() {
+                assert!(result.unwrap().is_empty());
+            } else {
+                let err = result.unwrap_err().to_string();
+                assert!(err.ends_with(message), "{}", err);
+            }
+        }
+    }
+
+    #[test]
+    fn srec() {
+        let image = Image::from_srec(
+            "S00F000068656C6C6F202020202000003C
+S11F00007C0802A6900100049421FFF07C6C1B787C8C23783C6000003863000026
+S11F001C4BFFFFE5398000007D83637880010014382100107C0803A64E800020E9
+S111003848656C6C6F20776F726C642E0A0042
+S5030003F9
+S9030000FC
+",
+        )
+        .unwrap();
+        assert_eq!(image.segments.len(), 1);
+        assert_eq!(image.segments[0].address, 0);
+        assert_eq!(image.segments[0].data[..4], [0x7C, 0x08, 0x02, 0xA6]);
+        assert_eq!(image.segments[0].data[56..62], *b"Hello ");
+        assert_eq!(image.len(), 70);
+        assert_eq!(image.entry_point, Some(0));
+
+        let image = Image::from_srec(
+            "S3060800000206E9\nS20712345601020356\nS307080000000405E7\nS70508000000F2",
+        )
+        .unwrap();
+        assert_eq!(
+            image.segments,
+            [
+                Segment {
+                    address: 0x12_3456,
+                    data: vec![1, 2, 3],
+                },
+                Segment {
+                    address: 0x0800_0000,
+                    data: vec![4, 5, 6],
+                },
+            ]
+        );
+        assert_eq!(image.entry_point, Some(0x0800_0000));
+        let image = Image::from_srec("S8041234565F").unwrap();
+        assert_eq!(image.entry_point, Some(0x12_3456));
+    }
+
+    #[test]
+    fn invalid_srec() {
+        for (image, message) in [
+            ("S20712345601020357", "line 1: checksum mismatch"),
+            ("S9030000FC\nS2071234560102035", "line 2: invalid record"),
+            ("S207123456010203", "line 1: invalid record length"),
+            ("S1020000FD", "line 1: invalid record length"),
+            ("S4030000FC", "line 1: invalid record type"),
+            (":0400000001020304F2", "line 1: invalid record"),
+            ("S", "line 1: invalid record"),
+        ] {
+            let err = Image::from_srec(image).unwrap_err().to_string();
+            assert!(err.ends_with(message), "{}", err);
+        }
+    }
+}
@@ -0,0 +1,534 @@
+//! XCP on CAN master for calibration and measurement.
+//!
+//! Memory is addressed by address, extension and size, as found in an A2L
+//! file or a linker map.
+//!
+//! ```text
+//! let mut master = xcp::Master::new(&mut interface, StandardId::new(0x7E0).unwrap(), StandardId::new(0x7E1).unwrap());
+//! master.connect()?;
+//! master.unlock(xcp::resource::CAL_PAG, |_resource, seed: &[u8]| seed.iter().map(|b| b ^ 0x5A).collect())?;
+//! let gain = master.read(0x2000_1000, 0, 4)?;
+//! master.write(0x2000_1000, 0, &1.5f32.to_le_bytes())?;
+//! ```
+
+use std::{fmt, time::Duration};
+
+use embedded_can::{Frame as _, Id};
+
+use crate::{Frame, Interface, ResponseFilter, dbc::ByteOrder, uds::SeedKey};
+
+pub mod daq;
+
+const CONNECT: u8 = 0xFF;
+const DISCONNECT: u8 = 0xFE;
+const GET_STATUS: u8 = 0xFD;
+const GET_SEED: u8 = 0xF8;
+const UNLOCK: u8 = 0xF7;
+const SET_MTA: u8 = 0xF6;
+const UPLOAD: u8 = 0xF5;
+const SHORT_UPLOAD: u8 = 0xF4;
+const DOWNLOAD: u8 = 0xF0;
+
+const POSITIVE_RESPONSE: u8 = 0xFF;
+const ERROR: u8 = 0xFE;
+
+/// Resources that can be protected by seed and key.
+pub mod resource {
+    /// Calibration and paging.
+    pub const CAL_PAG: u8 = 0x01;
+    /// Data acquisition.
+    pub const DAQ: u8 = 0x04;
+    /// Data stimulation.
+    pub const STIM: u8 = 0x08;
+    /// Flash programming.
+    pub const PGM: u8 = 0x10;
+}
+
+/// Error codes of negative responses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorCode {
+    CommandSynch,
+    CommandBusy,
+    DaqActive,
+    ProgramActive,
+    CommandUnknown,
+    CommandSyntax,
+    OutOfRange,
+    WriteProtected,
+    AccessDenied,
+    AccessLocked,
+    PageNotValid,
+    ModeNotValid,
+    SegmentNotValid,
+    Sequence,
+    DaqConfig,
+    MemoryOverflow,
+    Generic,
+    Verify,
+    ResourceTemporaryNotAccessible,
+    Other(u8),
+}
+
+impl From<u8> for ErrorCode {
+    fn from(code: u8) -> Self {
+        use ErrorCode::*;
+        match code {
+            0x00 => CommandSynch,
+            0x10 => CommandBusy,
+            0x11 => DaqActive,
+            0x12 => ProgramActive,
+            0x20 => CommandUnknown,
+            0x21 => CommandSyntax,
+            0x22 => OutOfRange,
+            0x23 => WriteProtected,
+            0x24 => AccessDenied,
+            0x25 => AccessLocked,
+            0x26 => PageNotValid,
+            0x27 => ModeNotValid,
+            0x28 => SegmentNotValid,
+            0x29 => Sequence,
+            0x2A => DaqConfig,
+            0x30 => MemoryOverflow,
+            0x31 => Generic,
+            0x32 => Verify,
+            0x33 => ResourceTemporaryNotAccessible,
+            code => Other(code),
+        }
+    }
+}
+
+/// Errors of XCP commands.
+#[derive(Debug)]
+pub enum Error {
+    /// The slave rejected `command`.
+    Negative { command: u8, code: ErrorCode },
+    /// The command was sent before connecting.
+    NotConnected,
+    /// No response was received within the timeout.
+    Timeout,
+    /// The response does not belong to the command or is too short.
+    InvalidResponse(Vec<u8>),
+    /// The seed and key function returned an empty key or one longer than 255
+    /// bytes.
+    InvalidKeyLength(usize),
+    /// The requested configuration cannot be set up on the slave.
+    Configuration(String),
+    /// Transmission or reception of the frames failed.
+    Transport(crate::Error),
+}
+
+impl From<crate::Error> for Error {
+    fn from(err: crate::Error) -> Self {
+        Error::Transport(err)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Negative { command, code } => {
+                write!(
+                    f,
+                    "Negative response to command {:#04x}: {:?}",
+                    command, code
+                )
+            }
+            Error::NotConnected => write!(f, "Not connected to the XCP slave"),
+            Error::Timeout => write!(f, "No response from the XCP slave"),
+            Error::InvalidResponse(response) => write!(f, "Invalid response {:02x?}", response),
+            Error::InvalidKeyLength(len) => write!(f, "Invalid key length {}", len),
+            Error::Configuration(reason) => write!(f, "Invalid configuration: {}", reason),
+            Error::Transport(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Properties of the slave reported by CONNECT.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Connection {
+    /// Available resources, see [`resource`].
+    pub resources: u8,
+    /// Byte order of multi-byte parameters and memory.
+    pub byte_order: ByteOrder,
+    /// Size in bytes of the smallest addressable memory element: 1, 2 or 4.
+    pub address_granularity: u8,
+    /// Maximum length of command and response packets.
+    pub max_cto: u8,
+    /// Maximum length of DAQ packets.
+    pub max_dto: u16,
+    pub protocol_version: u8,
+    pub transport_version: u8,
+}
+
+impl Connection {
+    fn parse(response: Vec<u8>) -> Result<Self, Error> {
+        if response.len() < 8 || (response[2] >> 1) & 0x3 == 3 || response[3] < 8 {
+            return Err(Error::InvalidResponse(response));
+        }
+        let byte_order = if response[2] & 0x01 == 0 {
+            ByteOrder::LittleEndian
+        } else {
+            ByteOrder::BigEndian
+        };
+        let connection = Self {
+            resources: response[1],
+            byte_order,
+            address_granularity: 1 << ((response[2] >> 1) & 0x3),
+            max_cto: response[3],
+            max_dto: from_bytes(byte_order, &response[4..6]) as u16,
+            protocol_version: response[6],
+            transport_version: response[7],
+        };
+        if connection.max_dto < 8 {
+            return Err(Error::InvalidResponse(response));
+        }
+        Ok(connection)
+    }
+}
+
+/// The state of the session reported by GET_STATUS.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Status {
+    pub session_status: u8,
+    /// Resources that are locked, see [`resource`].
+    pub protection: u8,
+    pub session_configuration_id: u16,
+}
+
+impl Status {
+    fn parse(byte_order: ByteOrder, response: Vec<u8>) -> Result<Self, Error> {
+        if response.len() < 6 {
+            return Err(Error::InvalidResponse(response));
+        }
+        Ok(Self {
+            session_status: response[1],
+            protection: response[2],
+            session_configuration_id: from_bytes(byte_order, &response[4..6]) as u16,
+        })
+    }
+
+    pub fn is_locked(&self, resource: u8) -> bool {
+        self.protection & resource != 0
+    }
+}
+
+/// An XCP master talking to one slave.
+pub struct Master<'a> {
+    interface: &'a mut Interface,
+    tx_id: Id,
+    rx_id: Id,
+    timeout: Duration,
+    connection: Option<Connection>,
+}
+
+impl<'a> Master<'a> {
+    /// Creates a master sending commands on `tx_id` and receiving responses
+    /// and DAQ packets on `rx_id`.
+    pub fn new(interface: &'a mut Interface, tx_id: impl Into<Id>, rx_id: impl Into<Id>) -> Self {
+        Self {
+            interface,
+            tx_id: tx_id.into(),
+            rx_id: rx_id.into(),
+            timeout: Duration::from_millis(1000),
+            connection: None,
+        }
+    }
+
+    /// Sets how long to wait for each response (T1). Defaults to 1 s.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn interface(&mut self) -> &mut Interface {
+        self.interface
+    }
+
+    /// The properties of the slave, once connected.
+    pub fn connection(&self) -> Option<&Connection> {
+        self.connection.as_ref()
+    }
+
+    /// Connects in normal mode. Fails with [`Error::InvalidResponse`] if the
+    /// slave reports an invalid address granularity, or command or DAQ packets
+    /// shorter than 8 bytes, the minimum of the standard.
+    pub fn connect(&mut self) -> Result<Connection, Error> {
+        let response = self.command(&[CONNECT, 0])?;
+        let connection = Connection::parse(response)?;
+        self.connection = Some(connection);
+        Ok(connection)
+    }
+
+    pub fn disconnect(&mut self) -> Result<(), Error> {
+        self.command(&[DISCONNECT])?;
+        self.connection = None;
+        Ok(())
+    }
+
+    pub fn get_status(&mut self) -> Result<Status, Error> {
+        let byte_order = self.byte_order()?;
+        let response = self.command(&[GET_STATUS])?;
+        Status::parse(byte_order, response)
+    }
+
+    /// Unlocks `resource` with the key computed from the seed, which is passed
+    /// the resource as level. Returns the resources that remain locked. Fails
+    /// with [`Error::InvalidKeyLength`] if the key is empty or does not fit in
+    /// the length of UNLOCK.
+    pub fn unlock(&mut self, resource: u8, mut seed_key: impl SeedKey) -> Result<u8, Error> {
+        let max_part = self.max_cto()? - 2;
+
+        let mut response = self.command(&[GET_SEED, 0, resource])?;
+        let mut seed = Vec::new();
+        loop {
+            let Some(&remaining) = response.get(1) else {
+                return Err(Error::InvalidResponse(response));
+            };
+            let remaining = remaining as usize;
+            if remaining == 0 && seed.is_empty() {
+                // The resource is not protected.
+                return Ok(self.get_status()?.protection);
+            }
+            let part = response
+                .get(2..2 + remaining.min(max_part))
+                .ok_or_else(|| Error::InvalidResponse(response.clone()))?;
+            seed.extend_from_slice(part);
+            if remaining <= max_part {
+                break;
+            }
+            response = self.command(&[GET_SEED, 1, resource])?;
+        }
+
+        let key = seed_key.key(resource, &seed);
+        if key.is_empty() || key.len() > u8::MAX as usize {
+            return Err(Error::InvalidKeyLength(key.len()));
+        }
+        let mut protection = 0;
+        for (i, part) in key.chunks(max_part).enumerate() {
+            let mut request = vec![UNLOCK, (key.len() - i * max_part) as u8];
+            request.extend_from_slice(part);
+            let response = self.command(&request)?;
+            protection = *response
+                .get(1)
+                .ok_or_else(|| Error::InvalidResponse(response.clone()))?;
+        }
+        Ok(protection)
+    }
+
+    /// Sets the memory transfer address used by [`Master::upload`] and
+    /// [`Master::download`].
+    pub fn set_mta(&mut self, address: u32, extension: u8) -> Result<(), Error> {
+        let mut request = vec![SET_MTA, 0, 0, extension];
+        request.extend(to_bytes(self.byte_order()?, address as u64, 4));
+        self.command(&request).map(|_| ())
+    }
+
+    /// Reads `size` bytes from the memory transfer address, which is advanced
+    /// past them.
+    pub fn upload(&mut self, size: usize) -> Result<Vec<u8>, Error> {
+        let granularity = self.connected()?.address_granularity as usize;
+        let max_elements = (self.max_cto()? - granularity) / granularity;
+        let mut data = Vec::with_capacity(size);
+        let mut elements = size.div_ceil(granularity);
+        while elements > 0 {
+            let n = elements.min(max_elements);
+            let response = self.command(&[UPLOAD, n as u8])?;
+            // The data is aligned to the address granularity.
+            let part = response
+                .get(granularity..granularity + n * granularity)
+                .ok_or_else(|| Error::InvalidResponse(response.clone()))?;
+            data.extend_from_slice(part);
+            elements -= n;
+        }
+        data.truncate(size);
+        Ok(data)
+    }
+
+    /// Reads up to a packet of memory at `address` in one command.
+    pub fn short_upload(
+        &mut self,
+        address: u32,
+        extension: u8,
+        size: usize,
+    ) -> Result<Vec<u8>, Error> {
+        let granularity = self.connected()?.address_granularity as usize;
+        let elements = size.div_ceil(granularity);
+        let mut request = vec![SHORT_UPLOAD, elements as u8, 0, extension];
+        request.extend(to_bytes(self.byte_order()?, address as u64, 4));
+        let response = self.command(&request)?;
+        let mut data = response
+            .get(granularity..granularity + elements * granularity)
+            .ok_or_else(|| Error::InvalidResponse(response.clone()))?
+            .to_vec();
+        data.truncate(size);
+        Ok(data)
+    }
+
+    /// Writes `data` to the memory transfer address, which is advanced past it.
+    pub fn download(&mut self, data: &[u8]) -> Result<(), Error> {
+        let granularity = self.connected()?.address_granularity as usize;
+        // Alignment bytes follow the command and length for 4 byte elements.
+        let offset = granularity.max(2);
+        let max_elements = (self.max_cto()? - offset) / granularity;
+        for part in data.chunks(max_elements * granularity) {
+            let mut request = vec![DOWNLOAD, part.len().div_ceil(granularity) as u8];
+            request.resize(offset, 0);
+            request.extend_from_slice(part);
+            self.command(&request)?;
+        }
+        Ok(())
+    }
+
+    /// Reads `size` bytes at `address`, with SHORT_UPLOAD if they fit in one
+    /// packet.
+    pub fn read(&mut self, address: u32, extension: u8, size: usize) -> Result<Vec<u8>, Error> {
+        let granularity = self.connected()?.address_granularity as usize;
+        if size <= self.max_cto()? - granularity {
+            return self.short_upload(address, extension, size);
+        }
+        self.set_mta(address, extension)?;
+        self.upload(size)
+    }
+
+    /// Writes `data` at `address`.
+    pub fn write(&mut self, address: u32, extension: u8, data: &[u8]) -> Result<(), Error> {
+        self.set_mta(address, extension)?;
+        self.download(data)
+    }
+
+    fn connected(&self) -> Result<&Connection, Error> {
+        self.connection.as_ref().ok_or(Error::NotConnected)
+    }
+
+    /// The maximum packet length, limited to classic CAN frames. It is always 8
+    /// as [`Master::connect`] rejects shorter packets, so that a packet holds
+    /// a command and at least one element of the address granularity.
+    fn max_cto(&self) -> Result<usize, Error> {
+        Ok(self.connected()?.max_cto.min(8) as usize)
+    }
+
+    fn byte_order(&self) -> Result<ByteOrder, Error> {
+        Ok(self.connected()?.byte_order)
+    }
+
+    /// Sends a command and waits for its positive or negative response.
+    fn command(&mut self, request: &[u8]) -> Result<Vec<u8>, Error> {
+        let frame = Frame::new(self.tx_id, request).unwrap();
+        let filter = ResponseFilter::new(self.rx_id)
+            .with_data(|data| matches!(data.first(), Some(&POSITIVE_RESPONSE | &ERROR)));
+        let response = self
+            .interface
+            .request(&frame, &filter, self.timeout)?
+            .ok_or(Error::Timeout)?;
+        let response = response.data().to_vec();
+        if response[0] == ERROR {
+            return Err(Error::Negative {
+                command: request[0],
+                code: ErrorCode::from(*response.get(1).unwrap_or(&0x31)),
+            });
+        }
+        Ok(response)
+    }
+}
+
+/// Decodes an unsigned parameter of up to 8 bytes.
+fn from_bytes(byte_order: ByteOrder, bytes: &[u8]) -> u64 {
+    let fold = |value, &byte| (value << 8) | byte as u64;
+    match byte_order {
+        ByteOrder::LittleEndian => bytes.iter().rev().fold(0, fold),
+        ByteOrder::BigEndian => bytes.iter().fold(0, fold),
+    }
+}
+
+/// Encodes the `len` low bytes of a parameter.
+fn to_bytes(byte_order: ByteOrder, value: u64, len: usize) -> Vec<u8> {
+    let bytes = value.to_le_bytes()[..len].to_vec();
+    match byte_order {
+        ByteOrder::LittleEndian => bytes,
+        ByteOrder::BigEndian => bytes.into_iter().rev().collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn connect_response() {
+        let connection =
+            Connection::parse(vec![0xFF, 0x15, 0x02, 0x08, 0x08, 0x00, 0x01, 0x01]).unwrap();
+        assert_eq!(
+            connection,
+            Connection {
+                resources: resource::CAL_PAG | resource::DAQ | resource::PGM,
+                byte_order: ByteOrder::LittleEndian,
+                address_granularity: 2,
+                max_cto: 8,
+                max_dto: 8,
+                protocol_version: 1,
+                transport_version: 1,
+            }
+        );
+
+        let connection =
+            Connection::parse(vec![0xFF, 0x00, 0x05, 0x40, 0x00, 0x40, 0x01, 0x02]).unwrap();
+        assert_eq!(connection.byte_order, ByteOrder::BigEndian);
+        assert_eq!(connection.address_granularity, 4);
+        assert_eq!(connection.max_cto, 64);
+        assert_eq!(connection.max_dto, 64);
+        assert_eq!(connection.transport_version, 2);
+
+        for response in [
+            // Too short.
+            vec![0xFF, 0x00, 0x00, 0x08, 0x08, 0x00, 0x01],
+            // Reserved address granularity.
+            vec![0xFF, 0x00, 0x06, 0x08, 0x08, 0x00, 0x01, 0x01],
+            // Command packets shorter than 8 bytes.
+            vec![0xFF, 0x00, 0x00, 0x07, 0x08, 0x00, 0x01, 0x01],
+            // DAQ packets shorter than 8 bytes, in either byte order.
+            vec![0xFF, 0x00, 0x00, 0x08, 0x07, 0x00, 0x01, 0x01],
+            vec![0xFF, 0x00, 0x01, 0x08, 0x00, 0x07, 0x01, 0x01],
+        ] {
+            assert!(
+                matches!(Connection::parse(response.clone()), Err(Error::InvalidResponse(r)) if r == response),
+                "{:02x?}",
+                response
+            );
+        }
+    }
+
+    #[test]
+    fn status_response() {
+        let response = vec![
+            0xFF,
+            0x01,
+            resource::CAL_PAG | resource::DAQ,
+            0x00,
+            0x12,
+            0x34,
+        ];
+        let status = Status::parse(ByteOrder::LittleEndian, response.clone()).unwrap();
+        assert_eq!(
+            status,
+            Status {
+                session_status: 0x01,
+                protection: 0x05,
+                session_configuration_id: 0x3412,
+            }
+        );
+        assert!(status.is_locked(resource::CAL_PAG));
+        assert!(status.is_locked(resource::DAQ));
+        assert!(!status.is_locked(resource::STIM));
+        assert!(!status.is_locked(resource::PGM));
+
+        let status = Status::parse(ByteOrder::BigEndian, response).unwrap();
+        assert_eq!(status.session_configuration_id, 0x1234);
+
+        assert!(matches!(
+            Status::parse(ByteOrder::LittleEndian, vec![0xFF, 0x00, 0x00, 0x00, 0x00]),
+            Err(Error::InvalidResponse(_))
+        ));
+    }
+}
@@ -0,0 +1,628 @@
+//! Dynamic DAQ lists: measurements sampled by the slave on an event channel and
+//! sent as DAQ packets, decoded into timestamped samples.
+//!
+//! ```text
+//! let list = DaqList::new(0)
+//!     .with_timestamp(true)
+//!     .with_measurement(0x2000_0010, 0, 4)
+//!     .with_measurement(0x2000_0020, 0, 2);
+//! let mut daq = master.configure_daq(&[list])?;
+//! master.start_daq()?;
+//! while let Some(sample) = master.receive_daq(&mut daq, Duration::from_secs(1))? {
+//!     println!("{:?} {:02x?}", sample.timestamp, sample.values);
+//! }
+//! master.stop_daq()?;
+//! ```
+
+use std::time::{Duration, Instant};
+
+use embedded_can::Frame as _;
+
+use super::{Error, Master, from_bytes, to_bytes};
+use crate::{ResponseFilter, dbc::ByteOrder};
+
+const SET_DAQ_LIST_MODE: u8 = 0xE0;
+const WRITE_DAQ: u8 = 0xE1;
+const SET_DAQ_PTR: u8 = 0xE2;
+const START_STOP_DAQ_LIST: u8 = 0xDE;
+const START_STOP_SYNCH: u8 = 0xDD;
+const GET_DAQ_PROCESSOR_INFO: u8 = 0xDA;
+const GET_DAQ_RESOLUTION_INFO: u8 = 0xD9;
+const FREE_DAQ: u8 = 0xD6;
+const ALLOC_DAQ: u8 = 0xD5;
+const ALLOC_ODT: u8 = 0xD4;
+const ALLOC_ODT_ENTRY: u8 = 0xD3;
+
+const MODE_TIMESTAMP: u8 = 0x10;
+
+/// START_STOP_DAQ_LIST mode selecting a list for START_STOP_SYNCH.
+const SELECT: u8 = 0x02;
+const STOP_ALL: u8 = 0x00;
+const START_SELECTED: u8 = 0x01;
+
+/// The first packet identifier of responses, events and service requests.
+const FIRST_CTO_PID: u8 = 0xFC;
+
+/// How DAQ packets identify their list and ODT.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IdentificationField {
+    /// One byte with the absolute ODT number.
+    Absolute,
+    /// The relative ODT number and a byte with the DAQ list number.
+    RelativeByte,
+    /// The relative ODT number and a word with the DAQ list number.
+    RelativeWord,
+    /// Like [`IdentificationField::RelativeWord`], with a fill byte in between.
+    RelativeWordAligned,
+}
+
+impl IdentificationField {
+    fn len(self) -> usize {
+        match self {
+            IdentificationField::Absolute => 1,
+            IdentificationField::RelativeByte => 2,
+            IdentificationField::RelativeWord => 3,
+            IdentificationField::RelativeWordAligned => 4,
+        }
+    }
+}
+
+/// General properties of the DAQ processor of the slave.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ProcessorInfo {
+    pub properties: u8,
+    pub max_daq: u16,
+    pub max_event_channel: u16,
+    /// Number of predefined lists, dynamic lists are numbered after them.
+    pub min_daq: u8,
+    pub identification: IdentificationField,
+}
+
+/// Entry size and timestamp resolution of the DAQ processor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ResolutionInfo {
+    /// Size in bytes ODT entries must be a multiple of: 1, 2, 4 or 8.
+    pub granularity: u8,
+    pub max_odt_entry_size: u8,
+    /// Size of the timestamp in bytes, 0 if not supported.
+    pub timestamp_size: u8,
+    /// Whether every list is timestamped regardless of its mode.
+    pub timestamp_fixed: bool,
+    /// Timestamp unit as a power of ten of nanoseconds, or of picoseconds from
+    /// 10 on.
+    pub timestamp_unit: u8,
+    /// Units per timestamp tick.
+    pub timestamp_ticks: u16,
+}
+
+impl ResolutionInfo {
+    /// Converts a timestamp into the time since the wrap around of the clock
+    /// of the slave.
+    pub fn timestamp(&self, raw: u64) -> Duration {
+        let picoseconds = match self.timestamp_unit {
+            unit @ 0..=9 => 1000 * 10u128.pow(unit as u32),
+            unit => 10u128.pow(unit.saturating_sub(10) as u32),
+        };
+        let nanos = raw as u128 * self.timestamp_ticks as u128 * picoseconds / 1000;
+        Duration::from_nanos(nanos as u64)
+    }
+}
+
+/// Memory sampled by a DAQ list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Measurement {
+    pub address: u32,
+    pub extension: u8,
+    /// Size in bytes.
+    pub size: usize,
+}
+
+/// A DAQ list to configure, sampled on an event channel of the slave.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DaqList {
+    pub event_channel: u16,
+    pub prescaler: u8,
+    pub priority: u8,
+    pub timestamp: bool,
+    pub measurements: Vec<Measurement>,
+}
+
+impl DaqList {
+    pub fn new(event_channel: u16) -> Self {
+        Self {
+            event_channel,
+            prescaler: 1,
+            priority: 0,
+            timestamp: false,
+            measurements: Vec::new(),
+        }
+    }
+
+    /// Samples on every nth event only.
+    pub fn with_prescaler(mut self, prescaler: u8) -> Self {
+        self.prescaler = prescaler.max(1);
+        self
+    }
+
+    pub fn with_priority(mut self, priority: u8) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Adds the timestamp of the slave to the first packet of every sample.
+    pub fn with_timestamp(mut self, timestamp: bool) -> Self {
+        self.timestamp = timestamp;
+        self
+    }
+
+    /// Appends `size` bytes at `address` to the sampled memory. Measurements are
+    /// split across ODT entries and packets as needed.
+    pub fn with_measurement(mut self, address: u32, extension: u8, size: usize) -> Self {
+        self.measurements.push(Measurement {
+            address,
+            extension,
+            size,
+        });
+        self
+    }
+}
+
+/// The measurement values of one DAQ list sample.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Sample {
+    /// Index of the list in the configuration.
+    pub list: usize,
+    /// Timestamp of the slave, if enabled for the list.
+    pub timestamp: Option<Duration>,
+    /// Memory of the measurements, in the byte order of the slave.
+    pub values: Vec<Vec<u8>>,
+}
+
+/// An ODT entry, part of a measurement.
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+    measurement: usize,
+    address: u32,
+    extension: u8,
+    size: usize,
+}
+
+#[derive(Debug)]
+struct Partial {
+    next_odt: usize,
+    timestamp: Option<Duration>,
+    values: Vec<Vec<u8>>,
+}
+
+#[derive(Debug)]
+struct List {
+    number: u16,
+    first_pid: u8,
+    timestamp: bool,
+    /// Sizes of the measurements, the entries may sample more to be aligned.
+    measurements: Vec<usize>,
+    odts: Vec<Vec<Entry>>,
+    partial: Option<Partial>,
+}
+
+/// Decodes the DAQ packets of configured lists into samples.
+#[derive(Debug)]
+pub struct Daq {
+    identification: IdentificationField,
+    byte_order: ByteOrder,
+    resolution: ResolutionInfo,
+    lists: Vec<List>,
+}
+
+impl Daq {
+    /// Decodes a DAQ packet. Returns a sample once all packets of the list were
+    /// received in order, other packets are dropped.
+    pub fn decode(&mut self, data: &[u8]) -> Option<Sample> {
+        let (index, odt) = self.identify(data)?;
+        let mut offset = self.identification.len();
+        let timestamp_size = self.resolution.timestamp_size as usize;
+        let list = &mut self.lists[index];
+
+        if odt == 0 {
+            let timestamp = if list.timestamp {
+                let raw = data.get(offset..offset + timestamp_size)?;
+                offset += timestamp_size;
+                Some(self.resolution.timestamp(from_bytes(self.byte_order, raw)))
+            } else {
+                None
+            };
+            list.partial = Some(Partial {
+                next_odt: 0,
+                timestamp,
+                values: vec![Vec::new(); list.measurements.len()],
+            });
+        }
+
+        let partial = list.partial.as_mut()?;
+        if partial.next_odt != odt {
+            list.partial = None;
+            return None;
+        }
+        for entry in &list.odts[odt] {
+            let Some(bytes) = data.get(offset..offset + entry.size) else {
+                list.partial = None;
+                return None;
+            };
+            partial.values[entry.measurement].extend_from_slice(bytes);
+            offset += entry.size;
+        }
+        partial.next_odt += 1;
+
+        if partial.next_odt < list.odts.len() {
+            return None;
+        }
+        let mut partial = list.partial.take()?;
+        for (value, &size) in partial.values.iter_mut().zip(&list.measurements) {
+            value.truncate(size);
+        }
+        Some(Sample {
+            list: index,
+            timestamp: partial.timestamp,
+            values: partial.values,
+        })
+    }
+
+    /// The index of the list and the relative ODT number of a packet.
+    fn identify(&self, data: &[u8]) -> Option<(usize, usize)> {
+        let daq = match self.identification {
+            IdentificationField::Absolute => {
+                let pid = *data.first()?;
+                return self.lists.iter().enumerate().find_map(|(index, list)| {
+                    let odt = pid.checked_sub(list.first_pid)? as usize;
+                    (odt < list.odts.len()).then_some((index, odt))
+                });
+            }
+            IdentificationField::RelativeByte => *data.get(1)? as u16,
+            IdentificationField::RelativeWord => {
+                from_bytes(self.byte_order, data.get(1..3)?) as u16
+            }
+            IdentificationField::RelativeWordAligned => {
+                from_bytes(self.byte_order, data.get(2..4)?) as u16
+            }
+        };
+        let odt = *data.first()? as usize;
+        let index = self.lists.iter().position(|list| list.number == daq)?;
+        (odt < self.lists[index].odts.len()).then_some((index, odt))
+    }
+}
+
+impl Master<'_> {
+    pub fn get_daq_processor_info(&mut self) -> Result<ProcessorInfo, Error> {
+        let response = self.command(&[GET_DAQ_PROCESSOR_INFO])?;
+        if response.len() < 8 {
+            return Err(Error::InvalidResponse(response));
+        }
+        let byte_order = self.byte_order()?;
+        Ok(ProcessorInfo {
+            properties: response[1],
+            max_daq: from_bytes(byte_order, &response[2..4]) as u16,
+            max_event_channel: from_bytes(byte_order, &response[4..6]) as u16,
+            min_daq: response[6],
+            identification: match response[7] >> 6 {
+                0 => IdentificationField::Absolute,
+                1 => IdentificationField::RelativeByte,
+                2 => IdentificationField::RelativeWord,
+                _ => IdentificationField::RelativeWordAligned,
+            },
+        })
+    }
+
+    pub fn get_daq_resolution_info(&mut self) -> Result<ResolutionInfo, Error> {
+        let response = self.command(&[GET_DAQ_RESOLUTION_INFO])?;
+        if response.len() < 8 || ![1, 2, 4, 8].contains(&response[1]) {
+            return Err(Error::InvalidResponse(response));
+        }
+        Ok(ResolutionInfo {
+            granularity: response[1],
+            max_odt_entry_size: response[2],
+            timestamp_size: response[5] & 0x07,
+            timestamp_fixed: response[5] & 0x08 != 0,
+            timestamp_unit: response[5] >> 4,
+            timestamp_ticks: from_bytes(self.byte_order()?, &response[6..8]) as u16,
+        })
+    }
+
+    /// Deletes all dynamic DAQ lists.
+    pub fn free_daq(&mut self) -> Result<(), Error> {
+        self.command(&[FREE_DAQ]).map(|_| ())
+    }
+
+    /// Replaces the dynamic DAQ lists of the slave with `lists` and selects them
+    /// for [`Master::start_daq`].
+    pub fn configure_daq(&mut self, lists: &[DaqList]) -> Result<Daq, Error> {
+        let processor = self.get_daq_processor_info()?;
+        let resolution = self.get_daq_resolution_info()?;
+        let byte_order = self.byte_order()?;
+        let word = |value: u16| to_bytes(byte_order, value as u64, 2);
+
+        let max_dto = self.connected()?.max_dto.min(8) as usize;
+        let packet_size = max_dto - processor.identification.len();
+        let granularity = resolution.granularity as usize;
+        if granularity > packet_size {
+            return Err(Error::Configuration(format!(
+                "DAQ entries of {} bytes do not fit in packets of {} bytes",
+                granularity, packet_size
+            )));
+        }
+        if processor.min_daq as usize + lists.len() > u16::MAX as usize + 1 {
+            return Err(Error::Configuration(format!(
+                "{} DAQ lists exceed the DAQ list numbers",
+                lists.len()
+            )));
+        }
+        let layouts: Vec<List> = lists
+            .iter()
+            .enumerate()
+            .map(|(i, list)| {
+                let timestamp =
+                    resolution.timestamp_size > 0 && (list.timestamp || resolution.timestamp_fixed);
+                let first_size = packet_size.saturating_sub(if timestamp {
+                    resolution.timestamp_size as usize
+                } else {
+                    0
+                });
+                List {
+                    number: processor.min_daq as u16 + i as u16,
+                    first_pid: 0,
+                    timestamp,
+                    measurements: list.measurements.iter().map(|m| m.size).collect(),
+                    odts: pack(
+                        &list.measurements,
+                        first_size,
+                        packet_size,
+                        resolution.max_odt_entry_size as usize,
+                        granularity,
+                    ),
+                    partial: None,
+                }
+            })
+            .collect();
+        // ODT numbers and entry counts are single bytes in the commands.
+        for list in &layouts {
+            if list.odts.len() > u8::MAX as usize {
+                return Err(Error::Configuration(format!(
+                    "DAQ list {} needs {} ODTs, at most 255 are supported",
+                    list.number,
+                    list.odts.len()
+                )));
+            }
+            if let Some(entries) = list
+                .odts
+                .iter()
+                .find(|entries| entries.len() > u8::MAX as usize)
+            {
+                return Err(Error::Configuration(format!(
+                    "DAQ list {} needs {} entries in an ODT, at most 255 are supported",
+                    list.number,
+                    entries.len()
+                )));
+            }
+        }
+
+        self.free_daq()?;
+        let mut request = vec![ALLOC_DAQ, 0];
+        request.extend(word(lists.len() as u16));
+        self.command(&request)?;
+        for list in &layouts {
+            let mut request = vec![ALLOC_ODT, 0];
+            request.extend(word(list.number));
+            request.push(list.odts.len() as u8);
+            self.command(&request)?;
+        }
+        for list in &layouts {
+            for (odt, entries) in list.odts.iter().enumerate() {
+                let mut request = vec![ALLOC_ODT_ENTRY, 0];
+                request.extend(word(list.number));
+                request.extend([odt as u8, entries.len() as u8]);
+                self.command(&request)?;
+            }
+        }
+
+        for list in &layouts {
+            for (odt, entries) in list.odts.iter().enumerate() {
+                let mut request = vec![SET_DAQ_PTR, 0];
+                request.extend(word(list.number));
+                request.extend([odt as u8, 0]);
+                self.command(&request)?;
+                for entry in entries {
+                    // 0xFF as bit offset means the entry is not a single bit.
+                    let mut request = vec![WRITE_DAQ, 0xFF, entry.size as u8, entry.extension];
+                    request.extend(to_bytes(byte_order, entry.address as u64, 4));
+                    self.command(&request)?;
+                }
+            }
+        }
+
+        let mut daq = Daq {
+            identification: processor.identification,
+            byte_order,
+            resolution,
+            lists: layouts,
+        };
+        for (list, config) in daq.lists.iter_mut().zip(lists) {
+            let mode = if list.timestamp { MODE_TIMESTAMP } else { 0 };
+            let mut request = vec![SET_DAQ_LIST_MODE, mode];
+            request.extend(word(list.number));
+            request.extend(word(config.event_channel));
+            request.extend([config.prescaler, config.priority]);
+            self.command(&request)?;
+
+            let mut request = vec![START_STOP_DAQ_LIST, SELECT];
+            request.extend(word(list.number));
+            let response = self.command(&request)?;
+            list.first_pid = *response
+                .get(1)
+                .ok_or_else(|| Error::InvalidResponse(response.clone()))?;
+        }
+        Ok(daq)
+    }
+
+    /// Starts the selected DAQ lists simultaneously.
+    pub fn start_daq(&mut self) -> Result<(), Error> {
+        self.command(&[START_STOP_SYNCH, START_SELECTED])
+            .map(|_| ())
+    }
+
+    /// Stops all DAQ lists.
+    pub fn stop_daq(&mut self) -> Result<(), Error> {
+        self.command(&[START_STOP_SYNCH, STOP_ALL]).map(|_| ())
+    }
+
+    /// Waits up to `timeout` for the next complete sample of the lists of `daq`.
+    /// Returns `Ok(None)` on timeout.
+    pub fn receive_daq(
+        &mut self,
+        daq: &mut Daq,
+        timeout: Duration,
+    ) -> Result<Option<Sample>, Error> {
+        let deadline = Instant::now() + timeout;
+        let filter = ResponseFilter::new(self.rx_id)
+            .with_data(|data| data.first().is_some_and(|&pid| pid < FIRST_CTO_PID));
+        loop {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            let Some(frame) = self.interface.receive_matching(&filter, timeout)? else {
+                return Ok(None);
+            };
+            if let Some(sample) = daq.decode(frame.data()) {
+                return Ok(Some(sample));
+            }
+        }
+    }
+}
+
+/// Splits the measurements into ODTs of at most `packet_size` bytes, the first
+/// `first_size` bytes long to leave room for the timestamp. Entry sizes are
+/// multiples of `granularity`, which must not exceed `packet_size`, so
+/// measurements are rounded up to it.
+fn pack(
+    measurements: &[Measurement],
+    first_size: usize,
+    packet_size: usize,
+    max_entry_size: usize,
+    granularity: usize,
+) -> Vec<Vec<Entry>> {
+    let align = |size: usize| size / granularity * granularity;
+    let max_entry_size = align(max_entry_size).max(granularity);
+    let mut odts = vec![Vec::new()];
+    let mut free = first_size;
+    for (index, measurement) in measurements.iter().enumerate() {
+        let mut address = measurement.address;
+        let mut remaining = measurement.size.next_multiple_of(granularity);
+        while remaining > 0 {
+            if free < granularity {
+                odts.push(Vec::new());
+                free = packet_size;
+            }
+            let size = align(remaining.min(free)).min(max_entry_size);
+            odts.last_mut().unwrap().push(Entry {
+                measurement: index,
+                address,
+                extension: measurement.extension,
+                size,
+            });
+            address = address.wrapping_add(size as u32);
+            remaining -= size;
+            free -= size;
+        }
+    }
+    odts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn measurements() -> Vec<Measurement> {
+        vec![
+            Measurement {
+                address: 0x100,
+                extension: 0,
+                size: 3,
+            },
+            Measurement {
+                address: 0x200,
+                extension: 1,
+                size: 6,
+            },
+        ]
+    }
+
+    fn layout(odts: &[Vec<Entry>]) -> Vec<Vec<(usize, u32, usize)>> {
+        odts.iter()
+            .map(|odt| {
+                odt.iter()
+                    .map(|entry| (entry.measurement, entry.address, entry.size))
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn pack_aligned() {
+        let measurements = measurements();
+        assert_eq!(
+            layout(&pack(&measurements, 5, 7, 255, 1)),
+            [vec![(0, 0x100, 3), (1, 0x200, 2)], vec![(1, 0x202, 4)],]
+        );
+        assert_eq!(
+            layout(&pack(&measurements, 5, 7, 255, 2)),
+            [vec![(0, 0x100, 4)], vec![(1, 0x200, 6)]]
+        );
+        assert_eq!(
+            layout(&pack(&measurements, 5, 7, 3, 2)),
+            [
+                vec![(0, 0x100, 2), (0, 0x102, 2)],
+                vec![(1, 0x200, 2), (1, 0x202, 2), (1, 0x204, 2)],
+            ]
+        );
+        assert_eq!(
+            layout(&pack(&measurements, 5, 7, 255, 4)),
+            [
+                vec![(0, 0x100, 4)],
+                vec![(1, 0x200, 4)],
+                vec![(1, 0x204, 4)],
+            ]
+        );
+    }
+
+    #[test]
+    fn decode() {
+        let resolution = ResolutionInfo {
+            granularity: 2,
+            max_odt_entry_size: 255,
+            timestamp_size: 2,
+            timestamp_fixed: false,
+            timestamp_unit: 3,
+            timestamp_ticks: 1,
+        };
+        let mut daq = Daq {
+            identification: IdentificationField::Absolute,
+            byte_order: ByteOrder::LittleEndian,
+            resolution,
+            lists: vec![List {
+                number: 0,
+                first_pid: 4,
+                timestamp: true,
+                measurements: vec![3, 6],
+                odts: pack(&measurements(), 5, 7, 255, 2),
+                partial: None,
+            }],
+        };
+
+        assert_eq!(daq.decode(&[4, 0x10, 0, 1, 2, 3, 0]), None);
+        let sample = daq.decode(&[5, 1, 2, 3, 4, 5, 6]).unwrap();
+        assert_eq!(sample.list, 0);
+        assert_eq!(sample.timestamp, Some(Duration::from_micros(16)));
+        // The alignment byte of the first measurement is dropped.
+        assert_eq!(sample.values, [vec![1, 2, 3], vec![1, 2, 3, 4, 5, 6]]);
+
+        // Packets out of order and of other lists are dropped.
+        assert_eq!(daq.decode(&[5, 1, 2, 3, 4, 5, 6]), None);
+        assert_eq!(daq.decode(&[6, 1, 2, 3, 4, 5, 6]), None);
+    }
+}